@@ -104,6 +104,9 @@ typedef struct RawEvent {
 
     /** <No documentation available> */
     Vec_uint8_t error;
+
+    /** <No documentation available> */
+    Vec_uint8_t input_timeout;
 } RawEvent_t;
 
 /** <No documentation available> */
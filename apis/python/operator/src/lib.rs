@@ -118,6 +118,7 @@ impl PyEvent {
             Event::Stop => "STOP",
             Event::Input { .. } => "INPUT",
             Event::InputClosed { .. } => "INPUT_CLOSED",
+            Event::InputTimeout { .. } => "INPUT_TIMEOUT",
             Event::Error(_) => "ERROR",
             _other => "UNKNOWN",
         }
@@ -127,6 +128,7 @@ impl PyEvent {
         match event {
             Event::Input { id, .. } => Some(id),
             Event::InputClosed { id } => Some(id),
+            Event::InputTimeout { id } => Some(id),
             _ => None,
         }
     }
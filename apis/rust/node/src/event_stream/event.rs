@@ -22,6 +22,11 @@ pub enum Event {
     InputClosed {
         id: DataId,
     },
+    /// No message was received on the input within the `deadline` that is set in the
+    /// dataflow descriptor.
+    InputTimeout {
+        id: DataId,
+    },
     Error(String),
 }
 
@@ -147,6 +147,7 @@ impl EventStream {
                 NodeEvent::Stop => Event::Stop,
                 NodeEvent::Reload { operator_id } => Event::Reload { operator_id },
                 NodeEvent::InputClosed { id } => Event::InputClosed { id },
+                NodeEvent::InputTimeout { id } => Event::InputTimeout { id },
                 NodeEvent::Input { id, metadata, data } => {
                     let data = match data {
                         None => Ok(None),
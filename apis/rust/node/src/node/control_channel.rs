@@ -90,16 +90,35 @@ impl ControlChannel {
         Ok(())
     }
 
+    pub fn report_consumed_inputs(&mut self, inputs: Vec<DataId>) -> eyre::Result<()> {
+        let reply = self
+            .channel
+            .request(&Timestamped {
+                inner: DaemonRequest::InputsConsumed(inputs),
+                timestamp: self.clock.new_timestamp(),
+            })
+            .wrap_err("failed to report consumed inputs to dora-daemon")?;
+        match reply {
+            DaemonReply::Result(result) => result
+                .map_err(|e| eyre!(e))
+                .wrap_err("failed to receive consumed inputs reply from dora-daemon")?,
+            other => bail!("unexpected consumed inputs reply: {other:?}"),
+        }
+        Ok(())
+    }
+
     pub fn send_message(
         &mut self,
         output_id: DataId,
         metadata: Metadata,
         data: Option<DataMessage>,
+        blocking: bool,
     ) -> eyre::Result<()> {
         let request = DaemonRequest::SendMessage {
             output_id,
             metadata,
             data,
+            blocking,
         };
         let reply = self
             .channel
@@ -109,7 +128,8 @@ impl ControlChannel {
             })
             .wrap_err("failed to send SendMessage request to dora-daemon")?;
         match reply {
-            DaemonReply::Empty => Ok(()),
+            DaemonReply::Empty | DaemonReply::Result(Ok(())) => Ok(()),
+            DaemonReply::Result(Err(err)) => bail!("{err}"),
             other => bail!("unexpected SendMessage reply: {other:?}"),
         }
     }
@@ -8,8 +8,9 @@ use self::{
 use aligned_vec::{AVec, ConstAlign};
 use arrow::array::Array;
 use dora_core::{
-    config::{DataId, NodeId, NodeRunConfig},
+    config::{DataId, InputMapping, NodeId, NodeRunConfig, OverflowPolicy},
     descriptor::Descriptor,
+    schema::{DataSchema, SchemaMismatch},
     topics::{DORA_DAEMON_LOCAL_LISTEN_PORT_DEFAULT, LOCALHOST},
     uhlc,
 };
@@ -23,7 +24,7 @@ use dora_message::{
 use eyre::{bail, WrapErr};
 use shared_memory_extended::{Shmem, ShmemConf};
 use std::{
-    collections::{HashMap, VecDeque},
+    collections::{BTreeSet, HashMap, VecDeque},
     ops::{Deref, DerefMut},
     sync::Arc,
     time::Duration,
@@ -49,6 +50,8 @@ pub struct DoraNode {
     sent_out_shared_memory: HashMap<DropToken, ShmemHandle>,
     drop_stream: DropStream,
     cache: VecDeque<ShmemHandle>,
+    /// Outputs that are mapped to inputs with `overflow: block`.
+    blocking_outputs: BTreeSet<DataId>,
 
     dataflow_descriptor: Descriptor,
 }
@@ -140,6 +143,7 @@ impl DoraNode {
             ControlChannel::init(dataflow_id, &node_id, &daemon_communication, clock.clone())
                 .wrap_err("failed to init control channel")?;
 
+        let blocking_outputs = blocking_outputs(&node_id, &dataflow_descriptor);
         let node = Self {
             id: node_id,
             dataflow_id,
@@ -149,6 +153,7 @@ impl DoraNode {
             sent_out_shared_memory: HashMap::new(),
             drop_stream,
             cache: VecDeque::new(),
+            blocking_outputs,
             dataflow_descriptor,
         };
         Ok((node, event_stream))
@@ -255,6 +260,17 @@ impl DoraNode {
         if !self.node_config.outputs.contains(&output_id) {
             eyre::bail!("unknown output");
         }
+        if let Some(schema) = self.node_config.outputs.schema(&output_id) {
+            if !schema.matches(&type_info.data_type) {
+                return Err(SchemaMismatch {
+                    inputs: self.receivers(&output_id),
+                    output: (self.id.clone(), output_id),
+                    expected: schema.clone(),
+                    found: DataSchema(type_info.data_type),
+                }
+                .into());
+            }
+        }
         let metadata = Metadata::from_parameters(self.clock.new_timestamp(), type_info, parameters);
 
         let (data, shmem) = match sample {
@@ -262,8 +278,9 @@ impl DoraNode {
             None => (None, None),
         };
 
+        let blocking = self.blocking_outputs.contains(&output_id);
         self.control_channel
-            .send_message(output_id.clone(), metadata, data)
+            .send_message(output_id.clone(), metadata, data, blocking)
             .wrap_err_with(|| format!("failed to send output {output_id}"))?;
 
         if let Some((shared_memory, drop_token)) = shmem {
@@ -288,6 +305,17 @@ impl DoraNode {
         Ok(())
     }
 
+    /// Frees the queue slots of delivered inputs with `overflow: block`.
+    ///
+    /// Only needed by the dora runtime, which queues the inputs of its operators. The
+    /// slots of the inputs of other nodes are freed when they are delivered.
+    #[doc(hidden)]
+    pub fn report_consumed_inputs(&mut self, inputs: Vec<DataId>) -> eyre::Result<()> {
+        self.control_channel
+            .report_consumed_inputs(inputs)
+            .wrap_err("failed to report consumed inputs to daemon")
+    }
+
     pub fn id(&self) -> &NodeId {
         &self.id
     }
@@ -370,6 +398,32 @@ impl DoraNode {
         }
     }
 
+    /// Returns the inputs that receive the given output of this node.
+    fn receivers(&self, output_id: &DataId) -> Vec<(NodeId, DataId)> {
+        let nodes = match self.dataflow_descriptor.resolve_aliases_and_set_defaults() {
+            Ok(nodes) => nodes,
+            Err(err) => {
+                tracing::warn!("failed to resolve dataflow descriptor: {err:?}");
+                return Vec::new();
+            }
+        };
+        nodes
+            .iter()
+            .flat_map(|node| {
+                node.kind.run_config().inputs.into_iter().filter_map(
+                    |(input_id, input)| match input.mapping {
+                        InputMapping::User(mapping)
+                            if mapping.source == self.id && &mapping.output == output_id =>
+                        {
+                            Some((node.id.clone(), input_id))
+                        }
+                        _ => None,
+                    },
+                )
+            })
+            .collect()
+    }
+
     /// Returns the full dataflow descriptor that this node is part of.
     ///
     /// This method returns the parsed dataflow YAML file.
@@ -378,6 +432,26 @@ impl DoraNode {
     }
 }
 
+/// Returns the outputs of the given node that are mapped to inputs with `overflow: block`.
+fn blocking_outputs(node_id: &NodeId, dataflow_descriptor: &Descriptor) -> BTreeSet<DataId> {
+    let nodes = match dataflow_descriptor.resolve_aliases_and_set_defaults() {
+        Ok(nodes) => nodes,
+        Err(err) => {
+            tracing::warn!("failed to resolve dataflow descriptor: {err:?}");
+            return BTreeSet::new();
+        }
+    };
+    nodes
+        .iter()
+        .flat_map(|node| node.kind.run_config().inputs.into_values())
+        .filter(|input| input.overflow == OverflowPolicy::Block)
+        .filter_map(|input| match input.mapping {
+            InputMapping::User(mapping) if &mapping.source == node_id => Some(mapping.output),
+            _ => None,
+        })
+        .collect()
+}
+
 impl Drop for DoraNode {
     #[tracing::instrument(skip(self), fields(self.id = %self.id), level = "trace")]
     fn drop(&mut self) {
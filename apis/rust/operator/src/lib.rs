@@ -32,9 +32,21 @@ pub mod raw;
 #[derive(Debug)]
 #[non_exhaustive]
 pub enum Event<'a> {
-    Input { id: &'a str, data: ArrowData },
-    InputParseError { id: &'a str, error: String },
-    InputClosed { id: &'a str },
+    Input {
+        id: &'a str,
+        data: ArrowData,
+    },
+    InputParseError {
+        id: &'a str,
+        error: String,
+    },
+    InputClosed {
+        id: &'a str,
+    },
+    /// No message was received on the input within its `deadline`.
+    InputTimeout {
+        id: &'a str,
+    },
     Stop,
 }
 
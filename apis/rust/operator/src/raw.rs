@@ -58,6 +58,8 @@ pub unsafe fn dora_on_event<O: DoraOperator>(
         }
     } else if let Some(input_id) = &event.input_closed {
         Event::InputClosed { id: input_id }
+    } else if let Some(input_id) = &event.input_timeout {
+        Event::InputTimeout { id: input_id }
     } else if event.stop {
         Event::Stop
     } else {
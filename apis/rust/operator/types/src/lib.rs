@@ -99,6 +99,7 @@ pub struct RawEvent {
     pub input_closed: Option<safer_ffi::String>,
     pub stop: bool,
     pub error: Option<safer_ffi::String>,
+    pub input_timeout: Option<safer_ffi::String>,
 }
 
 #[derive_ReprC]
@@ -4,13 +4,11 @@ use dora_core::descriptor::{resolve_path, CoreNodeKind, Descriptor};
 use dora_message::cli_to_coordinator::ControlRequest;
 use dora_message::common::LogMessage;
 use dora_message::coordinator_to_cli::ControlRequestReply;
+use dora_security::BlockingStream;
 use eyre::Context;
 use notify::event::ModifyKind;
 use notify::{Config, Event as NotifyEvent, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
-use std::{
-    collections::HashMap,
-    net::{SocketAddr, TcpStream},
-};
+use std::collections::HashMap;
 use std::{path::PathBuf, sync::mpsc, time::Duration};
 use tracing::{error, info};
 use uuid::Uuid;
@@ -23,7 +21,7 @@ pub fn attach_dataflow(
     dataflow_id: Uuid,
     session: &mut TcpRequestReplyConnection,
     hot_reload: bool,
-    coordinator_socket: SocketAddr,
+    mut log_session: TcpConnection<BlockingStream>,
     log_level: log::LevelFilter,
 ) -> Result<(), eyre::ErrReport> {
     let (tx, rx) = mpsc::sync_channel(2);
@@ -125,10 +123,6 @@ pub fn attach_dataflow(
     .wrap_err("failed to set ctrl-c handler")?;
 
     // subscribe to log messages
-    let mut log_session = TcpConnection {
-        stream: TcpStream::connect(coordinator_socket)
-            .wrap_err("failed to connect to dora coordinator")?,
-    };
     log_session
         .send(
             &serde_json::to_vec(&ControlRequest::LogSubscribe {
@@ -155,32 +149,7 @@ pub fn attach_dataflow(
             },
             Ok(AttachEvent::Control(control_request)) => control_request,
             Ok(AttachEvent::Log(Ok(log_message))) => {
-                let LogMessage {
-                    dataflow_id: _,
-                    node_id,
-                    level,
-                    target,
-                    module_path: _,
-                    file: _,
-                    line: _,
-                    message,
-                } = log_message;
-                let level = match level {
-                    log::Level::Error => "ERROR".red(),
-                    log::Level::Warn => "WARN ".yellow(),
-                    log::Level::Info => "INFO ".green(),
-                    other => format!("{other:5}").normal(),
-                };
-                let node = match node_id {
-                    Some(node_id) => format!(" {node_id}").bold(),
-                    None => "".normal(),
-                };
-                let target = match target {
-                    Some(target) => format!(" {target}").dimmed(),
-                    None => "".normal(),
-                };
-
-                println!("{level}{node}{target}: {message}");
+                print_log_message(log_message);
                 continue;
             }
             Ok(AttachEvent::Log(Err(err))) => {
@@ -208,6 +177,36 @@ pub fn attach_dataflow(
     }
 }
 
+/// Prints a log message received from the coordinator.
+pub(crate) fn print_log_message(log_message: LogMessage) {
+    let LogMessage {
+        dataflow_id: _,
+        node_id,
+        level,
+        target,
+        module_path: _,
+        file: _,
+        line: _,
+        message,
+    } = log_message;
+    let level = match level {
+        log::Level::Error => "ERROR".red(),
+        log::Level::Warn => "WARN ".yellow(),
+        log::Level::Info => "INFO ".green(),
+        other => format!("{other:5}").normal(),
+    };
+    let node = match node_id {
+        Some(node_id) => format!(" {node_id}").bold(),
+        None => "".normal(),
+    };
+    let target = match target {
+        Some(target) => format!(" {target}").dimmed(),
+        None => "".normal(),
+    };
+
+    println!("{level}{node}{target}: {message}");
+}
+
 enum AttachEvent {
     Control(ControlRequest),
     Log(eyre::Result<LogMessage>),
@@ -1,12 +1,22 @@
+use communication_layer_request_reply::TcpRequestReplyConnection;
 use dora_core::{
     config::OperatorId,
-    descriptor::{Descriptor, SINGLE_OPERATOR_DEFAULT_ID},
+    descriptor::{Descriptor, Variables, SINGLE_OPERATOR_DEFAULT_ID},
 };
-use eyre::{eyre, Context};
-use std::{path::Path, process::Command};
+use dora_message::{
+    cli_to_coordinator::ControlRequest,
+    coordinator_to_cli::{ControlRequestReply, LogMessage, NodeBuildResult},
+};
+use dora_security::Security;
+use eyre::{bail, eyre, Context};
+use std::{io::Write, net::SocketAddr, path::Path, process::Command};
+use tabwriter::TabWriter;
+use uuid::Uuid;
+
+use crate::{attach::print_log_message, connect_to_coordinator, open_coordinator_connection};
 
-pub fn build(dataflow: &Path) -> eyre::Result<()> {
-    let descriptor = Descriptor::blocking_read(dataflow)?;
+pub fn build(dataflow: &Path, variables: &Variables) -> eyre::Result<()> {
+    let descriptor = Descriptor::blocking_read_with_variables(dataflow, variables)?;
     let dataflow_absolute = if dataflow.is_relative() {
         std::env::current_dir().unwrap().join(dataflow)
     } else {
@@ -16,7 +26,9 @@ pub fn build(dataflow: &Path) -> eyre::Result<()> {
 
     let default_op_id = OperatorId::from(SINGLE_OPERATOR_DEFAULT_ID.to_string());
 
-    for node in descriptor.nodes {
+    for node in descriptor.flatten_nodes()? {
+        // nodes of included subflows are built in the directory of the subflow
+        let working_dir = &working_dir.join(node.build_dir.clone().unwrap_or_default());
         match node.kind()? {
             dora_core::descriptor::NodeKind::Standard(_) => {
                 run_build_command(node.build.as_deref(), working_dir).with_context(|| {
@@ -79,3 +91,132 @@ fn run_build_command(build: Option<&str>, working_dir: &Path) -> eyre::Result<()
         Ok(())
     }
 }
+
+/// Runs the build commands on the daemons of the machines that the nodes are deployed
+/// to and prints a summary of the results.
+///
+/// The build output of all machines is streamed to the terminal while the nodes are
+/// built in parallel.
+pub fn build_distributed(
+    dataflow: &Path,
+    variables: &Variables,
+    coordinator_addr: SocketAddr,
+    security: &Security,
+    log_level: log::LevelFilter,
+) -> eyre::Result<()> {
+    let descriptor = Descriptor::blocking_read_with_variables(dataflow, variables)?;
+    let working_dir = dataflow
+        .canonicalize()
+        .context("failed to canonicalize dataflow path")?
+        .parent()
+        .ok_or_else(|| eyre!("dataflow path has no parent dir"))?
+        .to_owned();
+
+    let mut session = connect_to_coordinator(coordinator_addr, security)
+        .wrap_err("failed to connect to dora coordinator")?;
+    let reply = request(
+        &mut *session,
+        &ControlRequest::Build {
+            dataflow: descriptor,
+            local_working_dir: working_dir,
+        },
+    )?;
+    let build_id = match reply {
+        ControlRequestReply::BuildStarted { build_id } => build_id,
+        other => bail!("unexpected build reply: {other:?}"),
+    };
+
+    // the coordinator closes the log connection when the build is finished
+    let mut log_session = open_coordinator_connection(coordinator_addr, security)
+        .wrap_err("failed to connect to dora coordinator")?;
+    log_session
+        .send(
+            &serde_json::to_vec(&ControlRequest::BuildLogSubscribe {
+                build_id,
+                level: log_level,
+            })
+            .wrap_err("failed to serialize message")?,
+        )
+        .wrap_err("failed to send log subscribe request to coordinator")?;
+    // wait for the subscription, the build might be removed after `WaitForBuild`
+    let reply = serde_json::from_slice(
+        &log_session
+            .receive()
+            .wrap_err("failed to receive log subscribe reply")?,
+    )
+    .wrap_err("failed to parse log subscribe reply")?;
+    match reply {
+        ControlRequestReply::BuildLogSubscribed => {}
+        ControlRequestReply::Error(err) => bail!("{err}"),
+        other => bail!("unexpected log subscribe reply: {other:?}"),
+    }
+    let log_thread = std::thread::spawn(move || {
+        while let Ok(raw) = log_session.receive() {
+            match serde_json::from_slice::<LogMessage>(&raw) {
+                Ok(message) => print_log_message(message),
+                Err(err) => tracing::warn!("failed to parse log message: {err:?}"),
+            }
+        }
+    });
+
+    let reply = request(&mut *session, &ControlRequest::WaitForBuild { build_id })?;
+    let mut results = match reply {
+        ControlRequestReply::BuildFinished {
+            build_id: _,
+            results,
+        } => results,
+        other => bail!("unexpected build reply: {other:?}"),
+    };
+    let _ = log_thread.join();
+
+    results.sort_by(|a, b| (&a.node_id, &a.machine_id).cmp(&(&b.node_id, &b.machine_id)));
+    print_build_results(build_id, &results)?;
+
+    let failed = results.iter().filter(|r| r.result.is_err()).count();
+    if failed > 0 {
+        bail!("build failed for {failed} of {} nodes", results.len());
+    }
+    Ok(())
+}
+
+fn request(
+    session: &mut TcpRequestReplyConnection,
+    request: &ControlRequest,
+) -> eyre::Result<ControlRequestReply> {
+    let reply_raw = session
+        .request(&serde_json::to_vec(request).unwrap())
+        .wrap_err("failed to send build request")?;
+    let reply: ControlRequestReply =
+        serde_json::from_slice(&reply_raw).wrap_err("failed to parse reply")?;
+    match reply {
+        ControlRequestReply::Error(err) => bail!("{err}"),
+        other => Ok(other),
+    }
+}
+
+fn print_build_results(build_id: Uuid, results: &[NodeBuildResult]) -> eyre::Result<()> {
+    let mut tw = TabWriter::new(vec![]);
+    tw.write_all(b"NODE\tMACHINE\tRESULT\n")?;
+    for NodeBuildResult {
+        node_id,
+        machine_id,
+        result,
+    } in results
+    {
+        let machine = if machine_id.is_empty() {
+            "<default>"
+        } else {
+            machine_id.as_str()
+        };
+        let result = match result {
+            Ok(()) => "ok".to_owned(),
+            Err(err) => format!("failed: {}", err.lines().next().unwrap_or_default()),
+        };
+        tw.write_all(format!("{node_id}\t{machine}\t{result}\n").as_bytes())?;
+    }
+    tw.flush()?;
+    let formatted = String::from_utf8(tw.into_inner()?)?;
+
+    println!("\nbuild {build_id}:\n{formatted}");
+    Ok(())
+}
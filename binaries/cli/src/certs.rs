@@ -0,0 +1,51 @@
+use dora_security::generate_certificates;
+use eyre::{bail, Context};
+use std::path::Path;
+
+const CA_CERT_FILE: &str = "ca.pem";
+const CERT_FILE: &str = "cert.pem";
+const KEY_FILE: &str = "key.pem";
+
+/// Generates a CA and a certificate for the dora components and writes them to the
+/// given directory.
+pub fn create(out_dir: &Path, hostnames: &[String], force: bool) -> eyre::Result<()> {
+    let files = [CA_CERT_FILE, CERT_FILE, KEY_FILE].map(|f| out_dir.join(f));
+    if !force {
+        if let Some(existing) = files.iter().find(|f| f.exists()) {
+            bail!(
+                "`{}` already exists (use `--force` to overwrite it)",
+                existing.display()
+            );
+        }
+    }
+
+    let certs = generate_certificates(hostnames)?;
+    std::fs::create_dir_all(out_dir)
+        .with_context(|| format!("failed to create `{}`", out_dir.display()))?;
+    let [ca_path, cert_path, key_path] = files;
+    write(&ca_path, &certs.ca_cert)?;
+    write(&cert_path, &certs.cert)?;
+    write(&key_path, &certs.key)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&key_path, std::fs::Permissions::from_mode(0o600))
+            .with_context(|| format!("failed to set permissions of `{}`", key_path.display()))?;
+    }
+
+    println!(
+        "Created certificates in `{}`. Copy the directory to all machines and add the \
+        following to their `dora-config.yml`:\n",
+        out_dir.display()
+    );
+    println!("security:");
+    println!("  tls:");
+    for (key, path) in [("ca", &ca_path), ("cert", &cert_path), ("key", &key_path)] {
+        println!("    {key}: {}", path.display());
+    }
+    Ok(())
+}
+
+fn write(path: &Path, content: &str) -> eyre::Result<()> {
+    std::fs::write(path, content).with_context(|| format!("failed to write `{}`", path.display()))
+}
@@ -1,14 +1,17 @@
-use crate::connect_to_coordinator;
+use crate::{connect_to_coordinator, resolve_dataflow};
 use communication_layer_request_reply::TcpRequestReplyConnection;
+use dora_core::config::ExpectedRate;
 use dora_message::{cli_to_coordinator::ControlRequest, coordinator_to_cli::ControlRequestReply};
+use dora_security::Security;
 use eyre::{bail, Context};
 use std::{
     io::{IsTerminal, Write},
     net::SocketAddr,
 };
+use tabwriter::TabWriter;
 use termcolor::{Color, ColorChoice, ColorSpec, WriteColor};
 
-pub fn check_environment(coordinator_addr: SocketAddr) -> eyre::Result<()> {
+pub fn check_environment(coordinator_addr: SocketAddr, security: &Security) -> eyre::Result<()> {
     let mut error_occurred = false;
 
     let color_choice = if std::io::stdout().is_terminal() {
@@ -20,7 +23,7 @@ pub fn check_environment(coordinator_addr: SocketAddr) -> eyre::Result<()> {
 
     // check whether coordinator is running
     write!(stdout, "Dora Coordinator: ")?;
-    let mut session = match connect_to_coordinator(coordinator_addr) {
+    let mut session = match connect_to_coordinator(coordinator_addr, security) {
         Ok(session) => {
             let _ = stdout.set_color(ColorSpec::new().set_fg(Some(Color::Green)));
             writeln!(stdout, "ok")?;
@@ -75,3 +78,67 @@ pub fn daemon_running(session: &mut TcpRequestReplyConnection) -> Result<bool, e
 
     Ok(running)
 }
+
+/// Prints the declared and measured rates of all outputs of a running dataflow.
+pub fn check_live(session: &mut TcpRequestReplyConnection, dataflow: &str) -> eyre::Result<()> {
+    let uuid = resolve_dataflow(session, dataflow)?;
+
+    let reply_raw = session
+        .request(
+            &serde_json::to_vec(&ControlRequest::OutputRates {
+                dataflow_uuid: uuid,
+            })
+            .unwrap(),
+        )
+        .wrap_err("failed to send OutputRates message")?;
+    let reply = serde_json::from_slice(&reply_raw).wrap_err("failed to parse reply")?;
+    let mut rates = match reply {
+        ControlRequestReply::OutputRates(rates) => rates,
+        ControlRequestReply::Error(err) => bail!("{err}"),
+        other => bail!("unexpected reply to output rates request: {other:?}"),
+    };
+    rates.sort_by(|a, b| (&a.node_id, &a.output_id).cmp(&(&b.node_id, &b.output_id)));
+
+    let mut tw = TabWriter::new(vec![]);
+    tw.write_all(b"NODE\tOUTPUT\tEXPECTED\tMEASURED\tSTATUS\n")?;
+    for rate in rates {
+        let expected = match (rate.expected, rate.tolerance_percent) {
+            (Some(expected), Some(tolerance)) => format!("{expected} ±{tolerance}%"),
+            (Some(expected), None) => expected.to_string(),
+            (None, _) => "-".to_owned(),
+        };
+        let measured = rate
+            .measured
+            .map(|m| m.to_string())
+            .unwrap_or_else(|| "-".to_owned());
+        let status = match (rate.expected, rate.tolerance_percent, rate.measured) {
+            (Some(rate), Some(tolerance_percent), Some(measured)) => {
+                if (ExpectedRate {
+                    rate,
+                    tolerance_percent,
+                })
+                .matches(measured)
+                {
+                    "ok"
+                } else {
+                    "deviating"
+                }
+            }
+            (Some(_), _, None) => "no data yet",
+            _ => "-",
+        };
+        tw.write_all(
+            format!(
+                "{}\t{}\t{expected}\t{measured}\t{status}\n",
+                rate.node_id, rate.output_id
+            )
+            .as_bytes(),
+        )?;
+    }
+    tw.flush()?;
+    let formatted = String::from_utf8(tw.into_inner()?)?;
+
+    println!("{formatted}");
+
+    Ok(())
+}
@@ -0,0 +1,51 @@
+use dora_security::{Security, SecurityConfig};
+use eyre::Context;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+const DEFAULT_CONFIG_PATH: &str = "dora-config.yml";
+
+/// Settings of the dora config file, shared by the CLI, the coordinator, and the daemon.
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DoraConfig {
+    #[serde(default)]
+    pub security: SecurityConfig,
+}
+
+impl DoraConfig {
+    /// Returns the given config path, or `dora-config.yml` if it exists in the current
+    /// directory.
+    pub fn path(config_path: Option<&Path>) -> Option<PathBuf> {
+        config_path
+            .map(ToOwned::to_owned)
+            .or_else(|| Some(PathBuf::from(DEFAULT_CONFIG_PATH)).filter(|p| p.exists()))
+    }
+
+    /// Parses the config file at the given path, or returns the default config.
+    ///
+    /// Relative paths in the config file are resolved against its directory.
+    pub fn load(path: Option<&Path>) -> eyre::Result<Self> {
+        let Some(path) = path else {
+            return Ok(Default::default());
+        };
+        let raw = fs::read_to_string(path)
+            .with_context(|| format!("failed to read `{}`", path.display()))?;
+        let mut config: Self = serde_yaml::from_str(&raw)
+            .with_context(|| format!("failed to parse `{}`", path.display()))?;
+        let base_dir = path
+            .canonicalize()
+            .with_context(|| format!("failed to canonicalize `{}`", path.display()))?
+            .parent()
+            .map(ToOwned::to_owned)
+            .unwrap_or_default();
+        config.security.resolve_paths(&base_dir);
+        Ok(config)
+    }
+
+    pub fn security(&self) -> eyre::Result<Security> {
+        Security::load(&self.security).wrap_err("failed to load security settings")
+    }
+}
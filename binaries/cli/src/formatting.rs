@@ -21,7 +21,12 @@ impl std::fmt::Display for FormatDataflowError<'_> {
         let hidden = if !non_cascading.is_empty() {
             let printed = non_cascading.len();
             for (id, err) in non_cascading {
-                writeln!(f, "Node `{id}` failed: {err}")?;
+                match self.0.node_restarts.get(id) {
+                    Some(restarts) => {
+                        writeln!(f, "Node `{id}` failed (after {restarts} restarts): {err}")?
+                    }
+                    None => writeln!(f, "Node `{id}` failed: {err}")?,
+                }
             }
             total_failed - printed
         } else {
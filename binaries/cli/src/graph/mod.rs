@@ -1,20 +1,25 @@
 use std::{fs::File, io::Write, path::Path};
 
-use dora_core::descriptor::Descriptor;
+use dora_core::descriptor::{Descriptor, Variables};
 use eyre::Context;
 
 const MERMAID_TEMPLATE: &str = include_str!("mermaid-template.html");
 
-pub(crate) fn create(dataflow: std::path::PathBuf, mermaid: bool, open: bool) -> eyre::Result<()> {
+pub(crate) fn create(
+    dataflow: std::path::PathBuf,
+    mermaid: bool,
+    open: bool,
+    variables: &Variables,
+) -> eyre::Result<()> {
     if mermaid {
-        let visualized = visualize_as_mermaid(&dataflow)?;
+        let visualized = visualize_as_mermaid(&dataflow, variables)?;
         println!("{visualized}");
         println!(
             "Paste the above output on https://mermaid.live/ or in a \
             ```mermaid code block on GitHub to display it."
         );
     } else {
-        let html = visualize_as_html(&dataflow)?;
+        let html = visualize_as_html(&dataflow, variables)?;
 
         let working_dir = std::env::current_dir().wrap_err("failed to get current working dir")?;
         let graph_filename = match dataflow.file_stem().and_then(|n| n.to_str()) {
@@ -51,13 +56,13 @@ pub(crate) fn create(dataflow: std::path::PathBuf, mermaid: bool, open: bool) ->
     Ok(())
 }
 
-pub fn visualize_as_html(dataflow: &Path) -> eyre::Result<String> {
-    let mermaid = visualize_as_mermaid(dataflow)?;
+pub fn visualize_as_html(dataflow: &Path, variables: &Variables) -> eyre::Result<String> {
+    let mermaid = visualize_as_mermaid(dataflow, variables)?;
     Ok(MERMAID_TEMPLATE.replacen("____insert____", &mermaid, 1))
 }
 
-pub fn visualize_as_mermaid(dataflow: &Path) -> eyre::Result<String> {
-    let descriptor = Descriptor::blocking_read(dataflow)
+pub fn visualize_as_mermaid(dataflow: &Path, variables: &Variables) -> eyre::Result<String> {
+    let descriptor = Descriptor::blocking_read_with_variables(dataflow, variables)
         .with_context(|| format!("failed to read dataflow at `{}`", dataflow.display()))?;
     let visualized = descriptor
         .visualize_as_mermaid()
@@ -1,27 +1,33 @@
 use attach::attach_dataflow;
 use clap::Parser;
 use colored::Colorize;
-use communication_layer_request_reply::{RequestReplyLayer, TcpLayer, TcpRequestReplyConnection};
+use communication_layer_request_reply::{TcpConnection, TcpRequestReplyConnection};
+use config::DoraConfig;
 use dora_coordinator::Event;
 use dora_core::{
-    descriptor::Descriptor,
+    descriptor::{Descriptor, Variables},
     topics::{
         DORA_COORDINATOR_PORT_CONTROL_DEFAULT, DORA_COORDINATOR_PORT_DEFAULT,
         DORA_DAEMON_LOCAL_LISTEN_PORT_DEFAULT,
     },
 };
-use dora_daemon::Daemon;
+use dora_daemon::{Daemon, DEFAULT_MAX_MESSAGE_SIZE};
 use dora_message::{
     cli_to_coordinator::ControlRequest,
     coordinator_to_cli::{ControlRequestReply, DataflowList, DataflowResult, DataflowStatus},
+    daemon_to_coordinator::MachineProperties,
 };
+use dora_security::{BlockingStream, Security};
 #[cfg(feature = "tracing")]
 use dora_tracing::set_up_tracing;
 use dora_tracing::set_up_tracing_opts;
 use duration_str::parse;
 use eyre::{bail, Context};
 use formatting::FormatDataflowError;
-use std::{io::Write, net::SocketAddr};
+use std::{
+    io::Write,
+    net::{SocketAddr, TcpStream},
+};
 use std::{
     net::{IpAddr, Ipv4Addr},
     path::PathBuf,
@@ -29,16 +35,21 @@ use std::{
 };
 use tabwriter::TabWriter;
 use tokio::runtime::Builder;
+use upload::Upload;
 use uuid::Uuid;
 
 mod attach;
 mod build;
+mod certs;
 mod check;
+mod config;
 mod formatting;
 mod graph;
 mod logs;
+mod stats;
 mod template;
 mod up;
+mod upload;
 
 const LOCALHOST: IpAddr = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
 const LISTEN_WILDCARD: IpAddr = IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0));
@@ -48,6 +59,9 @@ const LISTEN_WILDCARD: IpAddr = IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0));
 struct Args {
     #[clap(subcommand)]
     command: Command,
+    /// Path to the dora config file [default: `dora-config.yml` if it exists]
+    #[clap(long, global = true, value_name = "PATH", value_hint = clap::ValueHint::FilePath)]
+    config: Option<PathBuf>,
 }
 
 /// dora-rs cli client
@@ -58,6 +72,11 @@ enum Command {
         /// Path to the dataflow descriptor file (enables additional checks)
         #[clap(long, value_name = "PATH", value_hint = clap::ValueHint::FilePath)]
         dataflow: Option<PathBuf>,
+        /// Print the declared and measured output rates of a running dataflow
+        #[clap(long, value_name = "NAME_OR_UUID", conflicts_with = "dataflow")]
+        live: Option<String>,
+        #[clap(flatten)]
+        variables: VariableArgs,
         /// Address of the dora coordinator
         #[clap(long, value_name = "IP", default_value_t = LOCALHOST)]
         coordinator_addr: IpAddr,
@@ -76,12 +95,26 @@ enum Command {
         /// Open the HTML visualization in the browser
         #[clap(long, action)]
         open: bool,
+        #[clap(flatten)]
+        variables: VariableArgs,
     },
     /// Run build commands provided in the given dataflow.
     Build {
         /// Path to the dataflow descriptor file
         #[clap(value_name = "PATH", value_hint = clap::ValueHint::FilePath)]
         dataflow: PathBuf,
+        #[clap(flatten)]
+        variables: VariableArgs,
+        /// Run the build commands on the daemons of the machines that the nodes are
+        /// deployed to instead of locally
+        #[clap(long, action)]
+        distributed: bool,
+        /// Address of the dora coordinator, used with `--distributed`
+        #[clap(long, value_name = "IP", default_value_t = LOCALHOST)]
+        coordinator_addr: IpAddr,
+        /// Port number of the coordinator control server, used with `--distributed`
+        #[clap(long, value_name = "PORT", default_value_t = DORA_COORDINATOR_PORT_CONTROL_DEFAULT)]
+        coordinator_port: u16,
     },
     /// Generate a new project or node. Choose the language between Rust, Python, C or C++.
     New {
@@ -90,17 +123,22 @@ enum Command {
         #[clap(hide = true, long)]
         internal_create_with_path_dependencies: bool,
     },
-    /// Spawn coordinator and daemon in local mode (with default config)
-    Up {
-        /// Use a custom configuration
-        #[clap(long, hide = true, value_name = "PATH", value_hint = clap::ValueHint::FilePath)]
-        config: Option<PathBuf>,
+    /// Generate TLS certificates for the coordinator, daemon, and CLI connections
+    Certs {
+        /// Directory to write the certificates to
+        #[clap(long, value_name = "PATH", default_value = "certs")]
+        out_dir: PathBuf,
+        /// Additional host name or IP address to include in the certificate
+        #[clap(long = "hostname", value_name = "NAME")]
+        hostnames: Vec<String>,
+        /// Overwrite existing certificate files
+        #[clap(long)]
+        force: bool,
     },
+    /// Spawn coordinator and daemon in local mode (with default config)
+    Up,
     /// Destroy running coordinator and daemon. If some dataflows are still running, they will be stopped first.
     Destroy {
-        /// Use a custom configuration
-        #[clap(long, hide = true)]
-        config: Option<PathBuf>,
         /// Address of the dora coordinator
         #[clap(long, value_name = "IP", default_value_t = LOCALHOST)]
         coordinator_addr: IpAddr,
@@ -131,6 +169,36 @@ enum Command {
         /// Enable hot reloading (Python only)
         #[clap(long, action)]
         hot_reload: bool,
+        /// Upload the local node executables, shared libraries and Python files to the
+        /// daemons that run the nodes (up to 128 MiB of files that aren't cached yet)
+        #[clap(long, action)]
+        upload: bool,
+        #[clap(flatten)]
+        variables: VariableArgs,
+    },
+    /// Apply an updated descriptor to a running dataflow.
+    ///
+    /// Added nodes are spawned and removed nodes are stopped. Nodes whose definition
+    /// changed are restarted, unless only their input mappings changed.
+    Apply {
+        /// Identifier of the running dataflow
+        #[clap(value_name = "UUID_OR_NAME")]
+        dataflow: String,
+        /// Path to the updated dataflow descriptor file
+        #[clap(value_name = "PATH", value_hint = clap::ValueHint::FilePath)]
+        descriptor: PathBuf,
+        /// Kill stopped nodes if they don't exit after the given duration
+        #[clap(long, value_name = "DURATION")]
+        #[arg(value_parser = parse)]
+        grace_duration: Option<Duration>,
+        #[clap(flatten)]
+        variables: VariableArgs,
+        /// Address of the dora coordinator
+        #[clap(long, value_name = "IP", default_value_t = LOCALHOST)]
+        coordinator_addr: IpAddr,
+        /// Port number of the coordinator control server
+        #[clap(long, value_name = "PORT", default_value_t = DORA_COORDINATOR_PORT_CONTROL_DEFAULT)]
+        coordinator_port: u16,
     },
     /// Stop the given dataflow UUID. If no id is provided, you will be able to choose between the running dataflows.
     Stop {
@@ -150,6 +218,25 @@ enum Command {
         #[clap(long, value_name = "PORT", default_value_t = DORA_COORDINATOR_PORT_CONTROL_DEFAULT)]
         coordinator_port: u16,
     },
+    /// Restart a single node of a running dataflow.
+    Restart {
+        /// Identifier of the dataflow
+        #[clap(value_name = "UUID_OR_NAME")]
+        dataflow: String,
+        /// ID of the node that should be restarted
+        #[clap(value_name = "NODE")]
+        node: String,
+        /// Kill the node if it doesn't stop after the given duration
+        #[clap(long, value_name = "DURATION")]
+        #[arg(value_parser = parse)]
+        grace_duration: Option<Duration>,
+        /// Address of the dora coordinator
+        #[clap(long, value_name = "IP", default_value_t = LOCALHOST)]
+        coordinator_addr: IpAddr,
+        /// Port number of the coordinator control server
+        #[clap(long, value_name = "PORT", default_value_t = DORA_COORDINATOR_PORT_CONTROL_DEFAULT)]
+        coordinator_port: u16,
+    },
     /// List running dataflows.
     List {
         /// Address of the dora coordinator
@@ -178,7 +265,30 @@ enum Command {
         coordinator_port: u16,
     },
     // Metrics,
-    // Stats,
+    /// Show message statistics of a running dataflow.
+    Stats {
+        /// Identifier of the dataflow
+        #[clap(value_name = "UUID_OR_NAME")]
+        dataflow: String,
+        /// Refresh the statistics periodically
+        #[clap(long, action)]
+        watch: bool,
+        /// Refresh interval for `--watch`
+        #[clap(
+            long,
+            value_name = "DURATION",
+            default_value = "1s",
+            requires = "watch"
+        )]
+        #[arg(value_parser = parse)]
+        interval: Duration,
+        /// Address of the dora coordinator
+        #[clap(long, value_name = "IP", default_value_t = LOCALHOST)]
+        coordinator_addr: IpAddr,
+        /// Port number of the coordinator control server
+        #[clap(long, value_name = "PORT", default_value_t = DORA_COORDINATOR_PORT_CONTROL_DEFAULT)]
+        coordinator_port: u16,
+    },
     // Get,
     // Upgrade,
     /// Run daemon
@@ -192,6 +302,25 @@ enum Command {
         /// Local listen port for event such as dynamic node.
         #[clap(long, default_value_t = DORA_DAEMON_LOCAL_LISTEN_PORT_DEFAULT)]
         local_listen_port: u16,
+        /// Label of this machine for placing nodes with a `selector` (e.g. `--label gpu=true`)
+        #[clap(long = "label", value_name = "KEY=VALUE", value_parser = parse_label)]
+        labels: Vec<(String, String)>,
+        /// Maximum number of nodes that the coordinator places on this machine
+        #[clap(long, value_name = "NODES")]
+        capacity: Option<usize>,
+        /// Root directory for resolving the relative node paths of dataflows on this machine
+        #[clap(long, value_name = "PATH", value_hint = clap::ValueHint::DirPath)]
+        working_dir: Option<PathBuf>,
+        /// Zenoh configuration file used for dataflows with `_unstable_remote: zenoh`.
+        #[clap(long, value_name = "PATH")]
+        zenoh_config: Option<PathBuf>,
+        /// Directory for caching the artifacts uploaded through `dora start --upload`
+        #[clap(long, value_name = "PATH", value_hint = clap::ValueHint::DirPath)]
+        artifact_dir: Option<PathBuf>,
+        /// Maximum size in bytes of a compressed message from another machine after
+        /// decompression
+        #[clap(long, value_name = "BYTES", default_value_t = DEFAULT_MAX_MESSAGE_SIZE)]
+        max_message_size: usize,
         /// Address and port number of the dora coordinator
         #[clap(long, default_value_t = SocketAddr::new(LOCALHOST, DORA_COORDINATOR_PORT_DEFAULT))]
         coordinator_addr: SocketAddr,
@@ -217,6 +346,9 @@ enum Command {
         /// Port number to bind to for control communication
         #[clap(long, default_value_t = DORA_COORDINATOR_PORT_CONTROL_DEFAULT)]
         control_port: u16,
+        /// Directory to persist the dataflow state in, so that it survives coordinator restarts
+        #[clap(long, value_name = "PATH")]
+        state_dir: Option<PathBuf>,
         /// Suppresses all log output to stdout.
         #[clap(long)]
         quiet: bool,
@@ -238,6 +370,50 @@ pub struct CommandNew {
     path: Option<PathBuf>,
 }
 
+/// Values for the variables of a dataflow descriptor.
+#[derive(Debug, clap::Args)]
+pub struct VariableArgs {
+    /// Set a dataflow variable, overriding its default value (e.g. `--var camera=2`)
+    #[clap(long = "var", value_name = "KEY=VALUE", value_parser = parse_variable)]
+    vars: Vec<(String, serde_yaml::Value)>,
+    /// Read dataflow variables from the given YAML file (`--var` takes precedence)
+    #[clap(long, value_name = "PATH", value_hint = clap::ValueHint::FilePath)]
+    vars_file: Option<PathBuf>,
+}
+
+impl VariableArgs {
+    fn load(&self) -> eyre::Result<Variables> {
+        let mut variables = match &self.vars_file {
+            Some(path) => {
+                let file = std::fs::read(path)
+                    .with_context(|| format!("failed to read `{}`", path.display()))?;
+                serde_yaml::from_slice(&file)
+                    .with_context(|| format!("failed to parse `{}`", path.display()))?
+            }
+            None => Variables::new(),
+        };
+        variables.extend(self.vars.iter().cloned());
+        Ok(variables)
+    }
+}
+
+fn parse_variable(raw: &str) -> eyre::Result<(String, serde_yaml::Value)> {
+    let (key, value) = raw
+        .split_once('=')
+        .ok_or_else(|| eyre::eyre!("variable must be given as `KEY=VALUE`"))?;
+    // parse as YAML scalar to support numbers and booleans
+    let value =
+        serde_yaml::from_str(value).unwrap_or_else(|_| serde_yaml::Value::String(value.to_owned()));
+    Ok((key.to_owned(), value))
+}
+
+fn parse_label(raw: &str) -> eyre::Result<(String, String)> {
+    let (key, value) = raw
+        .split_once('=')
+        .ok_or_else(|| eyre::eyre!("label must be given as `KEY=VALUE`"))?;
+    Ok((key.to_owned(), value.to_owned()))
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
 enum Kind {
     Dataflow,
@@ -289,6 +465,13 @@ fn run() -> eyre::Result<()> {
         }
     };
 
+    let config_path = DoraConfig::path(args.config.as_deref());
+    let security = match &args.command {
+        // the runtime is started by the daemon in the working dir of the node
+        Command::Runtime => Security::default(),
+        _ => DoraConfig::load(config_path.as_deref())?.security()?,
+    };
+
     let log_level = env_logger::Builder::new()
         .filter_level(log::LevelFilter::Info)
         .parse_default_env()
@@ -298,37 +481,70 @@ fn run() -> eyre::Result<()> {
     match args.command {
         Command::Check {
             dataflow,
+            live,
+            variables,
             coordinator_addr,
             coordinator_port,
-        } => match dataflow {
-            Some(dataflow) => {
+        } => match (dataflow, live) {
+            (_, Some(live)) => {
+                let mut session =
+                    connect_to_coordinator((coordinator_addr, coordinator_port).into(), &security)
+                        .wrap_err("failed to connect to dora coordinator")?;
+                check::check_live(&mut *session, &live)?
+            }
+            (Some(dataflow), None) => {
                 let working_dir = dataflow
                     .canonicalize()
                     .context("failed to canonicalize dataflow path")?
                     .parent()
                     .ok_or_else(|| eyre::eyre!("dataflow path has no parent dir"))?
                     .to_owned();
-                Descriptor::blocking_read(&dataflow)?.check(&working_dir)?;
-                check::check_environment((coordinator_addr, coordinator_port).into())?
+                Descriptor::blocking_read_with_variables(&dataflow, &variables.load()?)?
+                    .check(&working_dir)?;
+                check::check_environment((coordinator_addr, coordinator_port).into(), &security)?
+            }
+            (None, None) => {
+                check::check_environment((coordinator_addr, coordinator_port).into(), &security)?
             }
-            None => check::check_environment((coordinator_addr, coordinator_port).into())?,
         },
         Command::Graph {
             dataflow,
             mermaid,
             open,
+            variables,
         } => {
-            graph::create(dataflow, mermaid, open)?;
+            graph::create(dataflow, mermaid, open, &variables.load()?)?;
         }
-        Command::Build { dataflow } => {
-            build::build(&dataflow)?;
+        Command::Build {
+            dataflow,
+            variables,
+            distributed,
+            coordinator_addr,
+            coordinator_port,
+        } => {
+            if distributed {
+                build::build_distributed(
+                    &dataflow,
+                    &variables.load()?,
+                    (coordinator_addr, coordinator_port).into(),
+                    &security,
+                    log_level,
+                )?;
+            } else {
+                build::build(&dataflow, &variables.load()?)?;
+            }
         }
         Command::New {
             args,
             internal_create_with_path_dependencies,
         } => template::create(args, internal_create_with_path_dependencies)?,
-        Command::Up { config } => {
-            up::up(config.as_deref())?;
+        Command::Certs {
+            out_dir,
+            hostnames,
+            force,
+        } => certs::create(&out_dir, &hostnames, force)?,
+        Command::Up => {
+            up::up(config_path.as_deref(), &security)?;
         }
         Command::Logs {
             dataflow,
@@ -336,8 +552,9 @@ fn run() -> eyre::Result<()> {
             coordinator_addr,
             coordinator_port,
         } => {
-            let mut session = connect_to_coordinator((coordinator_addr, coordinator_port).into())
-                .wrap_err("failed to connect to dora coordinator")?;
+            let mut session =
+                connect_to_coordinator((coordinator_addr, coordinator_port).into(), &security)
+                    .wrap_err("failed to connect to dora coordinator")?;
             let list = query_running_dataflows(&mut *session)
                 .wrap_err("failed to query running dataflows")?;
             if let Some(dataflow) = dataflow {
@@ -362,9 +579,12 @@ fn run() -> eyre::Result<()> {
             attach,
             detach,
             hot_reload,
+            upload,
+            variables,
         } => {
             let dataflow_descriptor =
-                Descriptor::blocking_read(&dataflow).wrap_err("Failed to read yaml dataflow")?;
+                Descriptor::blocking_read_with_variables(&dataflow, &variables.load()?)
+                    .wrap_err("Failed to read yaml dataflow")?;
             let working_dir = dataflow
                 .canonicalize()
                 .context("failed to canonicalize dataflow path")?
@@ -372,7 +592,7 @@ fn run() -> eyre::Result<()> {
                 .ok_or_else(|| eyre::eyre!("dataflow path has no parent dir"))?
                 .to_owned();
             if !coordinator_addr.is_loopback() {
-                dataflow_descriptor.check_in_daemon(&working_dir, &[], true)?;
+                dataflow_descriptor.check_in_daemon(&working_dir, &[], &[], true)?;
             } else {
                 dataflow_descriptor
                     .check(&working_dir)
@@ -380,12 +600,19 @@ fn run() -> eyre::Result<()> {
             }
 
             let coordinator_socket = (coordinator_addr, coordinator_port).into();
-            let mut session = connect_to_coordinator(coordinator_socket)
+            let mut session = connect_to_coordinator(coordinator_socket, &security)
                 .wrap_err("failed to connect to dora coordinator")?;
+            let upload = if upload {
+                upload::prepare(&dataflow_descriptor, &working_dir, &mut *session)
+                    .wrap_err("failed to prepare artifact upload")?
+            } else {
+                Upload::default()
+            };
             let dataflow_id = start_dataflow(
                 dataflow_descriptor.clone(),
                 name,
                 working_dir,
+                upload,
                 &mut *session,
             )?;
 
@@ -400,13 +627,15 @@ fn run() -> eyre::Result<()> {
             };
 
             if attach {
+                let log_session = open_coordinator_connection(coordinator_socket, &security)
+                    .wrap_err("failed to connect to dora coordinator")?;
                 attach_dataflow(
                     dataflow_descriptor,
                     dataflow,
                     dataflow_id,
                     &mut *session,
                     hot_reload,
-                    coordinator_socket,
+                    log_session,
                     log_level,
                 )?
             }
@@ -414,11 +643,12 @@ fn run() -> eyre::Result<()> {
         Command::List {
             coordinator_addr,
             coordinator_port,
-        } => match connect_to_coordinator((coordinator_addr, coordinator_port).into()) {
+        } => match connect_to_coordinator((coordinator_addr, coordinator_port).into(), &security) {
             Ok(mut session) => list(&mut *session)?,
-            Err(_) => {
+            Err(err) if is_connection_refused(&err) => {
                 bail!("No dora coordinator seems to be running.");
             }
+            Err(err) => return Err(err.wrap_err("failed to connect to dora coordinator")),
         },
         Command::Stop {
             uuid,
@@ -427,27 +657,87 @@ fn run() -> eyre::Result<()> {
             coordinator_addr,
             coordinator_port,
         } => {
-            let mut session = connect_to_coordinator((coordinator_addr, coordinator_port).into())
-                .wrap_err("could not connect to dora coordinator")?;
+            let mut session =
+                connect_to_coordinator((coordinator_addr, coordinator_port).into(), &security)
+                    .wrap_err("could not connect to dora coordinator")?;
             match (uuid, name) {
                 (Some(uuid), _) => stop_dataflow(uuid, grace_duration, &mut *session)?,
                 (None, Some(name)) => stop_dataflow_by_name(name, grace_duration, &mut *session)?,
                 (None, None) => stop_dataflow_interactive(grace_duration, &mut *session)?,
             }
         }
+        Command::Apply {
+            dataflow,
+            descriptor,
+            grace_duration,
+            variables,
+            coordinator_addr,
+            coordinator_port,
+        } => {
+            let dataflow_descriptor =
+                Descriptor::blocking_read_with_variables(&descriptor, &variables.load()?)
+                    .wrap_err("Failed to read yaml dataflow")?;
+            let working_dir = descriptor
+                .canonicalize()
+                .context("failed to canonicalize dataflow path")?
+                .parent()
+                .ok_or_else(|| eyre::eyre!("dataflow path has no parent dir"))?
+                .to_owned();
+            if !coordinator_addr.is_loopback() {
+                dataflow_descriptor.check_in_daemon(&working_dir, &[], &[], true)?;
+            } else {
+                dataflow_descriptor
+                    .check(&working_dir)
+                    .wrap_err("Could not validate yaml")?;
+            }
+
+            let mut session =
+                connect_to_coordinator((coordinator_addr, coordinator_port).into(), &security)
+                    .wrap_err("could not connect to dora coordinator")?;
+            let uuid = resolve_dataflow(&mut *session, &dataflow)?;
+            apply_dataflow(
+                uuid,
+                dataflow_descriptor,
+                working_dir,
+                grace_duration,
+                &mut *session,
+            )?;
+        }
+        Command::Stats {
+            dataflow,
+            watch,
+            interval,
+            coordinator_addr,
+            coordinator_port,
+        } => {
+            let mut session =
+                connect_to_coordinator((coordinator_addr, coordinator_port).into(), &security)
+                    .wrap_err("failed to connect to dora coordinator")?;
+            stats::stats(&mut *session, &dataflow, watch.then_some(interval))?
+        }
+        Command::Restart {
+            dataflow,
+            node,
+            grace_duration,
+            coordinator_addr,
+            coordinator_port,
+        } => {
+            let mut session =
+                connect_to_coordinator((coordinator_addr, coordinator_port).into(), &security)
+                    .wrap_err("could not connect to dora coordinator")?;
+            let uuid = resolve_dataflow(&mut *session, &dataflow)?;
+            restart_node(uuid, node, grace_duration, &mut *session)?;
+        }
         Command::Destroy {
-            config,
             coordinator_addr,
             coordinator_port,
-        } => up::destroy(
-            config.as_deref(),
-            (coordinator_addr, coordinator_port).into(),
-        )?,
+        } => up::destroy((coordinator_addr, coordinator_port).into(), &security)?,
         Command::Coordinator {
             interface,
             port,
             control_interface,
             control_port,
+            state_dir,
             quiet,
         } => {
             let rt = Builder::new_multi_thread()
@@ -457,9 +747,14 @@ fn run() -> eyre::Result<()> {
             rt.block_on(async {
                 let bind = SocketAddr::new(interface, port);
                 let bind_control = SocketAddr::new(control_interface, control_port);
-                let (port, task) =
-                    dora_coordinator::start(bind, bind_control, futures::stream::empty::<Event>())
-                        .await?;
+                let (port, task) = dora_coordinator::start(
+                    bind,
+                    bind_control,
+                    state_dir,
+                    security,
+                    futures::stream::empty::<Event>(),
+                )
+                .await?;
                 if !quiet {
                     println!("Listening for incoming daemon connection on {port}");
                 }
@@ -471,6 +766,12 @@ fn run() -> eyre::Result<()> {
             coordinator_addr,
             inter_daemon_addr,
             local_listen_port,
+            labels,
+            capacity,
+            working_dir,
+            zenoh_config,
+            artifact_dir,
+            max_message_size,
             machine_id,
             run_dataflow,
             quiet: _,
@@ -497,7 +798,19 @@ fn run() -> eyre::Result<()> {
                         if coordinator_addr.ip() == LOCALHOST {
                             tracing::info!("Starting in local mode");
                         }
-                        Daemon::run(coordinator_addr, machine_id.unwrap_or_default(), inter_daemon_addr, local_listen_port).await
+                        let working_dir = working_dir
+                            .map(|dir| {
+                                dir.canonicalize().with_context(|| {
+                                    format!("failed to canonicalize `{}`", dir.display())
+                                })
+                            })
+                            .transpose()?;
+                        let properties = MachineProperties {
+                            labels: labels.into_iter().collect(),
+                            capacity,
+                            working_dir,
+                        };
+                        Daemon::run(coordinator_addr, machine_id.unwrap_or_default(), inter_daemon_addr, local_listen_port, properties, zenoh_config, artifact_dir, max_message_size, security).await
                     }
                 }
             })
@@ -513,6 +826,7 @@ fn start_dataflow(
     dataflow: Descriptor,
     name: Option<String>,
     local_working_dir: PathBuf,
+    upload: Upload,
     session: &mut TcpRequestReplyConnection,
 ) -> Result<Uuid, eyre::ErrReport> {
     let reply_raw = session
@@ -521,6 +835,8 @@ fn start_dataflow(
                 dataflow,
                 name,
                 local_working_dir,
+                artifacts: upload.artifacts,
+                artifact_data: upload.artifact_data,
             })
             .unwrap(),
         )
@@ -619,11 +935,81 @@ fn stop_dataflow_by_name(
     }
 }
 
+fn apply_dataflow(
+    uuid: Uuid,
+    dataflow: Descriptor,
+    local_working_dir: PathBuf,
+    grace_duration: Option<Duration>,
+    session: &mut TcpRequestReplyConnection,
+) -> eyre::Result<()> {
+    let reply_raw = session
+        .request(
+            &serde_json::to_vec(&ControlRequest::Apply {
+                dataflow_uuid: uuid,
+                dataflow,
+                local_working_dir,
+                grace_duration,
+            })
+            .unwrap(),
+        )
+        .wrap_err("failed to send apply message")?;
+    let result: ControlRequestReply =
+        serde_json::from_slice(&reply_raw).wrap_err("failed to parse reply")?;
+    let changes = match result {
+        ControlRequestReply::DataflowChangesApplied { changes, .. } => changes,
+        ControlRequestReply::Error(err) => bail!("{err}"),
+        other => bail!("unexpected apply reply: {other:?}"),
+    };
+    if changes.is_empty() {
+        println!("dataflow {uuid} is up to date");
+        return Ok(());
+    }
+    for (label, nodes) in [
+        ("added", &changes.added),
+        ("removed", &changes.removed),
+        ("restarted", &changes.restarted),
+        ("rewired", &changes.rewired),
+    ] {
+        for node in nodes {
+            println!("{label}: {node}");
+        }
+    }
+    Ok(())
+}
+
+fn restart_node(
+    uuid: Uuid,
+    node: String,
+    grace_duration: Option<Duration>,
+    session: &mut TcpRequestReplyConnection,
+) -> eyre::Result<()> {
+    let reply_raw = session
+        .request(
+            &serde_json::to_vec(&ControlRequest::RestartNode {
+                dataflow: uuid,
+                node_id: node.into(),
+                grace_duration,
+            })
+            .unwrap(),
+        )
+        .wrap_err("failed to send restart node message")?;
+    let result: ControlRequestReply =
+        serde_json::from_slice(&reply_raw).wrap_err("failed to parse reply")?;
+    match result {
+        ControlRequestReply::NodeRestarted { uuid, node_id } => {
+            println!("restarting node `{node_id}` of dataflow {uuid}");
+            Ok(())
+        }
+        ControlRequestReply::Error(err) => bail!("{err}"),
+        other => bail!("unexpected restart node reply: {other:?}"),
+    }
+}
+
 fn list(session: &mut TcpRequestReplyConnection) -> Result<(), eyre::ErrReport> {
     let list = query_running_dataflows(session)?;
 
     let mut tw = TabWriter::new(vec![]);
-    tw.write_all(b"UUID\tName\tStatus\n")?;
+    tw.write_all(b"UUID\tName\tStatus\tPlacement\n")?;
     for entry in list.0 {
         let uuid = entry.id.uuid;
         let name = entry.id.name.unwrap_or_default();
@@ -632,7 +1018,13 @@ fn list(session: &mut TcpRequestReplyConnection) -> Result<(), eyre::ErrReport>
             DataflowStatus::Finished => "Succeeded",
             DataflowStatus::Failed => "Failed",
         };
-        tw.write_all(format!("{uuid}\t{name}\t{status}\n").as_bytes())?;
+        let placement = entry
+            .placement
+            .iter()
+            .map(|(node, machine)| format!("{node}@{machine}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        tw.write_all(format!("{uuid}\t{name}\t{status}\t{placement}\n").as_bytes())?;
     }
     tw.flush()?;
     let formatted = String::from_utf8(tw.into_inner()?)?;
@@ -657,8 +1049,61 @@ fn query_running_dataflows(session: &mut TcpRequestReplyConnection) -> eyre::Res
     Ok(ids)
 }
 
+/// Resolves the given dataflow UUID or name to the UUID of a running dataflow.
+fn resolve_dataflow(session: &mut TcpRequestReplyConnection, dataflow: &str) -> eyre::Result<Uuid> {
+    if let Ok(uuid) = Uuid::parse_str(dataflow) {
+        return Ok(uuid);
+    }
+    let list = query_running_dataflows(session).wrap_err("failed to query running dataflows")?;
+    let matching: Vec<_> = list
+        .get_active()
+        .into_iter()
+        .filter(|d| d.name.as_deref() == Some(dataflow))
+        .collect();
+    match &matching[..] {
+        [] => bail!("no running dataflow with name `{dataflow}`"),
+        [d] => Ok(d.uuid),
+        _ => bail!("multiple running dataflows with name `{dataflow}`, use the UUID instead"),
+    }
+}
+
 fn connect_to_coordinator(
     coordinator_addr: SocketAddr,
-) -> std::io::Result<Box<TcpRequestReplyConnection>> {
-    TcpLayer::new().connect(coordinator_addr)
+    security: &Security,
+) -> eyre::Result<Box<TcpRequestReplyConnection>> {
+    Ok(Box::new(open_coordinator_connection(
+        coordinator_addr,
+        security,
+    )?))
+}
+
+fn is_connection_refused(err: &eyre::Report) -> bool {
+    err.downcast_ref::<std::io::Error>()
+        .is_some_and(|err| err.kind() == std::io::ErrorKind::ConnectionRefused)
+}
+
+/// Connects to the coordinator using TLS if configured, and presents the token.
+fn open_coordinator_connection(
+    coordinator_addr: SocketAddr,
+    security: &Security,
+) -> eyre::Result<TcpConnection<BlockingStream>> {
+    let stream = TcpStream::connect(coordinator_addr)?;
+    let stream = security
+        .connect_blocking(stream)
+        .wrap_err("TLS handshake with dora coordinator failed")?;
+    let mut connection = TcpConnection { stream };
+    if let Some(token) = security.token() {
+        let request = ControlRequest::Authenticate {
+            token: token.to_owned(),
+        };
+        connection.send(&serde_json::to_vec(&request)?)?;
+        let reply = serde_json::from_slice(&connection.receive()?)
+            .wrap_err("failed to parse authentication reply")?;
+        match reply {
+            ControlRequestReply::Authenticated => {}
+            ControlRequestReply::Error(err) => bail!("{err}"),
+            other => bail!("unexpected authentication reply: {other:?}"),
+        }
+    }
+    Ok(connection)
 }
@@ -0,0 +1,154 @@
+use crate::resolve_dataflow;
+use communication_layer_request_reply::TcpRequestReplyConnection;
+use dora_message::{
+    cli_to_coordinator::ControlRequest,
+    coordinator_to_cli::{ControlRequestReply, DataflowStats},
+};
+use eyre::{bail, Context};
+use std::{io::Write, time::Duration};
+use tabwriter::TabWriter;
+use uuid::Uuid;
+
+/// Prints the message statistics of a running dataflow.
+///
+/// With `watch` set, the statistics are refreshed in the given interval until the
+/// dataflow is no longer running.
+pub fn stats(
+    session: &mut TcpRequestReplyConnection,
+    dataflow: &str,
+    watch: Option<Duration>,
+) -> eyre::Result<()> {
+    let uuid = resolve_dataflow(session, dataflow)?;
+    let Some(interval) = watch else {
+        let stats = query_stats(session, uuid)?;
+        print!("{}", format_stats(stats)?);
+        return Ok(());
+    };
+    loop {
+        let stats = match query_stats(session, uuid) {
+            Ok(stats) => stats,
+            Err(err) => {
+                println!("stopped watching: {err}");
+                return Ok(());
+            }
+        };
+        let formatted = format_stats(stats)?;
+        // clear the terminal before printing the refreshed tables
+        print!("\x1b[2J\x1b[H{formatted}");
+        std::io::stdout().flush()?;
+        std::thread::sleep(interval);
+    }
+}
+
+fn query_stats(session: &mut TcpRequestReplyConnection, uuid: Uuid) -> eyre::Result<DataflowStats> {
+    let reply_raw = session
+        .request(
+            &serde_json::to_vec(&ControlRequest::Stats {
+                dataflow_uuid: uuid,
+            })
+            .unwrap(),
+        )
+        .wrap_err("failed to send Stats message")?;
+    let reply = serde_json::from_slice(&reply_raw).wrap_err("failed to parse reply")?;
+    match reply {
+        ControlRequestReply::Stats(stats) => Ok(stats),
+        ControlRequestReply::Error(err) => bail!("{err}"),
+        other => bail!("unexpected reply to stats request: {other:?}"),
+    }
+}
+
+fn format_stats(mut stats: DataflowStats) -> eyre::Result<String> {
+    stats
+        .outputs
+        .sort_by(|a, b| (&a.node_id, &a.output_id).cmp(&(&b.node_id, &b.output_id)));
+    stats
+        .inputs
+        .sort_by(|a, b| (&a.node_id, &a.input_id).cmp(&(&b.node_id, &b.input_id)));
+    stats.connections.sort_by(|a, b| {
+        (&a.machine_id, &a.peer_machine_id).cmp(&(&b.machine_id, &b.peer_machine_id))
+    });
+    stats.best_effort.sort_by(|a, b| {
+        (&a.machine_id, &a.peer_machine_id).cmp(&(&b.machine_id, &b.peer_machine_id))
+    });
+
+    let mut tw = TabWriter::new(vec![]);
+    tw.write_all(b"NODE\tOUTPUT\tSENT\tREMOTE COMPRESSION\n")?;
+    for output in &stats.outputs {
+        tw.write_all(
+            format!(
+                "{}\t{}\t{}\t{}\n",
+                output.node_id,
+                output.output_id,
+                output.sent,
+                format_compression_ratio(output.remote_bytes, output.remote_compressed_bytes),
+            )
+            .as_bytes(),
+        )?;
+    }
+    tw.write_all(b"\nNODE\tINPUT\tDELIVERED\tDROPPED\tLATENCY P50\tP99\tMAX\n")?;
+    for input in &stats.inputs {
+        let latency = &input.stats.latency;
+        tw.write_all(
+            format!(
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+                input.node_id,
+                input.input_id,
+                input.stats.delivered,
+                input.stats.dropped,
+                format_latency(latency.quantile(0.5)),
+                format_latency(latency.quantile(0.99)),
+                format_latency(latency.max()),
+            )
+            .as_bytes(),
+        )?;
+    }
+    if !stats.connections.is_empty() {
+        tw.write_all(b"\nMACHINE\tPEER\tCONNECTED\tRELAYED\tRECONNECTS\tQUEUED\tDROPPED\n")?;
+        for connection in &stats.connections {
+            tw.write_all(
+                format!(
+                    "{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+                    connection.machine_id,
+                    connection.peer_machine_id,
+                    connection.connected,
+                    connection.relayed,
+                    connection.reconnects,
+                    connection.queued,
+                    connection.dropped,
+                )
+                .as_bytes(),
+            )?;
+        }
+    }
+    if !stats.best_effort.is_empty() {
+        tw.write_all(b"\nMACHINE\tBEST-EFFORT FROM\tRECEIVED\tINCOMPLETE\n")?;
+        for best_effort in &stats.best_effort {
+            tw.write_all(
+                format!(
+                    "{}\t{}\t{}\t{}\n",
+                    best_effort.machine_id,
+                    best_effort.peer_machine_id,
+                    best_effort.received,
+                    best_effort.incomplete,
+                )
+                .as_bytes(),
+            )?;
+        }
+    }
+    tw.flush()?;
+    Ok(String::from_utf8(tw.into_inner()?)?)
+}
+
+fn format_compression_ratio(bytes: u64, compressed_bytes: u64) -> String {
+    if bytes == 0 || compressed_bytes == 0 {
+        return "-".to_owned();
+    }
+    format!("{:.2}x", bytes as f64 / compressed_bytes as f64)
+}
+
+fn format_latency(latency: Option<Duration>) -> String {
+    match latency {
+        Some(latency) => format!("{latency:.1?}"),
+        None => "-".to_owned(),
+    }
+}
@@ -1,21 +1,23 @@
-use crate::{check::daemon_running, connect_to_coordinator, LOCALHOST};
+use crate::{check::daemon_running, connect_to_coordinator, is_connection_refused, LOCALHOST};
 use dora_core::topics::DORA_COORDINATOR_PORT_CONTROL_DEFAULT;
 use dora_message::cli_to_coordinator::ControlRequest;
+use dora_security::Security;
 use eyre::Context;
-use std::{fs, net::SocketAddr, path::Path, process::Command, time::Duration};
-#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
-struct UpConfig {}
+use std::{net::SocketAddr, path::Path, process::Command, time::Duration};
 
-pub(crate) fn up(config_path: Option<&Path>) -> eyre::Result<()> {
-    let UpConfig {} = parse_dora_config(config_path)?;
+pub(crate) fn up(config_path: Option<&Path>, security: &Security) -> eyre::Result<()> {
     let coordinator_addr = (LOCALHOST, DORA_COORDINATOR_PORT_CONTROL_DEFAULT).into();
-    let mut session = match connect_to_coordinator(coordinator_addr) {
+    let mut session = match connect_to_coordinator(coordinator_addr, security) {
         Ok(session) => session,
+        // e.g. the running coordinator rejected our token
+        Err(err) if !is_connection_refused(&err) => {
+            return Err(err.wrap_err("failed to connect to dora-coordinator"))
+        }
         Err(_) => {
-            start_coordinator().wrap_err("failed to start dora-coordinator")?;
+            start_coordinator(config_path).wrap_err("failed to start dora-coordinator")?;
 
             loop {
-                match connect_to_coordinator(coordinator_addr) {
+                match connect_to_coordinator(coordinator_addr, security) {
                     Ok(session) => break session,
                     Err(_) => {
                         // sleep a bit until the coordinator accepts connections
@@ -27,7 +29,7 @@ pub(crate) fn up(config_path: Option<&Path>) -> eyre::Result<()> {
     };
 
     if !daemon_running(&mut *session)? {
-        start_daemon().wrap_err("failed to start dora-daemon")?;
+        start_daemon(config_path).wrap_err("failed to start dora-daemon")?;
 
         // wait a bit until daemon is connected
         let mut i = 0;
@@ -48,11 +50,10 @@ pub(crate) fn up(config_path: Option<&Path>) -> eyre::Result<()> {
 }
 
 pub(crate) fn destroy(
-    config_path: Option<&Path>,
     coordinator_addr: SocketAddr,
+    security: &Security,
 ) -> Result<(), eyre::ErrReport> {
-    let UpConfig {} = parse_dora_config(config_path)?;
-    match connect_to_coordinator(coordinator_addr) {
+    match connect_to_coordinator(coordinator_addr, security) {
         Ok(mut session) => {
             // send destroy command to dora-coordinator
             session
@@ -68,25 +69,14 @@ pub(crate) fn destroy(
     Ok(())
 }
 
-fn parse_dora_config(config_path: Option<&Path>) -> Result<UpConfig, eyre::ErrReport> {
-    let path = config_path.or_else(|| Some(Path::new("dora-config.yml")).filter(|p| p.exists()));
-    let config = match path {
-        Some(path) => {
-            let raw = fs::read_to_string(path)
-                .with_context(|| format!("failed to read `{}`", path.display()))?;
-            serde_yaml::from_str(&raw)
-                .with_context(|| format!("failed to parse `{}`", path.display()))?
-        }
-        None => Default::default(),
-    };
-    Ok(config)
-}
-
-fn start_coordinator() -> eyre::Result<()> {
+fn start_coordinator(config_path: Option<&Path>) -> eyre::Result<()> {
     let mut cmd =
         Command::new(std::env::current_exe().wrap_err("failed to get current executable path")?);
     cmd.arg("coordinator");
     cmd.arg("--quiet");
+    if let Some(config_path) = config_path {
+        cmd.arg("--config").arg(config_path);
+    }
     cmd.spawn().wrap_err("failed to run `dora coordinator`")?;
 
     println!("started dora coordinator");
@@ -94,11 +84,14 @@ fn start_coordinator() -> eyre::Result<()> {
     Ok(())
 }
 
-fn start_daemon() -> eyre::Result<()> {
+fn start_daemon(config_path: Option<&Path>) -> eyre::Result<()> {
     let mut cmd =
         Command::new(std::env::current_exe().wrap_err("failed to get current executable path")?);
     cmd.arg("daemon");
     cmd.arg("--quiet");
+    if let Some(config_path) = config_path {
+        cmd.arg("--config").arg(config_path);
+    }
     cmd.spawn().wrap_err("failed to run `dora daemon`")?;
 
     println!("started dora daemon");
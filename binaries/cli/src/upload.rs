@@ -0,0 +1,169 @@
+//! Uploading of the node artifacts for `dora start --upload`.
+
+use communication_layer_request_reply::TcpRequestReplyConnection;
+use dora_core::{
+    adjust_shared_library_path,
+    config::NodeId,
+    descriptor::{
+        resolve_path, source_is_url, CoreNodeKind, Descriptor, OperatorSource, DYNAMIC_SOURCE,
+        SHELL_SOURCE,
+    },
+};
+use dora_message::{
+    cli_to_coordinator::ControlRequest,
+    common::{ArtifactData, NodeArtifact, MAX_ARTIFACT_UPLOAD_SIZE},
+    coordinator_to_cli::ControlRequestReply,
+};
+use eyre::{bail, Context, ContextCompat};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env::consts::EXE_EXTENSION,
+    path::{Path, PathBuf},
+};
+
+/// Artifacts that are sent together with the start request.
+#[derive(Debug, Default)]
+pub struct Upload {
+    pub artifacts: BTreeMap<NodeId, Vec<NodeArtifact>>,
+    /// Content of the artifacts that the target daemons didn't cache yet.
+    pub artifact_data: Vec<ArtifactData>,
+}
+
+/// Reads the artifacts of the dataflow and leaves out the content of the artifacts
+/// that the target daemons already cached.
+pub fn prepare(
+    dataflow: &Descriptor,
+    working_dir: &Path,
+    session: &mut TcpRequestReplyConnection,
+) -> eyre::Result<Upload> {
+    let mut artifacts = BTreeMap::new();
+    let mut data = BTreeMap::new();
+    for node in dataflow.resolve_aliases_and_set_defaults()? {
+        let mut node_artifacts = Vec::new();
+        let mut add = |operator, path: PathBuf| -> eyre::Result<()> {
+            let content = std::fs::read(&path)
+                .wrap_err_with(|| format!("failed to read artifact `{}`", path.display()))?;
+            let artifact = ArtifactData::new(content);
+            node_artifacts.push(NodeArtifact {
+                operator,
+                file_name: path
+                    .file_name()
+                    .and_then(|n| n.to_str())
+                    .context("artifact file name is not valid UTF8")?
+                    .to_owned(),
+                hash: artifact.hash.clone(),
+            });
+            data.insert(artifact.hash.clone(), artifact);
+            Ok(())
+        };
+        match &node.kind {
+            CoreNodeKind::Custom(custom) => {
+                if let Some(path) = local_source(&custom.source, working_dir)? {
+                    add(None, path)?;
+                }
+            }
+            CoreNodeKind::Runtime(runtime) => {
+                for operator in &runtime.operators {
+                    let path = match &operator.config.source {
+                        OperatorSource::SharedLibrary(source) if is_local_path(source) => {
+                            Some(working_dir.join(adjust_shared_library_path(Path::new(source))?))
+                        }
+                        OperatorSource::Python(python) if is_local_path(&python.source) => {
+                            Some(working_dir.join(&python.source))
+                        }
+                        OperatorSource::Wasm(source) if is_local_path(source) => {
+                            Some(working_dir.join(source))
+                        }
+                        _ => None,
+                    };
+                    if let Some(path) = path {
+                        add(Some(operator.id.clone()), path).wrap_err_with(|| {
+                            format!("failed to upload operator `{}/{}`", node.id, operator.id)
+                        })?;
+                    }
+                }
+            }
+        }
+        if !node_artifacts.is_empty() {
+            artifacts.insert(node.id, node_artifacts);
+        }
+    }
+
+    let missing = missing_artifacts(session, dataflow, &artifacts)?;
+    let total = data.len();
+    let artifact_data: Vec<_> = data
+        .into_values()
+        .filter(|d| missing.contains(&d.hash))
+        .collect();
+    let size: usize = artifact_data.iter().map(|d| d.data.len()).sum();
+    if size > MAX_ARTIFACT_UPLOAD_SIZE {
+        bail!(
+            "artifacts are too large to upload ({:.1} MB, limit is {:.1} MB)",
+            size as f64 / 1_000_000.0,
+            MAX_ARTIFACT_UPLOAD_SIZE as f64 / 1_000_000.0
+        );
+    }
+    println!(
+        "uploading {} artifacts ({:.1} MB), {} are cached already",
+        artifact_data.len(),
+        size as f64 / 1_000_000.0,
+        total - artifact_data.len()
+    );
+
+    Ok(Upload {
+        artifacts,
+        artifact_data,
+    })
+}
+
+/// Resolves the source of a custom node to a file in the working directory.
+///
+/// Returns `None` for sources that don't refer to a local file, e.g. executables in
+/// `$PATH`.
+fn local_source(source: &str, working_dir: &Path) -> eyre::Result<Option<PathBuf>> {
+    if matches!(source, SHELL_SOURCE | DYNAMIC_SOURCE) || !is_local_path(source) {
+        return Ok(None);
+    }
+    let path = Path::new(source);
+    let path = if path.extension().is_none() {
+        working_dir.join(path.with_extension(EXE_EXTENSION))
+    } else {
+        working_dir.join(path)
+    };
+    if path.is_file() {
+        Ok(Some(path))
+    } else {
+        resolve_path(source, working_dir).wrap_err_with(|| {
+            format!(
+                "could not find `{source}` (use `dora build` to build the nodes before uploading)"
+            )
+        })?;
+        Ok(None)
+    }
+}
+
+/// Only relative paths are uploaded, absolute paths refer to files on the target machine.
+fn is_local_path(source: &str) -> bool {
+    !source_is_url(source) && Path::new(source).is_relative()
+}
+
+fn missing_artifacts(
+    session: &mut TcpRequestReplyConnection,
+    dataflow: &Descriptor,
+    artifacts: &BTreeMap<NodeId, Vec<NodeArtifact>>,
+) -> eyre::Result<BTreeSet<String>> {
+    let request = ControlRequest::MissingArtifacts {
+        dataflow: dataflow.clone(),
+        artifacts: artifacts.clone(),
+    };
+    let reply_raw = session
+        .request(&serde_json::to_vec(&request).unwrap())
+        .wrap_err("failed to send missing artifacts request")?;
+    let reply: ControlRequestReply =
+        serde_json::from_slice(&reply_raw).wrap_err("failed to parse reply")?;
+    match reply {
+        ControlRequestReply::MissingArtifacts(missing) => Ok(missing),
+        ControlRequestReply::Error(err) => bail!("{err}"),
+        other => bail!("unexpected missing artifacts reply: {other:?}"),
+    }
+}
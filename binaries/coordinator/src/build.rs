@@ -0,0 +1,339 @@
+//! Distributed `dora build`: runs the build commands of the nodes on the daemons of
+//! the machines that run them.
+
+use crate::{
+    log_subscriber::LogSubscriber, run::machine_working_dir, tcp_utils::tcp_send, DaemonConnection,
+};
+use dora_core::{
+    config::NodeId,
+    descriptor::{CoreNodeKind, Descriptor, ResolvedNode},
+    uhlc::HLC,
+};
+use dora_message::{
+    coordinator_to_cli::{ControlRequestReply, LogMessage, NodeBuildResult},
+    coordinator_to_daemon::{DaemonCoordinatorEvent, NodeBuild, Timestamped},
+};
+use dora_security::MaybeTlsStream;
+use eyre::{bail, WrapErr};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+use tokio::sync::oneshot;
+use uuid::{NoContext, Timestamp, Uuid};
+
+/// Maximum number of log messages that are kept for subscribers that connect late.
+const MAX_LOG_MESSAGES: usize = 10_000;
+
+/// Finished builds are discarded if no `WaitForBuild` request arrives within this time.
+const RESULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+pub(super) struct RunningBuild {
+    /// Nodes that are built on each machine, removed when the machine reports its
+    /// results.
+    pending: BTreeMap<String, BTreeSet<NodeId>>,
+    results: Vec<NodeBuildResult>,
+    /// The latest log messages of the build, replayed to subscribers that connect late.
+    log_messages: VecDeque<LogMessage>,
+    log_subscribers: Vec<LogSubscriber>,
+    reply_sender: Option<oneshot::Sender<eyre::Result<ControlRequestReply>>>,
+    finished_at: Option<Instant>,
+}
+
+/// Sends the build commands of the dataflow nodes to the daemons of their machines.
+///
+/// Nodes with a deploy `selector` are built on all matching machines, since they
+/// might be placed on any of them.
+pub(super) async fn start_build(
+    dataflow: &Descriptor,
+    local_working_dir: &Path,
+    daemon_connections: &mut HashMap<String, DaemonConnection>,
+    clock: &HLC,
+) -> eyre::Result<(Uuid, RunningBuild)> {
+    let build_id = Uuid::new_v7(Timestamp::now(NoContext));
+    let nodes = dataflow.resolve_aliases_and_set_defaults()?;
+
+    let mut machine_builds: BTreeMap<String, BTreeMap<NodeId, NodeBuild>> = BTreeMap::new();
+    for node in &nodes {
+        let commands = build_commands(node);
+        if commands.is_empty() {
+            continue;
+        }
+        for machine in build_machines(node, daemon_connections)? {
+            // nodes of included subflows are built in the directory of the subflow
+            let working_dir =
+                build_working_dir(dataflow, &machine, daemon_connections, local_working_dir)
+                    .wrap_err_with(|| format!("cannot build node `{}`", node.id))?
+                    .join(node.build_dir.clone().unwrap_or_default());
+            machine_builds.entry(machine).or_default().insert(
+                node.id.clone(),
+                NodeBuild {
+                    working_dir,
+                    commands: commands.clone(),
+                },
+            );
+        }
+    }
+
+    if let Some(machine) = machine_builds
+        .keys()
+        .find(|m| !daemon_connections.contains_key(*m))
+    {
+        bail!("no daemon is connected for machine `{machine}`");
+    }
+    let mut messages = Vec::new();
+    for (machine, builds) in machine_builds {
+        let nodes: BTreeSet<_> = builds.keys().cloned().collect();
+        let message = serde_json::to_vec(&Timestamped {
+            inner: DaemonCoordinatorEvent::Build {
+                build_id,
+                nodes: builds,
+            },
+            timestamp: clock.new_timestamp(),
+        })?;
+        messages.push((machine, nodes, message));
+    }
+
+    // Once the first build message is sent, the build is running and has to be
+    // registered, so send failures are reported as failed node builds instead.
+    let mut pending = BTreeMap::new();
+    let mut results = Vec::new();
+    for (machine, nodes, message) in messages {
+        let sent = match daemon_connections.get_mut(&machine) {
+            Some(connection) => connection
+                .stream
+                .send(&message)
+                .await
+                .map_err(|err| format!("failed to send build message: {err}")),
+            None => Err("machine is not connected".to_owned()),
+        };
+        match sent {
+            Ok(()) => {
+                pending.insert(machine, nodes);
+            }
+            Err(err) => {
+                tracing::warn!("failed to start build on machine `{machine}`: {err}");
+                results.extend(nodes.into_iter().map(|node_id| NodeBuildResult {
+                    node_id,
+                    machine_id: machine.clone(),
+                    result: Err(err.clone()),
+                }));
+            }
+        }
+    }
+    tracing::info!("started build `{build_id}` on {} machines", pending.len());
+
+    let finished_at = pending.is_empty().then(Instant::now);
+    Ok((
+        build_id,
+        RunningBuild {
+            pending,
+            results,
+            log_messages: VecDeque::new(),
+            log_subscribers: Vec::new(),
+            reply_sender: None,
+            finished_at,
+        },
+    ))
+}
+
+impl RunningBuild {
+    pub fn is_finished(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Checks whether the build finished a while ago without a `WaitForBuild` request.
+    pub fn is_expired(&self) -> bool {
+        self.reply_sender.is_none()
+            && self
+                .finished_at
+                .is_some_and(|t| t.elapsed() > RESULT_TIMEOUT)
+    }
+
+    /// Replies to the `WaitForBuild` request once the build is finished.
+    ///
+    /// Returns `true` if the reply was sent, after which the build can be removed.
+    pub fn wait(
+        &mut self,
+        build_id: Uuid,
+        reply_sender: oneshot::Sender<eyre::Result<ControlRequestReply>>,
+    ) -> bool {
+        self.reply_sender = Some(reply_sender);
+        self.reply_if_finished(build_id)
+    }
+
+    pub fn machine_finished(
+        &mut self,
+        build_id: Uuid,
+        machine_id: String,
+        results: BTreeMap<NodeId, Result<(), String>>,
+    ) -> bool {
+        if self.pending.remove(&machine_id).is_none() {
+            tracing::warn!("unexpected build results of machine `{machine_id}`");
+        }
+        self.results.extend(
+            results
+                .into_iter()
+                .map(|(node_id, result)| NodeBuildResult {
+                    node_id,
+                    machine_id: machine_id.clone(),
+                    result,
+                }),
+        );
+        self.reply_if_finished(build_id)
+    }
+
+    /// Fails the nodes that were built on the given machine.
+    pub fn machine_lost(&mut self, build_id: Uuid, machine_id: &str) -> bool {
+        let Some(nodes) = self.pending.remove(machine_id) else {
+            return false;
+        };
+        self.results
+            .extend(nodes.into_iter().map(|node_id| NodeBuildResult {
+                node_id,
+                machine_id: machine_id.to_owned(),
+                result: Err("connection to machine was lost".into()),
+            }));
+        self.reply_if_finished(build_id)
+    }
+
+    async fn subscribe(&mut self, mut subscriber: LogSubscriber) {
+        for message in &self.log_messages {
+            if send_log_message(&mut subscriber, message).await.is_err() {
+                return;
+            }
+        }
+        self.log_subscribers.push(subscriber);
+    }
+
+    pub async fn log(&mut self, message: LogMessage) {
+        for subscriber in &mut self.log_subscribers {
+            if send_log_message(subscriber, &message).await.is_err() {
+                subscriber.close();
+            }
+        }
+        self.log_subscribers.retain(|s| !s.is_closed());
+        if self.log_messages.len() >= MAX_LOG_MESSAGES {
+            self.log_messages.pop_front();
+        }
+        self.log_messages.push_back(message);
+    }
+
+    fn reply_if_finished(&mut self, build_id: Uuid) -> bool {
+        if !self.is_finished() {
+            return false;
+        }
+        self.finished_at.get_or_insert_with(Instant::now);
+        let Some(reply_sender) = self.reply_sender.take() else {
+            return false;
+        };
+        let results = std::mem::take(&mut self.results);
+        let _ = reply_sender.send(Ok(ControlRequestReply::BuildFinished { build_id, results }));
+        true
+    }
+}
+
+/// Confirms a `BuildLogSubscribe` request and adds the subscriber to the build.
+///
+/// The confirmation lets the CLI wait for the subscription before it sends the
+/// `WaitForBuild` request, after which the build might be removed.
+pub(super) async fn subscribe(
+    build: Option<&mut RunningBuild>,
+    build_id: Uuid,
+    level: log::LevelFilter,
+    mut connection: MaybeTlsStream,
+) {
+    let reply = match &build {
+        Some(_) => ControlRequestReply::BuildLogSubscribed,
+        None => ControlRequestReply::Error(format!("no running build with ID `{build_id}`")),
+    };
+    let confirmed = match serde_json::to_vec(&reply) {
+        Ok(reply) => tokio::time::timeout(
+            Duration::from_millis(100),
+            tcp_send(&mut connection, &reply),
+        )
+        .await
+        .is_ok_and(|r| r.is_ok()),
+        Err(_) => false,
+    };
+    if let (Some(build), true) = (build, confirmed) {
+        build.subscribe(LogSubscriber::new(level, connection)).await;
+    }
+}
+
+async fn send_log_message(subscriber: &mut LogSubscriber, message: &LogMessage) -> Result<(), ()> {
+    match tokio::time::timeout(Duration::from_millis(100), subscriber.send_message(message)).await {
+        Ok(Ok(())) => Ok(()),
+        _ => Err(()),
+    }
+}
+
+/// Build commands of a custom node or of all operators of a runtime node.
+fn build_commands(node: &ResolvedNode) -> Vec<String> {
+    match &node.kind {
+        CoreNodeKind::Custom(custom) => custom.build.iter().cloned().collect(),
+        CoreNodeKind::Runtime(runtime) => runtime
+            .operators
+            .iter()
+            .filter_map(|operator| operator.config.build.clone())
+            .collect(),
+    }
+}
+
+/// Resolves the directory in which the nodes are built on the given machine.
+///
+/// The working directory of the CLI doesn't exist on remote machines, so they need
+/// their own working directory.
+fn build_working_dir(
+    dataflow: &Descriptor,
+    machine: &str,
+    daemon_connections: &HashMap<String, DaemonConnection>,
+    local_working_dir: &Path,
+) -> eyre::Result<PathBuf> {
+    if let Some(connection) = daemon_connections.get(machine) {
+        if !connection.listen_socket.ip().is_loopback()
+            && connection.properties.working_dir.is_none()
+            && !dataflow.working_dirs.contains_key(machine)
+        {
+            bail!(
+                "remote machine `{machine}` has no working dir (add it to `working_dirs` \
+                or start its daemon with `--working-dir`)"
+            );
+        }
+    }
+    Ok(machine_working_dir(
+        dataflow,
+        machine,
+        daemon_connections,
+        local_working_dir,
+    ))
+}
+
+fn build_machines(
+    node: &ResolvedNode,
+    daemon_connections: &HashMap<String, DaemonConnection>,
+) -> eyre::Result<Vec<String>> {
+    if node.deploy.machine.is_empty() && !node.deploy.selector.is_empty() {
+        let matching: Vec<_> = daemon_connections
+            .iter()
+            .filter(|(_, c)| c.properties.matches(&node.deploy.selector))
+            .map(|(id, _)| id.clone())
+            .collect();
+        if matching.is_empty() {
+            bail!(
+                "no connected machine matches the selector of node `{}`",
+                node.id
+            );
+        }
+        Ok(matching)
+    } else if daemon_connections.contains_key(&node.deploy.machine) {
+        Ok(vec![node.deploy.machine.clone()])
+    } else {
+        bail!(
+            "no daemon is connected for machine `{}` of node `{}`",
+            node.deploy.machine,
+            node.id
+        )
+    }
+}
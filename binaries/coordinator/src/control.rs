@@ -1,8 +1,12 @@
 use crate::{
-    tcp_utils::{tcp_receive, tcp_send},
+    tcp_utils::{tcp_receive_bounded, tcp_send},
     Event,
 };
-use dora_message::{cli_to_coordinator::ControlRequest, coordinator_to_cli::ControlRequestReply};
+use dora_message::{
+    cli_to_coordinator::ControlRequest, common::MAX_ARTIFACT_UPLOAD_SIZE,
+    coordinator_to_cli::ControlRequestReply,
+};
+use dora_security::{MaybeTlsStream, Security, MAX_PRE_AUTH_MESSAGE_SIZE};
 use eyre::{eyre, Context};
 use futures::{
     future::{self, Either},
@@ -17,16 +21,26 @@ use tokio::{
     task::JoinHandle,
 };
 use tokio_stream::wrappers::ReceiverStream;
+
+/// Upper bound for control messages, which leaves room for the base64-encoded artifacts
+/// of `dora start --upload` and the rest of the request.
+const MAX_CONTROL_MESSAGE_SIZE: usize = MAX_ARTIFACT_UPLOAD_SIZE / 3 * 4 + 16 * 1024 * 1024;
 use uuid::Uuid;
 
 pub(crate) async fn control_events(
     control_listen_addr: SocketAddr,
+    security: Security,
     tasks: &FuturesUnordered<JoinHandle<()>>,
 ) -> eyre::Result<impl Stream<Item = Event>> {
     let (tx, rx) = mpsc::channel(10);
 
     let (finish_tx, mut finish_rx) = mpsc::channel(1);
-    tasks.push(tokio::spawn(listen(control_listen_addr, tx, finish_tx)));
+    tasks.push(tokio::spawn(listen(
+        control_listen_addr,
+        security,
+        tx,
+        finish_tx,
+    )));
     tasks.push(tokio::spawn(async move {
         while let Some(()) = finish_rx.recv().await {}
     }));
@@ -36,6 +50,7 @@ pub(crate) async fn control_events(
 
 async fn listen(
     control_listen_addr: SocketAddr,
+    security: Security,
     tx: mpsc::Sender<ControlEvent>,
     _finish_tx: mpsc::Sender<()>,
 ) {
@@ -63,7 +78,12 @@ async fn listen(
         match connection.wrap_err("failed to connect") {
             Ok((connection, _)) => {
                 let tx = tx.clone();
-                tokio::spawn(handle_requests(connection, tx, _finish_tx.clone()));
+                tokio::spawn(handle_requests(
+                    connection,
+                    security.clone(),
+                    tx,
+                    _finish_tx.clone(),
+                ));
             }
             Err(err) => {
                 if tx.blocking_send(err.into()).is_err() {
@@ -75,12 +95,28 @@ async fn listen(
 }
 
 async fn handle_requests(
-    mut connection: TcpStream,
+    connection: TcpStream,
+    security: Security,
     tx: mpsc::Sender<ControlEvent>,
     _finish_tx: mpsc::Sender<()>,
 ) {
+    let mut connection = match security.accept(connection).await {
+        Ok(connection) => connection,
+        Err(err) => {
+            tracing::warn!("TLS handshake with control connection failed: {err}");
+            return;
+        }
+    };
+    let mut authenticated = security.verify_token(None);
     loop {
-        let next_request = tcp_receive(&mut connection).map(Either::Left);
+        let next_request = async {
+            if authenticated {
+                tcp_receive_bounded(&mut connection, MAX_CONTROL_MESSAGE_SIZE).await
+            } else {
+                tcp_receive_bounded(&mut connection, MAX_PRE_AUTH_MESSAGE_SIZE).await
+            }
+        }
+        .map(Either::Left);
         let coordinator_stopped = tx.closed().map(Either::Right);
         let raw = match (next_request, coordinator_stopped).race().await {
             Either::Right(()) => break,
@@ -103,23 +139,43 @@ async fn handle_requests(
         let request =
             serde_json::from_slice(&raw).wrap_err("failed to deserialize incoming message");
 
-        if let Ok(ControlRequest::LogSubscribe { dataflow_id, level }) = request {
-            let _ = tx
-                .send(ControlEvent::LogSubscribe {
-                    dataflow_id,
-                    level,
-                    connection,
-                })
-                .await;
-            break;
-        }
-
-        let result = match request {
-            Ok(request) => handle_request(request, &tx).await,
-            Err(err) => Err(err),
+        let reply = match request {
+            Ok(ControlRequest::Authenticate { token }) => {
+                authenticated = security.verify_token(Some(&token));
+                if authenticated {
+                    ControlRequestReply::Authenticated
+                } else {
+                    ControlRequestReply::Error("invalid authentication token".into())
+                }
+            }
+            Ok(_) if !authenticated => ControlRequestReply::Error(
+                "authentication required: the coordinator is configured with a token".into(),
+            ),
+            Ok(ControlRequest::LogSubscribe { dataflow_id, level }) => {
+                let _ = tx
+                    .send(ControlEvent::LogSubscribe {
+                        dataflow_id,
+                        level,
+                        connection,
+                    })
+                    .await;
+                break;
+            }
+            Ok(ControlRequest::BuildLogSubscribe { build_id, level }) => {
+                let _ = tx
+                    .send(ControlEvent::BuildLogSubscribe {
+                        build_id,
+                        level,
+                        connection,
+                    })
+                    .await;
+                break;
+            }
+            Ok(request) => handle_request(request, &tx)
+                .await
+                .unwrap_or_else(|err| ControlRequestReply::Error(format!("{err}"))),
+            Err(err) => ControlRequestReply::Error(format!("{err}")),
         };
-
-        let reply = result.unwrap_or_else(|err| ControlRequestReply::Error(format!("{err}")));
         let serialized: Vec<u8> =
             match serde_json::to_vec(&reply).wrap_err("failed to serialize ControlRequestReply") {
                 Ok(s) => s,
@@ -143,7 +199,8 @@ async fn handle_requests(
             },
         }
 
-        if matches!(reply, ControlRequestReply::CoordinatorStopped) {
+        // unauthenticated connections are closed after sending the error reply
+        if matches!(reply, ControlRequestReply::CoordinatorStopped) || !authenticated {
             break;
         }
     }
@@ -177,7 +234,12 @@ pub enum ControlEvent {
     LogSubscribe {
         dataflow_id: Uuid,
         level: log::LevelFilter,
-        connection: TcpStream,
+        connection: MaybeTlsStream,
+    },
+    BuildLogSubscribe {
+        build_id: Uuid,
+        level: log::LevelFilter,
+        connection: MaybeTlsStream,
     },
     Error(eyre::Report),
 }
@@ -1,27 +1,32 @@
 use crate::{
+    build::RunningBuild,
     run::spawn_dataflow,
-    tcp_utils::{tcp_receive, tcp_send},
+    tcp_utils::{tcp_send, DaemonStream},
 };
 pub use control::ControlEvent;
 use dora_core::{
-    config::{NodeId, OperatorId},
-    descriptor::{Descriptor, ResolvedNode},
+    config::{InputMapping, NodeId, OperatorId},
+    descriptor::{Descriptor, MachineLostPolicy, ResolvedNode},
     uhlc::{self, HLC},
 };
 use dora_message::{
     cli_to_coordinator::ControlRequest,
+    common::{ArtifactData, NodeArtifact, NodeError, NodeErrorCause, NodeExitStatus},
     coordinator_to_cli::{
         ControlRequestReply, DataflowIdAndName, DataflowList, DataflowListEntry, DataflowResult,
-        DataflowStatus, LogMessage,
+        DataflowStats, DataflowStatus, LogMessage, OutputRate,
     },
     coordinator_to_daemon::{DaemonCoordinatorEvent, RegisterResult, Timestamped},
-    daemon_to_coordinator::{DaemonCoordinatorReply, DataflowDaemonResult},
+    daemon_to_coordinator::{DaemonCoordinatorReply, DataflowDaemonResult, MachineProperties},
+    daemon_to_daemon::RelayedEvent,
 };
+use dora_security::{MaybeTlsStream, Security};
 use eyre::{bail, eyre, ContextCompat, WrapErr};
 use futures::{stream::FuturesUnordered, Future, Stream, StreamExt};
 use futures_concurrency::stream::Merge;
 use log_subscriber::LogSubscriber;
 use run::SpawnedDataflow;
+use state::{PersistedArchivedDataflow, PersistedRunningDataflow, StateStore};
 use std::{
     collections::{BTreeMap, BTreeSet, HashMap},
     net::SocketAddr,
@@ -33,17 +38,30 @@ use tokio::{net::TcpStream, sync::mpsc, task::JoinHandle};
 use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
 use uuid::Uuid;
 
+mod build;
 mod control;
 mod listener;
 mod log_subscriber;
 mod run;
+mod state;
 mod tcp_utils;
 
+/// Starts the coordinator.
+///
+/// If a `state_dir` is given, the coordinator persists its dataflows there and reloads
+/// them on the next start. The given `security` settings apply to both the daemon and
+/// the control connections.
 pub async fn start(
     bind: SocketAddr,
     bind_control: SocketAddr,
+    state_dir: Option<PathBuf>,
+    security: Security,
     external_events: impl Stream<Item = Event> + Unpin,
 ) -> Result<(u16, impl Future<Output = eyre::Result<()>>), eyre::ErrReport> {
+    let state_store = state_dir
+        .map(StateStore::open)
+        .transpose()
+        .wrap_err("failed to open coordinator state directory")?;
     let listener = listener::create_listener(bind).await?;
     let port = listener
         .local_addr()
@@ -56,7 +74,7 @@ pub async fn start(
     });
 
     let mut tasks = FuturesUnordered::new();
-    let control_events = control::control_events(bind_control, &tasks)
+    let control_events = control::control_events(bind_control, security.clone(), &tasks)
         .await
         .wrap_err("failed to create control events")?;
 
@@ -72,7 +90,7 @@ pub async fn start(
         .merge();
 
     let future = async move {
-        start_inner(events, &tasks).await?;
+        start_inner(events, &tasks, state_store, security).await?;
 
         tracing::debug!("coordinator main loop finished, waiting on spawned tasks");
         while let Some(join_result) = tasks.next().await {
@@ -124,6 +142,8 @@ fn resolve_name(
 async fn start_inner(
     events: impl Stream<Item = Event> + Unpin,
     tasks: &FuturesUnordered<JoinHandle<()>>,
+    state_store: Option<StateStore>,
+    security: Security,
 ) -> eyre::Result<()> {
     let clock = Arc::new(HLC::default());
 
@@ -146,6 +166,31 @@ async fn start_inner(
         HashMap::new();
     let mut archived_dataflows: HashMap<Uuid, ArchivedDataflow> = HashMap::new();
     let mut daemon_connections: HashMap<_, DaemonConnection> = HashMap::new();
+    let mut running_builds: HashMap<Uuid, RunningBuild> = HashMap::new();
+    // dataflows that were running before a coordinator restart, waiting to be
+    // re-announced by their daemons
+    let mut recovered_dataflows: HashMap<Uuid, PersistedRunningDataflow> = HashMap::new();
+
+    if let Some(store) = &state_store {
+        let state = store.load().wrap_err("failed to load coordinator state")?;
+        tracing::info!(
+            "loaded {} archived and {} running dataflows from `{}`",
+            state.archived.len(),
+            state.running.len(),
+            store.dir().display()
+        );
+        for dataflow in state.archived {
+            archived_dataflows.insert(
+                dataflow.uuid,
+                ArchivedDataflow {
+                    name: dataflow.name,
+                    nodes: dataflow.nodes,
+                },
+            );
+            dataflow_results.insert(dataflow.uuid, dataflow.results);
+        }
+        recovered_dataflows.extend(state.running.into_iter().map(|d| (d.uuid, d)));
+    }
 
     while let Some(event) = events.next().await {
         if event.log() {
@@ -160,6 +205,7 @@ async fn start_inner(
                         connection,
                         events_tx,
                         clock.clone(),
+                        security.clone(),
                     ));
                     tasks.push(task);
                 } else {
@@ -177,8 +223,11 @@ async fn start_inner(
                     mut connection,
                     version_check_result,
                     listen_port,
+                    running_dataflows: announced_dataflows,
+                    properties,
                 } => {
                     let peer_ip = connection
+                        .tcp()
                         .peer_addr()
                         .map(|addr| addr.ip())
                         .map_err(|err| format!("failed to get peer addr of connection: {err}"));
@@ -197,9 +246,10 @@ async fn start_inner(
                             let previous = daemon_connections.insert(
                                 machine_id.clone(),
                                 DaemonConnection {
-                                    stream: connection,
+                                    stream: DaemonStream::new(connection),
                                     listen_socket: (ip, listen_port).into(),
                                     last_heartbeat: Instant::now(),
+                                    properties,
                                 },
                             );
                             if let Some(_previous) = previous {
@@ -207,6 +257,31 @@ async fn start_inner(
                                     "closing previous connection `{machine_id}` on new register"
                                 );
                             }
+                            let stale_dataflows = recover_dataflows(
+                                &machine_id,
+                                announced_dataflows,
+                                &mut recovered_dataflows,
+                                &mut running_dataflows,
+                                &archived_dataflows,
+                                state_store.as_ref(),
+                            )
+                            .await;
+                            for uuid in stale_dataflows {
+                                tracing::info!(
+                                    "stopping stale dataflow `{uuid}` on machine `{machine_id}`"
+                                );
+                                let result = stop_dataflow_on_machine(
+                                    uuid,
+                                    &machine_id,
+                                    &mut daemon_connections,
+                                    clock.new_timestamp(),
+                                    None,
+                                )
+                                .await;
+                                if let Err(err) = result {
+                                    tracing::warn!("{err:?}");
+                                }
+                            }
                         }
                         (Err(err), _) => {
                             tracing::warn!("failed to register daemon connection for machine `{machine_id}`: {err}");
@@ -221,82 +296,55 @@ async fn start_inner(
                 DataflowEvent::ReadyOnMachine {
                     machine_id,
                     exited_before_subscribe,
-                } => {
-                    match running_dataflows.entry(uuid) {
-                        std::collections::hash_map::Entry::Occupied(mut entry) => {
-                            let dataflow = entry.get_mut();
-                            dataflow.pending_machines.remove(&machine_id);
-                            dataflow
-                                .exited_before_subscribe
-                                .extend(exited_before_subscribe);
-                            if dataflow.pending_machines.is_empty() {
-                                let message = serde_json::to_vec(&Timestamped {
-                                    inner: DaemonCoordinatorEvent::AllNodesReady {
-                                        dataflow_id: uuid,
-                                        exited_before_subscribe: dataflow
-                                            .exited_before_subscribe
-                                            .clone(),
-                                    },
-                                    timestamp: clock.new_timestamp(),
-                                })
-                                .wrap_err("failed to serialize AllNodesReady message")?;
-
-                                // notify all machines that run parts of the dataflow
-                                for machine_id in &dataflow.machines {
-                                    let Some(connection) = daemon_connections.get_mut(machine_id)
-                                    else {
-                                        tracing::warn!(
-                                            "no daemon connection found for machine `{machine_id}`"
-                                        );
-                                        continue;
-                                    };
-                                    tcp_send(&mut connection.stream, &message)
-                                        .await
-                                        .wrap_err_with(|| {
-                                            format!(
-                                                "failed to send AllNodesReady({uuid}) message \
-                                            to machine {machine_id}"
-                                            )
-                                        })?;
-                                }
-                            }
-                        }
-                        std::collections::hash_map::Entry::Vacant(_) => {
-                            tracing::warn!("dataflow not running on ReadyOnMachine");
-                        }
+                } => match running_dataflows.entry(uuid) {
+                    std::collections::hash_map::Entry::Occupied(mut entry) => {
+                        let dataflow = entry.get_mut();
+                        dataflow.pending_machines.remove(&machine_id);
+                        dataflow
+                            .exited_before_subscribe
+                            .extend(exited_before_subscribe);
+                        notify_if_all_nodes_ready(dataflow, &mut daemon_connections, &clock)
+                            .await?;
+                    }
+                    std::collections::hash_map::Entry::Vacant(_) => {
+                        tracing::warn!("dataflow not running on ReadyOnMachine");
+                    }
+                },
+                DataflowEvent::StopRequested { machine_id } => {
+                    let Some(dataflow) = running_dataflows.get(&uuid) else {
+                        tracing::warn!("dataflow not running on StopRequested");
+                        continue;
+                    };
+                    tracing::info!(
+                        "stopping dataflow `{uuid}` as requested by machine `{machine_id}`"
+                    );
+                    if let Err(err) = stop_dataflow(
+                        dataflow,
+                        uuid,
+                        &mut daemon_connections,
+                        clock.new_timestamp(),
+                        None,
+                    )
+                    .await
+                    {
+                        tracing::warn!("{err:?}");
                     }
                 }
                 DataflowEvent::DataflowFinishedOnMachine { machine_id, result } => {
-                    match running_dataflows.entry(uuid) {
-                        std::collections::hash_map::Entry::Occupied(mut entry) => {
-                            // Archive finished dataflow
-                            archived_dataflows
-                                .entry(uuid)
-                                .or_insert_with(|| ArchivedDataflow::from(entry.get()));
-                            entry.get_mut().machines.remove(&machine_id);
-                            dataflow_results
-                                .entry(uuid)
-                                .or_default()
-                                .insert(machine_id, result);
-                            if entry.get_mut().machines.is_empty() {
-                                let finished_dataflow = entry.remove();
-                                let reply = ControlRequestReply::DataflowStopped {
-                                    uuid,
-                                    result: dataflow_results
-                                        .get(&uuid)
-                                        .map(|r| dataflow_result(r, uuid, &clock))
-                                        .unwrap_or_else(|| {
-                                            DataflowResult::ok_empty(uuid, clock.new_timestamp())
-                                        }),
-                                };
-                                for sender in finished_dataflow.reply_senders {
-                                    let _ = sender.send(Ok(reply.clone()));
-                                }
-                            }
-                        }
-                        std::collections::hash_map::Entry::Vacant(_) => {
-                            tracing::warn!("dataflow not running on DataflowFinishedOnMachine");
-                        }
+                    if running_dataflows.contains_key(&uuid) {
+                        dataflow_finished_on_machine(
+                            uuid,
+                            machine_id,
+                            result,
+                            &mut running_dataflows,
+                            &mut archived_dataflows,
+                            &mut dataflow_results,
+                            state_store.as_ref(),
+                            &clock,
+                        )
+                        .await;
+                    } else {
+                        tracing::warn!("dataflow not running on DataflowFinishedOnMachine");
                     }
                 }
             },
@@ -311,6 +359,8 @@ async fn start_inner(
                             dataflow,
                             name,
                             local_working_dir,
+                            artifacts,
+                            artifact_data,
                         } => {
                             let name = name.or_else(|| names::Generator::default().next());
 
@@ -328,17 +378,24 @@ async fn start_inner(
                                     dataflow,
                                     local_working_dir,
                                     name,
+                                    artifacts,
+                                    artifact_data,
                                     &mut daemon_connections,
+                                    machine_load(running_dataflows.values()),
                                     &clock,
                                 )
                                 .await?;
                                 Ok(dataflow)
                             };
-                            let reply = inner.await.map(|dataflow| {
-                                let uuid = dataflow.uuid;
-                                running_dataflows.insert(uuid, dataflow);
-                                ControlRequestReply::DataflowStarted { uuid }
-                            });
+                            let reply = match inner.await {
+                                Ok(dataflow) => {
+                                    let uuid = dataflow.uuid;
+                                    persist_running(state_store.as_ref(), &dataflow).await;
+                                    running_dataflows.insert(uuid, dataflow);
+                                    Ok(ControlRequestReply::DataflowStarted { uuid })
+                                }
+                                Err(err) => Err(err),
+                            };
                             let _ = reply_sender.send(reply);
                         }
                         ControlRequest::Check { dataflow_uuid } => {
@@ -386,6 +443,45 @@ async fn start_inner(
                                     });
                             let _ = reply_sender.send(reply);
                         }
+                        ControlRequest::Apply {
+                            dataflow_uuid,
+                            dataflow,
+                            local_working_dir,
+                            grace_duration,
+                        } => {
+                            let load = machine_load(
+                                running_dataflows
+                                    .values()
+                                    .filter(|d| d.uuid != dataflow_uuid),
+                            );
+                            let reply = match running_dataflows.get_mut(&dataflow_uuid) {
+                                Some(running) => {
+                                    let result = run::apply_dataflow(
+                                        dataflow_uuid,
+                                        &mut running.nodes,
+                                        &running.machines,
+                                        dataflow,
+                                        local_working_dir,
+                                        grace_duration,
+                                        &mut daemon_connections,
+                                        load,
+                                        &clock,
+                                    )
+                                    .await;
+                                    persist_running(state_store.as_ref(), running).await;
+                                    result.map(|changes| {
+                                        ControlRequestReply::DataflowChangesApplied {
+                                            uuid: dataflow_uuid,
+                                            changes,
+                                        }
+                                    })
+                                }
+                                None => Err(eyre!(
+                                    "No running dataflow found with UUID `{dataflow_uuid}`"
+                                )),
+                            };
+                            let _ = reply_sender.send(reply);
+                        }
                         ControlRequest::Stop {
                             dataflow_uuid,
                             grace_duration,
@@ -423,6 +519,28 @@ async fn start_inner(
                                 let _ = reply_sender.send(Err(err));
                             }
                         },
+                        ControlRequest::RestartNode {
+                            dataflow,
+                            node_id,
+                            grace_duration,
+                        } => {
+                            let reply = restart_node(
+                                &running_dataflows,
+                                dataflow,
+                                node_id.clone(),
+                                grace_duration,
+                                &mut daemon_connections,
+                                clock.new_timestamp(),
+                            )
+                            .await
+                            .map(|()| {
+                                ControlRequestReply::NodeRestarted {
+                                    uuid: dataflow,
+                                    node_id,
+                                }
+                            });
+                            let _ = reply_sender.send(reply);
+                        }
                         ControlRequest::Logs { uuid, name, node } => {
                             let dataflow_uuid = if let Some(uuid) = uuid {
                                 uuid
@@ -444,11 +562,48 @@ async fn start_inner(
                             .map(ControlRequestReply::Logs);
                             let _ = reply_sender.send(reply);
                         }
+                        ControlRequest::Stats { dataflow_uuid } => {
+                            let reply = retrieve_stats(
+                                &running_dataflows,
+                                dataflow_uuid,
+                                &mut daemon_connections,
+                                clock.new_timestamp(),
+                            )
+                            .await
+                            .map(ControlRequestReply::Stats);
+                            let _ = reply_sender.send(reply);
+                        }
+                        ControlRequest::MissingArtifacts {
+                            dataflow,
+                            artifacts,
+                        } => {
+                            let reply = missing_artifacts(
+                                dataflow,
+                                artifacts,
+                                &mut daemon_connections,
+                                clock.new_timestamp(),
+                            )
+                            .await
+                            .map(ControlRequestReply::MissingArtifacts);
+                            let _ = reply_sender.send(reply);
+                        }
+                        ControlRequest::OutputRates { dataflow_uuid } => {
+                            let reply = retrieve_output_rates(
+                                &running_dataflows,
+                                dataflow_uuid,
+                                &mut daemon_connections,
+                                clock.new_timestamp(),
+                            )
+                            .await
+                            .map(ControlRequestReply::OutputRates);
+                            let _ = reply_sender.send(reply);
+                        }
                         ControlRequest::Destroy => {
                             tracing::info!("Received destroy command");
 
                             let reply = handle_destroy(
                                 &running_dataflows,
+                                state_store.as_ref(),
                                 &mut daemon_connections,
                                 &abort_handle,
                                 &mut daemon_events_tx,
@@ -468,9 +623,14 @@ async fn start_inner(
                                     name: d.name.clone(),
                                 },
                                 status: DataflowStatus::Running,
+                                placement: placement(&d.nodes),
                             });
-                            let finished_failed =
-                                dataflow_results.iter().map(|(&uuid, results)| {
+                            // results of lost machines are recorded while the rest of
+                            // the dataflow might still be running
+                            let finished_failed = dataflow_results
+                                .iter()
+                                .filter(|(uuid, _)| !running_dataflows.contains_key(uuid))
+                                .map(|(&uuid, results)| {
                                     let name =
                                         archived_dataflows.get(&uuid).and_then(|d| d.name.clone());
                                     let id = DataflowIdAndName { uuid, name };
@@ -479,7 +639,15 @@ async fn start_inner(
                                     } else {
                                         DataflowStatus::Failed
                                     };
-                                    DataflowListEntry { id, status }
+                                    let placement = archived_dataflows
+                                        .get(&uuid)
+                                        .map(|d| placement(&d.nodes))
+                                        .unwrap_or_default();
+                                    DataflowListEntry {
+                                        id,
+                                        status,
+                                        placement,
+                                    }
                                 });
 
                             let reply = Ok(ControlRequestReply::DataflowList(DataflowList(
@@ -498,9 +666,41 @@ async fn start_inner(
                             ));
                             let _ = reply_sender.send(reply);
                         }
-                        ControlRequest::LogSubscribe { .. } => {
+                        ControlRequest::Build {
+                            dataflow,
+                            local_working_dir,
+                        } => {
+                            let result = build::start_build(
+                                &dataflow,
+                                &local_working_dir,
+                                &mut daemon_connections,
+                                &clock,
+                            )
+                            .await;
+                            let reply = result.map(|(build_id, build)| {
+                                running_builds.insert(build_id, build);
+                                ControlRequestReply::BuildStarted { build_id }
+                            });
+                            let _ = reply_sender.send(reply);
+                        }
+                        ControlRequest::WaitForBuild { build_id } => {
+                            match running_builds.get_mut(&build_id) {
+                                Some(build) => {
+                                    if build.wait(build_id, reply_sender) {
+                                        running_builds.remove(&build_id);
+                                    }
+                                }
+                                None => {
+                                    let _ = reply_sender
+                                        .send(Err(eyre!("no running build with ID `{build_id}`")));
+                                }
+                            }
+                        }
+                        ControlRequest::LogSubscribe { .. }
+                        | ControlRequest::BuildLogSubscribe { .. }
+                        | ControlRequest::Authenticate { .. } => {
                             let _ = reply_sender.send(Err(eyre::eyre!(
-                                "LogSubscribe request should be handled separately"
+                                "request should be handled by the control connection"
                             )));
                         }
                     }
@@ -517,8 +717,30 @@ async fn start_inner(
                             .push(LogSubscriber::new(level, connection));
                     }
                 }
+                ControlEvent::BuildLogSubscribe {
+                    build_id,
+                    level,
+                    connection,
+                } => {
+                    build::subscribe(
+                        running_builds.get_mut(&build_id),
+                        build_id,
+                        level,
+                        connection,
+                    )
+                    .await;
+                }
             },
             Event::DaemonHeartbeatInterval => {
+                running_builds.retain(|build_id, build| {
+                    let expired = build.is_expired();
+                    if expired {
+                        tracing::warn!(
+                            "discarding results of build `{build_id}` that nobody waited for"
+                        );
+                    }
+                    !expired
+                });
                 let mut disconnected = BTreeSet::new();
                 for (machine_id, connection) in &mut daemon_connections {
                     if connection.last_heartbeat.elapsed() > Duration::from_secs(15) {
@@ -531,25 +753,42 @@ async fn start_inner(
                         disconnected.insert(machine_id.clone());
                         continue;
                     }
-                    let result: eyre::Result<()> = tokio::time::timeout(
-                        Duration::from_millis(500),
-                        send_heartbeat_message(&mut connection.stream, clock.new_timestamp()),
-                    )
-                    .await
-                    .wrap_err("timeout")
-                    .and_then(|r| r)
-                    .wrap_err_with(|| {
-                        format!("failed to send heartbeat message to daemon at `{machine_id}`")
+                    let result = heartbeat_message(clock.new_timestamp()).and_then(|message| {
+                        match connection.stream.try_send(message) {
+                            // the daemon is busy reading other messages, so it doesn't need
+                            // a heartbeat to keep the connection alive
+                            Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => Ok(()),
+                            other => other.wrap_err("failed to queue heartbeat message"),
+                        }
                     });
                     if let Err(err) = result {
-                        tracing::warn!("{err:?}");
+                        tracing::warn!(
+                            "{:?}",
+                            err.wrap_err(format!(
+                                "failed to send heartbeat message to daemon at `{machine_id}`"
+                            ))
+                        );
                         disconnected.insert(machine_id.clone());
                     }
                 }
                 if !disconnected.is_empty() {
                     tracing::error!("Disconnecting daemons that failed watchdog: {disconnected:?}");
-                    for machine_id in disconnected {
-                        daemon_connections.remove(&machine_id);
+                    for machine_id in &disconnected {
+                        daemon_connections.remove(machine_id);
+                    }
+                    for machine_id in &disconnected {
+                        running_builds
+                            .retain(|build_id, build| !build.machine_lost(*build_id, machine_id));
+                        handle_machine_lost(
+                            machine_id,
+                            &mut running_dataflows,
+                            &mut archived_dataflows,
+                            &mut dataflow_results,
+                            &mut daemon_connections,
+                            state_store.as_ref(),
+                            &clock,
+                        )
+                        .await;
                     }
                 }
             }
@@ -557,6 +796,7 @@ async fn start_inner(
                 tracing::info!("Destroying coordinator after receiving Ctrl-C signal");
                 handle_destroy(
                     &running_dataflows,
+                    state_store.as_ref(),
                     &mut daemon_connections,
                     &abort_handle,
                     &mut daemon_events_tx,
@@ -569,6 +809,36 @@ async fn start_inner(
                     connection.last_heartbeat = Instant::now();
                 }
             }
+            Event::Relay {
+                source_machine,
+                target_machine,
+                event,
+            } => {
+                let Some(connection) = daemon_connections.get_mut(&target_machine) else {
+                    tracing::warn!(
+                        "dropping event relayed from machine `{source_machine}` because \
+                        machine `{target_machine}` is not connected"
+                    );
+                    continue;
+                };
+                // relayed events are dropped as a whole if the daemon can't keep up, so
+                // that no partially written messages end up on the connection
+                let result = relayed_event_message(source_machine, event, clock.new_timestamp())
+                    .and_then(|message| {
+                        connection
+                            .stream
+                            .try_send(message)
+                            .wrap_err("failed to queue relayed event")
+                    });
+                if let Err(err) = result {
+                    tracing::warn!(
+                        "{:?}",
+                        err.wrap_err(format!(
+                            "failed to relay event to machine `{target_machine}`"
+                        ))
+                    );
+                }
+            }
             Event::Log(message) => {
                 if let Some(dataflow) = running_dataflows.get_mut(&message.dataflow_id) {
                     for subscriber in &mut dataflow.log_subscribers {
@@ -582,6 +852,21 @@ async fn start_inner(
                         }
                     }
                     dataflow.log_subscribers.retain(|s| !s.is_closed());
+                } else if let Some(build) = running_builds.get_mut(&message.dataflow_id) {
+                    build.log(message).await;
+                }
+            }
+            Event::BuildFinished {
+                build_id,
+                machine_id,
+                results,
+            } => {
+                if let Some(build) = running_builds.get_mut(&build_id) {
+                    if build.machine_finished(build_id, machine_id, results) {
+                        running_builds.remove(&build_id);
+                    }
+                } else {
+                    tracing::warn!("received results of unknown build `{build_id}`");
                 }
             }
         }
@@ -636,14 +921,231 @@ async fn stop_dataflow_by_uuid(
     Ok(())
 }
 
+/// Records the result of the given machine and finishes the dataflow once all of its
+/// machines are done.
+#[allow(clippy::too_many_arguments)]
+async fn dataflow_finished_on_machine(
+    uuid: Uuid,
+    machine_id: String,
+    result: DataflowDaemonResult,
+    running_dataflows: &mut HashMap<Uuid, RunningDataflow>,
+    archived_dataflows: &mut HashMap<Uuid, ArchivedDataflow>,
+    dataflow_results: &mut HashMap<Uuid, BTreeMap<String, DataflowDaemonResult>>,
+    state_store: Option<&StateStore>,
+    clock: &HLC,
+) {
+    let std::collections::hash_map::Entry::Occupied(mut entry) = running_dataflows.entry(uuid)
+    else {
+        return;
+    };
+    // Archive finished dataflow
+    archived_dataflows
+        .entry(uuid)
+        .or_insert_with(|| ArchivedDataflow::from(entry.get()));
+    entry.get_mut().machines.remove(&machine_id);
+    dataflow_results
+        .entry(uuid)
+        .or_default()
+        .insert(machine_id, result);
+    if entry.get_mut().machines.is_empty() {
+        let finished_dataflow = entry.remove();
+        let pruned =
+            persist_archived(state_store, &finished_dataflow, dataflow_results.get(&uuid)).await;
+        for pruned in pruned {
+            archived_dataflows.remove(&pruned);
+            dataflow_results.remove(&pruned);
+        }
+        let reply = ControlRequestReply::DataflowStopped {
+            uuid,
+            result: dataflow_results
+                .get(&uuid)
+                .map(|r| dataflow_result(r, uuid, clock))
+                .unwrap_or_else(|| DataflowResult::ok_empty(uuid, clock.new_timestamp())),
+        };
+        for sender in finished_dataflow.reply_senders {
+            let _ = sender.send(Ok(reply.clone()));
+        }
+    } else {
+        persist_running(state_store, entry.get()).await;
+    }
+}
+
+/// Handles the loss of the daemon connection to the given machine.
+///
+/// The nodes of the machine are marked as failed and the daemons of the remaining
+/// machines are told to close the inputs that were fed by these nodes. Depending on
+/// the dataflow's `on_machine_lost` policy, the remaining nodes are stopped too.
+/// Sends `AllNodesReady` to all machines of the dataflow once no machine is pending
+/// anymore.
+async fn notify_if_all_nodes_ready(
+    dataflow: &RunningDataflow,
+    daemon_connections: &mut HashMap<String, DaemonConnection>,
+    clock: &HLC,
+) -> eyre::Result<()> {
+    if !dataflow.pending_machines.is_empty() {
+        return Ok(());
+    }
+    let uuid = dataflow.uuid;
+    let message = serde_json::to_vec(&Timestamped {
+        inner: DaemonCoordinatorEvent::AllNodesReady {
+            dataflow_id: uuid,
+            exited_before_subscribe: dataflow.exited_before_subscribe.clone(),
+        },
+        timestamp: clock.new_timestamp(),
+    })
+    .wrap_err("failed to serialize AllNodesReady message")?;
+
+    // notify all machines that run parts of the dataflow
+    for machine_id in dataflow.machines.difference(&dataflow.lost_machines) {
+        let Some(connection) = daemon_connections.get_mut(machine_id) else {
+            tracing::warn!("no daemon connection found for machine `{machine_id}`");
+            continue;
+        };
+        connection.stream.send(&message).await.wrap_err_with(|| {
+            format!("failed to send AllNodesReady({uuid}) message to machine {machine_id}")
+        })?;
+    }
+    Ok(())
+}
+
+async fn handle_machine_lost(
+    machine_id: &str,
+    running_dataflows: &mut HashMap<Uuid, RunningDataflow>,
+    archived_dataflows: &mut HashMap<Uuid, ArchivedDataflow>,
+    dataflow_results: &mut HashMap<Uuid, BTreeMap<String, DataflowDaemonResult>>,
+    daemon_connections: &mut HashMap<String, DaemonConnection>,
+    state_store: Option<&StateStore>,
+    clock: &HLC,
+) {
+    let affected: Vec<_> = running_dataflows
+        .values()
+        .filter(|d| d.machines.contains(machine_id))
+        .map(|d| d.uuid)
+        .collect();
+    for uuid in affected {
+        let Some(dataflow) = running_dataflows.get_mut(&uuid) else {
+            continue;
+        };
+        tracing::warn!("dataflow `{uuid}` lost its machine `{machine_id}`");
+        dataflow.lost_machines.insert(machine_id.to_owned());
+        let was_pending = dataflow.pending_machines.remove(machine_id);
+
+        let lost_nodes: BTreeSet<_> = dataflow
+            .nodes
+            .iter()
+            .filter(|n| n.deploy.machine == machine_id)
+            .map(|n| n.id.clone())
+            .collect();
+        let mut closed_inputs: BTreeMap<_, BTreeSet<_>> = BTreeMap::new();
+        for node in dataflow
+            .nodes
+            .iter()
+            .filter(|n| n.deploy.machine != machine_id)
+        {
+            for (input_id, input) in node.kind.run_config().inputs {
+                if let InputMapping::User(mapping) = &input.mapping {
+                    if lost_nodes.contains(&mapping.source) {
+                        closed_inputs
+                            .entry(node.deploy.machine.clone())
+                            .or_default()
+                            .insert((node.id.clone(), input_id));
+                    }
+                }
+            }
+        }
+
+        for other_machine in dataflow.machines.iter().filter(|m| *m != machine_id) {
+            let Some(connection) = daemon_connections.get_mut(other_machine) else {
+                continue;
+            };
+            let result = async {
+                let message = serde_json::to_vec(&Timestamped {
+                    inner: DaemonCoordinatorEvent::MachineLost {
+                        dataflow_id: uuid,
+                        machine_id: machine_id.to_owned(),
+                        closed_inputs: closed_inputs.remove(other_machine).unwrap_or_default(),
+                    },
+                    timestamp: clock.new_timestamp(),
+                })?;
+                connection
+                    .stream
+                    .send(&message)
+                    .await
+                    .wrap_err("failed to send MachineLost message")
+            };
+            if let Err(err) = result.await {
+                tracing::warn!("{:?}", err.wrap_err(format!("machine `{other_machine}`")));
+            }
+        }
+        // the remaining machines might have been waiting only for the lost one
+        if was_pending {
+            if let Err(err) = notify_if_all_nodes_ready(dataflow, daemon_connections, clock).await {
+                tracing::warn!("{err:?}");
+            }
+        }
+
+        let timestamp = clock.new_timestamp();
+        let result = DataflowDaemonResult {
+            timestamp,
+            node_results: lost_nodes
+                .into_iter()
+                .map(|node_id| {
+                    let error = NodeError {
+                        timestamp,
+                        cause: NodeErrorCause::MachineLost {
+                            machine_id: machine_id.to_owned(),
+                        },
+                        exit_status: NodeExitStatus::Unknown,
+                    };
+                    (node_id, Err(error))
+                })
+                .collect(),
+            node_restarts: BTreeMap::new(),
+        };
+        let policy = dataflow.on_machine_lost;
+        dataflow_finished_on_machine(
+            uuid,
+            machine_id.to_owned(),
+            result,
+            running_dataflows,
+            archived_dataflows,
+            dataflow_results,
+            state_store,
+            clock,
+        )
+        .await;
+
+        if policy == MachineLostPolicy::Stop {
+            if let Some(dataflow) = running_dataflows.get(&uuid) {
+                tracing::info!(
+                    "stopping dataflow `{uuid}` because machine `{machine_id}` was lost"
+                );
+                if let Err(err) = stop_dataflow(
+                    dataflow,
+                    uuid,
+                    daemon_connections,
+                    clock.new_timestamp(),
+                    None,
+                )
+                .await
+                {
+                    tracing::warn!("{:?}", err.wrap_err("failed to stop dataflow"));
+                }
+            }
+        }
+    }
+}
+
 fn dataflow_result(
     results: &BTreeMap<String, DataflowDaemonResult>,
     dataflow_uuid: Uuid,
     clock: &uhlc::HLC,
 ) -> DataflowResult {
     let mut node_results = BTreeMap::new();
+    let mut node_restarts = BTreeMap::new();
     for result in results.values() {
         node_results.extend(result.node_results.clone());
+        node_restarts.extend(result.node_restarts.clone());
         if let Err(err) = clock.update_with_timestamp(&result.timestamp) {
             tracing::warn!("failed to update HLC: {err}");
         }
@@ -653,17 +1155,20 @@ fn dataflow_result(
         uuid: dataflow_uuid,
         timestamp: clock.new_timestamp(),
         node_results,
+        node_restarts,
     }
 }
 
 struct DaemonConnection {
-    stream: TcpStream,
+    stream: DaemonStream,
     listen_socket: SocketAddr,
     last_heartbeat: Instant,
+    properties: MachineProperties,
 }
 
 async fn handle_destroy(
     running_dataflows: &HashMap<Uuid, RunningDataflow>,
+    state_store: Option<&StateStore>,
     daemon_connections: &mut HashMap<String, DaemonConnection>,
     abortable_events: &futures::stream::AbortHandle,
     daemon_events_tx: &mut Option<mpsc::Sender<Event>>,
@@ -682,22 +1187,166 @@ async fn handle_destroy(
     }
     destroy_daemons(daemon_connections, clock.new_timestamp()).await?;
     *daemon_events_tx = None;
+
+    // all dataflows were stopped, so there is nothing left to recover on the next start
+    if let Some(store) = state_store {
+        for &uuid in running_dataflows.keys() {
+            if let Err(err) = store.remove_running(uuid).await {
+                tracing::warn!("{err:?}");
+            }
+        }
+    }
     Ok(())
 }
 
-async fn send_heartbeat_message(
-    connection: &mut TcpStream,
+/// Handles the dataflows that a newly registered daemon reports as running.
+///
+/// Dataflows that were running before a coordinator restart are added back to the
+/// running dataflows once the first of their daemons re-announces them.
+///
+/// Returns the announced dataflows that should no longer run on the machine because
+/// the machine was declared lost, the dataflow already finished, or the dataflow is
+/// unknown and has no persisted state to restore it from.
+async fn recover_dataflows(
+    machine_id: &str,
+    announced_dataflows: Vec<Uuid>,
+    recovered_dataflows: &mut HashMap<Uuid, PersistedRunningDataflow>,
+    running_dataflows: &mut HashMap<Uuid, RunningDataflow>,
+    archived_dataflows: &HashMap<Uuid, ArchivedDataflow>,
+    state_store: Option<&StateStore>,
+) -> Vec<Uuid> {
+    let mut stale = Vec::new();
+    for uuid in announced_dataflows {
+        if let Some(dataflow) = running_dataflows.get_mut(&uuid) {
+            if dataflow.lost_machines.contains(machine_id) {
+                stale.push(uuid);
+            } else {
+                dataflow.machines.insert(machine_id.to_owned());
+            }
+            continue;
+        }
+        if archived_dataflows.contains_key(&uuid) && !recovered_dataflows.contains_key(&uuid) {
+            stale.push(uuid);
+            continue;
+        }
+        let Some(recovered) = recovered_dataflows.get(&uuid) else {
+            // without the persisted state, the nodes of the dataflow are unknown, so it
+            // can't be managed by this coordinator
+            tracing::warn!(
+                "machine `{machine_id}` reports unknown running dataflow `{uuid}`, \
+                which has no persisted state"
+            );
+            stale.push(uuid);
+            continue;
+        };
+        tracing::info!("recovered running dataflow `{uuid}` from machine `{machine_id}`");
+        running_dataflows.insert(
+            uuid,
+            RunningDataflow {
+                name: recovered.name.clone(),
+                uuid,
+                machines: [machine_id.to_owned()].into(),
+                pending_machines: BTreeSet::new(),
+                exited_before_subscribe: Vec::new(),
+                nodes: recovered.nodes.clone(),
+                on_machine_lost: recovered.on_machine_lost,
+                lost_machines: BTreeSet::new(),
+                reply_senders: Vec::new(),
+                log_subscribers: Vec::new(),
+            },
+        );
+    }
+
+    // the daemon reported all its running dataflows, so it is no longer pending for any
+    // recovered dataflow
+    let mut resolved = Vec::new();
+    for (uuid, recovered) in recovered_dataflows.iter_mut() {
+        recovered.machines.remove(machine_id);
+        if recovered.machines.is_empty() {
+            resolved.push(*uuid);
+        }
+    }
+    for uuid in resolved {
+        recovered_dataflows.remove(&uuid);
+        if !running_dataflows.contains_key(&uuid) {
+            tracing::warn!(
+                "dataflow `{uuid}` is no longer running on any machine, \
+                its result was lost while the coordinator was down"
+            );
+            if let Some(store) = state_store {
+                if let Err(err) = store.remove_running(uuid).await {
+                    tracing::warn!("{err:?}");
+                }
+            }
+        }
+    }
+    stale
+}
+
+async fn persist_running(state_store: Option<&StateStore>, dataflow: &RunningDataflow) {
+    let Some(store) = state_store else {
+        return;
+    };
+    let persisted = PersistedRunningDataflow {
+        uuid: dataflow.uuid,
+        name: dataflow.name.clone(),
+        machines: dataflow.machines.clone(),
+        nodes: dataflow.nodes.clone(),
+        on_machine_lost: dataflow.on_machine_lost,
+    };
+    if let Err(err) = store.save_running(&persisted).await {
+        tracing::warn!("{:?}", err.wrap_err("failed to persist running dataflow"));
+    }
+}
+
+/// Returns the archived dataflows that were deleted because of the retention limit.
+async fn persist_archived(
+    state_store: Option<&StateStore>,
+    dataflow: &RunningDataflow,
+    results: Option<&BTreeMap<String, DataflowDaemonResult>>,
+) -> Vec<Uuid> {
+    let Some(store) = state_store else {
+        return Vec::new();
+    };
+    let archived = PersistedArchivedDataflow {
+        uuid: dataflow.uuid,
+        name: dataflow.name.clone(),
+        nodes: dataflow.nodes.clone(),
+        results: results.cloned().unwrap_or_default(),
+    };
+    match store.archive(&archived).await {
+        Ok(pruned) => pruned,
+        Err(err) => {
+            tracing::warn!("{:?}", err.wrap_err("failed to persist finished dataflow"));
+            Vec::new()
+        }
+    }
+}
+
+/// Serializes a relayed event for the target daemon.
+///
+/// Like heartbeats, relayed events are not answered by the daemon.
+fn relayed_event_message(
+    source_machine: String,
+    event: RelayedEvent,
     timestamp: uhlc::Timestamp,
-) -> eyre::Result<()> {
-    let message = serde_json::to_vec(&Timestamped {
-        inner: DaemonCoordinatorEvent::Heartbeat,
+) -> eyre::Result<Vec<u8>> {
+    serde_json::to_vec(&Timestamped {
+        inner: DaemonCoordinatorEvent::Relay {
+            source_machine,
+            event,
+        },
         timestamp,
     })
-    .context("Could not serialize heartbeat message")?;
+    .context("Could not serialize relayed event")
+}
 
-    tcp_send(connection, &message)
-        .await
-        .wrap_err("failed to send heartbeat message to daemon")
+fn heartbeat_message(timestamp: uhlc::Timestamp) -> eyre::Result<Vec<u8>> {
+    serde_json::to_vec(&Timestamped {
+        inner: DaemonCoordinatorEvent::Heartbeat,
+        timestamp,
+    })
+    .context("Could not serialize heartbeat message")
 }
 
 struct RunningDataflow {
@@ -709,12 +1358,35 @@ struct RunningDataflow {
     pending_machines: BTreeSet<String>,
     exited_before_subscribe: Vec<NodeId>,
     nodes: Vec<ResolvedNode>,
+    on_machine_lost: MachineLostPolicy,
+    /// Machines that were lost while the dataflow was running.
+    lost_machines: BTreeSet<String>,
 
     reply_senders: Vec<tokio::sync::oneshot::Sender<eyre::Result<ControlRequestReply>>>,
 
     log_subscribers: Vec<LogSubscriber>,
 }
 
+/// Counts the nodes of the given dataflows per machine.
+fn machine_load<'a>(
+    dataflows: impl IntoIterator<Item = &'a RunningDataflow>,
+) -> BTreeMap<String, usize> {
+    let mut load = BTreeMap::new();
+    for node in dataflows.into_iter().flat_map(|d| &d.nodes) {
+        *load.entry(node.deploy.machine.clone()).or_default() += 1;
+    }
+    load
+}
+
+/// The machines that were chosen for the nodes with a deploy `selector`.
+fn placement(nodes: &[ResolvedNode]) -> BTreeMap<NodeId, String> {
+    nodes
+        .iter()
+        .filter(|n| !n.deploy.selector.is_empty())
+        .map(|n| (n.id.clone(), n.deploy.machine.clone()))
+        .collect()
+}
+
 struct ArchivedDataflow {
     name: Option<String>,
     nodes: Vec<ResolvedNode>,
@@ -743,6 +1415,28 @@ async fn stop_dataflow(
     daemon_connections: &mut HashMap<String, DaemonConnection>,
     timestamp: uhlc::Timestamp,
     grace_duration: Option<Duration>,
+) -> eyre::Result<()> {
+    for machine_id in &dataflow.machines {
+        stop_dataflow_on_machine(
+            uuid,
+            machine_id,
+            daemon_connections,
+            timestamp,
+            grace_duration,
+        )
+        .await?;
+    }
+    tracing::info!("successfully send stop dataflow `{uuid}` to all daemons");
+
+    Ok(())
+}
+
+async fn stop_dataflow_on_machine(
+    uuid: Uuid,
+    machine_id: &str,
+    daemon_connections: &mut HashMap<String, DaemonConnection>,
+    timestamp: uhlc::Timestamp,
+    grace_duration: Option<Duration>,
 ) -> eyre::Result<()> {
     let message = serde_json::to_vec(&Timestamped {
         inner: DaemonCoordinatorEvent::StopDataflow {
@@ -751,31 +1445,29 @@ async fn stop_dataflow(
         },
         timestamp,
     })?;
+    let daemon_connection = daemon_connections
+        .get_mut(machine_id)
+        .wrap_err("no daemon connection")?;
+    daemon_connection
+        .stream
+        .send(&message)
+        .await
+        .wrap_err("failed to send stop message to daemon")?;
 
-    for machine_id in &dataflow.machines {
-        let daemon_connection = daemon_connections
-            .get_mut(machine_id)
-            .wrap_err("no daemon connection")?; // TODO: take from dataflow spec
-        tcp_send(&mut daemon_connection.stream, &message)
-            .await
-            .wrap_err("failed to send stop message to daemon")?;
-
-        // wait for reply
-        let reply_raw = tcp_receive(&mut daemon_connection.stream)
-            .await
-            .wrap_err("failed to receive stop reply from daemon")?;
-        match serde_json::from_slice(&reply_raw)
-            .wrap_err("failed to deserialize stop reply from daemon")?
-        {
-            DaemonCoordinatorReply::StopResult(result) => result
-                .map_err(|e| eyre!(e))
-                .wrap_err("failed to stop dataflow")?,
-            other => bail!("unexpected reply after sending stop: {other:?}"),
-        }
+    // wait for reply
+    let reply_raw = daemon_connection
+        .stream
+        .receive()
+        .await
+        .wrap_err("failed to receive stop reply from daemon")?;
+    match serde_json::from_slice(&reply_raw)
+        .wrap_err("failed to deserialize stop reply from daemon")?
+    {
+        DaemonCoordinatorReply::StopResult(result) => result
+            .map_err(|e| eyre!(e))
+            .wrap_err("failed to stop dataflow"),
+        other => bail!("unexpected reply after sending stop: {other:?}"),
     }
-    tracing::info!("successfully send stop dataflow `{uuid}` to all daemons");
-
-    Ok(())
 }
 
 async fn reload_dataflow(
@@ -802,12 +1494,16 @@ async fn reload_dataflow(
         let daemon_connection = daemon_connections
             .get_mut(machine_id)
             .wrap_err("no daemon connection")?; // TODO: take from dataflow spec
-        tcp_send(&mut daemon_connection.stream, &message)
+        daemon_connection
+            .stream
+            .send(&message)
             .await
             .wrap_err("failed to send reload message to daemon")?;
 
         // wait for reply
-        let reply_raw = tcp_receive(&mut daemon_connection.stream)
+        let reply_raw = daemon_connection
+            .stream
+            .receive()
             .await
             .wrap_err("failed to receive reload reply from daemon")?;
         match serde_json::from_slice(&reply_raw)
@@ -824,6 +1520,58 @@ async fn reload_dataflow(
     Ok(())
 }
 
+async fn restart_node(
+    running_dataflows: &HashMap<Uuid, RunningDataflow>,
+    dataflow_id: Uuid,
+    node_id: NodeId,
+    grace_duration: Option<Duration>,
+    daemon_connections: &mut HashMap<String, DaemonConnection>,
+    timestamp: uhlc::Timestamp,
+) -> eyre::Result<()> {
+    let Some(dataflow) = running_dataflows.get(&dataflow_id) else {
+        bail!("No running dataflow found with UUID `{dataflow_id}`")
+    };
+    let Some(node) = dataflow.nodes.iter().find(|node| node.id == node_id) else {
+        bail!("No node `{node_id}` in dataflow `{dataflow_id}`")
+    };
+    let machine_id = &node.deploy.machine;
+
+    let message = serde_json::to_vec(&Timestamped {
+        inner: DaemonCoordinatorEvent::RestartNode {
+            dataflow_id,
+            node_id: node_id.clone(),
+            grace_duration,
+        },
+        timestamp,
+    })?;
+    let daemon_connection = daemon_connections
+        .get_mut(machine_id)
+        .wrap_err_with(|| format!("no daemon connection to machine `{machine_id}`"))?;
+    daemon_connection
+        .stream
+        .send(&message)
+        .await
+        .wrap_err("failed to send restart message to daemon")?;
+
+    // wait for reply
+    let reply_raw = daemon_connection
+        .stream
+        .receive()
+        .await
+        .wrap_err("failed to receive restart reply from daemon")?;
+    match serde_json::from_slice(&reply_raw)
+        .wrap_err("failed to deserialize restart reply from daemon")?
+    {
+        DaemonCoordinatorReply::RestartResult(result) => {
+            result.map_err(|e| eyre!("failed to restart node `{node_id}`: {e}"))?
+        }
+        other => bail!("unexpected reply after sending restart: {other:?}"),
+    }
+    tracing::info!("restarting node `{dataflow_id}/{node_id}`");
+
+    Ok(())
+}
+
 async fn retrieve_logs(
     running_dataflows: &HashMap<Uuid, RunningDataflow>,
     archived_dataflows: &HashMap<Uuid, ArchivedDataflow>,
@@ -869,12 +1617,16 @@ async fn retrieve_logs(
     let daemon_connection = daemon_connections
         .get_mut(machine_id.as_str())
         .wrap_err("no daemon connection")?;
-    tcp_send(&mut daemon_connection.stream, &message)
+    daemon_connection
+        .stream
+        .send(&message)
         .await
         .wrap_err("failed to send logs message to daemon")?;
 
     // wait for reply
-    let reply_raw = tcp_receive(&mut daemon_connection.stream)
+    let reply_raw = daemon_connection
+        .stream
+        .receive()
         .await
         .wrap_err("failed to retrieve logs reply from daemon")?;
     let reply_logs = match serde_json::from_slice(&reply_raw)
@@ -888,18 +1640,191 @@ async fn retrieve_logs(
     reply_logs.map_err(|err| eyre!(err))
 }
 
+async fn retrieve_output_rates(
+    running_dataflows: &HashMap<Uuid, RunningDataflow>,
+    dataflow_id: Uuid,
+    daemon_connections: &mut HashMap<String, DaemonConnection>,
+    timestamp: uhlc::Timestamp,
+) -> eyre::Result<Vec<OutputRate>> {
+    let Some(dataflow) = running_dataflows.get(&dataflow_id) else {
+        bail!("No running dataflow found with UUID `{dataflow_id}`")
+    };
+    let message = serde_json::to_vec(&Timestamped {
+        inner: DaemonCoordinatorEvent::OutputRates { dataflow_id },
+        timestamp,
+    })?;
+
+    let mut rates = Vec::new();
+    for machine_id in &dataflow.machines {
+        let daemon_connection = daemon_connections
+            .get_mut(machine_id)
+            .wrap_err("no daemon connection")?;
+        daemon_connection
+            .stream
+            .send(&message)
+            .await
+            .wrap_err("failed to send output rates message to daemon")?;
+
+        // wait for reply
+        let reply_raw = daemon_connection
+            .stream
+            .receive()
+            .await
+            .wrap_err("failed to receive output rates reply from daemon")?;
+        match serde_json::from_slice(&reply_raw)
+            .wrap_err("failed to deserialize output rates reply from daemon")?
+        {
+            DaemonCoordinatorReply::OutputRates(result) => rates.extend(
+                result
+                    .map_err(|e| eyre!(e))
+                    .wrap_err_with(|| format!("failed to get output rates of `{machine_id}`"))?,
+            ),
+            other => bail!("unexpected reply after sending output rates: {other:?}"),
+        }
+    }
+
+    Ok(rates)
+}
+
+async fn retrieve_stats(
+    running_dataflows: &HashMap<Uuid, RunningDataflow>,
+    dataflow_id: Uuid,
+    daemon_connections: &mut HashMap<String, DaemonConnection>,
+    timestamp: uhlc::Timestamp,
+) -> eyre::Result<DataflowStats> {
+    let Some(dataflow) = running_dataflows.get(&dataflow_id) else {
+        bail!("No running dataflow found with UUID `{dataflow_id}`")
+    };
+    let message = serde_json::to_vec(&Timestamped {
+        inner: DaemonCoordinatorEvent::Stats { dataflow_id },
+        timestamp,
+    })?;
+
+    let mut stats = DataflowStats::default();
+    for machine_id in &dataflow.machines {
+        let daemon_connection = daemon_connections
+            .get_mut(machine_id)
+            .wrap_err("no daemon connection")?;
+        daemon_connection
+            .stream
+            .send(&message)
+            .await
+            .wrap_err("failed to send stats message to daemon")?;
+
+        // wait for reply
+        let reply_raw = daemon_connection
+            .stream
+            .receive()
+            .await
+            .wrap_err("failed to receive stats reply from daemon")?;
+        match serde_json::from_slice(&reply_raw)
+            .wrap_err("failed to deserialize stats reply from daemon")?
+        {
+            DaemonCoordinatorReply::Stats(result) => {
+                let machine_stats = result
+                    .map_err(|e| eyre!(e))
+                    .wrap_err_with(|| format!("failed to get stats of `{machine_id}`"))?;
+                stats.outputs.extend(machine_stats.outputs);
+                stats.inputs.extend(machine_stats.inputs);
+                stats.connections.extend(machine_stats.connections);
+                stats.best_effort.extend(machine_stats.best_effort);
+            }
+            other => bail!("unexpected reply after sending stats: {other:?}"),
+        }
+    }
+
+    Ok(stats)
+}
+
+/// Collects the hashes of the artifacts that are not cached yet by the daemons that
+/// might run their nodes.
+///
+/// Nodes with a `selector` might be placed on any of the matching machines.
+async fn missing_artifacts(
+    dataflow: Descriptor,
+    artifacts: BTreeMap<NodeId, Vec<NodeArtifact>>,
+    daemon_connections: &mut HashMap<String, DaemonConnection>,
+    timestamp: uhlc::Timestamp,
+) -> eyre::Result<BTreeSet<String>> {
+    let nodes = dataflow.resolve_aliases_and_set_defaults()?;
+
+    let mut missing = BTreeSet::new();
+    for (machine_id, daemon_connection) in daemon_connections {
+        let hashes: BTreeSet<_> = nodes
+            .iter()
+            .filter(|n| {
+                if n.deploy.selector.is_empty() {
+                    &n.deploy.machine == machine_id
+                } else {
+                    daemon_connection.properties.matches(&n.deploy.selector)
+                }
+            })
+            .filter_map(|n| artifacts.get(&n.id))
+            .flatten()
+            .map(|a| a.hash.clone())
+            .collect();
+        if hashes.is_empty() {
+            continue;
+        }
+        let message = serde_json::to_vec(&Timestamped {
+            inner: DaemonCoordinatorEvent::MissingArtifacts { hashes },
+            timestamp,
+        })?;
+        daemon_connection
+            .stream
+            .send(&message)
+            .await
+            .wrap_err("failed to send missing artifacts message to daemon")?;
+
+        // wait for reply
+        let reply_raw = daemon_connection
+            .stream
+            .receive()
+            .await
+            .wrap_err("failed to receive missing artifacts reply from daemon")?;
+        match serde_json::from_slice(&reply_raw)
+            .wrap_err("failed to deserialize missing artifacts reply from daemon")?
+        {
+            DaemonCoordinatorReply::MissingArtifacts(machine_missing) => {
+                tracing::debug!(
+                    "machine `{machine_id}` misses {} artifacts",
+                    machine_missing.len()
+                );
+                missing.extend(machine_missing);
+            }
+            other => bail!("unexpected reply after sending missing artifacts: {other:?}"),
+        }
+    }
+
+    Ok(missing)
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn start_dataflow(
     dataflow: Descriptor,
     working_dir: PathBuf,
     name: Option<String>,
+    artifacts: BTreeMap<NodeId, Vec<NodeArtifact>>,
+    artifact_data: Vec<ArtifactData>,
     daemon_connections: &mut HashMap<String, DaemonConnection>,
+    machine_load: BTreeMap<String, usize>,
     clock: &HLC,
 ) -> eyre::Result<RunningDataflow> {
+    let on_machine_lost = dataflow.on_machine_lost;
     let SpawnedDataflow {
         uuid,
         machines,
         nodes,
-    } = spawn_dataflow(dataflow, working_dir, daemon_connections, clock).await?;
+    } = spawn_dataflow(
+        dataflow,
+        working_dir,
+        artifacts,
+        artifact_data,
+        daemon_connections,
+        machine_load,
+        clock,
+    )
+    .await?;
     Ok(RunningDataflow {
         uuid,
         name,
@@ -911,6 +1836,8 @@ async fn start_dataflow(
         exited_before_subscribe: Default::default(),
         machines,
         nodes,
+        on_machine_lost,
+        lost_machines: BTreeSet::new(),
         reply_senders: Vec::new(),
         log_subscribers: Vec::new(),
     })
@@ -926,12 +1853,16 @@ async fn destroy_daemons(
     })?;
 
     for (machine_id, mut daemon_connection) in daemon_connections.drain() {
-        tcp_send(&mut daemon_connection.stream, &message)
+        daemon_connection
+            .stream
+            .send(&message)
             .await
             .wrap_err("failed to send destroy message to daemon")?;
 
         // wait for reply
-        let reply_raw = tcp_receive(&mut daemon_connection.stream)
+        let reply_raw = daemon_connection
+            .stream
+            .receive()
             .await
             .wrap_err("failed to receive destroy reply from daemon")?;
         match serde_json::from_slice(&reply_raw)
@@ -953,13 +1884,30 @@ async fn destroy_daemons(
 pub enum Event {
     NewDaemonConnection(TcpStream),
     DaemonConnectError(eyre::Report),
-    DaemonHeartbeat { machine_id: String },
-    Dataflow { uuid: Uuid, event: DataflowEvent },
+    DaemonHeartbeat {
+        machine_id: String,
+    },
+    Dataflow {
+        uuid: Uuid,
+        event: DataflowEvent,
+    },
     Control(ControlEvent),
     Daemon(DaemonRequest),
     DaemonHeartbeatInterval,
     CtrlC,
     Log(LogMessage),
+    /// A daemon finished all build commands that it received for the given build.
+    BuildFinished {
+        build_id: Uuid,
+        machine_id: String,
+        results: BTreeMap<NodeId, Result<(), String>>,
+    },
+    /// Event of a daemon for another daemon that it cannot reach directly.
+    Relay {
+        source_machine: String,
+        target_machine: String,
+        event: RelayedEvent,
+    },
 }
 
 impl Event {
@@ -968,6 +1916,7 @@ impl Event {
     pub fn log(&self) -> bool {
         match self {
             Event::DaemonHeartbeatInterval => false,
+            Event::Relay { .. } => false,
             _ => true,
         }
     }
@@ -983,6 +1932,8 @@ pub enum DataflowEvent {
         machine_id: String,
         exited_before_subscribe: Vec<NodeId>,
     },
+    /// The daemon of the given machine requested to stop the dataflow.
+    StopRequested { machine_id: String },
 }
 
 #[derive(Debug)]
@@ -990,8 +1941,10 @@ pub enum DaemonRequest {
     Register {
         version_check_result: Result<(), String>,
         machine_id: String,
-        connection: TcpStream,
+        connection: MaybeTlsStream,
         listen_port: u16,
+        running_dataflows: Vec<Uuid>,
+        properties: MachineProperties,
     },
 }
 
@@ -1,6 +1,10 @@
-use crate::{tcp_utils::tcp_receive, DaemonRequest, DataflowEvent, Event};
+use crate::{
+    tcp_utils::{tcp_receive, tcp_receive_bounded},
+    DaemonRequest, DataflowEvent, Event,
+};
 use dora_core::uhlc::HLC;
 use dora_message::daemon_to_coordinator::{CoordinatorRequest, DaemonEvent, Timestamped};
+use dora_security::{Security, MAX_PRE_AUTH_MESSAGE_SIZE};
 use eyre::Context;
 use std::{io::ErrorKind, net::SocketAddr, sync::Arc};
 use tokio::{
@@ -19,15 +23,40 @@ pub async fn create_listener(bind: SocketAddr) -> eyre::Result<TcpListener> {
 }
 
 pub async fn handle_connection(
-    mut connection: TcpStream,
+    connection: TcpStream,
     events_tx: mpsc::Sender<Event>,
     clock: Arc<HLC>,
+    security: Security,
 ) {
+    let mut connection = match security.accept(connection).await {
+        Ok(connection) => connection,
+        Err(err) => {
+            tracing::warn!("TLS handshake with daemon failed: {err}");
+            return;
+        }
+    };
+    // connections that register a daemon present the token as part of the register
+    // request, event connections authenticate through a separate message
+    let mut authenticated = security.verify_token(None);
     loop {
         // receive the next message and parse it
-        let raw = match tcp_receive(&mut connection).await {
+        let raw = if authenticated {
+            tcp_receive(&mut connection).await
+        } else {
+            tcp_receive_bounded(&mut connection, MAX_PRE_AUTH_MESSAGE_SIZE).await
+        };
+        let raw = match raw {
             Ok(data) => data,
-            Err(err) if err.kind() == ErrorKind::UnexpectedEof => {
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    ErrorKind::UnexpectedEof | ErrorKind::ConnectionReset
+                ) =>
+            {
+                break;
+            }
+            Err(err) if err.kind() == ErrorKind::InvalidData => {
+                tracing::warn!("closing unauthenticated daemon connection: {err}");
                 break;
             }
             Err(err) => {
@@ -51,15 +80,35 @@ pub async fn handle_connection(
         // handle the message and translate it to a DaemonEvent
         match message.inner {
             CoordinatorRequest::Register(register_request) => {
+                let version_check_result = register_request.check_version().and_then(|()| {
+                    if security.verify_token(register_request.token.as_deref()) {
+                        Ok(())
+                    } else {
+                        Err("invalid authentication token".to_owned())
+                    }
+                });
                 let event = DaemonRequest::Register {
                     connection,
-                    version_check_result: register_request.check_version(),
+                    version_check_result,
                     machine_id: register_request.machine_id,
                     listen_port: register_request.listen_port,
+                    running_dataflows: register_request.running_dataflows,
+                    properties: register_request.properties,
                 };
                 let _ = events_tx.send(Event::Daemon(event)).await;
                 break;
             }
+            CoordinatorRequest::Authenticate { token } => {
+                authenticated = security.verify_token(token.as_deref());
+                if !authenticated {
+                    tracing::warn!("closing daemon connection because of an invalid token");
+                    break;
+                }
+            }
+            CoordinatorRequest::Event { .. } if !authenticated => {
+                tracing::warn!("closing unauthenticated daemon connection");
+                break;
+            }
             CoordinatorRequest::Event { machine_id, event } => match event {
                 DaemonEvent::AllNodesReady {
                     dataflow_id,
@@ -88,6 +137,15 @@ pub async fn handle_connection(
                         break;
                     }
                 }
+                DaemonEvent::StopDataflow { dataflow_id } => {
+                    let event = Event::Dataflow {
+                        uuid: dataflow_id,
+                        event: DataflowEvent::StopRequested { machine_id },
+                    };
+                    if events_tx.send(event).await.is_err() {
+                        break;
+                    }
+                }
                 DaemonEvent::Heartbeat => {
                     let event = Event::DaemonHeartbeat { machine_id };
                     if events_tx.send(event).await.is_err() {
@@ -100,6 +158,29 @@ pub async fn handle_connection(
                         break;
                     }
                 }
+                DaemonEvent::BuildFinished { build_id, results } => {
+                    let event = Event::BuildFinished {
+                        build_id,
+                        machine_id,
+                        results,
+                    };
+                    if events_tx.send(event).await.is_err() {
+                        break;
+                    }
+                }
+                DaemonEvent::Relay {
+                    target_machine,
+                    event,
+                } => {
+                    let event = Event::Relay {
+                        source_machine: machine_id,
+                        target_machine,
+                        event,
+                    };
+                    if events_tx.send(event).await.is_err() {
+                        break;
+                    }
+                }
             },
         };
     }
@@ -1,15 +1,16 @@
 use dora_message::coordinator_to_cli::LogMessage;
+use dora_security::MaybeTlsStream;
 use eyre::{Context, ContextCompat};
 
 use crate::tcp_utils::tcp_send;
 
 pub struct LogSubscriber {
     pub level: log::LevelFilter,
-    connection: Option<tokio::net::TcpStream>,
+    connection: Option<MaybeTlsStream>,
 }
 
 impl LogSubscriber {
-    pub fn new(level: log::LevelFilter, connection: tokio::net::TcpStream) -> Self {
+    pub fn new(level: log::LevelFilter, connection: MaybeTlsStream) -> Self {
         Self {
             level,
             connection: Some(connection),
@@ -1,43 +1,53 @@
-use crate::{
-    tcp_utils::{tcp_receive, tcp_send},
-    DaemonConnection,
-};
+use crate::DaemonConnection;
 
 use dora_core::{
-    descriptor::{Descriptor, ResolvedNode},
+    config::{InputMapping, NodeId},
+    descriptor::{CoreNodeKind, Descriptor, ResolvedNode},
     uhlc::HLC,
 };
 use dora_message::{
-    coordinator_to_daemon::{DaemonCoordinatorEvent, SpawnDataflowNodes, Timestamped},
+    common::{ArtifactData, NodeArtifact, MAX_ARTIFACT_UPLOAD_SIZE},
+    coordinator_to_cli::NodeChanges,
+    coordinator_to_daemon::{
+        DaemonCoordinatorEvent, DataflowChanges, NodeInput, SpawnDataflowNodes, Timestamped,
+    },
     daemon_to_coordinator::DaemonCoordinatorReply,
 };
 use eyre::{bail, eyre, ContextCompat, WrapErr};
 use std::{
     collections::{BTreeMap, BTreeSet, HashMap},
-    path::PathBuf,
+    path::{Path, PathBuf},
+    time::Duration,
 };
 use uuid::{NoContext, Timestamp, Uuid};
 
-#[tracing::instrument(skip(daemon_connections, clock))]
+#[tracing::instrument(skip(artifacts, artifact_data, daemon_connections, machine_load, clock))]
 pub(super) async fn spawn_dataflow(
     dataflow: Descriptor,
     working_dir: PathBuf,
+    artifacts: BTreeMap<NodeId, Vec<NodeArtifact>>,
+    artifact_data: Vec<ArtifactData>,
     daemon_connections: &mut HashMap<String, DaemonConnection>,
+    machine_load: BTreeMap<String, usize>,
     clock: &HLC,
 ) -> eyre::Result<SpawnedDataflow> {
-    let remote_machine_id: Vec<_> = daemon_connections
-        .iter()
-        .filter_map(|(id, c)| {
-            if !c.listen_socket.ip().is_loopback() {
-                Some(id.as_str())
-            } else {
-                None
-            }
-        })
-        .collect();
-    dataflow.check_in_daemon(&working_dir, &remote_machine_id, false)?;
+    check_dataflow(&dataflow, &working_dir, daemon_connections)?;
 
-    let nodes = dataflow.resolve_aliases_and_set_defaults()?;
+    let mut nodes = dataflow.resolve_aliases_and_set_defaults()?;
+    if let Some(node_id) = artifacts
+        .keys()
+        .find(|id| !nodes.iter().any(|n| &n.id == *id))
+    {
+        bail!("artifacts were uploaded for unknown node `{node_id}`");
+    }
+    let upload_size: usize = artifact_data.iter().map(|d| d.data.len()).sum();
+    if upload_size > MAX_ARTIFACT_UPLOAD_SIZE {
+        bail!(
+            "uploaded artifacts exceed the limit of {MAX_ARTIFACT_UPLOAD_SIZE} bytes \
+            ({upload_size} bytes)"
+        );
+    }
+    place_nodes(&mut nodes, daemon_connections, None, machine_load)?;
     let uuid = Uuid::new_v7(Timestamp::now(NoContext));
 
     let machines: BTreeSet<_> = nodes.iter().map(|n| n.deploy.machine.clone()).collect();
@@ -51,19 +61,21 @@ pub(super) async fn spawn_dataflow(
         })
         .collect::<Result<BTreeMap<_, _>, _>>()?;
 
-    let spawn_command = SpawnDataflowNodes {
-        dataflow_id: uuid,
-        working_dir,
-        nodes: nodes.clone(),
-        machine_listen_ports,
-        dataflow_descriptor: dataflow,
-    };
-    let message = serde_json::to_vec(&Timestamped {
-        inner: DaemonCoordinatorEvent::Spawn(spawn_command),
-        timestamp: clock.new_timestamp(),
-    })?;
-
     for machine in &machines {
+        let spawn_command = SpawnDataflowNodes {
+            dataflow_id: uuid,
+            working_dir: machine_working_dir(&dataflow, machine, daemon_connections, &working_dir),
+            nodes: nodes.clone(),
+            machine_listen_ports: machine_listen_ports.clone(),
+            dataflow_descriptor: dataflow.clone(),
+            artifacts: artifacts.clone(),
+            artifact_data: machine_artifact_data(&nodes, machine, &artifacts, &artifact_data),
+        };
+        let message = serde_json::to_vec(&Timestamped {
+            inner: DaemonCoordinatorEvent::Spawn(spawn_command),
+            timestamp: clock.new_timestamp(),
+        })?;
+
         tracing::trace!("Spawning dataflow `{uuid}` on machine `{machine}`");
         spawn_dataflow_on_machine(daemon_connections, machine, &message)
             .await
@@ -79,6 +91,363 @@ pub(super) async fn spawn_dataflow(
     })
 }
 
+/// Applies an updated descriptor to a running dataflow.
+///
+/// Nodes are added and removed as needed. Nodes whose definition changed are
+/// restarted, unless only their input mappings changed. In that case, the inputs
+/// are rewired on the daemons without restarting the node.
+///
+/// The changes are validated on all machines before they are applied. If any machine
+/// rejects them, nothing is changed. Otherwise, `current_nodes` is replaced by the
+/// updated list of nodes.
+#[tracing::instrument(skip(current_nodes, dataflow, daemon_connections, machine_load, clock))]
+#[allow(clippy::too_many_arguments)]
+pub(super) async fn apply_dataflow(
+    dataflow_id: Uuid,
+    current_nodes: &mut Vec<ResolvedNode>,
+    machines: &BTreeSet<String>,
+    dataflow: Descriptor,
+    working_dir: PathBuf,
+    grace_duration: Option<Duration>,
+    daemon_connections: &mut HashMap<String, DaemonConnection>,
+    machine_load: BTreeMap<String, usize>,
+    clock: &HLC,
+) -> eyre::Result<NodeChanges> {
+    check_dataflow(&dataflow, &working_dir, daemon_connections)?;
+
+    let mut nodes = dataflow.resolve_aliases_and_set_defaults()?;
+    // keep the placement of existing nodes, new nodes are placed on the dataflow's machines
+    for node in &mut nodes {
+        if let Some(old_node) = current_nodes.iter().find(|n| n.id == node.id) {
+            if node.deploy.machine.is_empty() && node.deploy.selector == old_node.deploy.selector {
+                node.deploy.machine = old_node.deploy.machine.clone();
+            }
+        }
+    }
+    place_nodes(&mut nodes, daemon_connections, Some(machines), machine_load)?;
+    let old: BTreeMap<_, _> = current_nodes.iter().map(|n| (&n.id, n)).collect();
+    let new: BTreeMap<_, _> = nodes.iter().map(|n| (&n.id, n)).collect();
+
+    let mut changes = NodeChanges::default();
+    let mut removed_inputs = Vec::new();
+    let mut added_inputs = Vec::new();
+    for (node_id, old_node) in &old {
+        if !new.contains_key(node_id) {
+            changes.removed.insert((*node_id).clone());
+            removed_inputs.extend(node_inputs(old_node));
+        }
+    }
+    for (node_id, new_node) in &new {
+        if !machines.contains(&new_node.deploy.machine) {
+            bail!(
+                "node `{node_id}` is deployed to machine `{}`, which is not part of the dataflow",
+                new_node.deploy.machine
+            );
+        }
+        let Some(old_node) = old.get(node_id) else {
+            changes.added.insert((*node_id).clone());
+            added_inputs.extend(node_inputs(new_node));
+            continue;
+        };
+        if old_node.deploy.machine != new_node.deploy.machine {
+            bail!(
+                "cannot move node `{node_id}` to a different machine while the dataflow is running"
+            );
+        }
+        if without_input_mappings(old_node)? != without_input_mappings(new_node)? {
+            if new_node.kind.dynamic() {
+                bail!("dynamic node `{node_id}` cannot be changed while the dataflow is running");
+            }
+            changes.restarted.insert((*node_id).clone());
+        }
+
+        let old_inputs = node_inputs(old_node);
+        let new_inputs = node_inputs(new_node);
+        let removed: Vec<_> = old_inputs
+            .iter()
+            .filter(|input| !new_inputs.iter().any(|i| is_same_input(i, input)))
+            .cloned()
+            .collect();
+        let added: Vec<_> = new_inputs
+            .iter()
+            .filter(|input| !old_inputs.iter().any(|i| is_same_input(i, input)))
+            .cloned()
+            .collect();
+        if (!removed.is_empty() || !added.is_empty()) && !changes.restarted.contains(*node_id) {
+            changes.rewired.insert((*node_id).clone());
+        }
+        removed_inputs.extend(removed);
+        added_inputs.extend(added);
+    }
+
+    if changes.is_empty() {
+        *current_nodes = nodes;
+        return Ok(changes);
+    }
+
+    // validate the changes on all machines first, so that a failure doesn't leave the
+    // dataflow partially updated
+    let mut prepare_error = None;
+    for machine in machines {
+        let message = serde_json::to_vec(&Timestamped {
+            inner: DaemonCoordinatorEvent::PrepareChanges(DataflowChanges {
+                dataflow_id,
+                working_dir: machine_working_dir(
+                    &dataflow,
+                    machine,
+                    daemon_connections,
+                    &working_dir,
+                ),
+                nodes: nodes.clone(),
+                dataflow_descriptor: dataflow.clone(),
+                added: changes.added.clone(),
+                removed: changes.removed.clone(),
+                restarted: changes.restarted.clone(),
+                removed_inputs: removed_inputs.clone(),
+                added_inputs: added_inputs.clone(),
+                grace_duration,
+            }),
+            timestamp: clock.new_timestamp(),
+        })?;
+        let result = send_change_request(daemon_connections, machine, &message)
+            .await
+            .and_then(|reply| match reply {
+                DaemonCoordinatorReply::PrepareResult(result) => result.map_err(|e| eyre!(e)),
+                other => bail!("unexpected reply after sending prepare: {other:?}"),
+            });
+        if let Err(err) = result {
+            prepare_error = Some(eyre!("machine `{machine}` rejected the changes: {err:?}"));
+            break;
+        }
+    }
+    if let Some(err) = prepare_error {
+        for machine in machines {
+            let message = serde_json::to_vec(&Timestamped {
+                inner: DaemonCoordinatorEvent::DiscardChanges { dataflow_id },
+                timestamp: clock.new_timestamp(),
+            })?;
+            if let Some(connection) = daemon_connections.get_mut(machine) {
+                if let Err(err) = connection.stream.send(&message).await {
+                    tracing::warn!("failed to discard changes on machine `{machine}`: {err}");
+                }
+            }
+        }
+        return Err(err);
+    }
+
+    // all machines accepted the changes -> the dataflow uses the new nodes from now on
+    *current_nodes = nodes;
+    let mut apply_errors = Vec::new();
+    for machine in machines {
+        let message = serde_json::to_vec(&Timestamped {
+            inner: DaemonCoordinatorEvent::ApplyChanges { dataflow_id },
+            timestamp: clock.new_timestamp(),
+        })?;
+        let result = send_change_request(daemon_connections, machine, &message)
+            .await
+            .and_then(|reply| match reply {
+                DaemonCoordinatorReply::ApplyResult(result) => result.map_err(|e| eyre!(e)),
+                other => bail!("unexpected reply after sending apply: {other:?}"),
+            });
+        if let Err(err) = result {
+            apply_errors.push(format!(
+                "failed to apply changes on machine `{machine}`: {err:?}"
+            ));
+        }
+    }
+    if !apply_errors.is_empty() {
+        bail!(apply_errors.join("\n"));
+    }
+
+    tracing::info!("successfully applied changes to dataflow `{dataflow_id}`");
+
+    Ok(changes)
+}
+
+/// Sends a `PrepareChanges` or `ApplyChanges` message to the given machine and waits
+/// for the reply.
+async fn send_change_request(
+    daemon_connections: &mut HashMap<String, DaemonConnection>,
+    machine: &str,
+    message: &[u8],
+) -> eyre::Result<DaemonCoordinatorReply> {
+    let daemon_connection = daemon_connections
+        .get_mut(machine)
+        .wrap_err_with(|| format!("no daemon connection for machine `{machine}`"))?;
+    daemon_connection
+        .stream
+        .send(message)
+        .await
+        .wrap_err("failed to send message to daemon")?;
+    let reply_raw = daemon_connection
+        .stream
+        .receive()
+        .await
+        .wrap_err("failed to receive reply from daemon")?;
+    serde_json::from_slice(&reply_raw).wrap_err("failed to deserialize reply from daemon")
+}
+
+/// Selects the uploaded artifacts that the nodes of the given machine need.
+fn machine_artifact_data(
+    nodes: &[ResolvedNode],
+    machine: &str,
+    artifacts: &BTreeMap<NodeId, Vec<NodeArtifact>>,
+    artifact_data: &[ArtifactData],
+) -> Vec<ArtifactData> {
+    let hashes: BTreeSet<_> = nodes
+        .iter()
+        .filter(|n| n.deploy.machine == machine)
+        .filter_map(|n| artifacts.get(&n.id))
+        .flatten()
+        .map(|a| a.hash.as_str())
+        .collect();
+    artifact_data
+        .iter()
+        .filter(|d| hashes.contains(d.hash.as_str()))
+        .cloned()
+        .collect()
+}
+
+/// Validates the dataflow against the connected machines.
+///
+/// The node paths of remote machines and of machines with their own working dir are
+/// resolved on these machines, so the coordinator can't check them.
+fn check_dataflow(
+    dataflow: &Descriptor,
+    working_dir: &Path,
+    daemon_connections: &HashMap<String, DaemonConnection>,
+) -> eyre::Result<()> {
+    let remote_machines: Vec<_> = daemon_connections
+        .iter()
+        .filter(|(_, c)| !c.listen_socket.ip().is_loopback())
+        .map(|(id, _)| id.as_str())
+        .collect();
+    let working_dir_machines: Vec<_> = daemon_connections
+        .iter()
+        .filter(|(_, c)| c.properties.working_dir.is_some())
+        .map(|(id, _)| id.as_str())
+        .collect();
+    dataflow.check_in_daemon(working_dir, &remote_machines, &working_dir_machines, false)
+}
+
+/// Resolves the working directory of the dataflow on the given machine.
+///
+/// Relative entries of the dataflow's `working_dirs` are joined to the directory that
+/// the daemon advertises, which defaults to the working directory of the CLI.
+pub(super) fn machine_working_dir(
+    dataflow: &Descriptor,
+    machine: &str,
+    daemon_connections: &HashMap<String, DaemonConnection>,
+    local_working_dir: &Path,
+) -> PathBuf {
+    let root = daemon_connections
+        .get(machine)
+        .and_then(|c| c.properties.working_dir.as_deref())
+        .unwrap_or(local_working_dir);
+    match dataflow.working_dirs.get(machine) {
+        Some(dir) => root.join(dir),
+        None => root.to_owned(),
+    }
+}
+
+/// Chooses a machine for every node that specifies a deploy `selector`.
+///
+/// Among the matching machines that have capacity left, the one with the fewest nodes
+/// is chosen, so that the nodes are spread across equal candidates. If `machines` is
+/// set, only these machines are considered.
+fn place_nodes(
+    nodes: &mut [ResolvedNode],
+    daemon_connections: &HashMap<String, DaemonConnection>,
+    machines: Option<&BTreeSet<String>>,
+    mut machine_load: BTreeMap<String, usize>,
+) -> eyre::Result<()> {
+    let unplaced =
+        |node: &ResolvedNode| node.deploy.machine.is_empty() && !node.deploy.selector.is_empty();
+    for node in nodes.iter().filter(|n| !unplaced(n)) {
+        *machine_load.entry(node.deploy.machine.clone()).or_default() += 1;
+    }
+    // sorted by machine ID to break ties deterministically
+    let candidates: BTreeMap<_, _> = daemon_connections
+        .iter()
+        .filter(|(id, _)| machines.map_or(true, |machines| machines.contains(*id)))
+        .map(|(id, connection)| (id, &connection.properties))
+        .collect();
+
+    for node in nodes.iter_mut().filter(|n| unplaced(n)) {
+        let selector = &node.deploy.selector;
+        let formatted = selector
+            .iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect::<Vec<_>>()
+            .join(",");
+        let matching: Vec<_> = candidates
+            .iter()
+            .filter(|(_, properties)| properties.matches(selector))
+            .collect();
+        if matching.is_empty() {
+            bail!(
+                "no machine matches the selector `{formatted}` of node `{}`",
+                node.id
+            );
+        }
+        let load = |machine: &str| machine_load.get(machine).copied().unwrap_or_default();
+        let machine = matching
+            .into_iter()
+            .filter(|(id, properties)| properties.capacity.map_or(true, |c| load(id) < c))
+            .min_by_key(|(id, _)| load(id))
+            .map(|(id, _)| (*id).clone())
+            .ok_or_else(|| {
+                eyre!(
+                    "all machines matching the selector `{formatted}` of node `{}` \
+                    reached their capacity",
+                    node.id
+                )
+            })?;
+        tracing::info!("placing node `{}` on machine `{machine}`", node.id);
+        *machine_load.entry(machine.clone()).or_default() += 1;
+        node.deploy.machine = machine;
+    }
+    Ok(())
+}
+
+fn node_inputs(node: &ResolvedNode) -> Vec<NodeInput> {
+    node.kind
+        .run_config()
+        .inputs
+        .into_iter()
+        .map(|(input_id, input)| NodeInput {
+            node_id: node.id.clone(),
+            machine: node.deploy.machine.clone(),
+            input_id,
+            input,
+        })
+        .collect()
+}
+
+fn is_same_input(a: &NodeInput, b: &NodeInput) -> bool {
+    a.input_id == b.input_id && a.input == b.input
+}
+
+/// Serializes the node with all input mappings set to the same value, for
+/// checking whether anything besides the mappings changed.
+fn without_input_mappings(node: &ResolvedNode) -> eyre::Result<serde_json::Value> {
+    let mut node = node.clone();
+    let inputs: Vec<_> = match &mut node.kind {
+        CoreNodeKind::Custom(n) => n.run_config.inputs.values_mut().collect(),
+        CoreNodeKind::Runtime(n) => n
+            .operators
+            .iter_mut()
+            .flat_map(|op| op.config.inputs.values_mut())
+            .collect(),
+    };
+    for input in inputs {
+        input.mapping = InputMapping::Timer {
+            interval: Duration::ZERO,
+        };
+    }
+    serde_json::to_value(node).wrap_err("failed to serialize node")
+}
+
 async fn spawn_dataflow_on_machine(
     daemon_connections: &mut HashMap<String, DaemonConnection>,
     machine: &str,
@@ -87,10 +456,14 @@ async fn spawn_dataflow_on_machine(
     let daemon_connection = daemon_connections
         .get_mut(machine)
         .wrap_err_with(|| format!("no daemon connection for machine `{machine}`"))?;
-    tcp_send(&mut daemon_connection.stream, message)
+    daemon_connection
+        .stream
+        .send(message)
         .await
         .wrap_err("failed to send spawn message to daemon")?;
-    let reply_raw = tcp_receive(&mut daemon_connection.stream)
+    let reply_raw = daemon_connection
+        .stream
+        .receive()
         .await
         .wrap_err("failed to receive spawn reply from daemon")?;
     match serde_json::from_slice(&reply_raw)
@@ -0,0 +1,369 @@
+//! On-disk store for the coordinator state.
+//!
+//! Every dataflow is stored as a separate JSON file, either in the `running` or in the
+//! `archived` subdirectory of the state directory. Files are replaced atomically, so a
+//! crash of the coordinator never leaves a partially written file behind.
+//!
+//! Only the latest [`MAX_ARCHIVED_DATAFLOWS`] archived dataflows are kept, older
+//! archive files are deleted.
+
+use dora_core::descriptor::{MachineLostPolicy, ResolvedNode};
+use dora_message::daemon_to_coordinator::DataflowDaemonResult;
+use eyre::Context;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use uuid::Uuid;
+
+const RUNNING_DIR: &str = "running";
+const ARCHIVED_DIR: &str = "archived";
+
+/// Maximum number of archived dataflows that are kept in the state directory.
+const MAX_ARCHIVED_DATAFLOWS: usize = 1000;
+
+pub struct StateStore {
+    dir: PathBuf,
+    max_archived: usize,
+    /// UUIDs of the archive files. Dataflow UUIDs are v7, so they are ordered by the
+    /// start time of the dataflow.
+    archived: Mutex<BTreeSet<Uuid>>,
+}
+
+/// A dataflow that was running when the state was written.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PersistedRunningDataflow {
+    pub uuid: Uuid,
+    pub name: Option<String>,
+    pub machines: BTreeSet<String>,
+    pub nodes: Vec<ResolvedNode>,
+    #[serde(default)]
+    pub on_machine_lost: MachineLostPolicy,
+}
+
+/// A finished dataflow, together with the results reported by the daemons.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PersistedArchivedDataflow {
+    pub uuid: Uuid,
+    pub name: Option<String>,
+    pub nodes: Vec<ResolvedNode>,
+    pub results: BTreeMap<String, DataflowDaemonResult>,
+}
+
+#[derive(Debug, Default)]
+pub struct PersistedState {
+    pub running: Vec<PersistedRunningDataflow>,
+    pub archived: Vec<PersistedArchivedDataflow>,
+}
+
+impl StateStore {
+    pub fn open(dir: PathBuf) -> eyre::Result<Self> {
+        Self::open_with_limit(dir, MAX_ARCHIVED_DATAFLOWS)
+    }
+
+    fn open_with_limit(dir: PathBuf, max_archived: usize) -> eyre::Result<Self> {
+        for subdir in [RUNNING_DIR, ARCHIVED_DIR] {
+            let path = dir.join(subdir);
+            std::fs::create_dir_all(&path).wrap_err_with(|| {
+                format!("failed to create state directory `{}`", path.display())
+            })?;
+        }
+        Ok(Self {
+            dir,
+            max_archived,
+            archived: Mutex::new(BTreeSet::new()),
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Reads all stored dataflows.
+    ///
+    /// Files that cannot be parsed are skipped with a warning instead of failing the
+    /// coordinator start. Archived dataflows that exceed the retention limit are
+    /// deleted before loading.
+    pub fn load(&self) -> eyre::Result<PersistedState> {
+        let archived_dir = self.dir.join(ARCHIVED_DIR);
+        let mut archived = BTreeSet::new();
+        let read_dir = std::fs::read_dir(&archived_dir).wrap_err_with(|| {
+            format!(
+                "failed to read state directory `{}`",
+                archived_dir.display()
+            )
+        })?;
+        for entry in read_dir {
+            let path = entry
+                .wrap_err_with(|| format!("failed to read entry of `{}`", archived_dir.display()))?
+                .path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            if let Some(uuid) = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .and_then(|s| Uuid::parse_str(s).ok())
+            {
+                archived.insert(uuid);
+            }
+        }
+        for uuid in prune(&mut archived, self.max_archived) {
+            let path = self.file_path(ARCHIVED_DIR, uuid);
+            if let Err(err) = std::fs::remove_file(&path) {
+                tracing::warn!(
+                    "failed to remove old archive file `{}`: {err}",
+                    path.display()
+                );
+            }
+        }
+        *self.archived.lock().unwrap() = archived;
+
+        Ok(PersistedState {
+            running: load_dir(&self.dir.join(RUNNING_DIR))?,
+            archived: load_dir(&archived_dir)?,
+        })
+    }
+
+    pub async fn save_running(&self, dataflow: &PersistedRunningDataflow) -> eyre::Result<()> {
+        self.write(RUNNING_DIR, dataflow.uuid, dataflow).await
+    }
+
+    pub async fn remove_running(&self, uuid: Uuid) -> eyre::Result<()> {
+        let path = self.file_path(RUNNING_DIR, uuid);
+        match tokio::fs::remove_file(&path).await {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err).wrap_err_with(|| format!("failed to remove `{}`", path.display())),
+        }
+    }
+
+    /// Stores the given finished dataflow and removes its `running` entry.
+    ///
+    /// Returns the UUIDs of the oldest archived dataflows that were deleted to stay
+    /// within the retention limit.
+    pub async fn archive(&self, dataflow: &PersistedArchivedDataflow) -> eyre::Result<Vec<Uuid>> {
+        self.write(ARCHIVED_DIR, dataflow.uuid, dataflow).await?;
+        self.remove_running(dataflow.uuid).await?;
+
+        let pruned = {
+            let mut archived = self.archived.lock().unwrap();
+            archived.insert(dataflow.uuid);
+            prune(&mut archived, self.max_archived)
+        };
+        for &uuid in &pruned {
+            let path = self.file_path(ARCHIVED_DIR, uuid);
+            match tokio::fs::remove_file(&path).await {
+                Ok(()) => {}
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => {
+                    return Err(err)
+                        .wrap_err_with(|| format!("failed to remove `{}`", path.display()))
+                }
+            }
+        }
+        Ok(pruned)
+    }
+
+    async fn write(&self, subdir: &str, uuid: Uuid, value: &impl Serialize) -> eyre::Result<()> {
+        let path = self.file_path(subdir, uuid);
+        let tmp_path = path.with_extension("json.tmp");
+        let serialized =
+            serde_json::to_vec_pretty(value).wrap_err("failed to serialize dataflow state")?;
+        tokio::fs::write(&tmp_path, serialized)
+            .await
+            .wrap_err_with(|| format!("failed to write `{}`", tmp_path.display()))?;
+        tokio::fs::rename(&tmp_path, &path)
+            .await
+            .wrap_err_with(|| format!("failed to rename `{}`", tmp_path.display()))
+    }
+
+    fn file_path(&self, subdir: &str, uuid: Uuid) -> PathBuf {
+        self.dir.join(subdir).join(format!("{uuid}.json"))
+    }
+}
+
+/// Removes the oldest entries that exceed `max` and returns them.
+fn prune(archived: &mut BTreeSet<Uuid>, max: usize) -> Vec<Uuid> {
+    let mut pruned = Vec::new();
+    while archived.len() > max {
+        pruned.extend(archived.pop_first());
+    }
+    pruned
+}
+
+fn load_dir<T: DeserializeOwned>(dir: &Path) -> eyre::Result<Vec<T>> {
+    let mut entries = Vec::new();
+    let read_dir = std::fs::read_dir(dir)
+        .wrap_err_with(|| format!("failed to read state directory `{}`", dir.display()))?;
+    for entry in read_dir {
+        let path = entry
+            .wrap_err_with(|| format!("failed to read entry of `{}`", dir.display()))?
+            .path();
+        if path.extension().and_then(|e| e.to_str()) != Some("json") {
+            continue;
+        }
+        let parsed = std::fs::read(&path)
+            .wrap_err("failed to read file")
+            .and_then(|raw| serde_json::from_slice(&raw).context("failed to parse file"));
+        match parsed {
+            Ok(value) => entries.push(value),
+            Err(err) => {
+                let err = err.wrap_err(format!("ignoring state file `{}`", path.display()));
+                tracing::warn!("{err:?}");
+            }
+        }
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        PersistedArchivedDataflow, PersistedRunningDataflow, StateStore, ARCHIVED_DIR, RUNNING_DIR,
+    };
+    use std::{collections::BTreeMap, path::PathBuf};
+    use uuid::{Builder, Uuid};
+
+    fn state_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "dora-coordinator-state-test-{}-{name}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    /// Creates a dataflow UUID that was started `secs` seconds after the epoch.
+    fn uuid(secs: u64) -> Uuid {
+        Builder::from_unix_timestamp_millis(secs * 1000, &[0; 10]).into_uuid()
+    }
+
+    fn running(uuid: Uuid) -> PersistedRunningDataflow {
+        PersistedRunningDataflow {
+            uuid,
+            name: Some(format!("dataflow-{uuid}")),
+            machines: ["A".to_owned(), "B".to_owned()].into(),
+            nodes: Vec::new(),
+            on_machine_lost: Default::default(),
+        }
+    }
+
+    fn archived(uuid: Uuid) -> PersistedArchivedDataflow {
+        PersistedArchivedDataflow {
+            uuid,
+            name: None,
+            nodes: Vec::new(),
+            results: BTreeMap::new(),
+        }
+    }
+
+    fn sorted_uuids(uuids: impl IntoIterator<Item = Uuid>) -> Vec<Uuid> {
+        let mut uuids: Vec<_> = uuids.into_iter().collect();
+        uuids.sort();
+        uuids
+    }
+
+    #[tokio::test]
+    async fn reload() {
+        let dir = state_dir("reload");
+        let store = StateStore::open(dir.clone()).unwrap();
+        store.save_running(&running(uuid(1))).await.unwrap();
+        store.save_running(&running(uuid(2))).await.unwrap();
+        store.archive(&archived(uuid(1))).await.unwrap();
+
+        let state = StateStore::open(dir.clone()).unwrap().load().unwrap();
+        let [running] = state.running.as_slice() else {
+            panic!("expected one running dataflow, got {:?}", state.running);
+        };
+        assert_eq!(running.uuid, uuid(2));
+        assert_eq!(running.name, Some(format!("dataflow-{}", uuid(2))));
+        assert_eq!(running.machines, ["A".to_owned(), "B".to_owned()].into());
+        let [archived] = state.archived.as_slice() else {
+            panic!("expected one archived dataflow, got {:?}", state.archived);
+        };
+        assert_eq!(archived.uuid, uuid(1));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn corrupt_files() {
+        let dir = state_dir("corrupt");
+        let store = StateStore::open(dir.clone()).unwrap();
+        store.save_running(&running(uuid(1))).await.unwrap();
+        store.archive(&archived(uuid(2))).await.unwrap();
+
+        // a truncated file and a file that is not a dataflow state
+        let serialized = serde_json::to_vec(&running(uuid(3))).unwrap();
+        std::fs::write(
+            dir.join(RUNNING_DIR).join(format!("{}.json", uuid(3))),
+            &serialized[..serialized.len() / 2],
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join(ARCHIVED_DIR).join(format!("{}.json", uuid(4))),
+            b"[1, 2, 3]",
+        )
+        .unwrap();
+        // leftover of an interrupted write
+        std::fs::write(
+            dir.join(RUNNING_DIR).join(format!("{}.json.tmp", uuid(5))),
+            b"{",
+        )
+        .unwrap();
+
+        let state = StateStore::open(dir.clone()).unwrap().load().unwrap();
+        assert_eq!(
+            sorted_uuids(state.running.iter().map(|d| d.uuid)),
+            [uuid(1)]
+        );
+        assert_eq!(
+            sorted_uuids(state.archived.iter().map(|d| d.uuid)),
+            [uuid(2)]
+        );
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn archive_retention() {
+        let dir = state_dir("retention");
+        let store = StateStore::open_with_limit(dir.clone(), 2).unwrap();
+        store.load().unwrap();
+        // finish order differs from start order, the oldest started dataflow is pruned
+        assert_eq!(store.archive(&archived(uuid(2))).await.unwrap(), []);
+        assert_eq!(store.archive(&archived(uuid(1))).await.unwrap(), []);
+        assert_eq!(store.archive(&archived(uuid(3))).await.unwrap(), [uuid(1)]);
+        assert_eq!(store.archive(&archived(uuid(4))).await.unwrap(), [uuid(2)]);
+        for secs in [1, 2] {
+            let path = dir.join(ARCHIVED_DIR).join(format!("{}.json", uuid(secs)));
+            assert!(!path.exists());
+        }
+
+        let state = StateStore::open_with_limit(dir.clone(), 2)
+            .unwrap()
+            .load()
+            .unwrap();
+        assert_eq!(
+            sorted_uuids(state.archived.iter().map(|d| d.uuid)),
+            [uuid(3), uuid(4)]
+        );
+
+        // files exceeding a lower limit are deleted on load
+        let state = StateStore::open_with_limit(dir.clone(), 1)
+            .unwrap()
+            .load()
+            .unwrap();
+        assert_eq!(
+            sorted_uuids(state.archived.iter().map(|d| d.uuid)),
+            [uuid(4)]
+        );
+        let files = std::fs::read_dir(dir.join(ARCHIVED_DIR)).unwrap().count();
+        assert_eq!(files, 1);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
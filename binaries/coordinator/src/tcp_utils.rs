@@ -1,9 +1,16 @@
+use dora_security::MaybeTlsStream;
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
-    net::TcpStream,
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadHalf},
+    sync::mpsc,
 };
 
-pub async fn tcp_send(connection: &mut TcpStream, message: &[u8]) -> std::io::Result<()> {
+/// Maximum number of messages that wait to be written to a daemon connection.
+const DAEMON_WRITE_QUEUE_SIZE: usize = 64;
+
+pub async fn tcp_send(
+    connection: &mut (impl AsyncWrite + Unpin),
+    message: &[u8],
+) -> std::io::Result<()> {
     let len_raw = (message.len() as u64).to_le_bytes();
     connection.write_all(&len_raw).await?;
     connection.write_all(message).await?;
@@ -11,7 +18,7 @@ pub async fn tcp_send(connection: &mut TcpStream, message: &[u8]) -> std::io::Re
     Ok(())
 }
 
-pub async fn tcp_receive(connection: &mut TcpStream) -> std::io::Result<Vec<u8>> {
+pub async fn tcp_receive(connection: &mut (impl AsyncRead + Unpin)) -> std::io::Result<Vec<u8>> {
     let reply_len = {
         let mut raw = [0; 8];
         connection.read_exact(&mut raw).await?;
@@ -21,3 +28,82 @@ pub async fn tcp_receive(connection: &mut TcpStream) -> std::io::Result<Vec<u8>>
     connection.read_exact(&mut reply).await?;
     Ok(reply)
 }
+
+/// Like [`tcp_receive`], but fails with [`std::io::ErrorKind::InvalidData`] instead of
+/// allocating a buffer if the announced message is larger than `max_len`.
+pub async fn tcp_receive_bounded(
+    connection: &mut (impl AsyncRead + Unpin),
+    max_len: usize,
+) -> std::io::Result<Vec<u8>> {
+    let len = {
+        let mut raw = [0; 8];
+        connection.read_exact(&mut raw).await?;
+        u64::from_le_bytes(raw)
+    };
+    if len > max_len as u64 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("message of {len} bytes exceeds the limit of {max_len} bytes"),
+        ));
+    }
+    let mut message = vec![0; len as usize];
+    connection.read_exact(&mut message).await?;
+    Ok(message)
+}
+
+/// Connection to a daemon whose messages are written by a dedicated task.
+///
+/// Messages are written as a whole, so a slow or stuck daemon can't leave partially
+/// written messages on the stream. Messages that may be lost, such as relayed events
+/// and heartbeats, are dropped through [`DaemonStream::try_send`] when the write queue
+/// is full.
+pub struct DaemonStream {
+    reader: ReadHalf<MaybeTlsStream>,
+    writer: mpsc::Sender<Vec<u8>>,
+}
+
+impl DaemonStream {
+    pub fn new(stream: MaybeTlsStream) -> Self {
+        let (reader, mut writer) = tokio::io::split(stream);
+        let (tx, mut rx) = mpsc::channel::<Vec<u8>>(DAEMON_WRITE_QUEUE_SIZE);
+        tokio::spawn(async move {
+            while let Some(message) = rx.recv().await {
+                if let Err(err) = tcp_send(&mut writer, &message).await {
+                    tracing::warn!("failed to write to daemon connection: {err}");
+                    break;
+                }
+            }
+        });
+        Self { reader, writer: tx }
+    }
+
+    /// Queues the message for writing, waiting for space in the queue if necessary.
+    pub async fn send(&mut self, message: &[u8]) -> std::io::Result<()> {
+        self.writer
+            .send(message.to_vec())
+            .await
+            .map_err(|_| closed_error())
+    }
+
+    /// Queues the message for writing, fails instead of waiting if the queue is full.
+    pub fn try_send(&mut self, message: Vec<u8>) -> std::io::Result<()> {
+        self.writer.try_send(message).map_err(|err| match err {
+            mpsc::error::TrySendError::Full(_) => std::io::Error::new(
+                std::io::ErrorKind::WouldBlock,
+                "write queue of daemon connection is full",
+            ),
+            mpsc::error::TrySendError::Closed(_) => closed_error(),
+        })
+    }
+
+    pub async fn receive(&mut self) -> std::io::Result<Vec<u8>> {
+        tcp_receive(&mut self.reader).await
+    }
+}
+
+fn closed_error() -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::BrokenPipe,
+        "daemon connection was closed",
+    )
+}
@@ -0,0 +1,258 @@
+//! Cache for the node artifacts that the CLI uploads through `dora start --upload`.
+//!
+//! Artifacts are stored by content hash as `<dir>/<hash>/<file name>`, so that unchanged
+//! files are only uploaded once. Cached files are hashed again before they are used, so
+//! that modified files are replaced instead of run.
+//!
+//! The cache is never cleaned up automatically. Old artifacts can be removed by deleting
+//! the cache directory while no dataflow is running.
+
+use dora_core::descriptor::{CoreNodeKind, OperatorSource, ResolvedNode};
+use dora_message::common::{ArtifactData, NodeArtifact};
+use eyre::{bail, eyre, Context, ContextCompat};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+use uuid::{NoContext, Timestamp, Uuid};
+
+pub struct ArtifactCache {
+    dir: PathBuf,
+}
+
+impl ArtifactCache {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// Returns the per-user cache directory, e.g. `~/.cache/dora/artifacts` on Linux.
+    pub fn default_dir() -> PathBuf {
+        let cache_dir = if cfg!(windows) {
+            std::env::var_os("LOCALAPPDATA").map(PathBuf::from)
+        } else {
+            std::env::var_os("XDG_CACHE_HOME")
+                .map(PathBuf::from)
+                .filter(|dir| dir.is_absolute())
+                .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+        };
+        match cache_dir {
+            Some(dir) => dir.join("dora").join("artifacts"),
+            // the ownership of the directory is checked before it is used
+            None => std::env::temp_dir().join("dora-artifacts"),
+        }
+    }
+
+    /// Returns the given hashes that are not in the cache.
+    pub async fn missing(&self, hashes: BTreeSet<String>) -> BTreeSet<String> {
+        if let Err(err) = self.prepare_dir() {
+            tracing::warn!("{err:?}");
+            return hashes;
+        }
+        let mut missing = BTreeSet::new();
+        for hash in hashes {
+            if !is_valid_hash(&hash) || self.verified_file(&hash).await.is_none() {
+                missing.insert(hash);
+            }
+        }
+        missing
+    }
+
+    /// Stores the artifacts of the given node and points its paths to the cached files.
+    ///
+    /// The `data` must contain the content of all artifacts that are not cached yet.
+    pub async fn apply(
+        &self,
+        node: &mut ResolvedNode,
+        artifacts: &[NodeArtifact],
+        data: &BTreeMap<&str, &[u8]>,
+    ) -> eyre::Result<()> {
+        for artifact in artifacts {
+            let path = self.file(artifact, data).await.wrap_err_with(|| {
+                format!(
+                    "failed to cache artifact `{}` ({})",
+                    artifact.file_name, artifact.hash
+                )
+            })?;
+            let source = match (&mut node.kind, &artifact.operator) {
+                (CoreNodeKind::Custom(custom), None) => &mut custom.source,
+                (CoreNodeKind::Runtime(runtime), Some(operator_id)) => {
+                    let operator = runtime
+                        .operators
+                        .iter_mut()
+                        .find(|o| &o.id == operator_id)
+                        .ok_or_else(|| eyre!("node has no operator `{operator_id}`"))?;
+                    match &mut operator.config.source {
+                        OperatorSource::SharedLibrary(source) | OperatorSource::Wasm(source) => {
+                            source
+                        }
+                        OperatorSource::Python(python) => &mut python.source,
+                    }
+                }
+                _ => bail!(
+                    "artifact `{}` does not match the node kind",
+                    artifact.file_name
+                ),
+            };
+            *source = cached_source(source, &path)?;
+        }
+        Ok(())
+    }
+
+    /// Makes sure that the artifact is cached under its file name and returns its path.
+    async fn file(
+        &self,
+        artifact: &NodeArtifact,
+        data: &BTreeMap<&str, &[u8]>,
+    ) -> eyre::Result<PathBuf> {
+        if !is_valid_hash(&artifact.hash) {
+            bail!("invalid artifact hash");
+        }
+        if Path::new(&artifact.file_name).file_name() != Some(artifact.file_name.as_ref()) {
+            bail!("artifact file name must not contain a path");
+        }
+        self.prepare_dir()?;
+        let dir = self.dir.join(&artifact.hash);
+        let path = dir.join(&artifact.file_name);
+        if path.is_file() && verify(&path, &artifact.hash).await {
+            return Ok(path);
+        }
+
+        let content = match data.get(artifact.hash.as_str()) {
+            Some(data) => Cow::Borrowed(*data),
+            // the same content is already cached under a different file name
+            None => match self.verified_file(&artifact.hash).await {
+                Some(existing) => Cow::Owned(
+                    tokio::fs::read(&existing)
+                        .await
+                        .wrap_err_with(|| format!("failed to read `{}`", existing.display()))?,
+                ),
+                None => bail!("artifact is neither cached nor uploaded"),
+            },
+        };
+        if ArtifactData::hash(&content) != artifact.hash {
+            bail!("content does not match the artifact hash");
+        }
+
+        // write to a temporary file first, so that interrupted writes don't leave
+        // incomplete files in the cache
+        let tmp_dir = self.dir.join(".tmp");
+        tokio::fs::create_dir_all(&tmp_dir)
+            .await
+            .wrap_err_with(|| format!("failed to create `{}`", tmp_dir.display()))?;
+        let tmp_path = tmp_dir.join(Uuid::new_v7(Timestamp::now(NoContext)).to_string());
+        tokio::fs::write(&tmp_path, &content)
+            .await
+            .wrap_err_with(|| format!("failed to write `{}`", tmp_path.display()))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            tokio::fs::set_permissions(&tmp_path, std::fs::Permissions::from_mode(0o755))
+                .await
+                .wrap_err("failed to make artifact executable")?;
+        }
+        tokio::fs::create_dir_all(&dir)
+            .await
+            .wrap_err_with(|| format!("failed to create `{}`", dir.display()))?;
+        tokio::fs::rename(&tmp_path, &path)
+            .await
+            .wrap_err_with(|| format!("failed to move artifact to `{}`", path.display()))?;
+        tracing::info!("cached artifact `{}`", path.display());
+
+        Ok(path)
+    }
+
+    /// Returns a cached file with the given hash, removing files whose content was
+    /// modified.
+    async fn verified_file(&self, hash: &str) -> Option<PathBuf> {
+        let mut entries = tokio::fs::read_dir(self.dir.join(hash)).await.ok()?;
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let path = entry.path();
+            if path.is_file() && verify(&path, hash).await {
+                return Some(path);
+            }
+        }
+        None
+    }
+
+    /// Creates the cache directory if it doesn't exist yet and makes sure that no other
+    /// user can modify it.
+    fn prepare_dir(&self) -> eyre::Result<()> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+
+            std::fs::DirBuilder::new()
+                .recursive(true)
+                .mode(0o700)
+                .create(&self.dir)
+                .wrap_err_with(|| format!("failed to create `{}`", self.dir.display()))?;
+            let metadata = std::fs::symlink_metadata(&self.dir)
+                .wrap_err_with(|| format!("failed to read metadata of `{}`", self.dir.display()))?;
+            if !metadata.is_dir() {
+                bail!("artifact cache `{}` is not a directory", self.dir.display());
+            }
+            // SAFETY: `geteuid` has no preconditions and always succeeds
+            if metadata.uid() != unsafe { libc::geteuid() } {
+                bail!(
+                    "artifact cache `{}` is owned by a different user",
+                    self.dir.display()
+                );
+            }
+            if metadata.mode() & 0o077 != 0 {
+                std::fs::set_permissions(&self.dir, std::fs::Permissions::from_mode(0o700))
+                    .wrap_err_with(|| {
+                        format!("failed to restrict permissions of `{}`", self.dir.display())
+                    })?;
+            }
+        }
+        #[cfg(not(unix))]
+        std::fs::create_dir_all(&self.dir)
+            .wrap_err_with(|| format!("failed to create `{}`", self.dir.display()))?;
+        Ok(())
+    }
+}
+
+/// Checks that the content of the cached file matches the hash, and removes the file
+/// otherwise.
+async fn verify(path: &Path, hash: &str) -> bool {
+    let file = path.to_owned();
+    let matches = tokio::task::spawn_blocking(move || {
+        std::fs::read(file).map(|content| ArtifactData::hash(&content))
+    })
+    .await
+    .is_ok_and(|actual| actual.is_ok_and(|actual| actual == hash));
+    if !matches {
+        tracing::warn!(
+            "removing cached artifact `{}` because its content was modified",
+            path.display()
+        );
+        let _ = tokio::fs::remove_file(path).await;
+    }
+    matches
+}
+
+/// Replaces the directory of the given descriptor path with the directory of the
+/// cached file.
+///
+/// The file name of the descriptor path is kept because it might differ from the
+/// cached file name, e.g. for shared libraries that are referenced without prefix
+/// and extension.
+fn cached_source(source: &str, cached_path: &Path) -> eyre::Result<String> {
+    let file_name = Path::new(source)
+        .file_name()
+        .wrap_err_with(|| format!("path `{source}` has no file name"))?;
+    let dir = cached_path
+        .parent()
+        .context("cached artifact has no parent dir")?;
+    dir.join(file_name)
+        .to_str()
+        .map(ToOwned::to_owned)
+        .context("artifact cache path is not valid UTF8")
+}
+
+/// Checks that the hash is a hex-encoded SHA-256 hash, so that it's safe to use it as
+/// directory name.
+fn is_valid_hash(hash: &str) -> bool {
+    hash.len() == 64 && hash.bytes().all(|b| b.is_ascii_hexdigit())
+}
@@ -0,0 +1,76 @@
+//! Flow control for inputs with `overflow: block`.
+//!
+//! Every blocking input has a semaphore whose permits are the free slots of its
+//! queue. A permit is taken before an output is forwarded to the input and given
+//! back once the input event was passed to the receiving node, or could not be
+//! delivered.
+//!
+//! The semaphores are held by the daemon of the sending node, so that only the
+//! sender waits. For inputs on other machines, the receiving daemon reports the
+//! freed slots through `InterDaemonEvent::InputCredits`.
+
+use crate::OutputId;
+use dora_message::DataflowId;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::Semaphore;
+
+/// Queue credits of the blocking receivers, by the output they are mapped to.
+type BlockingReceivers = HashMap<(DataflowId, OutputId), Vec<Arc<Semaphore>>>;
+
+#[derive(Debug, Clone, Default)]
+pub struct Backpressure {
+    blocking_receivers: Arc<Mutex<BlockingReceivers>>,
+}
+
+impl Backpressure {
+    /// Registers the queue credits of an input with `overflow: block` that is mapped
+    /// to the given local output.
+    pub fn register(&self, dataflow_id: DataflowId, output: OutputId, credits: Arc<Semaphore>) {
+        self.blocking_receivers
+            .lock()
+            .unwrap()
+            .entry((dataflow_id, output))
+            .or_default()
+            .push(credits);
+    }
+
+    /// Removes the credits of an input that is no longer mapped to the given output.
+    ///
+    /// The credits are closed, so that senders that are currently waiting for them
+    /// continue.
+    pub fn unregister(&self, dataflow_id: DataflowId, output: OutputId, credits: &Arc<Semaphore>) {
+        credits.close();
+        let mut receivers = self.blocking_receivers.lock().unwrap();
+        if let Some(list) = receivers.get_mut(&(dataflow_id, output)) {
+            list.retain(|c| !Arc::ptr_eq(c, credits));
+        }
+    }
+
+    pub fn remove_dataflow(&self, dataflow_id: &DataflowId) {
+        self.blocking_receivers
+            .lock()
+            .unwrap()
+            .retain(|(id, _), _| id != dataflow_id);
+    }
+
+    /// Waits until all receivers of the given output that use `overflow: block` have
+    /// a free slot in their queue and reserves it.
+    pub async fn acquire(&self, dataflow_id: DataflowId, output: OutputId) {
+        let credits = self
+            .blocking_receivers
+            .lock()
+            .unwrap()
+            .get(&(dataflow_id, output))
+            .cloned()
+            .unwrap_or_default();
+        for credits in credits {
+            if let Ok(permit) = credits.acquire().await {
+                // given back by the receiving side through `add_permits`
+                permit.forget();
+            }
+        }
+    }
+}
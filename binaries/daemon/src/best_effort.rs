@@ -0,0 +1,497 @@
+//! Best-effort transport of inter-daemon events through UDP datagrams.
+//!
+//! Every event is split into fragments that fit into a single datagram. The receiver
+//! reassembles the fragments and drops events whose fragments do not all arrive within
+//! [`REASSEMBLY_TIMEOUT`], so lost datagrams never delay later events.
+
+use dora_message::common::BestEffortStats;
+use eyre::{bail, eyre, Context};
+use std::{
+    collections::{btree_map, BTreeMap, HashMap, VecDeque},
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::net::UdpSocket;
+
+/// Maximum size of an IPv4 datagram, chosen so that it fits into a single Ethernet frame
+/// (1500 byte MTU minus the IPv4 and UDP headers).
+const MAX_DATAGRAM_SIZE_IPV4: usize = 1472;
+/// Maximum size of an IPv6 datagram (1500 byte MTU minus the IPv6 and UDP headers).
+const MAX_DATAGRAM_SIZE_IPV6: usize = 1452;
+/// Marks the datagrams of this protocol, including its version.
+const MAGIC: [u8; 4] = *b"dbe1";
+/// Time after which incomplete events are dropped.
+pub const REASSEMBLY_TIMEOUT: Duration = Duration::from_secs(1);
+/// Maximum number of incomplete events per sender, the oldest one is dropped first.
+const MAX_INCOMPLETE_PER_SENDER: usize = 16;
+/// Maximum number of incomplete events of all senders, the oldest one is dropped first.
+///
+/// The sender ID of a datagram is not authenticated, so the per-sender limit alone
+/// doesn't bound the memory usage.
+const MAX_INCOMPLETE: usize = 64;
+/// Maximum number of received bytes of all incomplete events, which is larger than the
+/// largest event that can be fragmented.
+const MAX_INCOMPLETE_BYTES: usize = 128 * 1024 * 1024;
+/// Maximum number of senders whose finished event IDs and counters are remembered, the
+/// least recently active one is forgotten first.
+const MAX_SENDERS: usize = 256;
+/// Number of finished event IDs that are remembered per sender to ignore duplicated
+/// or late fragments.
+const FINISHED_HISTORY: usize = 64;
+
+/// Counters of the events received through best-effort transport, per sending machine.
+#[derive(Debug, Clone, Default)]
+pub struct ReceiveCounters(Arc<Mutex<BTreeMap<String, Counters>>>);
+
+#[derive(Debug)]
+struct Counters {
+    received: u64,
+    incomplete: u64,
+    last_update: Instant,
+}
+
+impl ReceiveCounters {
+    pub fn stats(&self, machine_id: &str) -> Vec<BestEffortStats> {
+        self.0
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(peer, counters)| BestEffortStats {
+                machine_id: machine_id.to_owned(),
+                peer_machine_id: peer.clone(),
+                received: counters.received,
+                incomplete: counters.incomplete,
+            })
+            .collect()
+    }
+
+    fn record(&self, sender: &str, complete: bool) {
+        let mut counters = self.0.lock().unwrap();
+        if !counters.contains_key(sender) && counters.len() >= MAX_SENDERS {
+            let least_recent = counters
+                .iter()
+                .min_by_key(|(_, c)| c.last_update)
+                .map(|(sender, _)| sender.clone());
+            if let Some(least_recent) = least_recent {
+                counters.remove(&least_recent);
+            }
+        }
+        let counters = counters
+            .entry(sender.to_owned())
+            .or_insert_with(|| Counters {
+                received: 0,
+                incomplete: 0,
+                last_update: Instant::now(),
+            });
+        counters.last_update = Instant::now();
+        if complete {
+            counters.received += 1;
+        } else {
+            counters.incomplete += 1;
+        }
+    }
+}
+
+/// Splits events into datagrams and sends them to other machines.
+pub struct BestEffortSender {
+    socket: UdpSocket,
+    machine_id: String,
+    next_message_id: u64,
+    max_datagram_size: usize,
+}
+
+impl BestEffortSender {
+    /// Binds a UDP socket on an ephemeral port.
+    pub async fn bind(machine_id: String, ipv6: bool) -> eyre::Result<Self> {
+        if machine_id.len() > u8::MAX as usize {
+            bail!("machine ID `{machine_id}` is too long for best-effort transport");
+        }
+        let bind = if ipv6 {
+            SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0))
+        } else {
+            SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0))
+        };
+        let socket = UdpSocket::bind(bind)
+            .await
+            .wrap_err("failed to bind UDP socket")?;
+        let max_datagram_size = if socket
+            .local_addr()
+            .wrap_err("failed to get local address of UDP socket")?
+            .is_ipv6()
+        {
+            MAX_DATAGRAM_SIZE_IPV6
+        } else {
+            MAX_DATAGRAM_SIZE_IPV4
+        };
+        // start with the current time to avoid colliding with the IDs that were used
+        // before a restart of this daemon
+        let next_message_id = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default();
+        Ok(Self {
+            socket,
+            machine_id,
+            next_message_id,
+            max_datagram_size,
+        })
+    }
+
+    /// Splits the given serialized event into datagrams.
+    pub fn fragment(&mut self, message: &[u8]) -> eyre::Result<Vec<Vec<u8>>> {
+        let message_id = self.next_message_id;
+        self.next_message_id = self.next_message_id.wrapping_add(1);
+        fragment(
+            &self.machine_id,
+            message_id,
+            message,
+            self.max_datagram_size,
+        )
+    }
+
+    pub async fn send(&self, target: SocketAddr, datagrams: &[Vec<u8>]) -> std::io::Result<()> {
+        for datagram in datagrams {
+            self.socket.send_to(datagram, target).await?;
+        }
+        Ok(())
+    }
+}
+
+fn fragment(
+    machine_id: &str,
+    message_id: u64,
+    message: &[u8],
+    max_datagram_size: usize,
+) -> eyre::Result<Vec<Vec<u8>>> {
+    let header_len = MAGIC.len() + 1 + machine_id.len() + 8 + 2 + 2;
+    let chunk_size = max_datagram_size - header_len;
+    let count = message.len().div_ceil(chunk_size).max(1);
+    let count: u16 = count.try_into().map_err(|_| {
+        eyre!(
+            "message of {} bytes is too large for best-effort transport",
+            message.len()
+        )
+    })?;
+    let chunks = message
+        .chunks(chunk_size)
+        .chain(message.is_empty().then_some(&[][..]));
+    Ok(chunks
+        .enumerate()
+        .map(|(index, chunk)| {
+            let mut datagram = Vec::with_capacity(header_len + chunk.len());
+            datagram.extend_from_slice(&MAGIC);
+            datagram.push(machine_id.len() as u8);
+            datagram.extend_from_slice(machine_id.as_bytes());
+            datagram.extend_from_slice(&message_id.to_be_bytes());
+            datagram.extend_from_slice(&(index as u16).to_be_bytes());
+            datagram.extend_from_slice(&count.to_be_bytes());
+            datagram.extend_from_slice(chunk);
+            datagram
+        })
+        .collect())
+}
+
+/// Reassembles the events of received datagrams.
+pub struct Reassembler {
+    incomplete: HashMap<(String, u64), PartialMessage>,
+    /// Number of received bytes of all incomplete events.
+    incomplete_bytes: usize,
+    /// Recently completed or dropped events per sender.
+    finished: HashMap<String, FinishedMessages>,
+    counters: ReceiveCounters,
+}
+
+struct PartialMessage {
+    /// Received fragments by index, the event is complete once there are `count`.
+    fragments: BTreeMap<u16, Vec<u8>>,
+    count: u16,
+    started: Instant,
+}
+
+struct FinishedMessages {
+    ids: VecDeque<u64>,
+    last_update: Instant,
+}
+
+impl Reassembler {
+    pub fn new(counters: ReceiveCounters) -> Self {
+        Self {
+            incomplete: HashMap::new(),
+            incomplete_bytes: 0,
+            finished: HashMap::new(),
+            counters,
+        }
+    }
+
+    /// Adds the given datagram, returns the serialized event once all of its fragments
+    /// were received.
+    pub fn push(&mut self, datagram: &[u8]) -> eyre::Result<Option<Vec<u8>>> {
+        let (sender, message_id, index, count, chunk) = parse(datagram)?;
+        let key = (sender.to_owned(), message_id);
+        if self
+            .finished
+            .get(sender)
+            .is_some_and(|finished| finished.ids.contains(&message_id))
+        {
+            return Ok(None);
+        }
+
+        if !self.incomplete.contains_key(&key) {
+            self.drop_excess(sender);
+        }
+        let partial = self
+            .incomplete
+            .entry(key.clone())
+            .or_insert(PartialMessage {
+                fragments: BTreeMap::new(),
+                count,
+                started: Instant::now(),
+            });
+        if partial.count != count {
+            bail!("fragment count {count} does not match previous fragments");
+        }
+        if let btree_map::Entry::Vacant(entry) = partial.fragments.entry(index) {
+            entry.insert(chunk.to_owned());
+            self.incomplete_bytes += chunk.len();
+        }
+        if partial.fragments.len() < usize::from(partial.count) {
+            while self.incomplete_bytes > MAX_INCOMPLETE_BYTES {
+                self.drop_oldest(|_| true);
+            }
+            return Ok(None);
+        }
+
+        let partial = self.remove(&key);
+        self.finish(&key, true);
+        Ok(Some(partial.fragments.into_values().flatten().collect()))
+    }
+
+    /// Drops the events that did not receive all of their fragments in time.
+    pub fn drop_expired(&mut self) {
+        let expired: Vec<_> = self
+            .incomplete
+            .iter()
+            .filter(|(_, partial)| partial.started.elapsed() > REASSEMBLY_TIMEOUT)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in expired {
+            self.remove(&key);
+            self.finish(&key, false);
+        }
+    }
+
+    /// Makes room for a new incomplete event of the given sender by dropping the oldest
+    /// event of the sender or of all senders.
+    fn drop_excess(&mut self, sender: &str) {
+        let pending = self.incomplete.keys().filter(|(s, _)| s == sender).count();
+        if pending >= MAX_INCOMPLETE_PER_SENDER {
+            self.drop_oldest(|s| s == sender);
+        }
+        if self.incomplete.len() >= MAX_INCOMPLETE {
+            self.drop_oldest(|_| true);
+        }
+    }
+
+    /// Drops the oldest incomplete event of the senders that match the filter.
+    fn drop_oldest(&mut self, filter: impl Fn(&str) -> bool) {
+        let oldest = self
+            .incomplete
+            .iter()
+            .filter(|((sender, _), _)| filter(sender))
+            .min_by_key(|(_, partial)| partial.started)
+            .map(|(key, _)| key.clone());
+        if let Some(oldest) = oldest {
+            self.remove(&oldest);
+            self.finish(&oldest, false);
+        }
+    }
+
+    fn remove(&mut self, key: &(String, u64)) -> PartialMessage {
+        let partial = self.incomplete.remove(key).unwrap();
+        self.incomplete_bytes -= partial.fragments.values().map(Vec::len).sum::<usize>();
+        partial
+    }
+
+    fn finish(&mut self, (sender, message_id): &(String, u64), complete: bool) {
+        if !complete {
+            tracing::debug!("dropping incomplete best-effort message from machine `{sender}`");
+        }
+        self.counters.record(sender, complete);
+        if !self.finished.contains_key(sender) && self.finished.len() >= MAX_SENDERS {
+            let least_recent = self
+                .finished
+                .iter()
+                .min_by_key(|(_, f)| f.last_update)
+                .map(|(sender, _)| sender.clone());
+            if let Some(least_recent) = least_recent {
+                self.finished.remove(&least_recent);
+            }
+        }
+        let finished = self
+            .finished
+            .entry(sender.clone())
+            .or_insert_with(|| FinishedMessages {
+                ids: VecDeque::new(),
+                last_update: Instant::now(),
+            });
+        finished.last_update = Instant::now();
+        if finished.ids.len() >= FINISHED_HISTORY {
+            finished.ids.pop_front();
+        }
+        finished.ids.push_back(*message_id);
+    }
+}
+
+fn parse(datagram: &[u8]) -> eyre::Result<(&str, u64, u16, u16, &[u8])> {
+    let rest = datagram
+        .strip_prefix(&MAGIC)
+        .ok_or_else(|| eyre!("not a best-effort datagram"))?;
+    let (&sender_len, rest) = rest
+        .split_first()
+        .ok_or_else(|| eyre!("datagram too short"))?;
+    let sender_len = usize::from(sender_len);
+    if rest.len() < sender_len + 12 {
+        bail!("datagram too short");
+    }
+    let (sender, rest) = rest.split_at(sender_len);
+    let sender = std::str::from_utf8(sender).wrap_err("invalid sender machine ID")?;
+    let (message_id, rest) = rest.split_at(8);
+    let (index, rest) = rest.split_at(2);
+    let (count, chunk) = rest.split_at(2);
+    let message_id = u64::from_be_bytes(message_id.try_into().unwrap());
+    let index = u16::from_be_bytes(index.try_into().unwrap());
+    let count = u16::from_be_bytes(count.try_into().unwrap());
+    if index >= count {
+        bail!("fragment index {index} out of bounds");
+    }
+    Ok((sender, message_id, index, count, chunk))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: usize = MAX_DATAGRAM_SIZE_IPV4;
+
+    fn reassembler() -> Reassembler {
+        Reassembler::new(ReceiveCounters::default())
+    }
+
+    fn message(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        for len in [0, 10, SIZE, SIZE * 5 + 7] {
+            let message = message(len);
+            let datagrams = fragment("a", 1, &message, SIZE).unwrap();
+            assert!(datagrams.iter().all(|d| d.len() <= SIZE));
+
+            let mut reassembler = reassembler();
+            let (last, first) = datagrams.split_last().unwrap();
+            for datagram in first {
+                assert_eq!(reassembler.push(datagram).unwrap(), None);
+            }
+            assert_eq!(reassembler.push(last).unwrap(), Some(message));
+        }
+        assert!(fragment("a", 1, &message(SIZE * usize::from(u16::MAX)), SIZE).is_err());
+    }
+
+    #[test]
+    fn ipv6_datagram_size() {
+        let datagrams = fragment("a", 1, &message(10_000), MAX_DATAGRAM_SIZE_IPV6).unwrap();
+        assert!(datagrams.iter().all(|d| d.len() <= MAX_DATAGRAM_SIZE_IPV6));
+    }
+
+    #[test]
+    fn out_of_order_and_duplicates() {
+        let message = message(SIZE * 3);
+        let datagrams = fragment("a", 1, &message, SIZE).unwrap();
+        assert_eq!(datagrams.len(), 4);
+
+        let mut reassembler = reassembler();
+        for index in [3, 1, 1, 0, 3] {
+            assert_eq!(reassembler.push(&datagrams[index]).unwrap(), None);
+        }
+        assert_eq!(reassembler.push(&datagrams[2]).unwrap(), Some(message));
+        // late duplicates of a finished event are ignored
+        assert_eq!(reassembler.push(&datagrams[0]).unwrap(), None);
+        assert!(reassembler.incomplete.is_empty());
+        assert_eq!(reassembler.incomplete_bytes, 0);
+    }
+
+    #[test]
+    fn expiry() {
+        let counters = ReceiveCounters::default();
+        let mut reassembler = Reassembler::new(counters.clone());
+        let datagrams = fragment("a", 1, &message(SIZE * 2), SIZE).unwrap();
+        reassembler.push(&datagrams[0]).unwrap();
+
+        reassembler.drop_expired();
+        assert_eq!(reassembler.incomplete.len(), 1);
+
+        for partial in reassembler.incomplete.values_mut() {
+            partial.started -= REASSEMBLY_TIMEOUT * 2;
+        }
+        reassembler.drop_expired();
+        assert!(reassembler.incomplete.is_empty());
+        assert_eq!(reassembler.incomplete_bytes, 0);
+        assert_eq!(counters.stats("b")[0].incomplete, 1);
+
+        // the remaining fragments of the dropped event are ignored
+        assert_eq!(reassembler.push(&datagrams[1]).unwrap(), None);
+        assert!(reassembler.incomplete.is_empty());
+    }
+
+    #[test]
+    fn excess() {
+        let mut reassembler = reassembler();
+        let first =
+            |sender: &str, id| fragment(sender, id, &message(SIZE * 2), SIZE).unwrap()[0].clone();
+
+        for id in 0..MAX_INCOMPLETE_PER_SENDER as u64 + 1 {
+            reassembler.push(&first("a", id)).unwrap();
+        }
+        assert_eq!(reassembler.incomplete.len(), MAX_INCOMPLETE_PER_SENDER);
+        assert!(!reassembler.incomplete.contains_key(&("a".to_owned(), 0)));
+
+        // spoofed sender IDs can't grow the state without bound
+        for id in 0..MAX_INCOMPLETE as u64 * 2 {
+            reassembler.push(&first(&format!("s{id}"), id)).unwrap();
+        }
+        assert_eq!(reassembler.incomplete.len(), MAX_INCOMPLETE);
+        for id in 0..MAX_SENDERS as u64 * 2 {
+            let datagrams = fragment(&format!("t{id}"), id, &[], SIZE).unwrap();
+            reassembler.push(&datagrams[0]).unwrap();
+        }
+        assert_eq!(reassembler.finished.len(), MAX_SENDERS);
+        assert_eq!(reassembler.counters.0.lock().unwrap().len(), MAX_SENDERS);
+    }
+
+    #[test]
+    fn bad_headers() {
+        let datagram = fragment("a", 1, b"data", SIZE).unwrap().remove(0);
+        assert!(parse(&datagram).is_ok());
+
+        assert!(parse(b"").is_err());
+        assert!(parse(b"xxxx").is_err());
+        assert!(parse(&datagram[..MAGIC.len()]).is_err());
+        // truncated header
+        assert!(parse(&datagram[..MAGIC.len() + 2 + 8]).is_err());
+        // sender length beyond the end of the datagram
+        let mut long_sender = datagram.clone();
+        long_sender[MAGIC.len()] = 255;
+        assert!(parse(&long_sender).is_err());
+        // index not below count
+        let mut bad_index = datagram.clone();
+        let index = MAGIC.len() + 2 + 8;
+        bad_index[index..index + 2].copy_from_slice(&1u16.to_be_bytes());
+        assert!(parse(&bad_index).is_err());
+        // invalid UTF-8 sender
+        let mut bad_sender = datagram;
+        bad_sender[MAGIC.len() + 1] = 0xff;
+        assert!(parse(&bad_sender).is_err());
+    }
+}
@@ -0,0 +1,125 @@
+//! Runs the build commands that the coordinator forwards for `dora build`.
+
+use crate::{DoraEvent, Event};
+use dora_core::{config::NodeId, uhlc::HLC};
+use dora_message::{
+    common::{LogLevel, LogMessage},
+    coordinator_to_daemon::NodeBuild,
+    daemon_to_coordinator::Timestamped,
+};
+use eyre::{bail, eyre, WrapErr};
+use futures::future;
+use std::{collections::BTreeMap, path::Path, process::Stdio, sync::Arc};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, BufReader},
+    sync::mpsc,
+};
+use uuid::Uuid;
+
+/// Runs the build commands of all nodes in parallel.
+///
+/// The output lines are reported as [`DoraEvent::BuildOutput`] and the results as
+/// [`DoraEvent::BuildFinished`] once all nodes are built.
+pub fn spawn_build(
+    build_id: Uuid,
+    nodes: BTreeMap<NodeId, NodeBuild>,
+    events_tx: mpsc::Sender<Timestamped<Event>>,
+    clock: Arc<HLC>,
+) {
+    tokio::spawn(async move {
+        let builds = nodes.into_iter().map(|(node_id, build)| {
+            let output = Output {
+                build_id,
+                node_id: node_id.clone(),
+                events_tx: events_tx.clone(),
+                clock: clock.clone(),
+            };
+            async move {
+                let result = build_node(&build.commands, &build.working_dir, &output).await;
+                if let Err(err) = &result {
+                    output.send(LogLevel::Error, format!("{err:#}")).await;
+                }
+                (node_id, result.map_err(|err| format!("{err:#}")))
+            }
+        });
+        let results = future::join_all(builds).await.into_iter().collect();
+
+        let event = Timestamped {
+            inner: DoraEvent::BuildFinished { build_id, results }.into(),
+            timestamp: clock.new_timestamp(),
+        };
+        let _ = events_tx.send(event).await;
+    });
+}
+
+async fn build_node(commands: &[String], working_dir: &Path, output: &Output) -> eyre::Result<()> {
+    for build in commands {
+        output
+            .send(LogLevel::Info, format!("running `{build}`"))
+            .await;
+        let mut split = build.split_whitespace();
+        let mut cmd = tokio::process::Command::new(
+            split
+                .next()
+                .ok_or_else(|| eyre!("build command is empty"))?,
+        );
+        cmd.args(split)
+            .current_dir(working_dir)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        let mut child = cmd
+            .spawn()
+            .wrap_err_with(|| format!("failed to run `{build}`"))?;
+        let stdout = child.stdout.take();
+        let stderr = child.stderr.take();
+        let (exit_status, (), ()) = tokio::join!(
+            child.wait(),
+            output.forward_lines(stdout),
+            output.forward_lines(stderr)
+        );
+        let exit_status = exit_status.wrap_err_with(|| format!("failed to wait for `{build}`"))?;
+        if !exit_status.success() {
+            bail!("build command `{build}` returned {exit_status}");
+        }
+    }
+    Ok(())
+}
+
+/// Sends the output of the build commands of a node as log messages.
+struct Output {
+    build_id: Uuid,
+    node_id: NodeId,
+    events_tx: mpsc::Sender<Timestamped<Event>>,
+    clock: Arc<HLC>,
+}
+
+impl Output {
+    async fn forward_lines(&self, stream: Option<impl AsyncRead + Unpin>) {
+        let Some(stream) = stream else {
+            return;
+        };
+        let mut lines = BufReader::new(stream).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            self.send(LogLevel::Info, line).await;
+        }
+    }
+
+    async fn send(&self, level: LogLevel, message: String) {
+        let message = LogMessage {
+            dataflow_id: self.build_id,
+            node_id: Some(self.node_id.clone()),
+            level,
+            target: Some("build".into()),
+            module_path: None,
+            file: None,
+            line: None,
+            message,
+        };
+        let event = Timestamped {
+            inner: DoraEvent::BuildOutput(message).into(),
+            timestamp: self.clock.new_timestamp(),
+        };
+        let _ = self.events_tx.send(event).await;
+    }
+}
@@ -0,0 +1,267 @@
+//! Resource limits of nodes through cgroups v2.
+//!
+//! Nodes with a `resources` block get their own cgroup below the cgroup of the
+//! daemon. Controllers can only be enabled for the children of a cgroup that has no
+//! processes itself, so the daemon first moves itself into a `dora-daemon` leaf group.
+//! This requires that the daemon runs in its own, delegated cgroup, e.g. through
+//! `systemd-run --user --scope -p Delegate=yes dora daemon`.
+
+use dora_core::{config::NodeId, descriptor::Resources};
+use dora_message::DataflowId;
+use eyre::{bail, eyre, Context, ContextCompat};
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        OnceLock,
+    },
+    time::Duration,
+};
+
+/// Period of the `cpu.max` quota.
+const CPU_PERIOD_MICROS: u64 = 100_000;
+
+/// The cgroup of the daemon, in which the node cgroups are created.
+static PARENT: OnceLock<Result<PathBuf, String>> = OnceLock::new();
+
+/// Suffix of the next node cgroup, which makes the cgroup of every spawn unique.
+static NEXT_SUFFIX: AtomicU64 = AtomicU64::new(0);
+
+pub struct NodeCgroup {
+    path: PathBuf,
+}
+
+impl NodeCgroup {
+    /// Creates the cgroup for the given node and applies the resource limits.
+    pub fn create(
+        dataflow_id: DataflowId,
+        node_id: &NodeId,
+        resources: &Resources,
+    ) -> eyre::Result<Self> {
+        if !cfg!(target_os = "linux") {
+            bail!("resource limits are only supported on Linux");
+        }
+        let parent = parent()?;
+
+        let mut controllers = Vec::new();
+        if resources.memory_max.is_some() {
+            controllers.push("memory");
+        }
+        if resources.cpu_quota.is_some() {
+            controllers.push("cpu");
+        }
+        if resources.pids_max.is_some() {
+            controllers.push("pids");
+        }
+        enable_controllers(parent, &controllers)?;
+
+        // use a fresh cgroup for every spawn, so that the `memory.events` counters
+        // only cover this run of the node
+        let path = loop {
+            let suffix = NEXT_SUFFIX.fetch_add(1, Ordering::Relaxed);
+            let path = parent.join(format!(
+                "dora-{dataflow_id}-{}-{suffix}",
+                node_id.file_name()
+            ));
+            match fs::create_dir(&path) {
+                Ok(()) => break path,
+                // left over from a previous daemon
+                Err(err) if err.kind() == ErrorKind::AlreadyExists => continue,
+                Err(err) => {
+                    return Err(err)
+                        .wrap_err_with(|| format!("failed to create cgroup `{}`", path.display()))
+                }
+            }
+        };
+        let cgroup = Self { path };
+
+        if let Some(memory_max) = resources.memory_max {
+            cgroup.write("memory.max", &memory_max.to_string())?;
+        }
+        if let Some(cpu_quota) = resources.cpu_quota {
+            // the kernel requires a quota of at least 1ms
+            let quota = ((cpu_quota * CPU_PERIOD_MICROS as f64).round() as u64).max(1000);
+            cgroup.write("cpu.max", &format!("{quota} {CPU_PERIOD_MICROS}"))?;
+        }
+        if let Some(pids_max) = resources.pids_max {
+            cgroup.write("pids.max", &pids_max.to_string())?;
+        }
+
+        Ok(cgroup)
+    }
+
+    fn write(&self, file: &str, value: &str) -> eyre::Result<()> {
+        fs::write(self.path.join(file), value).wrap_err_with(|| {
+            format!(
+                "failed to set `{file}` of cgroup `{}` to `{value}`",
+                self.path.display()
+            )
+        })
+    }
+
+    /// Opens the `cgroup.procs` file of the cgroup.
+    ///
+    /// Writing `0` to it moves the writing process into the cgroup.
+    pub fn procs_file(&self) -> eyre::Result<fs::File> {
+        let path = self.path.join("cgroup.procs");
+        fs::OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .wrap_err_with(|| format!("failed to open `{}`", path.display()))
+    }
+
+    /// Whether the kernel OOM killer killed a process of this cgroup.
+    pub fn out_of_memory(&self) -> bool {
+        fs::read_to_string(self.path.join("memory.events"))
+            .map(|events| {
+                events.lines().any(|line| {
+                    line.strip_prefix("oom_kill ")
+                        .and_then(|count| count.trim().parse::<u64>().ok())
+                        .is_some_and(|count| count > 0)
+                })
+            })
+            .unwrap_or(false)
+    }
+
+    /// Removes the cgroup, killing processes that are still left in it.
+    pub async fn remove(self) {
+        for attempt in 0..10 {
+            match fs::remove_dir(&self.path) {
+                Ok(()) => return,
+                Err(_) if attempt == 0 => {
+                    // the node might have left child processes behind
+                    let _ = fs::write(self.path.join("cgroup.kill"), "1");
+                }
+                Err(_) => {}
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        tracing::warn!("failed to remove cgroup `{}`", self.path.display());
+    }
+}
+
+fn parent() -> eyre::Result<&'static Path> {
+    PARENT
+        .get_or_init(|| init_parent().map_err(|err| format!("{err:?}")))
+        .as_deref()
+        .map_err(|err| eyre!("failed to set up cgroup for node resource limits: {err}"))
+}
+
+fn init_parent() -> eyre::Result<PathBuf> {
+    let mount = cgroup2_mount()?;
+    let own_cgroups =
+        fs::read_to_string("/proc/self/cgroup").wrap_err("failed to read `/proc/self/cgroup`")?;
+    let own = own_cgroups
+        .lines()
+        .find_map(|line| line.strip_prefix("0::"))
+        .context("daemon is not part of a cgroups v2 hierarchy")?
+        .trim_start_matches('/');
+    // the root cgroup is exempt from the "no internal processes" rule
+    if own.is_empty() {
+        return Ok(mount);
+    }
+    let parent = mount.join(own);
+
+    let leaf = parent.join("dora-daemon");
+    match fs::create_dir(&leaf) {
+        Ok(()) => {}
+        Err(err) if err.kind() == ErrorKind::AlreadyExists => {}
+        Err(err) => {
+            return Err(err)
+                .wrap_err_with(|| format!("failed to create cgroup `{}`", leaf.display()))
+        }
+    }
+    fs::write(leaf.join("cgroup.procs"), std::process::id().to_string()).wrap_err_with(|| {
+        format!(
+            "failed to move daemon into cgroup `{}`, the daemon's cgroup needs to be \
+            delegated to the daemon's user",
+            leaf.display()
+        )
+    })?;
+    let procs = fs::read_to_string(parent.join("cgroup.procs"))
+        .wrap_err_with(|| format!("failed to read processes of `{}`", parent.display()))?;
+    // nodes that were spawned before are moved along with the daemon
+    let others = procs.lines().filter(|pid| {
+        !is_daemon_descendant(pid) || fs::write(leaf.join("cgroup.procs"), pid).is_err()
+    });
+    if others.count() > 0 {
+        bail!(
+            "cgroup `{}` contains other processes than the daemon, so no resource limits \
+            can be applied to nodes (run the daemon in its own delegated cgroup, e.g. \
+            through `systemd-run --user --scope -p Delegate=yes dora daemon`)",
+            parent.display()
+        );
+    }
+
+    Ok(parent)
+}
+
+/// Whether the process with the given PID was started by the daemon, directly or
+/// indirectly.
+fn is_daemon_descendant(pid: &str) -> bool {
+    let own_pid = std::process::id();
+    let mut pid = pid.to_owned();
+    // the PIDs of the ancestors are read one by one, so limit the depth
+    for _ in 0..32 {
+        let Ok(stat) = fs::read_to_string(format!("/proc/{pid}/stat")) else {
+            return false;
+        };
+        // the process name in the second field is in parentheses and might contain spaces
+        let Some(ppid) = stat
+            .rsplit_once(')')
+            .and_then(|(_, rest)| rest.split_whitespace().nth(1))
+        else {
+            return false;
+        };
+        match ppid.parse::<u32>() {
+            Ok(ppid) if ppid == own_pid => return true,
+            Ok(0 | 1) | Err(_) => return false,
+            Ok(_) => pid = ppid.to_owned(),
+        }
+    }
+    false
+}
+
+/// Finds the mount point of the cgroups v2 hierarchy, which is not at
+/// `/sys/fs/cgroup` on systems that still use cgroups v1 in parallel.
+fn cgroup2_mount() -> eyre::Result<PathBuf> {
+    let mountinfo = fs::read_to_string("/proc/self/mountinfo")
+        .wrap_err("failed to read `/proc/self/mountinfo`")?;
+    mountinfo
+        .lines()
+        .find_map(|line| {
+            let (mount, filesystem) = line.split_once(" - ")?;
+            if filesystem.split_whitespace().next()? != "cgroup2" {
+                return None;
+            }
+            mount.split_whitespace().nth(4).map(PathBuf::from)
+        })
+        .context("cgroups v2 is not mounted")
+}
+
+fn enable_controllers(parent: &Path, controllers: &[&str]) -> eyre::Result<()> {
+    let available = fs::read_to_string(parent.join("cgroup.controllers"))
+        .wrap_err_with(|| format!("failed to read controllers of `{}`", parent.display()))?;
+    for controller in controllers {
+        if !available.split_whitespace().any(|c| c == *controller) {
+            bail!(
+                "cgroup controller `{controller}` is not available in `{}` \
+                (it needs to be delegated to the daemon's cgroup)",
+                parent.display()
+            );
+        }
+        fs::write(
+            parent.join("cgroup.subtree_control"),
+            format!("+{controller}"),
+        )
+        .wrap_err_with(|| {
+            format!(
+                "failed to enable cgroup controller `{controller}` in `{}`",
+                parent.display()
+            )
+        })?;
+    }
+    Ok(())
+}
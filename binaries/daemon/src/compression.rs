@@ -0,0 +1,145 @@
+//! Compression of output data that is sent to other machines.
+
+use aligned_vec::{AVec, ConstAlign};
+use dora_core::config::Compression;
+use eyre::{bail, Context};
+use std::io::Read;
+
+/// The default zstd compression level.
+const ZSTD_LEVEL: i32 = 0;
+
+/// Payloads above this size are compressed and decompressed on a blocking thread, so
+/// that they don't stall the event loop of the daemon.
+const BLOCKING_THRESHOLD: usize = 64 * 1024;
+
+pub async fn compress(compression: Compression, data: &[u8]) -> eyre::Result<Vec<u8>> {
+    if data.len() > BLOCKING_THRESHOLD {
+        let data = data.to_vec();
+        tokio::task::spawn_blocking(move || compress_blocking(compression, &data))
+            .await
+            .wrap_err("compression task failed")?
+    } else {
+        compress_blocking(compression, data)
+    }
+}
+
+fn compress_blocking(compression: Compression, data: &[u8]) -> eyre::Result<Vec<u8>> {
+    match compression {
+        Compression::Lz4 => Ok(lz4_flex::compress_prepend_size(data)),
+        Compression::Zstd => {
+            zstd::encode_all(data, ZSTD_LEVEL).wrap_err("failed to compress data with zstd")
+        }
+    }
+}
+
+/// Decompresses data that was received from another machine.
+///
+/// Fails if the decompressed data would be larger than `max_size`, instead of trusting
+/// the size announced by the sender.
+pub async fn decompress(
+    compression: Compression,
+    data: AVec<u8, ConstAlign<128>>,
+    max_size: usize,
+) -> eyre::Result<AVec<u8, ConstAlign<128>>> {
+    if data.len() > BLOCKING_THRESHOLD {
+        tokio::task::spawn_blocking(move || decompress_blocking(compression, &data, max_size))
+            .await
+            .wrap_err("decompression task failed")?
+    } else {
+        decompress_blocking(compression, &data, max_size)
+    }
+}
+
+fn decompress_blocking(
+    compression: Compression,
+    data: &[u8],
+    max_size: usize,
+) -> eyre::Result<AVec<u8, ConstAlign<128>>> {
+    let decompressed = match compression {
+        Compression::Lz4 => {
+            let (size, compressed) = lz4_flex::block::uncompressed_size(data)
+                .wrap_err("failed to read size of lz4 data")?;
+            if size > max_size {
+                bail!("lz4 data decompresses to {size} bytes, which exceeds the limit of {max_size} bytes");
+            }
+            lz4_flex::block::decompress(compressed, size)
+                .wrap_err("failed to decompress lz4 data")?
+        }
+        Compression::Zstd => {
+            let decoder =
+                zstd::stream::read::Decoder::new(data).wrap_err("failed to create zstd decoder")?;
+            let mut decompressed = Vec::new();
+            decoder
+                .take(max_size as u64 + 1)
+                .read_to_end(&mut decompressed)
+                .wrap_err("failed to decompress zstd data")?;
+            if decompressed.len() > max_size {
+                bail!("zstd data exceeds the limit of {max_size} bytes after decompression");
+            }
+            decompressed
+        }
+    };
+    Ok(AVec::from_slice(128, &decompressed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compress, decompress, BLOCKING_THRESHOLD};
+    use aligned_vec::AVec;
+    use dora_core::config::Compression;
+
+    const CODECS: [Compression; 2] = [Compression::Lz4, Compression::Zstd];
+
+    async fn round_trip(compression: Compression, data: &[u8], max_size: usize) -> Vec<u8> {
+        let compressed = compress(compression, data).await.unwrap();
+        let decompressed = decompress(compression, AVec::from_slice(128, &compressed), max_size)
+            .await
+            .unwrap();
+        decompressed.to_vec()
+    }
+
+    #[tokio::test]
+    async fn small_round_trip() {
+        let data = b"hello hello hello hello";
+        for compression in CODECS {
+            assert_eq!(round_trip(compression, data, data.len()).await, data);
+            assert_eq!(round_trip(compression, &[], 0).await, b"");
+        }
+    }
+
+    #[tokio::test]
+    async fn blocking_round_trip() {
+        // large enough to be compressed on a blocking thread
+        let data: Vec<u8> = (0..BLOCKING_THRESHOLD * 4)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        for compression in CODECS {
+            let compressed = compress(compression, &data).await.unwrap();
+            assert!(compressed.len() < data.len());
+            assert_eq!(round_trip(compression, &data, data.len()).await, data);
+        }
+    }
+
+    #[tokio::test]
+    async fn max_size() {
+        let data = vec![0; BLOCKING_THRESHOLD * 2];
+        for compression in CODECS {
+            let compressed = compress(compression, &data).await.unwrap();
+            let result = decompress(
+                compression,
+                AVec::from_slice(128, &compressed),
+                data.len() - 1,
+            )
+            .await;
+            assert!(result.is_err(), "{compression:?} exceeded max_size");
+        }
+    }
+
+    #[tokio::test]
+    async fn invalid_data() {
+        let data = AVec::from_slice(128, &[0xff; 16]);
+        for compression in CODECS {
+            assert!(decompress(compression, data.clone(), 1024).await.is_err());
+        }
+    }
+}
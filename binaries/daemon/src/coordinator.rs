@@ -2,40 +2,64 @@ use crate::{
     socket_stream_utils::{socket_stream_receive, socket_stream_send},
     DaemonCoordinatorEvent,
 };
-use dora_core::uhlc::HLC;
+use dora_core::uhlc::{Timestamp, HLC};
 use dora_message::{
     common::Timestamped,
     coordinator_to_daemon::RegisterResult,
-    daemon_to_coordinator::{CoordinatorRequest, DaemonCoordinatorReply, DaemonRegisterRequest},
+    daemon_to_coordinator::{
+        CoordinatorRequest, DaemonCoordinatorReply, DaemonEvent, DaemonRegisterRequest,
+        MachineProperties,
+    },
+    DataflowId,
 };
+use dora_security::{MaybeTlsStream, Security};
 use eyre::{eyre, Context};
-use std::{io::ErrorKind, net::SocketAddr};
+use std::{
+    collections::VecDeque, fmt, future::Future, io::ErrorKind, net::SocketAddr, sync::Arc,
+    time::Duration,
+};
 use tokio::{
     net::TcpStream,
     sync::{mpsc, oneshot},
 };
 use tokio_stream::{wrappers::ReceiverStream, Stream};
 
+/// A registration at the coordinator, see [`CoordinatorConnection::registration`].
+pub struct Registration {
+    stream: MaybeTlsStream,
+    events: ReceiverStream<Timestamped<CoordinatorEvent>>,
+}
+
+impl fmt::Debug for Registration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Registration").finish_non_exhaustive()
+    }
+}
+
 #[derive(Debug)]
 pub struct CoordinatorEvent {
     pub event: DaemonCoordinatorEvent,
     pub reply_tx: oneshot::Sender<Option<DaemonCoordinatorReply>>,
 }
 
-pub async fn register(
+async fn register(
     addr: SocketAddr,
     machine_id: String,
     listen_port: u16,
+    running_dataflows: Vec<DataflowId>,
+    properties: MachineProperties,
+    security: &Security,
     clock: &HLC,
-) -> eyre::Result<impl Stream<Item = Timestamped<CoordinatorEvent>>> {
-    let mut stream = TcpStream::connect(addr)
-        .await
-        .wrap_err("failed to connect to dora-coordinator")?;
-    stream
-        .set_nodelay(true)
-        .wrap_err("failed to set TCP_NODELAY")?;
+) -> eyre::Result<ReceiverStream<Timestamped<CoordinatorEvent>>> {
+    let mut stream = connect(addr, security).await?;
     let register = serde_json::to_vec(&Timestamped {
-        inner: CoordinatorRequest::Register(DaemonRegisterRequest::new(machine_id, listen_port)),
+        inner: CoordinatorRequest::Register(DaemonRegisterRequest::new(
+            machine_id,
+            listen_port,
+            running_dataflows,
+            security.token().map(ToOwned::to_owned),
+            properties,
+        )),
         timestamp: clock.new_timestamp(),
     })?;
     socket_stream_send(&mut stream, &register)
@@ -66,7 +90,7 @@ pub async fn register(
                         continue;
                     }
                 },
-                Err(err) if err.kind() == ErrorKind::UnexpectedEof => break,
+                Err(err) if is_disconnect(&err) => break,
                 Err(err) => {
                     let err = eyre!(err).wrap_err("failed to receive incoming event");
                     tracing::warn!("{err:?}");
@@ -122,3 +146,401 @@ pub async fn register(
 
     Ok(ReceiverStream::new(rx))
 }
+
+async fn connect(addr: SocketAddr, security: &Security) -> eyre::Result<MaybeTlsStream> {
+    let stream = TcpStream::connect(addr)
+        .await
+        .wrap_err("failed to connect to dora-coordinator")?;
+    stream
+        .set_nodelay(true)
+        .wrap_err("failed to set TCP_NODELAY")?;
+    security
+        .connect(stream)
+        .await
+        .wrap_err("TLS handshake with dora-coordinator failed")
+}
+
+fn is_disconnect(err: &std::io::Error) -> bool {
+    matches!(
+        err.kind(),
+        ErrorKind::UnexpectedEof
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::BrokenPipe
+    )
+}
+
+/// Maximum number of log messages that are kept while the coordinator is unreachable.
+const MAX_BUFFERED_LOG_MESSAGES: usize = 10_000;
+/// Maximum number of relayed events that are kept while the coordinator is unreachable.
+const MAX_BUFFERED_RELAY_EVENTS: usize = 1_000;
+const MAX_RECONNECT_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Connection for reporting daemon events to the coordinator.
+///
+/// Events are buffered while the coordinator is unreachable and sent out once the daemon
+/// is registered again.
+pub struct CoordinatorConnection {
+    addr: SocketAddr,
+    machine_id: String,
+    listen_port: u16,
+    properties: MachineProperties,
+    security: Security,
+    stream: Option<MaybeTlsStream>,
+    buffer: EventBuffer,
+    /// Incremented on every reconnect, used to ignore disconnects of old connections.
+    generation: u64,
+    reconnect_backoff: Duration,
+}
+
+impl CoordinatorConnection {
+    /// Registers at the coordinator and opens the connection for reporting events.
+    ///
+    /// Returns the connection and the stream of events sent by the coordinator.
+    pub async fn connect(
+        addr: SocketAddr,
+        machine_id: String,
+        listen_port: u16,
+        properties: MachineProperties,
+        security: Security,
+        clock: &Arc<HLC>,
+    ) -> eyre::Result<(Self, impl Stream<Item = Timestamped<CoordinatorEvent>>)> {
+        let mut connection = Self {
+            addr,
+            machine_id,
+            listen_port,
+            properties,
+            security,
+            stream: None,
+            buffer: EventBuffer::default(),
+            generation: 0,
+            reconnect_backoff: Duration::from_secs(1),
+        };
+        // a newly started daemon has no running dataflows yet, later registrations
+        // announce them
+        let registration = connection.registration(Vec::new(), clock.clone()).await?;
+        connection.stream = Some(registration.stream);
+        Ok((connection, registration.events))
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    /// Marks the connection as lost, returns `false` if it was already disconnected.
+    pub fn disconnect(&mut self) -> bool {
+        self.stream.take().is_some()
+    }
+
+    /// The delay before the next reconnect attempt, doubled on every call.
+    pub fn next_reconnect_backoff(&mut self) -> Duration {
+        let backoff = self.reconnect_backoff;
+        self.reconnect_backoff = (backoff * 2).min(MAX_RECONNECT_BACKOFF);
+        backoff
+    }
+
+    /// Dataflows whose finish result was not reported to the coordinator yet.
+    pub fn unreported_finished_dataflows(&self) -> impl Iterator<Item = DataflowId> + '_ {
+        self.buffer
+            .other
+            .iter()
+            .filter_map(|(_, event)| match &event.inner {
+                DaemonEvent::AllNodesFinished { dataflow_id, .. } => Some(*dataflow_id),
+                _ => None,
+            })
+    }
+
+    /// Registers at the coordinator.
+    ///
+    /// The given `running_dataflows` are announced to the coordinator as part of the
+    /// register request. The returned future does not borrow the connection, so that it
+    /// can run in a separate task. Pass the result to [`Self::reconnected`].
+    pub fn registration(
+        &self,
+        running_dataflows: Vec<DataflowId>,
+        clock: Arc<HLC>,
+    ) -> impl Future<Output = eyre::Result<Registration>> + Send + 'static {
+        let addr = self.addr;
+        let machine_id = self.machine_id.clone();
+        let listen_port = self.listen_port;
+        let properties = self.properties.clone();
+        let security = self.security.clone();
+        async move {
+            let events = register(
+                addr,
+                machine_id,
+                listen_port,
+                running_dataflows,
+                properties,
+                &security,
+                &clock,
+            )
+            .await?;
+            let mut stream = connect(addr, &security).await?;
+            if let Some(token) = security.token() {
+                let authenticate = serde_json::to_vec(&Timestamped {
+                    inner: CoordinatorRequest::Authenticate {
+                        token: Some(token.to_owned()),
+                    },
+                    timestamp: clock.new_timestamp(),
+                })?;
+                socket_stream_send(&mut stream, &authenticate)
+                    .await
+                    .wrap_err("failed to authenticate at dora-coordinator")?;
+            }
+            Ok(Registration { stream, events })
+        }
+    }
+
+    /// Uses the connection of a new registration and sends out the buffered events.
+    ///
+    /// Returns the stream of events sent by the coordinator.
+    pub async fn reconnected(
+        &mut self,
+        registration: Registration,
+    ) -> impl Stream<Item = Timestamped<CoordinatorEvent>> {
+        let Registration { stream, events } = registration;
+        self.stream = Some(stream);
+        self.generation += 1;
+        self.reconnect_backoff = Duration::from_secs(1);
+
+        if self.buffer.dropped_log_messages > 0 {
+            tracing::warn!(
+                "dropped {} log messages while the coordinator was unreachable",
+                self.buffer.dropped_log_messages
+            );
+            self.buffer.dropped_log_messages = 0;
+        }
+        if self.buffer.dropped_relay_events > 0 {
+            tracing::warn!(
+                "dropped {} relayed events while the coordinator was unreachable",
+                self.buffer.dropped_relay_events
+            );
+            self.buffer.dropped_relay_events = 0;
+        }
+        while let Some(event) = self.buffer.front() {
+            let event = event.clone();
+            if !self.try_send(&event).await {
+                break;
+            }
+            self.buffer.pop_front();
+        }
+        events
+    }
+
+    /// Sends the given event to the coordinator, or buffers it if the coordinator is
+    /// currently unreachable.
+    ///
+    /// Heartbeats are not buffered.
+    pub async fn send_event(&mut self, event: DaemonEvent, timestamp: Timestamp) {
+        let event = Timestamped {
+            inner: event,
+            timestamp,
+        };
+        // keep the order of events: buffered events need to be sent first
+        if !self.buffer.is_empty() {
+            self.buffer.push(event);
+            return;
+        }
+        if !self.try_send(&event).await {
+            self.buffer.push(event);
+        }
+    }
+
+    /// Tries to send the event, returns whether it was sent successfully.
+    ///
+    /// A failed send marks the connection as lost.
+    async fn try_send(&mut self, event: &Timestamped<DaemonEvent>) -> bool {
+        let Some(stream) = &mut self.stream else {
+            return false;
+        };
+        let message = Timestamped {
+            inner: CoordinatorRequest::Event {
+                machine_id: self.machine_id.clone(),
+                event: event.inner.clone(),
+            },
+            timestamp: event.timestamp,
+        };
+        let serialized = match serde_json::to_vec(&message) {
+            Ok(serialized) => serialized,
+            Err(err) => {
+                // retrying would not help, so the event is dropped
+                tracing::error!("failed to serialize daemon event: {err}");
+                return true;
+            }
+        };
+        match socket_stream_send(stream, &serialized).await {
+            Ok(()) => true,
+            Err(err) => {
+                tracing::warn!("failed to send event to dora-coordinator: {err}");
+                self.stream = None;
+                false
+            }
+        }
+    }
+}
+
+/// Events that wait to be sent to the coordinator.
+///
+/// Log messages and relayed events are kept in separate queues, so that the oldest
+/// event of their class can be dropped in constant time when the class exceeds its
+/// limit. The sequence numbers restore the original order of all events.
+#[derive(Default)]
+struct EventBuffer {
+    next_seq: u64,
+    logs: VecDeque<(u64, Timestamped<DaemonEvent>)>,
+    relays: VecDeque<(u64, Timestamped<DaemonEvent>)>,
+    other: VecDeque<(u64, Timestamped<DaemonEvent>)>,
+    dropped_log_messages: usize,
+    dropped_relay_events: usize,
+}
+
+impl EventBuffer {
+    fn is_empty(&self) -> bool {
+        self.logs.is_empty() && self.relays.is_empty() && self.other.is_empty()
+    }
+
+    /// Appends the given event. Heartbeats are not buffered.
+    fn push(&mut self, event: Timestamped<DaemonEvent>) {
+        let (queue, max, dropped) = match &event.inner {
+            DaemonEvent::Heartbeat => return,
+            DaemonEvent::Log(_) => (
+                &mut self.logs,
+                MAX_BUFFERED_LOG_MESSAGES,
+                Some(&mut self.dropped_log_messages),
+            ),
+            DaemonEvent::Relay { .. } => (
+                &mut self.relays,
+                MAX_BUFFERED_RELAY_EVENTS,
+                Some(&mut self.dropped_relay_events),
+            ),
+            _ => (&mut self.other, usize::MAX, None),
+        };
+        if queue.len() >= max {
+            queue.pop_front();
+            if let Some(dropped) = dropped {
+                *dropped += 1;
+            }
+        }
+        queue.push_back((self.next_seq, event));
+        self.next_seq += 1;
+    }
+
+    /// The queue that contains the oldest event.
+    fn oldest(&mut self) -> Option<&mut VecDeque<(u64, Timestamped<DaemonEvent>)>> {
+        [&mut self.logs, &mut self.relays, &mut self.other]
+            .into_iter()
+            .filter(|queue| !queue.is_empty())
+            .min_by_key(|queue| queue[0].0)
+    }
+
+    fn front(&mut self) -> Option<&Timestamped<DaemonEvent>> {
+        self.oldest().map(|queue| &queue[0].1)
+    }
+
+    fn pop_front(&mut self) -> Option<Timestamped<DaemonEvent>> {
+        self.oldest()
+            .and_then(|queue| queue.pop_front())
+            .map(|(_, event)| event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EventBuffer, MAX_BUFFERED_LOG_MESSAGES, MAX_BUFFERED_RELAY_EVENTS};
+    use dora_core::uhlc::HLC;
+    use dora_message::{
+        common::{LogLevel, LogMessage, Timestamped},
+        daemon_to_coordinator::DaemonEvent,
+        daemon_to_daemon::RelayedEvent,
+    };
+    use uuid::Uuid;
+
+    fn log(index: usize) -> DaemonEvent {
+        DaemonEvent::Log(LogMessage {
+            dataflow_id: Uuid::nil(),
+            node_id: None,
+            level: LogLevel::Info,
+            target: None,
+            module_path: None,
+            file: None,
+            line: None,
+            message: index.to_string(),
+        })
+    }
+
+    fn relay(index: usize) -> DaemonEvent {
+        DaemonEvent::Relay {
+            target_machine: "B".to_owned(),
+            event: RelayedEvent(index.to_string().into_bytes()),
+        }
+    }
+
+    fn stop(index: usize) -> DaemonEvent {
+        DaemonEvent::StopDataflow {
+            dataflow_id: Uuid::from_u128(index as u128),
+        }
+    }
+
+    fn describe(event: &DaemonEvent) -> String {
+        match event {
+            DaemonEvent::Log(log) => format!("log {}", log.message),
+            DaemonEvent::Relay { event, .. } => {
+                format!("relay {}", String::from_utf8_lossy(&event.0))
+            }
+            DaemonEvent::StopDataflow { dataflow_id } => format!("stop {}", dataflow_id.as_u128()),
+            other => panic!("unexpected event {other:?}"),
+        }
+    }
+
+    #[test]
+    fn overflow() {
+        let clock = HLC::default();
+        let mut buffer = EventBuffer::default();
+        let push = |buffer: &mut EventBuffer, event| {
+            buffer.push(Timestamped {
+                inner: event,
+                timestamp: clock.new_timestamp(),
+            })
+        };
+
+        let logs = MAX_BUFFERED_LOG_MESSAGES + 5;
+        let relays = MAX_BUFFERED_RELAY_EVENTS + 3;
+        let mut expected = Vec::new();
+        for i in 0..logs {
+            push(&mut buffer, log(i));
+            if i >= logs - MAX_BUFFERED_LOG_MESSAGES {
+                expected.push(format!("log {i}"));
+            }
+            if i < relays {
+                push(&mut buffer, relay(i));
+                if i >= relays - MAX_BUFFERED_RELAY_EVENTS {
+                    expected.push(format!("relay {i}"));
+                }
+            }
+            if i % 1000 == 0 {
+                push(&mut buffer, stop(i));
+                expected.push(format!("stop {i}"));
+            }
+            push(&mut buffer, DaemonEvent::Heartbeat);
+        }
+
+        assert_eq!(buffer.dropped_log_messages, 5);
+        assert_eq!(buffer.dropped_relay_events, 3);
+
+        // the newest events of each class survive, in their original order
+        let mut sent = Vec::new();
+        while let Some(event) = buffer.front() {
+            let front = describe(&event.inner);
+            let popped = buffer.pop_front().unwrap();
+            assert_eq!(describe(&popped.inner), front);
+            sent.push(front);
+        }
+        assert_eq!(sent, expected);
+        assert!(buffer.is_empty());
+    }
+}
@@ -1,69 +1,693 @@
-use crate::socket_stream_utils::{socket_stream_receive, socket_stream_send};
-use dora_message::{common::Timestamped, daemon_to_daemon::InterDaemonEvent};
-use eyre::{Context, ContextCompat};
-use std::{collections::BTreeMap, io::ErrorKind, net::SocketAddr};
-use tokio::net::{TcpListener, TcpStream};
+use crate::{
+    best_effort::{self, BestEffortSender, Reassembler, ReceiveCounters},
+    socket_stream_utils::{
+        socket_stream_receive, socket_stream_receive_bounded, socket_stream_send,
+    },
+    OutputId,
+};
+use communication_layer_pub_sub::{
+    zenoh::{self, ZenohCommunicationLayer},
+    CommunicationLayer, Publisher, Subscriber,
+};
+use dora_core::config::{OverflowPolicy, RemoteCommunicationConfig, Transport};
+use dora_message::{
+    common::{BestEffortStats, ConnectionStats, Timestamped},
+    daemon_to_daemon::InterDaemonEvent,
+};
+use dora_security::{MaybeTlsStream, Security, MAX_PRE_AUTH_MESSAGE_SIZE};
+use eyre::{eyre, Context, ContextCompat};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    io::ErrorKind,
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::{
+    net::{TcpListener, TcpStream, UdpSocket},
+    sync::{mpsc, Notify},
+};
 
-pub struct InterDaemonConnection {
+/// Prefix of all zenoh topics used by dora.
+const ZENOH_PREFIX: &str = "dora";
+
+/// Connections to the daemons on other machines.
+///
+/// Dataflows with `_unstable_remote: tcp` use a TCP connection per machine, dataflows with
+/// `_unstable_remote: zenoh` send their events through a zenoh session that is shared by
+/// all dataflows of this daemon. Outputs to inputs with `transport: best-effort` are sent
+/// through UDP instead of the TCP connection.
+///
+/// Dataflows with `_unstable_remote: relay` send their events through the coordinator.
+/// The same happens for `tcp` dataflows if no direct connection to a machine can be
+/// established.
+#[derive(Default)]
+pub struct InterDaemonConnections {
+    machine_id: String,
+    tcp: BTreeMap<String, InterDaemonConnection>,
+    /// Passes events to the coordinator connection, `None` if there is no coordinator.
+    relay_tx: Option<mpsc::Sender<RelayMessage>>,
+    best_effort: BestEffortState,
+    best_effort_counters: ReceiveCounters,
+    pub_sub: Option<PubSubConnection>,
+    pub_sub_receiver: Option<PubSubReceiver>,
+    /// TLS and token settings for the TCP connections.
+    security: Security,
+}
+
+#[derive(Default)]
+enum BestEffortState {
+    /// The UDP socket is bound when the first best-effort message is sent.
+    #[default]
+    Unbound,
+    Bound(BestEffortSender),
+    /// Best-effort messages are sent through TCP instead.
+    Unavailable,
+}
+
+/// Settings for receiving the inter-daemon events that are sent to this daemon through
+/// zenoh.
+pub struct PubSubReceiver {
+    pub machine_id: String,
+    /// Path to a zenoh configuration file, the zenoh defaults are used if not set.
+    pub zenoh_config: Option<PathBuf>,
+    pub events_tx: flume::Sender<Timestamped<InterDaemonEvent>>,
+}
+
+/// An event for another machine that is sent through the coordinator.
+#[derive(Debug)]
+pub struct RelayMessage {
+    pub target_machine: String,
+    /// The serialized `Timestamped<InterDaemonEvent>`.
+    pub data: Arc<[u8]>,
+}
+
+struct PubSubConnection {
+    layer: Box<dyn CommunicationLayer>,
+    /// Publishers for the topics of the target machines.
+    publishers: BTreeMap<String, Box<dyn Publisher>>,
+}
+
+impl InterDaemonConnections {
+    pub fn new(
+        machine_id: String,
+        pub_sub_receiver: Option<PubSubReceiver>,
+        relay_tx: mpsc::Sender<RelayMessage>,
+        security: Security,
+    ) -> Self {
+        Self {
+            machine_id,
+            tcp: BTreeMap::new(),
+            relay_tx: Some(relay_tx),
+            best_effort: BestEffortState::Unbound,
+            best_effort_counters: ReceiveCounters::default(),
+            pub_sub: None,
+            pub_sub_receiver,
+            security,
+        }
+    }
+
+    /// Counters of the messages received through best-effort transport, to be updated
+    /// by the listener loop.
+    pub fn best_effort_counters(&self) -> ReceiveCounters {
+        self.best_effort_counters.clone()
+    }
+
+    /// Sets the listen sockets of the given machines, replacing connections to outdated
+    /// sockets.
+    pub fn update_sockets(&mut self, machine_listen_ports: BTreeMap<String, SocketAddr>) {
+        for (machine_id, socket) in machine_listen_ports {
+            match self.tcp.entry(machine_id) {
+                std::collections::btree_map::Entry::Vacant(entry) => {
+                    let machine_id = entry.key().clone();
+                    entry.insert(InterDaemonConnection::new(
+                        socket,
+                        machine_id,
+                        self.security.clone(),
+                        self.relay_tx.clone(),
+                    ));
+                }
+                std::collections::btree_map::Entry::Occupied(mut entry) => {
+                    if entry.get().socket() != socket {
+                        let machine_id = entry.key().clone();
+                        entry.insert(InterDaemonConnection::new(
+                            socket,
+                            machine_id,
+                            self.security.clone(),
+                            self.relay_tx.clone(),
+                        ));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the state of the TCP connections to the given machines.
+    pub fn stats<'a>(
+        &self,
+        machine_id: &str,
+        peers: impl IntoIterator<Item = &'a String>,
+    ) -> Vec<ConnectionStats> {
+        peers
+            .into_iter()
+            .filter_map(|peer| {
+                let connection = self.tcp.get(peer)?;
+                Some(connection.stats(machine_id.to_owned(), peer.clone()))
+            })
+            .collect()
+    }
+
+    /// Returns the number of messages received through best-effort transport.
+    pub fn best_effort_stats(&self, machine_id: &str) -> Vec<BestEffortStats> {
+        self.best_effort_counters.stats(machine_id)
+    }
+
+    /// Opens the zenoh session and subscribes to the events for this machine, unless
+    /// this was already done for an earlier dataflow.
+    pub fn open_zenoh(&mut self) -> eyre::Result<()> {
+        if self.pub_sub.is_some() {
+            return Ok(());
+        }
+        let receiver = self
+            .pub_sub_receiver
+            .as_ref()
+            .context("daemon does not support inter-daemon communication")?;
+        let config = match &receiver.zenoh_config {
+            Some(path) => zenoh::Config::from_file(path)
+                .map_err(|err| eyre!(err))
+                .wrap_err_with(|| format!("failed to read zenoh config `{}`", path.display()))?,
+            None => zenoh::Config::default(),
+        };
+        let mut layer = ZenohCommunicationLayer::init(config, ZENOH_PREFIX.to_owned())
+            .map_err(|err| eyre!(err))
+            .wrap_err("failed to open zenoh session")?;
+        let subscriber = layer
+            .subscribe(&machine_topic(&receiver.machine_id))
+            .map_err(|err| eyre!(err))
+            .wrap_err("failed to subscribe to inter-daemon events")?;
+        spawn_subscriber_loop(subscriber, receiver.events_tx.clone());
+        self.pub_sub = Some(PubSubConnection {
+            layer: Box::new(layer),
+            publishers: BTreeMap::new(),
+        });
+        Ok(())
+    }
+}
+
+impl PubSubConnection {
+    fn send(&mut self, target_machine: &str, message: &[u8]) -> eyre::Result<()> {
+        let publisher = match self.publishers.entry(target_machine.to_owned()) {
+            std::collections::btree_map::Entry::Occupied(entry) => entry.into_mut(),
+            std::collections::btree_map::Entry::Vacant(entry) => {
+                let publisher = self
+                    .layer
+                    .publisher(&machine_topic(target_machine))
+                    .map_err(|err| eyre!(err))
+                    .wrap_err("failed to create publisher")?;
+                entry.insert(publisher)
+            }
+        };
+        publisher.publish(message).map_err(|err| eyre!(err))
+    }
+}
+
+/// The topic on which the daemon of the given machine receives its events.
+fn machine_topic(machine_id: &str) -> String {
+    // zenoh key expressions must not contain empty chunks
+    format!("daemon-{machine_id}")
+}
+
+/// Limits the number of queued messages of an output, using the queue settings of the
+/// receiving inputs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputQueue {
+    pub output_id: OutputId,
+    pub size: usize,
+    pub overflow: OverflowPolicy,
+}
+
+/// Sends messages to the daemon of another machine.
+///
+/// The messages are queued and sent in order by a background task, which reconnects
+/// with an exponential backoff when the connection is lost. While the peer is
+/// unreachable, the queue limits of the outputs apply, so messages are dropped instead
+/// of failing the dataflow.
+///
+/// If the first connection attempt fails, the peer is assumed to be unreachable from
+/// this machine, e.g. because of NAT, and messages are relayed through the coordinator
+/// instead. A direct connection is retried periodically while messages are relayed.
+struct InterDaemonConnection {
     socket: SocketAddr,
-    connection: Option<TcpStream>,
+    shared: Arc<SharedPeerQueue>,
+}
+
+#[derive(Default)]
+struct SharedPeerQueue {
+    state: Mutex<PeerQueue>,
+    /// Notified when a message was added or the connection was dropped.
+    message_added: Notify,
+    /// Notified when a message was removed or the peer became unreachable.
+    space_freed: Notify,
+}
+
+#[derive(Default)]
+struct PeerQueue {
+    messages: VecDeque<QueuedMessage>,
+    closed: bool,
+    unreachable: bool,
+    /// No direct connection could be established, so messages are relayed.
+    relayed: bool,
+    reconnects: u64,
+    dropped: u64,
+}
+
+struct QueuedMessage {
+    /// The output of the message, `None` for control events that are never dropped.
+    output_id: Option<OutputId>,
+    data: Arc<[u8]>,
+    /// Send the message through the coordinator, regardless of the connection state.
+    relay: bool,
 }
 
 impl InterDaemonConnection {
-    pub fn new(socket: SocketAddr) -> Self {
-        Self {
+    fn new(
+        socket: SocketAddr,
+        peer_machine_id: String,
+        security: Security,
+        relay_tx: Option<mpsc::Sender<RelayMessage>>,
+    ) -> Self {
+        let shared = Arc::new(SharedPeerQueue::default());
+        tokio::spawn(sender_loop(
             socket,
-            connection: None,
+            peer_machine_id,
+            shared.clone(),
+            security,
+            relay_tx,
+        ));
+        Self { socket, shared }
+    }
+
+    fn socket(&self) -> SocketAddr {
+        self.socket
+    }
+
+    fn count_dropped(&self) {
+        self.shared.state.lock().unwrap().dropped += 1;
+    }
+
+    fn is_relayed(&self) -> bool {
+        self.shared.state.lock().unwrap().relayed
+    }
+
+    /// Adds the given message to the send queue.
+    ///
+    /// If the queue already holds the maximum number of messages of the output, the
+    /// overflow policy of the receiving inputs applies. With `overflow: block`, this
+    /// function waits until the message can be queued, unless the peer is unreachable
+    /// or the message is relayed.
+    async fn enqueue(&self, data: Arc<[u8]>, queue: Option<&OutputQueue>, relay: bool) {
+        loop {
+            let space_freed = self.shared.space_freed.notified();
+            if self.try_enqueue(&data, queue, relay) {
+                break;
+            }
+            space_freed.await;
         }
+        self.shared.message_added.notify_one();
     }
 
-    #[tracing::instrument(skip(self), fields(%self.socket))]
-    async fn connect(&mut self) -> eyre::Result<&mut TcpStream> {
-        match &mut self.connection {
-            Some(c) => Ok(c),
-            entry @ None => {
-                let connection = TcpStream::connect(self.socket)
-                    .await
-                    .wrap_err("failed to connect")?;
-                connection
-                    .set_nodelay(true)
-                    .wrap_err("failed to set nodelay")?;
-                Ok(entry.insert(connection))
+    /// Queues or drops the message, returns `false` if the caller should wait for free
+    /// space instead.
+    fn try_enqueue(&self, data: &Arc<[u8]>, queue: Option<&OutputQueue>, relay: bool) -> bool {
+        let mut state = self.shared.state.lock().unwrap();
+        if let Some(queue) = queue {
+            let max_len = queue.overflow.queue_size(queue.size);
+            let queued = state
+                .messages
+                .iter()
+                .filter(|m| m.output_id.as_ref() == Some(&queue.output_id))
+                .count();
+            if queued >= max_len {
+                let drop_oldest = match queue.overflow {
+                    OverflowPolicy::DropOldest | OverflowPolicy::KeepLatest => true,
+                    OverflowPolicy::DropNewest => false,
+                    // relayed messages are forwarded by the main loop of the daemon, so
+                    // waiting for them could deadlock
+                    OverflowPolicy::Block if state.unreachable || relay => true,
+                    OverflowPolicy::Block => return false,
+                };
+                state.dropped += 1;
+                if !drop_oldest {
+                    return true;
+                }
+                let oldest = state
+                    .messages
+                    .iter()
+                    .position(|m| m.output_id.as_ref() == Some(&queue.output_id));
+                if let Some(index) = oldest {
+                    state.messages.remove(index);
+                }
             }
         }
+        state.messages.push_back(QueuedMessage {
+            output_id: queue.map(|q| q.output_id.clone()),
+            data: data.clone(),
+            relay,
+        });
+        true
     }
 
-    pub fn socket(&self) -> SocketAddr {
-        self.socket
+    fn stats(&self, machine_id: String, peer_machine_id: String) -> ConnectionStats {
+        let state = self.shared.state.lock().unwrap();
+        ConnectionStats {
+            machine_id,
+            peer_machine_id,
+            connected: !state.unreachable,
+            relayed: state.relayed,
+            reconnects: state.reconnects,
+            queued: state.messages.len() as u64,
+            dropped: state.dropped,
+        }
+    }
+}
+
+impl Drop for InterDaemonConnection {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().closed = true;
+        self.shared.message_added.notify_one();
     }
 }
 
+const RECONNECT_BACKOFF_MIN: Duration = Duration::from_millis(100);
+const RECONNECT_BACKOFF_MAX: Duration = Duration::from_secs(5);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// How often a relayed peer is tried to be reached directly again.
+const DIRECT_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+#[tracing::instrument(skip(shared, security, relay_tx))]
+async fn sender_loop(
+    socket: SocketAddr,
+    peer_machine_id: String,
+    shared: Arc<SharedPeerQueue>,
+    security: Security,
+    relay_tx: Option<mpsc::Sender<RelayMessage>>,
+) {
+    let mut connection: Option<MaybeTlsStream> = None;
+    let mut was_connected = false;
+    // time of the next direct connection attempt while messages are relayed
+    let mut relayed: Option<Instant> = None;
+    let mut backoff = RECONNECT_BACKOFF_MIN;
+    loop {
+        let message = {
+            let message_added = shared.message_added.notified();
+            let next = {
+                let mut state = shared.state.lock().unwrap();
+                if state.closed {
+                    break;
+                }
+                state.messages.pop_front()
+            };
+            match next {
+                Some(message) => message,
+                None => {
+                    message_added.await;
+                    continue;
+                }
+            }
+        };
+        shared.space_freed.notify_waiters();
+
+        if message.relay {
+            shared.state.lock().unwrap().relayed = true;
+            relay(&relay_tx, &peer_machine_id, message).await;
+            continue;
+        }
+        if let Some(next_attempt) = relayed {
+            if Instant::now() < next_attempt {
+                relay(&relay_tx, &peer_machine_id, message).await;
+                continue;
+            }
+            match connect(socket, &security).await {
+                Ok(stream) => {
+                    tracing::info!(
+                        "connected to machine `{peer_machine_id}` directly, \
+                        no longer relaying messages through the coordinator"
+                    );
+                    relayed = None;
+                    was_connected = true;
+                    {
+                        let mut state = shared.state.lock().unwrap();
+                        state.relayed = false;
+                        state.unreachable = false;
+                    }
+                    connection = Some(stream);
+                }
+                Err(err) => {
+                    tracing::debug!(
+                        "{:?}",
+                        err.wrap_err(format!(
+                            "machine `{peer_machine_id}` is still not reachable directly"
+                        ))
+                    );
+                    relayed = Some(Instant::now() + DIRECT_RETRY_INTERVAL);
+                    relay(&relay_tx, &peer_machine_id, message).await;
+                    continue;
+                }
+            }
+        }
+        let stream = match &mut connection {
+            Some(stream) => stream,
+            None => match connect(socket, &security).await {
+                Ok(stream) => {
+                    if was_connected {
+                        tracing::info!("reconnected to machine `{peer_machine_id}`");
+                        shared.state.lock().unwrap().reconnects += 1;
+                    }
+                    was_connected = true;
+                    backoff = RECONNECT_BACKOFF_MIN;
+                    shared.state.lock().unwrap().unreachable = false;
+                    connection.insert(stream)
+                }
+                Err(err) if !was_connected && relay_tx.is_some() => {
+                    tracing::warn!(
+                        "{:?}",
+                        err.wrap_err(format!(
+                            "failed to connect to machine `{peer_machine_id}`, \
+                            relaying messages through the coordinator"
+                        ))
+                    );
+                    relayed = Some(Instant::now() + DIRECT_RETRY_INTERVAL);
+                    {
+                        let mut state = shared.state.lock().unwrap();
+                        state.relayed = true;
+                        state.unreachable = true;
+                    }
+                    relay(&relay_tx, &peer_machine_id, message).await;
+                    continue;
+                }
+                Err(err) => {
+                    tracing::warn!(
+                        "{:?}",
+                        err.wrap_err(format!(
+                            "failed to connect to machine `{peer_machine_id}`, \
+                            retrying in {backoff:?}"
+                        ))
+                    );
+                    requeue(&shared, message);
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(RECONNECT_BACKOFF_MAX);
+                    continue;
+                }
+            },
+        };
+        if let Err(err) = socket_stream_send(stream, &message.data).await {
+            tracing::warn!("connection to machine `{peer_machine_id}` lost: {err}");
+            connection = None;
+            requeue(&shared, message);
+        }
+    }
+}
+
+/// Passes the message to the coordinator connection.
+async fn relay(
+    relay_tx: &Option<mpsc::Sender<RelayMessage>>,
+    peer_machine_id: &str,
+    message: QueuedMessage,
+) {
+    let message = RelayMessage {
+        target_machine: peer_machine_id.to_owned(),
+        data: message.data,
+    };
+    let sent = match relay_tx {
+        Some(relay_tx) => relay_tx.send(message).await.is_ok(),
+        None => false,
+    };
+    if !sent {
+        tracing::warn!("cannot relay message to machine `{peer_machine_id}` without coordinator");
+    }
+}
+
+/// Puts back a message that could not be sent and marks the peer as unreachable.
+fn requeue(shared: &SharedPeerQueue, message: QueuedMessage) {
+    let mut state = shared.state.lock().unwrap();
+    state.messages.push_front(message);
+    state.unreachable = true;
+    drop(state);
+    shared.space_freed.notify_waiters();
+}
+
+/// Opens a connection to the given inter-daemon listener.
+///
+/// If a token is configured, it is sent as the first message of the connection.
+async fn connect(socket: SocketAddr, security: &Security) -> eyre::Result<MaybeTlsStream> {
+    // unreachable peers might drop the connection request silently
+    let connection = tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect(socket))
+        .await
+        .map_err(|_| eyre!("connection timed out after {CONNECT_TIMEOUT:?}"))?
+        .wrap_err("failed to connect")?;
+    connection
+        .set_nodelay(true)
+        .wrap_err("failed to set nodelay")?;
+    let mut connection = security
+        .connect(connection)
+        .await
+        .wrap_err("TLS handshake failed")?;
+    if let Some(token) = security.token() {
+        socket_stream_send(&mut connection, token.as_bytes())
+            .await
+            .wrap_err("failed to send token")?;
+    }
+    Ok(connection)
+}
+
 #[tracing::instrument(skip(inter_daemon_connections))]
 pub async fn send_inter_daemon_event(
     target_machines: &[String],
-    inter_daemon_connections: &mut BTreeMap<String, InterDaemonConnection>,
+    inter_daemon_connections: &mut InterDaemonConnections,
+    remote: &RemoteCommunicationConfig,
     event: &Timestamped<InterDaemonEvent>,
+    queue: Option<&OutputQueue>,
+    transport: Transport,
 ) -> eyre::Result<()> {
     let message = bincode::serialize(event).wrap_err("failed to serialize InterDaemonEvent")?;
-    for target_machine in target_machines {
+    if let RemoteCommunicationConfig::Zenoh = remote {
         let connection = inter_daemon_connections
-            .get_mut(target_machine)
+            .pub_sub
+            .as_mut()
+            .context("zenoh session is not open")?;
+        for target_machine in target_machines {
+            connection
+                .send(target_machine, &message)
+                .wrap_err_with(|| format!("failed to send event to machine `{target_machine}`"))?;
+        }
+        return Ok(());
+    }
+    let relay = matches!(remote, RemoteCommunicationConfig::Relay);
+    if transport == Transport::BestEffort && !relay {
+        let InterDaemonConnections {
+            machine_id,
+            tcp,
+            best_effort,
+            security,
+            ..
+        } = inter_daemon_connections;
+        let ipv6 = target_machines
+            .first()
+            .and_then(|m| tcp.get(m))
+            .is_some_and(|c| c.socket().is_ipv6());
+        if let Some(sender) = best_effort_sender(best_effort, machine_id, security, ipv6).await {
+            match sender.fragment(&message) {
+                Ok(datagrams) => {
+                    for target_machine in target_machines {
+                        let connection = tcp.get(target_machine).wrap_err_with(|| {
+                            format!("unknown target machine `{target_machine}`")
+                        })?;
+                        if connection.is_relayed() {
+                            // the sender task relays the message or sends it directly
+                            // once the peer becomes reachable again
+                            connection
+                                .enqueue(message.clone().into(), queue, false)
+                                .await;
+                            continue;
+                        }
+                        if let Err(err) = sender.send(connection.socket(), &datagrams).await {
+                            tracing::debug!(
+                                "failed to send best-effort message to machine \
+                                `{target_machine}`: {err}"
+                            );
+                            connection.count_dropped();
+                        }
+                    }
+                    return Ok(());
+                }
+                Err(err) => tracing::warn!("{err:?}, sending it through TCP instead"),
+            }
+        }
+    }
+    let message: Arc<[u8]> = message.into();
+    for target_machine in target_machines {
+        inter_daemon_connections
+            .tcp
+            .get(target_machine)
             .wrap_err_with(|| format!("unknown target machine `{target_machine}`"))?
-            .connect()
-            .await
-            .wrap_err_with(|| format!("failed to connect to machine `{target_machine}`"))?;
-        socket_stream_send(connection, &message)
-            .await
-            .wrap_err_with(|| format!("failed to send event to machine `{target_machine}`"))?;
+            .enqueue(message.clone(), queue, relay)
+            .await;
     }
 
     Ok(())
 }
 
+/// Returns the sender for best-effort messages, binding its socket on first use.
+///
+/// Best-effort messages are neither encrypted nor authenticated, so they are sent through
+/// TCP instead if TLS or a token is configured.
+async fn best_effort_sender<'a>(
+    state: &'a mut BestEffortState,
+    machine_id: &str,
+    security: &Security,
+    ipv6: bool,
+) -> Option<&'a mut BestEffortSender> {
+    if let BestEffortState::Unbound = state {
+        *state = if !best_effort_supported(security) {
+            tracing::warn!(
+                "sending best-effort messages through TCP because TLS or token \
+                authentication is enabled"
+            );
+            BestEffortState::Unavailable
+        } else {
+            match BestEffortSender::bind(machine_id.to_owned(), ipv6).await {
+                Ok(sender) => BestEffortState::Bound(sender),
+                Err(err) => {
+                    tracing::warn!(
+                        "{:?}",
+                        err.wrap_err("sending best-effort messages through TCP instead")
+                    );
+                    BestEffortState::Unavailable
+                }
+            }
+        };
+    }
+    match state {
+        BestEffortState::Bound(sender) => Some(sender),
+        _ => None,
+    }
+}
+
+fn best_effort_supported(security: &Security) -> bool {
+    !security.tls_enabled() && security.token().is_none()
+}
+
+/// Listens for connections of other daemons on the given address.
+///
+/// Messages for inputs with `transport: best-effort` are received on a UDP socket with
+/// the same address and port.
 pub async fn spawn_listener_loop(
     bind: SocketAddr,
     machine_id: String,
     events_tx: flume::Sender<Timestamped<InterDaemonEvent>>,
+    security: Security,
+    best_effort_counters: ReceiveCounters,
 ) -> eyre::Result<u16> {
     let socket = match TcpListener::bind(bind).await {
         Ok(socket) => socket,
@@ -76,8 +700,24 @@ pub async fn spawn_listener_loop(
         .wrap_err("failed to get local addr of socket")?
         .port();
 
+    if best_effort_supported(&security) {
+        match UdpSocket::bind(SocketAddr::new(bind.ip(), listen_port)).await {
+            Ok(socket) => {
+                tokio::spawn(best_effort_receiver_loop(
+                    socket,
+                    events_tx.clone(),
+                    best_effort_counters,
+                ));
+            }
+            Err(err) => tracing::warn!(
+                "failed to bind UDP socket, inputs with `transport: best-effort` will not \
+                receive messages from other machines: {err}"
+            ),
+        }
+    }
+
     tokio::spawn(async move {
-        listener_loop(socket, events_tx).await;
+        listener_loop(socket, events_tx, security).await;
         tracing::debug!("inter-daemon listener loop finished for machine `{machine_id}`");
     });
 
@@ -87,6 +727,7 @@ pub async fn spawn_listener_loop(
 async fn listener_loop(
     listener: TcpListener,
     events_tx: flume::Sender<Timestamped<InterDaemonEvent>>,
+    security: Security,
 ) {
     loop {
         match listener
@@ -98,24 +739,46 @@ async fn listener_loop(
                 tracing::info!("{err}");
             }
             Ok((connection, _)) => {
-                tokio::spawn(handle_connection_loop(connection, events_tx.clone()));
+                tokio::spawn(handle_connection_loop(
+                    connection,
+                    events_tx.clone(),
+                    security.clone(),
+                ));
             }
         }
     }
 }
 
 async fn handle_connection_loop(
-    mut connection: TcpStream,
+    connection: TcpStream,
     events_tx: flume::Sender<Timestamped<InterDaemonEvent>>,
+    security: Security,
 ) {
     if let Err(err) = connection.set_nodelay(true) {
         tracing::warn!("failed to set nodelay for connection: {err}");
     }
+    let mut connection = match security.accept(connection).await {
+        Ok(connection) => connection,
+        Err(err) => {
+            tracing::warn!("TLS handshake with other daemon failed: {err}");
+            return;
+        }
+    };
+    if security.token().is_some() {
+        let token = socket_stream_receive_bounded(&mut connection, MAX_PRE_AUTH_MESSAGE_SIZE)
+            .await
+            .ok();
+        let token = token.as_deref().and_then(|t| std::str::from_utf8(t).ok());
+        if !security.verify_token(token) {
+            tracing::warn!("closing inter-daemon connection because of an invalid token");
+            return;
+        }
+    }
 
     loop {
         match receive_message(&mut connection).await {
             Ok(Some(message)) => {
-                if events_tx.send_async(message).await.is_err() {
+                if forward_event(message, &events_tx).await.is_err() {
                     break;
                 }
             }
@@ -128,8 +791,97 @@ async fn handle_connection_loop(
     }
 }
 
+/// Receives the events that other daemons send through best-effort transport.
+async fn best_effort_receiver_loop(
+    socket: UdpSocket,
+    events_tx: flume::Sender<Timestamped<InterDaemonEvent>>,
+    counters: ReceiveCounters,
+) {
+    let mut reassembler = Reassembler::new(counters);
+    let mut buffer = vec![0; u16::MAX.into()];
+    let mut expiry = tokio::time::interval(best_effort::REASSEMBLY_TIMEOUT);
+    loop {
+        let len = tokio::select! {
+            received = socket.recv(&mut buffer) => match received {
+                Ok(len) => len,
+                Err(err) => {
+                    tracing::debug!("failed to receive best-effort datagram: {err}");
+                    continue;
+                }
+            },
+            _ = expiry.tick() => {
+                reassembler.drop_expired();
+                continue;
+            }
+        };
+        let raw = match reassembler.push(&buffer[..len]) {
+            Ok(Some(raw)) => raw,
+            Ok(None) => continue,
+            Err(err) => {
+                tracing::debug!("ignoring invalid best-effort datagram: {err}");
+                continue;
+            }
+        };
+        let message = match bincode::deserialize(&raw) {
+            Ok(message) => message,
+            Err(err) => {
+                tracing::warn!("failed to deserialize inter-daemon event: {err}");
+                continue;
+            }
+        };
+        if forward_event(message, &events_tx).await.is_err() {
+            break;
+        }
+    }
+}
+
+/// Receives the events published to this daemon's zenoh topic on a separate thread since
+/// the [`Subscriber`] interface is blocking.
+fn spawn_subscriber_loop(
+    mut subscriber: Box<dyn Subscriber>,
+    events_tx: flume::Sender<Timestamped<InterDaemonEvent>>,
+) {
+    let runtime = tokio::runtime::Handle::current();
+    std::thread::spawn(move || loop {
+        let sample = match subscriber.recv() {
+            Ok(Some(sample)) => sample,
+            Ok(None) => break,
+            Err(err) => {
+                tracing::warn!("failed to receive inter-daemon event: {err}");
+                break;
+            }
+        };
+        let message = match bincode::deserialize(&sample.get()) {
+            Ok(message) => message,
+            Err(err) => {
+                tracing::warn!("failed to deserialize inter-daemon event: {err}");
+                continue;
+            }
+        };
+        if runtime
+            .block_on(forward_event(message, &events_tx))
+            .is_err()
+        {
+            break;
+        }
+    });
+}
+
+/// Passes the given event to the daemon, returns an error if the daemon is no longer
+/// running.
+///
+/// Inputs with `overflow: block` never hold up the receiving side. Their queue credits
+/// are kept by the sending daemon and given back through [`InterDaemonEvent::InputCredits`]
+/// once the input was delivered.
+async fn forward_event(
+    message: Timestamped<InterDaemonEvent>,
+    events_tx: &flume::Sender<Timestamped<InterDaemonEvent>>,
+) -> Result<(), flume::SendError<Timestamped<InterDaemonEvent>>> {
+    events_tx.send_async(message).await
+}
+
 async fn receive_message(
-    connection: &mut TcpStream,
+    connection: &mut MaybeTlsStream,
 ) -> eyre::Result<Option<Timestamped<InterDaemonEvent>>> {
     let raw = match socket_stream_receive(connection).await {
         Ok(raw) => raw,
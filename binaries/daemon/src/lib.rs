@@ -1,61 +1,85 @@
 use aligned_vec::{AVec, ConstAlign};
-use coordinator::CoordinatorEvent;
+use artifacts::ArtifactCache;
+use backpressure::Backpressure;
+use coordinator::{CoordinatorConnection, CoordinatorEvent, Registration};
 use crossbeam::queue::ArrayQueue;
 use dora_core::{
-    config::{DataId, Input, InputMapping, NodeId, OperatorId},
-    descriptor::{runtime_node_inputs, CoreNodeKind, Descriptor, ResolvedNode},
+    config::{
+        Compression, DataId, HumanDuration, Input, InputMapping, NodeId, OperatorId,
+        OverflowPolicy, Transport,
+    },
+    descriptor::{
+        resolve_path, runtime_node_inputs, source_is_url, CoreNodeKind, Descriptor, ResolvedNode,
+        DYNAMIC_SOURCE, SHELL_SOURCE,
+    },
     topics::LOCALHOST,
     uhlc::{self, HLC},
 };
 use dora_message::{
-    common::{DataMessage, DropToken, LogLevel, NodeError, NodeErrorCause, NodeExitStatus},
+    common::{
+        ArtifactData, DataMessage, DataflowStats, DropToken, InputStats, LogLevel, NodeArtifact,
+        NodeError, NodeErrorCause, NodeExitStatus, NodeInputStats, OutputRate, OutputStats,
+    },
     coordinator_to_cli::DataflowResult,
-    coordinator_to_daemon::{DaemonCoordinatorEvent, SpawnDataflowNodes},
+    coordinator_to_daemon::{
+        DaemonCoordinatorEvent, DataflowChanges, NodeInput, SpawnDataflowNodes,
+    },
     daemon_to_coordinator::{
-        CoordinatorRequest, DaemonCoordinatorReply, DaemonEvent, DataflowDaemonResult, LogMessage,
+        DaemonCoordinatorReply, DaemonEvent, DataflowDaemonResult, LogMessage, MachineProperties,
     },
-    daemon_to_daemon::InterDaemonEvent,
+    daemon_to_daemon::{InterDaemonEvent, RelayedEvent},
     daemon_to_node::{DaemonReply, NodeConfig, NodeDropEvent, NodeEvent},
     metadata::{self, ArrowTypeInfo},
     node_to_daemon::{DynamicNodeEvent, Timestamped},
     DataflowId,
 };
 use dora_node_api::Parameter;
+use dora_security::Security;
 use eyre::{bail, eyre, Context, ContextCompat, Result};
 use futures::{future, stream, FutureExt, TryFutureExt};
 use futures_concurrency::stream::Merge;
-use inter_daemon::InterDaemonConnection;
+use inter_daemon::{InterDaemonConnections, OutputQueue, PubSubReceiver, RelayMessage};
 use local_listener::DynamicNodeEventWrapper;
+use node_communication::{InputQueue, QueueConfig};
 use pending::PendingNodes;
+use rate_monitor::{RateDeviation, RateMonitor};
 use shared_memory_server::ShmemConf;
-use socket_stream_utils::socket_stream_send;
 use std::{
     collections::{BTreeMap, BTreeSet, HashMap},
     net::SocketAddr,
     path::{Path, PathBuf},
-    sync::Arc,
+    pin::Pin,
+    sync::{Arc, Mutex},
     time::{Duration, Instant},
 };
 use sysinfo::Pid;
 use tokio::{
     fs::File,
     io::AsyncReadExt,
-    net::TcpStream,
     sync::{
         mpsc::{self, UnboundedSender},
         oneshot::{self, Sender},
+        Semaphore,
     },
 };
 use tokio_stream::{wrappers::ReceiverStream, Stream, StreamExt};
 use tracing::{error, warn};
 use uuid::{NoContext, Timestamp, Uuid};
 
+mod artifacts;
+mod backpressure;
+mod best_effort;
+mod build;
+mod cgroup;
+mod compression;
 mod coordinator;
 mod inter_daemon;
 mod local_listener;
 mod log;
 mod node_communication;
 mod pending;
+mod rate_monitor;
+mod scheduling;
 mod socket_stream_utils;
 mod spawn;
 
@@ -68,62 +92,103 @@ use crate::pending::DataflowStatus;
 
 const STDERR_LOG_LINES: usize = 10;
 
+/// Default limit for the size of a message from another machine after decompression.
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 1 << 30;
+
 pub struct Daemon {
     running: HashMap<DataflowId, RunningDataflow>,
     working_dir: HashMap<DataflowId, PathBuf>,
 
     events_tx: mpsc::Sender<Timestamped<Event>>,
 
-    coordinator_connection: Option<TcpStream>,
+    coordinator_connection: Option<CoordinatorConnection>,
     last_coordinator_heartbeat: Instant,
-    inter_daemon_connections: BTreeMap<String, InterDaemonConnection>,
+    coordinator_reconnect_scheduled: bool,
+    inter_daemon_connections: InterDaemonConnections,
     machine_id: String,
 
     /// used for testing and examples
     exit_when_done: Option<BTreeSet<(Uuid, NodeId)>>,
     /// used to record dataflow results when `exit_when_done` is used
     dataflow_node_results: BTreeMap<Uuid, BTreeMap<NodeId, Result<(), NodeError>>>,
+    /// number of restarts per node, caused by the node's restart policy
+    dataflow_node_restarts: BTreeMap<Uuid, BTreeMap<NodeId, u32>>,
+
+    /// queue credits of local inputs with `overflow: block`
+    backpressure: Backpressure,
+
+    /// node artifacts uploaded through `dora start --upload`
+    artifact_cache: ArtifactCache,
+
+    /// maximum size of a message from another machine after decompression
+    max_message_size: usize,
 
     clock: Arc<uhlc::HLC>,
 }
 
-type DaemonRunResult = BTreeMap<Uuid, BTreeMap<NodeId, Result<(), NodeError>>>;
+#[derive(Debug, Default)]
+struct DaemonRunResult {
+    node_results: BTreeMap<Uuid, BTreeMap<NodeId, Result<(), NodeError>>>,
+    node_restarts: BTreeMap<Uuid, BTreeMap<NodeId, u32>>,
+}
 
 impl Daemon {
+    #[allow(clippy::too_many_arguments)]
     pub async fn run(
         coordinator_addr: SocketAddr,
         machine_id: String,
         inter_daemon_addr: SocketAddr,
         local_listen_port: u16,
+        properties: MachineProperties,
+        zenoh_config: Option<PathBuf>,
+        artifact_dir: Option<PathBuf>,
+        max_message_size: usize,
+        security: Security,
     ) -> eyre::Result<()> {
         let clock = Arc::new(HLC::default());
 
         let ctrlc_events = set_up_ctrlc_handler(clock.clone())?;
 
         // spawn inter daemon listen loop
+        let backpressure = Backpressure::default();
         let (events_tx, events_rx) = flume::bounded(10);
-        let listen_port =
-            inter_daemon::spawn_listener_loop(inter_daemon_addr, machine_id.clone(), events_tx)
-                .await?;
+        let (relay_tx, relay_rx) = mpsc::channel(10);
+        let inter_daemon_connections = InterDaemonConnections::new(
+            machine_id.clone(),
+            Some(PubSubReceiver {
+                machine_id: machine_id.clone(),
+                zenoh_config,
+                events_tx: events_tx.clone(),
+            }),
+            relay_tx,
+            security.clone(),
+        );
+        let listen_port = inter_daemon::spawn_listener_loop(
+            inter_daemon_addr,
+            machine_id.clone(),
+            events_tx,
+            security.clone(),
+            inter_daemon_connections.best_effort_counters(),
+        )
+        .await?;
         let daemon_events = events_rx.into_stream().map(|e| Timestamped {
             inner: Event::Daemon(e.inner),
             timestamp: e.timestamp,
         });
 
         // connect to the coordinator
+        let (coordinator_connection, events) = CoordinatorConnection::connect(
+            coordinator_addr,
+            machine_id.clone(),
+            listen_port,
+            properties,
+            security,
+            &clock,
+        )
+        .await
+        .wrap_err("failed to connect to dora-coordinator")?;
         let coordinator_events =
-            coordinator::register(coordinator_addr, machine_id.clone(), listen_port, &clock)
-                .await
-                .wrap_err("failed to connect to dora-coordinator")?
-                .map(
-                    |Timestamped {
-                         inner: event,
-                         timestamp,
-                     }| Timestamped {
-                        inner: Event::Coordinator(event),
-                        timestamp,
-                    },
-                );
+            coordinator_events(events, coordinator_connection.generation(), clock.clone());
 
         // Spawn local listener loop
         let (events_tx, events_rx) = flume::bounded(10);
@@ -137,17 +202,27 @@ impl Daemon {
             inner: Event::DynamicNode(e.inner),
             timestamp: e.timestamp,
         });
+        let relay_clock = clock.clone();
+        let relay_events = ReceiverStream::new(relay_rx).map(move |message| Timestamped {
+            inner: Event::Relay(message),
+            timestamp: relay_clock.new_timestamp(),
+        });
         Self::run_general(
             (
                 coordinator_events,
                 ctrlc_events,
                 daemon_events,
                 dynamic_node_events,
+                relay_events,
             )
                 .merge(),
-            Some(coordinator_addr),
+            Some(coordinator_connection),
+            inter_daemon_connections,
             machine_id,
             None,
+            backpressure,
+            ArtifactCache::new(artifact_dir.unwrap_or_else(ArtifactCache::default_dir)),
+            max_message_size,
             clock,
         )
         .await
@@ -173,6 +248,8 @@ impl Daemon {
             nodes,
             machine_listen_ports: BTreeMap::new(),
             dataflow_descriptor: descriptor,
+            artifacts: BTreeMap::new(),
+            artifact_data: Vec::new(),
         };
 
         let clock = Arc::new(HLC::default());
@@ -196,8 +273,12 @@ impl Daemon {
         let run_result = Self::run_general(
             Box::pin(coordinator_events),
             None,
+            InterDaemonConnections::default(),
             "".to_string(),
             Some(exit_when_done),
+            Backpressure::default(),
+            ArtifactCache::new(ArtifactCache::default_dir()),
+            DEFAULT_MAX_MESSAGE_SIZE,
             clock.clone(),
         );
 
@@ -218,31 +299,29 @@ impl Daemon {
             uuid: dataflow_id,
             timestamp: clock.new_timestamp(),
             node_results: dataflow_results
+                .node_results
                 .remove(&dataflow_id)
                 .context("no node results for dataflow_id")?,
+            node_restarts: dataflow_results
+                .node_restarts
+                .remove(&dataflow_id)
+                .unwrap_or_default(),
         })
     }
 
+    #[allow(clippy::too_many_arguments)]
+    #[allow(clippy::too_many_arguments)]
     async fn run_general(
         external_events: impl Stream<Item = Timestamped<Event>> + Unpin,
-        coordinator_addr: Option<SocketAddr>,
+        coordinator_connection: Option<CoordinatorConnection>,
+        inter_daemon_connections: InterDaemonConnections,
         machine_id: String,
         exit_when_done: Option<BTreeSet<(Uuid, NodeId)>>,
+        backpressure: Backpressure,
+        artifact_cache: ArtifactCache,
+        max_message_size: usize,
         clock: Arc<HLC>,
     ) -> eyre::Result<DaemonRunResult> {
-        let coordinator_connection = match coordinator_addr {
-            Some(addr) => {
-                let stream = TcpStream::connect(addr)
-                    .await
-                    .wrap_err("failed to connect to dora-coordinator")?;
-                stream
-                    .set_nodelay(true)
-                    .wrap_err("failed to set TCP_NODELAY")?;
-                Some(stream)
-            }
-            None => None,
-        };
-
         let (dora_events_tx, dora_events_rx) = mpsc::channel(5);
         let daemon = Self {
             running: HashMap::new(),
@@ -250,10 +329,15 @@ impl Daemon {
             events_tx: dora_events_tx,
             coordinator_connection,
             last_coordinator_heartbeat: Instant::now(),
-            inter_daemon_connections: BTreeMap::new(),
+            coordinator_reconnect_scheduled: false,
+            inter_daemon_connections,
             machine_id,
             exit_when_done,
             dataflow_node_results: BTreeMap::new(),
+            dataflow_node_restarts: BTreeMap::new(),
+            backpressure,
+            artifact_cache,
+            max_message_size,
             clock,
         };
 
@@ -295,6 +379,23 @@ impl Daemon {
                 Event::Daemon(event) => {
                     self.handle_inter_daemon_event(event).await?;
                 }
+                Event::Relay(RelayMessage {
+                    target_machine,
+                    data,
+                }) => match &mut self.coordinator_connection {
+                    Some(connection) => {
+                        let event = DaemonEvent::Relay {
+                            target_machine,
+                            event: RelayedEvent(data.to_vec()),
+                        };
+                        connection
+                            .send_event(event, self.clock.new_timestamp())
+                            .await;
+                    }
+                    None => tracing::warn!(
+                        "cannot relay message to machine `{target_machine}` without coordinator"
+                    ),
+                },
                 Event::Node {
                     dataflow_id: dataflow,
                     node_id,
@@ -307,53 +408,145 @@ impl Daemon {
                 Event::DynamicNode(event) => self.handle_dynamic_node_event(event).await?,
                 Event::HeartbeatInterval => {
                     if let Some(connection) = &mut self.coordinator_connection {
-                        let msg = serde_json::to_vec(&Timestamped {
-                            inner: CoordinatorRequest::Event {
-                                machine_id: self.machine_id.clone(),
-                                event: DaemonEvent::Heartbeat,
-                            },
-                            timestamp: self.clock.new_timestamp(),
-                        })?;
-                        socket_stream_send(connection, &msg)
-                            .await
-                            .wrap_err("failed to send watchdog message to dora-coordinator")?;
-
-                        if self.last_coordinator_heartbeat.elapsed() > Duration::from_secs(20) {
-                            bail!("lost connection to coordinator")
+                        if connection.is_connected() {
+                            connection
+                                .send_event(DaemonEvent::Heartbeat, self.clock.new_timestamp())
+                                .await;
+                        }
+                    }
+                    self.check_coordinator_connection();
+                    self.check_output_rates().await?;
+                }
+                Event::CoordinatorDisconnected { generation } => {
+                    if let Some(connection) = &mut self.coordinator_connection {
+                        if connection.generation() == generation && connection.disconnect() {
+                            tracing::warn!("lost connection to dora-coordinator");
                         }
                     }
+                    self.check_coordinator_connection();
+                }
+                Event::ReconnectCoordinator => self.reconnect_to_coordinator(),
+                Event::CoordinatorReconnected(result) => {
+                    self.handle_coordinator_reconnected(result).await
                 }
                 Event::CtrlC => {
-                    for dataflow in self.running.values_mut() {
-                        dataflow
-                            .stop_all(&mut self.coordinator_connection, &self.clock, None)
-                            .await?;
+                    let dataflow_ids: Vec<_> = self.running.keys().copied().collect();
+                    for dataflow_id in dataflow_ids {
+                        match self.stop_dataflow(dataflow_id, None).await? {
+                            RunStatus::Continue => {}
+                            RunStatus::Exit => break,
+                        }
                     }
                 }
             }
         }
 
-        Ok(self.dataflow_node_results)
+        Ok(DaemonRunResult {
+            node_results: self.dataflow_node_results,
+            node_restarts: self.dataflow_node_restarts,
+        })
     }
 
     async fn send_log_message(&mut self, message: LogMessage) -> eyre::Result<()> {
         if let Some(connection) = &mut self.coordinator_connection {
-            let msg = serde_json::to_vec(&Timestamped {
-                inner: CoordinatorRequest::Event {
-                    machine_id: self.machine_id.clone(),
-                    event: DaemonEvent::Log(message),
-                },
-                timestamp: self.clock.new_timestamp(),
-            })?;
-            socket_stream_send(connection, &msg)
-                .await
-                .wrap_err("failed to send watchdog message to dora-coordinator")?;
+            connection
+                .send_event(DaemonEvent::Log(message), self.clock.new_timestamp())
+                .await;
+        }
+        Ok(())
+    }
+
+    /// Treats the coordinator connection as lost if no heartbeat was received recently
+    /// and schedules a reconnect if the connection is lost.
+    fn check_coordinator_connection(&mut self) {
+        let Some(connection) = &mut self.coordinator_connection else {
+            return;
+        };
+        if connection.is_connected()
+            && self.last_coordinator_heartbeat.elapsed() > Duration::from_secs(20)
+        {
+            tracing::warn!("no heartbeat from dora-coordinator since 20s");
+            connection.disconnect();
+        }
+        if !connection.is_connected() && !self.coordinator_reconnect_scheduled {
+            let delay = connection.next_reconnect_backoff();
+            self.coordinator_reconnect_scheduled = true;
+            let events_tx = self.events_tx.clone();
+            let clock = self.clock.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(delay).await;
+                let event = Timestamped {
+                    inner: Event::ReconnectCoordinator,
+                    timestamp: clock.new_timestamp(),
+                };
+                let _ = events_tx.send(event).await;
+            });
+        }
+    }
+
+    /// Registers at the coordinator again in a background task, announcing the dataflows
+    /// that are still running on this daemon.
+    ///
+    /// The result is reported through an [`Event::CoordinatorReconnected`] event.
+    fn reconnect_to_coordinator(&mut self) {
+        let Some(connection) = &mut self.coordinator_connection else {
+            self.coordinator_reconnect_scheduled = false;
+            return;
+        };
+        if connection.is_connected() {
+            self.coordinator_reconnect_scheduled = false;
+            return;
+        }
+        // dataflows with an unreported result are announced as running, so that the
+        // coordinator can still match the result to the dataflow
+        let mut running_dataflows: BTreeSet<_> = self.running.keys().copied().collect();
+        running_dataflows.extend(connection.unreported_finished_dataflows());
+
+        let registration =
+            connection.registration(running_dataflows.into_iter().collect(), self.clock.clone());
+        let events_tx = self.events_tx.clone();
+        let clock = self.clock.clone();
+        tokio::spawn(async move {
+            let result = match tokio::time::timeout(Duration::from_secs(5), registration).await {
+                Ok(result) => result,
+                Err(_) => Err(eyre!("timeout while reconnecting to dora-coordinator")),
+            };
+            let event = Timestamped {
+                inner: Event::CoordinatorReconnected(result),
+                timestamp: clock.new_timestamp(),
+            };
+            let _ = events_tx.send(event).await;
+        });
+    }
 
-            if self.last_coordinator_heartbeat.elapsed() > Duration::from_secs(20) {
-                bail!("lost connection to coordinator")
+    async fn handle_coordinator_reconnected(&mut self, result: eyre::Result<Registration>) {
+        self.coordinator_reconnect_scheduled = false;
+        let Some(connection) = &mut self.coordinator_connection else {
+            return;
+        };
+        match result {
+            Ok(registration) => {
+                tracing::info!("reconnected to dora-coordinator");
+                self.last_coordinator_heartbeat = Instant::now();
+                let events = connection.reconnected(registration).await;
+                let mut events =
+                    coordinator_events(events, connection.generation(), self.clock.clone());
+                let events_tx = self.events_tx.clone();
+                tokio::spawn(async move {
+                    while let Some(event) = events.next().await {
+                        if events_tx.send(event).await.is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+            Err(err) => {
+                tracing::debug!("failed to reconnect to dora-coordinator: {err:?}");
             }
         }
-        Ok(())
+        // schedules the next attempt if the reconnect failed or the connection was lost
+        // again while sending the buffered events
+        self.check_coordinator_connection();
     }
 
     async fn handle_coordinator_event(
@@ -368,26 +561,39 @@ impl Daemon {
                 nodes,
                 machine_listen_ports,
                 dataflow_descriptor,
+                artifacts,
+                artifact_data,
             }) => {
-                match dataflow_descriptor.communication.remote {
-                    dora_core::config::RemoteCommunicationConfig::Tcp => {}
-                }
-                for (machine_id, socket) in machine_listen_ports {
-                    match self.inter_daemon_connections.entry(machine_id) {
-                        std::collections::btree_map::Entry::Vacant(entry) => {
-                            entry.insert(InterDaemonConnection::new(socket));
-                        }
-                        std::collections::btree_map::Entry::Occupied(mut entry) => {
-                            if entry.get().socket() != socket {
-                                entry.insert(InterDaemonConnection::new(socket));
-                            }
-                        }
+                let is_distributed = machine_listen_ports
+                    .keys()
+                    .any(|machine_id| machine_id != &self.machine_id);
+                let connect_result = match dataflow_descriptor.communication.remote {
+                    dora_core::config::RemoteCommunicationConfig::Tcp
+                    | dora_core::config::RemoteCommunicationConfig::Relay => {
+                        self.inter_daemon_connections
+                            .update_sockets(machine_listen_ports);
+                        Ok(())
                     }
-                }
+                    dora_core::config::RemoteCommunicationConfig::Zenoh if is_distributed => {
+                        self.inter_daemon_connections.open_zenoh()
+                    }
+                    dora_core::config::RemoteCommunicationConfig::Zenoh => Ok(()),
+                };
 
-                let result = self
-                    .spawn_dataflow(dataflow_id, working_dir, nodes, dataflow_descriptor)
-                    .await;
+                let result = match connect_result {
+                    Ok(()) => {
+                        self.spawn_dataflow(
+                            dataflow_id,
+                            working_dir,
+                            nodes,
+                            dataflow_descriptor,
+                            artifacts,
+                            artifact_data,
+                        )
+                        .await
+                    }
+                    Err(err) => Err(err.wrap_err("failed to set up inter-daemon communication")),
+                };
                 if let Err(err) = &result {
                     tracing::error!("{err:?}");
                 }
@@ -484,28 +690,148 @@ impl Daemon {
                     .map_err(|_| error!("could not send reload reply from daemon to coordinator"));
                 RunStatus::Continue
             }
+            DaemonCoordinatorEvent::PrepareChanges(changes) => {
+                let result = self.prepare_changes(changes);
+                let reply =
+                    DaemonCoordinatorReply::PrepareResult(result.map_err(|err| format!("{err:?}")));
+                let _ = reply_tx
+                    .send(Some(reply))
+                    .map_err(|_| error!("could not send prepare reply from daemon to coordinator"));
+                RunStatus::Continue
+            }
+            DaemonCoordinatorEvent::ApplyChanges { dataflow_id } => {
+                let changes = self
+                    .running
+                    .get_mut(&dataflow_id)
+                    .and_then(|dataflow| dataflow.prepared_changes.take())
+                    .wrap_err_with(|| format!("no prepared changes for dataflow `{dataflow_id}`"));
+                let result = match changes {
+                    Ok(changes) => self.apply_changes(changes).await,
+                    Err(err) => Err(err),
+                };
+                let reply =
+                    DaemonCoordinatorReply::ApplyResult(result.map_err(|err| format!("{err:?}")));
+                let _ = reply_tx
+                    .send(Some(reply))
+                    .map_err(|_| error!("could not send apply reply from daemon to coordinator"));
+                RunStatus::Continue
+            }
+            DaemonCoordinatorEvent::DiscardChanges { dataflow_id } => {
+                if let Some(dataflow) = self.running.get_mut(&dataflow_id) {
+                    dataflow.prepared_changes = None;
+                }
+                let _ = reply_tx.send(None).map_err(|_| {
+                    error!("could not send `DiscardChanges` reply from daemon to coordinator")
+                });
+                RunStatus::Continue
+            }
+            DaemonCoordinatorEvent::RestartNode {
+                dataflow_id,
+                node_id,
+                grace_duration,
+            } => {
+                let result = self
+                    .request_restart(dataflow_id, node_id, grace_duration)
+                    .await;
+                let reply =
+                    DaemonCoordinatorReply::RestartResult(result.map_err(|err| format!("{err:?}")));
+                let _ = reply_tx
+                    .send(Some(reply))
+                    .map_err(|_| error!("could not send restart reply from daemon to coordinator"));
+                RunStatus::Continue
+            }
+            DaemonCoordinatorEvent::Stats { dataflow_id } => {
+                let stats = match self.running.get(&dataflow_id) {
+                    Some(dataflow) => {
+                        let mut stats = dataflow.stats();
+                        let peers: BTreeSet<_> = dataflow
+                            .open_external_mappings
+                            .values()
+                            .flat_map(|m| m.keys())
+                            .collect();
+                        stats.connections =
+                            self.inter_daemon_connections.stats(&self.machine_id, peers);
+                        stats.best_effort = self
+                            .inter_daemon_connections
+                            .best_effort_stats(&self.machine_id);
+                        Ok(stats)
+                    }
+                    None => Err(format!("no running dataflow with ID `{dataflow_id}`")),
+                };
+                let _ = reply_tx
+                    .send(Some(DaemonCoordinatorReply::Stats(stats)))
+                    .map_err(|_| error!("could not send stats reply from daemon to coordinator"));
+                RunStatus::Continue
+            }
+            DaemonCoordinatorEvent::Build { build_id, nodes } => {
+                let _ = reply_tx.send(None);
+                tracing::info!("building {} nodes for build `{build_id}`", nodes.len());
+                build::spawn_build(build_id, nodes, self.events_tx.clone(), self.clock.clone());
+                RunStatus::Continue
+            }
+            DaemonCoordinatorEvent::MissingArtifacts { hashes } => {
+                let missing = self.artifact_cache.missing(hashes).await;
+                let _ = reply_tx
+                    .send(Some(DaemonCoordinatorReply::MissingArtifacts(missing)))
+                    .map_err(|_| {
+                        error!("could not send missing artifacts reply from daemon to coordinator")
+                    });
+                RunStatus::Continue
+            }
+            DaemonCoordinatorEvent::MachineLost {
+                dataflow_id,
+                machine_id,
+                closed_inputs,
+            } => {
+                let _ = reply_tx.send(None);
+                match self.running.get_mut(&dataflow_id) {
+                    Some(dataflow) => {
+                        tracing::warn!(
+                            "machine `{machine_id}` of dataflow `{dataflow_id}` was lost, \
+                            closing inputs {closed_inputs:?}"
+                        );
+                        // stop sending outputs to the lost machine
+                        for machines in dataflow.open_external_mappings.values_mut() {
+                            machines.remove(&machine_id);
+                        }
+                        for (receiver_id, input_id) in &closed_inputs {
+                            close_input(dataflow, receiver_id, input_id, &self.clock);
+                        }
+                    }
+                    None => {
+                        tracing::warn!(
+                            "received MachineLost for unknown dataflow (ID `{dataflow_id}`)"
+                        );
+                    }
+                }
+                RunStatus::Continue
+            }
+            DaemonCoordinatorEvent::OutputRates { dataflow_id } => {
+                let rates = match self.running.get(&dataflow_id) {
+                    Some(dataflow) => Ok(dataflow.output_rates()),
+                    None => Err(format!("no running dataflow with ID `{dataflow_id}`")),
+                };
+                let _ = reply_tx
+                    .send(Some(DaemonCoordinatorReply::OutputRates(rates)))
+                    .map_err(|_| {
+                        error!("could not send output rates reply from daemon to coordinator")
+                    });
+                RunStatus::Continue
+            }
             DaemonCoordinatorEvent::StopDataflow {
                 dataflow_id,
                 grace_duration,
             } => {
-                let dataflow = self
-                    .running
-                    .get_mut(&dataflow_id)
-                    .wrap_err_with(|| format!("no running dataflow with ID `{dataflow_id}`"))?;
+                if !self.running.contains_key(&dataflow_id) {
+                    bail!("no running dataflow with ID `{dataflow_id}`");
+                }
 
                 let reply = DaemonCoordinatorReply::StopResult(Ok(()));
                 let _ = reply_tx
                     .send(Some(reply))
                     .map_err(|_| error!("could not send stop reply from daemon to coordinator"));
 
-                dataflow
-                    .stop_all(
-                        &mut self.coordinator_connection,
-                        &self.clock,
-                        grace_duration,
-                    )
-                    .await?;
-                RunStatus::Continue
+                self.stop_dataflow(dataflow_id, grace_duration).await?
             }
             DaemonCoordinatorEvent::Destroy => {
                 tracing::info!("received destroy command -> exiting");
@@ -528,6 +854,24 @@ impl Daemon {
                 let _ = reply_tx.send(None);
                 RunStatus::Continue
             }
+            DaemonCoordinatorEvent::Relay {
+                source_machine,
+                event,
+            } => {
+                let _ = reply_tx.send(None);
+                match bincode::deserialize::<Timestamped<InterDaemonEvent>>(&event.0) {
+                    Ok(event) => {
+                        if let Err(err) = self.clock.update_with_timestamp(&event.timestamp) {
+                            tracing::warn!("failed to update HLC with relayed event: {err}");
+                        }
+                        self.handle_inter_daemon_event(event.inner).await?;
+                    }
+                    Err(err) => tracing::warn!(
+                        "failed to deserialize event relayed from machine `{source_machine}`: {err}"
+                    ),
+                }
+                RunStatus::Continue
+            }
         };
         Ok(status)
     }
@@ -540,11 +884,19 @@ impl Daemon {
                 output_id,
                 metadata,
                 data,
+                compression,
             } => {
                 let inner = async {
                     let dataflow = self.running.get_mut(&dataflow_id).wrap_err_with(|| {
                         format!("send out failed: no running dataflow with ID `{dataflow_id}`")
                     })?;
+                    let data = match (compression, data) {
+                        (Some(compression), Some(data)) => Some(
+                            compression::decompress(compression, data, self.max_message_size)
+                                .await?,
+                        ),
+                        (_, data) => data,
+                    };
                     send_output_to_local_receivers(
                         node_id.clone(),
                         output_id.clone(),
@@ -564,6 +916,21 @@ impl Daemon {
                 }
                 Ok(())
             }
+            InterDaemonEvent::InputCredits {
+                dataflow_id,
+                node_id,
+                input_id,
+                credits,
+            } => {
+                if let Some(semaphore) = self
+                    .running
+                    .get(&dataflow_id)
+                    .and_then(|dataflow| dataflow.remote_input_credits.get(&(node_id, input_id)))
+                {
+                    semaphore.add_permits(credits);
+                }
+                Ok(())
+            }
             InterDaemonEvent::InputsClosed {
                 dataflow_id,
                 inputs,
@@ -593,10 +960,30 @@ impl Daemon {
         &mut self,
         dataflow_id: uuid::Uuid,
         working_dir: PathBuf,
-        nodes: Vec<ResolvedNode>,
+        mut nodes: Vec<ResolvedNode>,
         dataflow_descriptor: Descriptor,
+        artifacts: BTreeMap<NodeId, Vec<NodeArtifact>>,
+        artifact_data: Vec<ArtifactData>,
     ) -> eyre::Result<()> {
-        let dataflow = RunningDataflow::new(dataflow_id, self.machine_id.clone());
+        let artifact_data: BTreeMap<_, _> = artifact_data
+            .iter()
+            .map(|d| (d.hash.as_str(), d.data.as_slice()))
+            .collect();
+        for node in &mut nodes {
+            if node.deploy.machine != self.machine_id {
+                continue;
+            }
+            if let Some(node_artifacts) = artifacts.get(&node.id) {
+                self.artifact_cache
+                    .apply(node, node_artifacts, &artifact_data)
+                    .await
+                    .wrap_err_with(|| {
+                        format!("failed to prepare artifacts of node `{}`", node.id)
+                    })?;
+            }
+        }
+
+        let dataflow = RunningDataflow::new(dataflow_id, dataflow_descriptor.clone());
         let dataflow = match self.running.entry(dataflow_id) {
             std::collections::hash_map::Entry::Vacant(entry) => {
                 self.working_dir.insert(dataflow_id, working_dir.clone());
@@ -607,45 +994,37 @@ impl Daemon {
             }
         };
 
+        dataflow.node_machines = nodes
+            .iter()
+            .map(|node| (node.id.clone(), node.deploy.machine.clone()))
+            .collect();
         let mut log_messages = Vec::new();
         for node in nodes {
             let local = node.deploy.machine == self.machine_id;
 
-            let inputs = node_inputs(&node);
-            for (input_id, input) in inputs {
-                if local {
-                    dataflow
-                        .open_inputs
-                        .entry(node.id.clone())
-                        .or_default()
-                        .insert(input_id.clone());
-                    match input.mapping {
-                        InputMapping::User(mapping) => {
-                            dataflow
-                                .mappings
-                                .entry(OutputId(mapping.source, mapping.output))
-                                .or_default()
-                                .insert((node.id.clone(), input_id));
-                        }
-                        InputMapping::Timer { interval } => {
-                            dataflow
-                                .timers
-                                .entry(interval)
-                                .or_default()
-                                .insert((node.id.clone(), input_id));
-                        }
-                    }
-                } else if let InputMapping::User(mapping) = input.mapping {
-                    dataflow
-                        .open_external_mappings
-                        .entry(OutputId(mapping.source, mapping.output))
-                        .or_default()
-                        .entry(node.deploy.machine.clone())
-                        .or_default()
-                        .insert((node.id.clone(), input_id));
-                }
+            for (input_id, input) in node_inputs(&node) {
+                let input = NodeInput {
+                    node_id: node.id.clone(),
+                    machine: node.deploy.machine.clone(),
+                    input_id,
+                    input,
+                };
+                dataflow.add_input(
+                    input,
+                    &self.machine_id,
+                    &self.backpressure,
+                    &self.events_tx,
+                    &self.clock,
+                );
             }
             if local {
+                let outputs = node.kind.run_config().outputs;
+                for output_id in &outputs {
+                    dataflow.output_rates.insert(
+                        OutputId(node.id.clone(), output_id.clone()),
+                        RateMonitor::new(outputs.expected_rate(output_id).copied()),
+                    );
+                }
                 if node.kind.dynamic() {
                     dataflow.dynamic_nodes.insert(node.id.clone());
                 } else {
@@ -653,17 +1032,20 @@ impl Daemon {
                 }
 
                 let node_id = node.id.clone();
+                dataflow.nodes.insert(node_id.clone(), node.clone());
                 let node_stderr_most_recent = dataflow
                     .node_stderr_most_recent
                     .entry(node.id.clone())
                     .or_insert_with(|| Arc::new(ArrayQueue::new(STDERR_LOG_LINES)))
                     .clone();
+                let queues = dataflow.queue_config(&node, &self.backpressure);
                 match spawn::spawn_node(
                     dataflow_id,
                     &working_dir,
                     node,
                     self.events_tx.clone(),
                     dataflow_descriptor.clone(),
+                    queues,
                     self.clock.clone(),
                     node_stderr_most_recent,
                 )
@@ -791,6 +1173,12 @@ impl Daemon {
                     Err(err) => {
                         let _ = reply_sender.send(DaemonReply::Result(Err(err)));
                     }
+                    Ok(dataflow) if dataflow.started => {
+                        // the node was restarted after the dataflow was started already
+                        tracing::debug!("restarted node `{node_id}` is ready");
+                        Self::subscribe(dataflow, node_id.clone(), event_sender, &self.clock).await;
+                        let _ = reply_sender.send(DaemonReply::Result(Ok(())));
+                    }
                     Ok(dataflow) => {
                         tracing::debug!("node `{node_id}` is ready");
                         Self::subscribe(dataflow, node_id.clone(), event_sender, &self.clock).await;
@@ -837,6 +1225,10 @@ impl Daemon {
                         .running
                         .get_mut(&dataflow_id)
                         .wrap_err_with(|| format!("failed to get downstream nodes: no running dataflow with ID `{dataflow_id}`"))?;
+                    if dataflow.restarting.contains(&node_id) {
+                        // the outputs are taken over by the restarted node
+                        return Ok(());
+                    }
                     send_input_closed_events(
                         dataflow,
                         &mut self.inter_daemon_connections,
@@ -853,6 +1245,12 @@ impl Daemon {
             }
             DaemonNodeEvent::OutputsDone { reply_sender } => {
                 let result = match self.running.get_mut(&dataflow_id) {
+                    // the outputs stay open while the node might be restarted, they are
+                    // closed in `handle_node_stop` once the node stops for good
+                    Some(dataflow) if dataflow.may_restart(&node_id) => {
+                        dataflow.drop_channels.remove(&node_id);
+                        Ok(())
+                    }
                     Some(dataflow) => {
                         Self::handle_outputs_done(dataflow, &mut self.inter_daemon_connections, &node_id, &self.clock)
                     .await
@@ -954,31 +1352,93 @@ impl Daemon {
         .await?;
 
         let output_id = OutputId(node_id, output_id);
-        let remote_receivers: Vec<_> = dataflow
+        dataflow
+            .output_stats
+            .entry(output_id.clone())
+            .or_default()
+            .sent += 1;
+        let rate_log = dataflow
+            .output_rates
+            .get_mut(&output_id)
+            .and_then(|monitor| monitor.record())
+            .map(|deviation| rate_deviation_log(dataflow_id, &output_id, deviation));
+        // group the remote machines by the settings of their receiving inputs
+        let mut remote_receivers: Vec<(RemoteInputs, Vec<String>)> = Vec::new();
+        for machine in dataflow
             .open_external_mappings
             .get(&output_id)
-            .map(|m| m.keys().cloned().collect())
-            .unwrap_or_default();
-        if !remote_receivers.is_empty() {
+            .into_iter()
+            .flat_map(|m| m.keys())
+        {
+            let inputs = dataflow
+                .remote_inputs
+                .get(&(output_id.clone(), machine.clone()))
+                .copied()
+                .unwrap_or_default();
+            match remote_receivers.iter_mut().find(|(i, _)| *i == inputs) {
+                Some((_, machines)) => machines.push(machine.clone()),
+                None => remote_receivers.push((inputs, vec![machine.clone()])),
+            }
+        }
+        for (inputs, machines) in remote_receivers {
+            let compression = inputs.compression;
+            let data = match (compression, &data_bytes) {
+                (Some(compression), Some(data)) => {
+                    match compression::compress(compression, data).await {
+                        Ok(compressed) => Some(AVec::from_slice(128, &compressed)),
+                        Err(err) => {
+                            tracing::warn!(
+                                "{:?}",
+                                err.wrap_err(format!(
+                                    "dropping output `{}/{}` for machines {machines:?}",
+                                    output_id.0, output_id.1
+                                ))
+                            );
+                            continue;
+                        }
+                    }
+                }
+                _ => data_bytes.clone(),
+            };
+            let counters = dataflow.output_stats.entry(output_id.clone()).or_default();
+            let len = |data: &Option<AVec<u8, ConstAlign<128>>>| {
+                data.as_ref().map(|d| d.len() as u64).unwrap_or(0)
+            };
+            counters.remote_bytes += len(&data_bytes) * machines.len() as u64;
+            counters.remote_compressed_bytes += len(&data) * machines.len() as u64;
+
             let event = Timestamped {
                 inner: InterDaemonEvent::Output {
                     dataflow_id,
-                    node_id: output_id.0,
-                    output_id: output_id.1,
-                    metadata,
-                    data: data_bytes,
+                    node_id: output_id.0.clone(),
+                    output_id: output_id.1.clone(),
+                    metadata: metadata.clone(),
+                    data,
+                    compression,
                 },
                 timestamp: self.clock.new_timestamp(),
             };
+            let queue = OutputQueue {
+                output_id: output_id.clone(),
+                size: inputs.queue_size,
+                overflow: inputs.overflow,
+            };
             inter_daemon::send_inter_daemon_event(
-                &remote_receivers,
+                &machines,
                 &mut self.inter_daemon_connections,
+                &dataflow.descriptor.communication.remote,
                 &event,
+                Some(&queue),
+                inputs.transport,
             )
             .await
             .wrap_err("failed to forward output to remote receivers")?;
         }
 
+        if let Some(log_message) = rate_log {
+            self.send_log_message(log_message).await?;
+        }
+
         Ok(())
     }
 
@@ -1027,7 +1487,7 @@ impl Daemon {
     #[tracing::instrument(skip(dataflow, inter_daemon_connections, clock), fields(uuid = %dataflow.id), level = "trace")]
     async fn handle_outputs_done(
         dataflow: &mut RunningDataflow,
-        inter_daemon_connections: &mut BTreeMap<String, InterDaemonConnection>,
+        inter_daemon_connections: &mut InterDaemonConnections,
         node_id: &NodeId,
         clock: &HLC,
     ) -> eyre::Result<()> {
@@ -1078,6 +1538,11 @@ impl Daemon {
                     .get(&dataflow.id)
                     .context("failed to get dataflow node results")?
                     .clone(),
+                node_restarts: self
+                    .dataflow_node_restarts
+                    .get(&dataflow.id)
+                    .cloned()
+                    .unwrap_or_default(),
             };
 
             tracing::info!(
@@ -1085,21 +1550,18 @@ impl Daemon {
                 self.machine_id
             );
             if let Some(connection) = &mut self.coordinator_connection {
-                let msg = serde_json::to_vec(&Timestamped {
-                    inner: CoordinatorRequest::Event {
-                        machine_id: self.machine_id.clone(),
-                        event: DaemonEvent::AllNodesFinished {
+                connection
+                    .send_event(
+                        DaemonEvent::AllNodesFinished {
                             dataflow_id,
                             result,
                         },
-                    },
-                    timestamp: self.clock.new_timestamp(),
-                })?;
-                socket_stream_send(connection, &msg)
-                    .await
-                    .wrap_err("failed to report dataflow finish to dora-coordinator")?;
+                        self.clock.new_timestamp(),
+                    )
+                    .await;
             }
             self.running.remove(&dataflow_id);
+            self.backpressure.remove_dataflow(&dataflow_id);
         }
 
         for log_message in log_messages {
@@ -1188,7 +1650,14 @@ impl Daemon {
                         &self.clock,
                     );
                     match send_result {
-                        Ok(()) => {}
+                        Ok(()) => {
+                            if let Some(deadline) = dataflow
+                                .input_deadlines
+                                .get_mut(&(receiver_id.clone(), input_id.clone()))
+                            {
+                                deadline.received();
+                            }
+                        }
                         Err(_) => {
                             closed.push(receiver_id);
                         }
@@ -1202,6 +1671,7 @@ impl Daemon {
                 dataflow_id,
                 node_id,
                 exit_status,
+                out_of_memory,
             } => {
                 let node_result = match exit_status {
                     NodeExitStatus::Success => {
@@ -1224,6 +1694,7 @@ impl Daemon {
                                 tracing::info!("marking `{node_id}` as cascading error caused by `{caused_by_node}`");
                                 NodeErrorCause::Cascading { caused_by_node }
                             }
+                            None if out_of_memory => NodeErrorCause::OutOfMemory,
                             None if grace_duration_kill => NodeErrorCause::GraceDuration,
                             None => NodeErrorCause::Other {
                                 stderr: dataflow
@@ -1269,44 +1740,726 @@ impl Daemon {
                 })
                 .await?;
 
-                self.dataflow_node_results
-                    .entry(dataflow_id)
-                    .or_default()
-                    .insert(node_id.clone(), node_result);
-
-                self.handle_node_stop(dataflow_id, &node_id).await?;
+                if let Some(dataflow) = self.running.get_mut(&dataflow_id) {
+                    if dataflow.removed_nodes.remove(&node_id) {
+                        // removed nodes are not part of the dataflow result
+                        self.handle_node_stop(dataflow_id, &node_id).await?;
+                        return Ok(RunStatus::Continue);
+                    }
+                    if dataflow.restarting.remove(&node_id) {
+                        if let Some(running_node) = dataflow.running_nodes.get_mut(&node_id) {
+                            running_node.pid = None;
+                        }
+                        dataflow
+                            .pending_restarts
+                            .insert(node_id.clone(), node_result);
+                        return self.restart_node(dataflow_id, node_id).await;
+                    }
+                }
 
-                if let Some(exit_when_done) = &mut self.exit_when_done {
-                    exit_when_done.remove(&(dataflow_id, node_id));
-                    if exit_when_done.is_empty() {
-                        tracing::info!(
-                            "exiting daemon because all required dataflows are finished"
-                        );
-                        return Ok(RunStatus::Exit);
+                if self
+                    .schedule_restart(dataflow_id, &node_id, &node_result)
+                    .await?
+                {
+                    if let Some(dataflow) = self.running.get_mut(&dataflow_id) {
+                        dataflow.pending_restarts.insert(node_id, node_result);
                     }
+                    return Ok(RunStatus::Continue);
                 }
-            }
-        }
-        Ok(RunStatus::Continue)
-    }
-}
 
-async fn send_output_to_local_receivers(
-    node_id: NodeId,
-    output_id: DataId,
-    dataflow: &mut RunningDataflow,
-    metadata: &metadata::Metadata,
-    data: Option<DataMessage>,
-    clock: &HLC,
-) -> Result<Option<AVec<u8, ConstAlign<128>>>, eyre::ErrReport> {
-    let timestamp = metadata.timestamp();
+                return self
+                    .handle_node_result(dataflow_id, node_id, node_result)
+                    .await;
+            }
+            DoraEvent::RestartNode {
+                dataflow_id,
+                node_id,
+            } => return self.restart_node(dataflow_id, node_id).await,
+            DoraEvent::InputTimeout {
+                dataflow_id,
+                node_id,
+                input_id,
+            } => {
+                return self
+                    .handle_input_timeout(dataflow_id, node_id, input_id)
+                    .await
+            }
+            DoraEvent::ReturnCredits {
+                dataflow_id,
+                node_id,
+                input_id,
+                credits,
+            } => {
+                self.return_credits(dataflow_id, node_id, input_id, credits)
+                    .await?
+            }
+            DoraEvent::BuildOutput(message) => self.send_log_message(message).await?,
+            DoraEvent::BuildFinished { build_id, results } => {
+                if let Some(connection) = &mut self.coordinator_connection {
+                    connection
+                        .send_event(
+                            DaemonEvent::BuildFinished { build_id, results },
+                            self.clock.new_timestamp(),
+                        )
+                        .await;
+                }
+            }
+        }
+        Ok(RunStatus::Continue)
+    }
+
+    /// Gives freed queue slots of a local input back to the daemon of its sender.
+    async fn return_credits(
+        &mut self,
+        dataflow_id: Uuid,
+        node_id: NodeId,
+        input_id: DataId,
+        credits: usize,
+    ) -> eyre::Result<()> {
+        let Some(dataflow) = self.running.get(&dataflow_id) else {
+            return Ok(());
+        };
+        let source_machine = dataflow
+            .nodes
+            .get(&node_id)
+            .and_then(|node| node_inputs(node).remove(&input_id))
+            .and_then(|input| match input.mapping {
+                InputMapping::User(mapping) => dataflow.node_machines.get(&mapping.source),
+                InputMapping::Timer { .. } => None,
+            });
+        let Some(source_machine) = source_machine else {
+            return Ok(());
+        };
+        let event = Timestamped {
+            inner: InterDaemonEvent::InputCredits {
+                dataflow_id,
+                node_id,
+                input_id,
+                credits,
+            },
+            timestamp: self.clock.new_timestamp(),
+        };
+        inter_daemon::send_inter_daemon_event(
+            &[source_machine.clone()],
+            &mut self.inter_daemon_connections,
+            &dataflow.descriptor.communication.remote,
+            &event,
+            None,
+            Transport::Reliable,
+        )
+        .await
+        .wrap_err("failed to give input credits back to sending daemon")
+    }
+
+    /// Reports outputs whose publish rate deviates from their `expected_rate`, including
+    /// outputs that stopped publishing.
+    async fn check_output_rates(&mut self) -> eyre::Result<()> {
+        let mut log_messages = Vec::new();
+        for (dataflow_id, dataflow) in &mut self.running {
+            if !dataflow.started {
+                continue;
+            }
+            for (output_id, monitor) in &mut dataflow.output_rates {
+                if let Some(deviation) = monitor.check() {
+                    log_messages.push(rate_deviation_log(*dataflow_id, output_id, deviation));
+                }
+            }
+        }
+        for message in log_messages {
+            self.send_log_message(message).await?;
+        }
+        Ok(())
+    }
+
+    async fn handle_input_timeout(
+        &mut self,
+        dataflow_id: Uuid,
+        node_id: NodeId,
+        input_id: DataId,
+    ) -> eyre::Result<RunStatus> {
+        let Some(dataflow) = self.running.get_mut(&dataflow_id) else {
+            tracing::warn!("InputTimeout event for unknown dataflow `{dataflow_id}`");
+            return Ok(RunStatus::Continue);
+        };
+        if dataflow.stop_sent {
+            return Ok(RunStatus::Continue);
+        }
+        let Some(deadline) = dataflow
+            .input_deadlines
+            .get_mut(&(node_id.clone(), input_id.clone()))
+        else {
+            // input was closed in the meantime
+            return Ok(RunStatus::Continue);
+        };
+        deadline.misses += 1;
+        let misses = deadline.misses;
+        let stop = deadline.max_misses.is_some_and(|max| misses >= max);
+        let message = format!(
+            "no message received on input `{input_id}` within {} ({misses} times in a row)",
+            HumanDuration(deadline.deadline)
+        );
+
+        if let Some(channel) = dataflow.subscribe_channels.get(&node_id) {
+            let _ = send_with_timestamp(
+                channel,
+                NodeEvent::InputTimeout { id: input_id },
+                &self.clock,
+            );
+        }
+
+        tracing::warn!("{dataflow_id}/{node_id}: {message}");
+        self.send_log_message(LogMessage {
+            dataflow_id,
+            node_id: Some(node_id.clone()),
+            level: if stop {
+                LogLevel::Error
+            } else {
+                LogLevel::Warn
+            },
+            target: None,
+            module_path: None,
+            file: None,
+            line: None,
+            message: if stop {
+                format!("{message} -> stopping dataflow")
+            } else {
+                message
+            },
+        })
+        .await?;
+
+        if stop {
+            // let the coordinator stop the dataflow on all machines
+            if let Some(connection) = &mut self.coordinator_connection {
+                connection
+                    .send_event(
+                        DaemonEvent::StopDataflow { dataflow_id },
+                        self.clock.new_timestamp(),
+                    )
+                    .await;
+            } else {
+                return self.stop_dataflow(dataflow_id, None).await;
+            }
+        }
+        Ok(RunStatus::Continue)
+    }
+
+    /// Records the final result of the given node and cleans up after it.
+    async fn handle_node_result(
+        &mut self,
+        dataflow_id: Uuid,
+        node_id: NodeId,
+        node_result: Result<(), NodeError>,
+    ) -> eyre::Result<RunStatus> {
+        self.dataflow_node_results
+            .entry(dataflow_id)
+            .or_default()
+            .insert(node_id.clone(), node_result);
+
+        self.handle_node_stop(dataflow_id, &node_id).await?;
+
+        if let Some(exit_when_done) = &mut self.exit_when_done {
+            exit_when_done.remove(&(dataflow_id, node_id));
+            if exit_when_done.is_empty() {
+                tracing::info!("exiting daemon because all required dataflows are finished");
+                return Ok(RunStatus::Exit);
+            }
+        }
+        Ok(RunStatus::Continue)
+    }
+
+    /// Checks the restart policy of the given node and schedules a restart if required.
+    ///
+    /// Returns `true` if the node is going to be restarted.
+    async fn schedule_restart(
+        &mut self,
+        dataflow_id: Uuid,
+        node_id: &NodeId,
+        node_result: &Result<(), NodeError>,
+    ) -> eyre::Result<bool> {
+        let Some(dataflow) = self.running.get_mut(&dataflow_id) else {
+            return Ok(false);
+        };
+        let Some((restart, delay)) = dataflow.next_restart(node_id, node_result.is_ok()) else {
+            return Ok(false);
+        };
+        if let Some(running_node) = dataflow.running_nodes.get_mut(node_id) {
+            // the process is gone, so make sure that we don't try to kill it on stop
+            running_node.pid = None;
+        }
+        let max_retries = dataflow
+            .nodes
+            .get(node_id)
+            .and_then(|n| n.restart.max_retries);
+        self.dataflow_node_restarts
+            .entry(dataflow_id)
+            .or_default()
+            .insert(node_id.clone(), restart);
+
+        let events_tx = self.events_tx.clone();
+        let clock = self.clock.clone();
+        let restart_node_id = node_id.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            let event = Timestamped {
+                inner: DoraEvent::RestartNode {
+                    dataflow_id,
+                    node_id: restart_node_id,
+                }
+                .into(),
+                timestamp: clock.new_timestamp(),
+            };
+            let _ = events_tx.send(event).await;
+        });
+
+        self.send_log_message(LogMessage {
+            dataflow_id,
+            node_id: Some(node_id.clone()),
+            level: LogLevel::Warn,
+            target: None,
+            module_path: None,
+            file: None,
+            line: None,
+            message: match max_retries {
+                Some(max) => format!("restarting node in {delay:?} (restart {restart}/{max})"),
+                None => format!("restarting node in {delay:?} (restart {restart})"),
+            },
+        })
+        .await?;
+
+        Ok(true)
+    }
+
+    /// Stops the given node for a restart requested through `dora restart`.
+    ///
+    /// The node is respawned as soon as it exits. Its outputs stay open in the
+    /// meantime, so downstream nodes don't receive `InputClosed` events.
+    async fn request_restart(
+        &mut self,
+        dataflow_id: Uuid,
+        node_id: NodeId,
+        grace_duration: Option<Duration>,
+    ) -> eyre::Result<()> {
+        let dataflow = self
+            .running
+            .get_mut(&dataflow_id)
+            .wrap_err_with(|| format!("no running dataflow with ID `{dataflow_id}`"))?;
+        if dataflow.stop_sent {
+            bail!("dataflow `{dataflow_id}` is stopping");
+        }
+        if !dataflow.started {
+            bail!("dataflow `{dataflow_id}` is not started yet");
+        }
+        if dataflow.dynamic_nodes.contains(&node_id) {
+            bail!("dynamic node `{node_id}` cannot be restarted by dora");
+        }
+        if dataflow.pending_restarts.contains_key(&node_id) {
+            // the node already exited and waits for the restart delay of its policy
+            let event = Timestamped {
+                inner: DoraEvent::RestartNode {
+                    dataflow_id,
+                    node_id,
+                }
+                .into(),
+                timestamp: self.clock.new_timestamp(),
+            };
+            self.events_tx
+                .send(event)
+                .await
+                .map_err(|_| eyre!("failed to send restart event"))?;
+            return Ok(());
+        }
+        if !dataflow.running_nodes.contains_key(&node_id) {
+            bail!("node `{node_id}` is not running");
+        }
+        if !dataflow.restarting.insert(node_id.clone()) {
+            bail!("node `{node_id}` is already restarting");
+        }
+
+        tracing::info!("stopping node `{dataflow_id}/{node_id}` for restart");
+        dataflow.stop_node(&node_id, grace_duration, &self.clock);
+
+        self.send_log_message(LogMessage {
+            dataflow_id,
+            node_id: Some(node_id),
+            level: LogLevel::Info,
+            target: None,
+            module_path: None,
+            file: None,
+            line: None,
+            message: "stopping node for restart".to_string(),
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    /// Checks whether the changes of `dora apply` can be applied to the local nodes and
+    /// keeps them until the coordinator confirms that all machines accepted them.
+    fn prepare_changes(&mut self, changes: DataflowChanges) -> eyre::Result<()> {
+        let dataflow_id = changes.dataflow_id;
+        let dataflow = self
+            .running
+            .get_mut(&dataflow_id)
+            .wrap_err_with(|| format!("no running dataflow with ID `{dataflow_id}`"))?;
+        dataflow.prepared_changes = None;
+        if dataflow.stop_sent {
+            bail!("dataflow `{dataflow_id}` is stopping");
+        }
+        if !dataflow.started {
+            bail!("dataflow `{dataflow_id}` is not started yet");
+        }
+
+        let spawned = changes.added.iter().chain(&changes.restarted);
+        for node in changes.nodes.iter().filter(|node| {
+            node.deploy.machine == self.machine_id && spawned.clone().any(|id| id == &node.id)
+        }) {
+            let CoreNodeKind::Custom(custom) = &node.kind else {
+                continue;
+            };
+            let source = custom.source.as_str();
+            if source != SHELL_SOURCE && source != DYNAMIC_SOURCE && !source_is_url(source) {
+                resolve_path(source, &changes.working_dir).wrap_err_with(|| {
+                    format!("failed to resolve source `{source}` of node `{}`", node.id)
+                })?;
+            }
+        }
+
+        dataflow.prepared_changes = Some(changes);
+        Ok(())
+    }
+
+    /// Applies the changes of `dora apply` to the local nodes of a running dataflow.
+    async fn apply_changes(&mut self, changes: DataflowChanges) -> eyre::Result<()> {
+        let DataflowChanges {
+            dataflow_id,
+            working_dir,
+            nodes,
+            dataflow_descriptor,
+            added,
+            removed,
+            restarted,
+            removed_inputs,
+            added_inputs,
+            grace_duration,
+        } = changes;
+        let dataflow = self
+            .running
+            .get_mut(&dataflow_id)
+            .wrap_err_with(|| format!("no running dataflow with ID `{dataflow_id}`"))?;
+        if dataflow.stop_sent {
+            bail!("dataflow `{dataflow_id}` is stopping");
+        }
+        if !dataflow.started {
+            bail!("dataflow `{dataflow_id}` is not started yet");
+        }
+
+        for input in &removed_inputs {
+            dataflow.remove_input(input, &self.machine_id, &self.backpressure);
+        }
+        dataflow.node_machines = nodes
+            .iter()
+            .map(|node| (node.id.clone(), node.deploy.machine.clone()))
+            .collect();
+        for input in added_inputs {
+            dataflow.add_input(
+                input,
+                &self.machine_id,
+                &self.backpressure,
+                &self.events_tx,
+                &self.clock,
+            );
+        }
+        dataflow.descriptor = dataflow_descriptor.clone();
+        self.working_dir.insert(dataflow_id, working_dir.clone());
+
+        let mut stopped = Vec::new();
+        for node_id in &removed {
+            if dataflow.nodes.remove(node_id).is_none() {
+                // not a local node
+                continue;
+            }
+            dataflow
+                .output_rates
+                .retain(|OutputId(source, _), _| source != node_id);
+            if dataflow.dynamic_nodes.remove(node_id) {
+                dataflow.stop_node(node_id, grace_duration, &self.clock);
+                dataflow.running_nodes.remove(node_id);
+            } else if dataflow.pending_restarts.remove(node_id).is_some() {
+                stopped.push(node_id.clone());
+            } else if dataflow.running_nodes.contains_key(node_id) {
+                tracing::info!("stopping removed node `{dataflow_id}/{node_id}`");
+                dataflow.removed_nodes.insert(node_id.clone());
+                dataflow.stop_node(node_id, grace_duration, &self.clock);
+            }
+        }
+
+        for node in nodes {
+            if node.deploy.machine != self.machine_id {
+                continue;
+            }
+            if added.contains(&node.id) || restarted.contains(&node.id) {
+                dataflow
+                    .output_rates
+                    .retain(|OutputId(source, _), _| source != &node.id);
+                let outputs = node.kind.run_config().outputs;
+                for output_id in &outputs {
+                    dataflow.output_rates.insert(
+                        OutputId(node.id.clone(), output_id.clone()),
+                        RateMonitor::new(outputs.expected_rate(output_id).copied()),
+                    );
+                }
+            }
+            dataflow.nodes.insert(node.id.clone(), node);
+        }
+        let local_inputs: BTreeSet<InputId> = dataflow
+            .nodes
+            .values()
+            .flat_map(|node| {
+                node_inputs(node)
+                    .into_keys()
+                    .map(|input_id| (node.id.clone(), input_id))
+            })
+            .collect();
+        dataflow
+            .input_stats
+            .retain(|input, _| local_inputs.contains(input));
+        let output_rates = &dataflow.output_rates;
+        dataflow
+            .output_stats
+            .retain(|output_id, _| output_rates.contains_key(output_id));
+
+        for node_id in &restarted {
+            if !dataflow.nodes.contains_key(node_id) {
+                continue;
+            }
+            if dataflow.pending_restarts.contains_key(node_id) {
+                // the node waits for the restart delay of its policy -> restart it right away
+                let event = Timestamped {
+                    inner: DoraEvent::RestartNode {
+                        dataflow_id,
+                        node_id: node_id.clone(),
+                    }
+                    .into(),
+                    timestamp: self.clock.new_timestamp(),
+                };
+                self.events_tx
+                    .send(event)
+                    .await
+                    .map_err(|_| eyre!("failed to send restart event"))?;
+            } else if dataflow.running_nodes.contains_key(node_id)
+                && dataflow.restarting.insert(node_id.clone())
+            {
+                tracing::info!("restarting changed node `{dataflow_id}/{node_id}`");
+                dataflow.stop_node(node_id, grace_duration, &self.clock);
+            }
+        }
+
+        let mut spawn_errors = Vec::new();
+        for node_id in &added {
+            let Some(node) = dataflow.nodes.get(node_id).cloned() else {
+                continue;
+            };
+            if node.kind.dynamic() {
+                dataflow.dynamic_nodes.insert(node_id.clone());
+                continue;
+            }
+            let node_stderr_most_recent = dataflow
+                .node_stderr_most_recent
+                .entry(node_id.clone())
+                .or_insert_with(|| Arc::new(ArrayQueue::new(STDERR_LOG_LINES)))
+                .clone();
+            let queues = dataflow.queue_config(&node, &self.backpressure);
+            let spawn_result = spawn::spawn_node(
+                dataflow_id,
+                &working_dir,
+                node,
+                self.events_tx.clone(),
+                dataflow_descriptor.clone(),
+                queues,
+                self.clock.clone(),
+                node_stderr_most_recent,
+            )
+            .await
+            .wrap_err_with(|| format!("failed to spawn node `{node_id}`"));
+            match spawn_result {
+                Ok(running_node) => {
+                    dataflow.running_nodes.insert(node_id.clone(), running_node);
+                }
+                Err(err) => spawn_errors.push((node_id.clone(), err)),
+            }
+        }
+
+        for node_id in stopped {
+            self.handle_node_stop(dataflow_id, &node_id).await?;
+        }
+        let mut messages = Vec::new();
+        for (node_id, err) in spawn_errors {
+            messages.push(format!("{err:?}"));
+            let node_result = Err(NodeError {
+                timestamp: self.clock.new_timestamp(),
+                cause: NodeErrorCause::Other {
+                    stderr: format!("{err:?}"),
+                },
+                exit_status: NodeExitStatus::Unknown,
+            });
+            // `RunStatus::Exit` is only used for `exit_when_done`, which is not set for
+            // dataflows that are managed by a coordinator
+            let _ = self
+                .handle_node_result(dataflow_id, node_id, node_result)
+                .await?;
+        }
+        if !messages.is_empty() {
+            bail!(messages.join("\n"));
+        }
+
+        Ok(())
+    }
+
+    async fn restart_node(
+        &mut self,
+        dataflow_id: Uuid,
+        node_id: NodeId,
+    ) -> eyre::Result<RunStatus> {
+        let Some(dataflow) = self.running.get_mut(&dataflow_id) else {
+            tracing::warn!("Restart event for unknown dataflow `{dataflow_id}`");
+            return Ok(RunStatus::Continue);
+        };
+        let Some(last_result) = dataflow.pending_restarts.remove(&node_id) else {
+            // restart was already handled, e.g. because the dataflow was stopped
+            return Ok(RunStatus::Continue);
+        };
+        if dataflow.stop_sent {
+            tracing::info!("not restarting node `{node_id}` because dataflow is stopping");
+            return self
+                .handle_node_result(dataflow_id, node_id, last_result)
+                .await;
+        }
+
+        let node = dataflow
+            .nodes
+            .get(&node_id)
+            .cloned()
+            .wrap_err_with(|| format!("no node `{node_id}` in dataflow `{dataflow_id}`"))?;
+        let working_dir = self
+            .working_dir
+            .get(&dataflow_id)
+            .cloned()
+            .wrap_err_with(|| format!("no working dir for dataflow `{dataflow_id}`"))?;
+        let node_stderr_most_recent = dataflow
+            .node_stderr_most_recent
+            .entry(node_id.clone())
+            .or_insert_with(|| Arc::new(ArrayQueue::new(STDERR_LOG_LINES)))
+            .clone();
+
+        tracing::info!("restarting node `{dataflow_id}/{node_id}`");
+        let queues = dataflow.queue_config(&node, &self.backpressure);
+        let spawn_result = spawn::spawn_node(
+            dataflow_id,
+            &working_dir,
+            node,
+            self.events_tx.clone(),
+            dataflow.descriptor.clone(),
+            queues,
+            self.clock.clone(),
+            node_stderr_most_recent,
+        )
+        .await
+        .wrap_err_with(|| format!("failed to restart node `{node_id}`"));
+        match spawn_result {
+            Ok(running_node) => {
+                dataflow.running_nodes.insert(node_id, running_node);
+                Ok(RunStatus::Continue)
+            }
+            Err(err) => {
+                self.send_log_message(LogMessage {
+                    dataflow_id,
+                    node_id: Some(node_id.clone()),
+                    level: LogLevel::Error,
+                    target: None,
+                    module_path: None,
+                    file: None,
+                    line: None,
+                    message: format!("{err:?}"),
+                })
+                .await?;
+                self.handle_node_result(dataflow_id, node_id, last_result)
+                    .await
+            }
+        }
+    }
+
+    async fn stop_dataflow(
+        &mut self,
+        dataflow_id: Uuid,
+        grace_duration: Option<Duration>,
+    ) -> eyre::Result<RunStatus> {
+        let dataflow = self
+            .running
+            .get_mut(&dataflow_id)
+            .wrap_err_with(|| format!("no running dataflow with ID `{dataflow_id}`"))?;
+        dataflow
+            .stop_all(
+                &mut self.coordinator_connection,
+                &self.clock,
+                grace_duration,
+            )
+            .await?;
+
+        // nodes that wait for a restart are not running, so they can be finished directly
+        let pending_restarts: Vec<_> = dataflow.pending_restarts.keys().cloned().collect();
+        for node_id in pending_restarts {
+            if let RunStatus::Exit = self.restart_node(dataflow_id, node_id).await? {
+                return Ok(RunStatus::Exit);
+            }
+        }
+        Ok(RunStatus::Continue)
+    }
+}
+
+/// Creates the log message for a changed deviation from the expected rate of an output.
+fn rate_deviation_log(
+    dataflow_id: DataflowId,
+    output_id: &OutputId,
+    deviation: RateDeviation,
+) -> LogMessage {
+    let OutputId(node_id, output_id) = output_id;
+    let message = format!("output `{output_id}`: {deviation}");
+    let level = if deviation.recovered {
+        tracing::info!("{dataflow_id}/{node_id}: {message}");
+        LogLevel::Info
+    } else {
+        tracing::warn!("{dataflow_id}/{node_id}: {message}");
+        LogLevel::Warn
+    };
+    LogMessage {
+        dataflow_id,
+        node_id: Some(node_id.clone()),
+        level,
+        target: None,
+        module_path: None,
+        file: None,
+        line: None,
+        message,
+    }
+}
+
+async fn send_output_to_local_receivers(
+    node_id: NodeId,
+    output_id: DataId,
+    dataflow: &mut RunningDataflow,
+    metadata: &metadata::Metadata,
+    data: Option<DataMessage>,
+    clock: &HLC,
+) -> Result<Option<AVec<u8, ConstAlign<128>>>, eyre::ErrReport> {
+    let timestamp = metadata.timestamp();
     let empty_set = BTreeSet::new();
     let output_id = OutputId(node_id, output_id);
     let local_receivers = dataflow.mappings.get(&output_id).unwrap_or(&empty_set);
     let OutputId(node_id, _) = output_id;
     let mut closed = Vec::new();
     for (receiver_id, input_id) in local_receivers {
-        if let Some(channel) = dataflow.subscribe_channels.get(receiver_id) {
+        let delivered = if let Some(channel) = dataflow.subscribe_channels.get(receiver_id) {
             let item = NodeEvent::Input {
                 id: input_id.clone(),
                 metadata: metadata.clone(),
@@ -1328,11 +2481,31 @@ async fn send_output_to_local_receivers(
                             .pending_nodes
                             .insert(receiver_id.clone());
                     }
+                    if let Some(deadline) = dataflow
+                        .input_deadlines
+                        .get_mut(&(receiver_id.clone(), input_id.clone()))
+                    {
+                        deadline.received();
+                    }
+                    true
                 }
                 Err(_) => {
                     closed.push(receiver_id);
+                    false
                 }
             }
+        } else {
+            false
+        };
+        if !delivered {
+            let input_key = (receiver_id.clone(), input_id.clone());
+            // free the queue slot that the sender reserved for this input
+            if let Some(credits) = dataflow.input_credits.get(&input_key) {
+                credits.add_permits(1);
+            }
+            if let Some(stats) = dataflow.input_stats.get(&input_key) {
+                stats.lock().unwrap().dropped += 1;
+            }
         }
     }
     for id in closed {
@@ -1378,7 +2551,7 @@ fn node_inputs(node: &ResolvedNode) -> BTreeMap<DataId, Input> {
 
 async fn send_input_closed_events<F>(
     dataflow: &mut RunningDataflow,
-    inter_daemon_connections: &mut BTreeMap<String, InterDaemonConnection>,
+    inter_daemon_connections: &mut InterDaemonConnections,
     mut filter: F,
     clock: &HLC,
 ) -> eyre::Result<()>
@@ -1414,7 +2587,10 @@ where
             inter_daemon::send_inter_daemon_event(
                 &[target_machine],
                 inter_daemon_connections,
+                &dataflow.descriptor.communication.remote,
                 &event,
+                None,
+                Transport::Reliable,
             )
             .await
             .wrap_err("failed to sent InputClosed event to remote receiver")?;
@@ -1434,6 +2610,9 @@ fn close_input(
             return;
         }
     }
+    dataflow
+        .input_deadlines
+        .remove(&(receiver_id.clone(), input_id.clone()));
     if let Some(channel) = dataflow.subscribe_channels.get(receiver_id) {
         let _ = send_with_timestamp(
             channel,
@@ -1449,16 +2628,184 @@ fn close_input(
     }
 }
 
+/// Watchdog state of an input with a `deadline`.
+struct InputDeadline {
+    deadline: Duration,
+    max_misses: Option<u32>,
+    /// Time of the last received message, shared with the watchdog task.
+    last_received: Arc<Mutex<Instant>>,
+    /// Number of consecutive deadline misses.
+    misses: u32,
+    _watchdog: Option<futures::future::RemoteHandle<()>>,
+}
+
+impl InputDeadline {
+    fn new(deadline: Duration, max_misses: Option<u32>) -> Self {
+        Self {
+            deadline,
+            max_misses,
+            last_received: Arc::new(Mutex::new(Instant::now())),
+            misses: 0,
+            _watchdog: None,
+        }
+    }
+
+    fn received(&mut self) {
+        *self.last_received.lock().unwrap() = Instant::now();
+        self.misses = 0;
+    }
+
+    /// Spawns the watchdog task, which is stopped when the deadline is dropped.
+    fn start_watchdog(
+        &mut self,
+        dataflow_id: DataflowId,
+        node_id: NodeId,
+        input_id: DataId,
+        events_tx: mpsc::Sender<Timestamped<Event>>,
+        clock: Arc<HLC>,
+    ) {
+        let task = self.watchdog(dataflow_id, node_id, input_id, events_tx, clock);
+        let (task, handle) = task.remote_handle();
+        tokio::spawn(task);
+        self._watchdog = Some(handle);
+    }
+
+    /// Sends an `InputTimeout` event whenever no message was received within the deadline.
+    fn watchdog(
+        &self,
+        dataflow_id: DataflowId,
+        node_id: NodeId,
+        input_id: DataId,
+        events_tx: mpsc::Sender<Timestamped<Event>>,
+        clock: Arc<HLC>,
+    ) -> impl std::future::Future<Output = ()> {
+        let deadline = self.deadline;
+        let last_received = self.last_received.clone();
+        *last_received.lock().unwrap() = Instant::now();
+        async move {
+            loop {
+                let expiry = *last_received.lock().unwrap() + deadline;
+                if expiry > Instant::now() {
+                    tokio::time::sleep_until(expiry.into()).await;
+                    continue;
+                }
+                // restart the deadline for the next timeout
+                *last_received.lock().unwrap() = Instant::now();
+                let event = Timestamped {
+                    inner: DoraEvent::InputTimeout {
+                        dataflow_id,
+                        node_id: node_id.clone(),
+                        input_id: input_id.clone(),
+                    }
+                    .into(),
+                    timestamp: clock.new_timestamp(),
+                };
+                if events_tx.send(event).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Reports the freed queue slots of a local input with `overflow: block` whose sender
+/// runs on another machine, so that the daemon of the sender can give them back.
+async fn return_credits(
+    dataflow_id: DataflowId,
+    node_id: NodeId,
+    input_id: DataId,
+    credits: Arc<Semaphore>,
+    events_tx: mpsc::Sender<Timestamped<Event>>,
+    clock: Arc<HLC>,
+) {
+    loop {
+        let Ok(permit) = credits.acquire().await else {
+            break;
+        };
+        permit.forget();
+        // combine slots that were freed at the same time into a single message
+        let freed = 1 + credits.forget_permits(credits.available_permits());
+        let event = Timestamped {
+            inner: DoraEvent::ReturnCredits {
+                dataflow_id,
+                node_id: node_id.clone(),
+                input_id: input_id.clone(),
+                credits: freed,
+            }
+            .into(),
+            timestamp: clock.new_timestamp(),
+        };
+        if events_tx.send(event).await.is_err() {
+            break;
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 struct RunningNode {
     pid: Option<u32>,
     node_config: NodeConfig,
 }
 
+/// Combined settings of the inputs on another machine that receive the same output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct RemoteInputs {
+    compression: Option<Compression>,
+    queue_size: usize,
+    overflow: OverflowPolicy,
+    transport: Transport,
+}
+
+impl Default for RemoteInputs {
+    fn default() -> Self {
+        Self {
+            compression: None,
+            queue_size: 10,
+            overflow: OverflowPolicy::default(),
+            transport: Transport::default(),
+        }
+    }
+}
+
+impl RemoteInputs {
+    /// Combines the settings so that no input receives fewer messages than configured.
+    fn merge(&mut self, other: Self) {
+        // ordered from the policy that keeps the fewest messages to the one that keeps all
+        let rank = |overflow| match overflow {
+            OverflowPolicy::KeepLatest => 0,
+            OverflowPolicy::DropNewest => 1,
+            OverflowPolicy::DropOldest => 2,
+            OverflowPolicy::Block => 3,
+        };
+        self.compression = self.compression.max(other.compression);
+        self.queue_size = self.queue_size.max(other.queue_size);
+        // a single reliable input requires reliable transport for the whole machine
+        if other.transport == Transport::Reliable {
+            self.transport = Transport::Reliable;
+        }
+        if rank(other.overflow) > rank(self.overflow) {
+            self.overflow = other.overflow;
+        }
+    }
+}
+
+/// Message counters of an output, reported through `dora stats`.
+#[derive(Debug, Default)]
+struct OutputCounters {
+    sent: u64,
+    /// Size of the messages sent to other machines, before compression.
+    remote_bytes: u64,
+    /// Size of the messages sent to other machines, after compression.
+    remote_compressed_bytes: u64,
+}
+
 pub struct RunningDataflow {
     id: Uuid,
+    descriptor: Descriptor,
     /// Local nodes that are not started yet
     pending_nodes: PendingNodes,
+    /// Whether the dataflow was started, i.e. all nodes were ready.
+    started: bool,
 
     subscribe_channels: HashMap<NodeId, UnboundedSender<Timestamped<NodeEvent>>>,
     drop_channels: HashMap<NodeId, UnboundedSender<Timestamped<NodeDropEvent>>>,
@@ -1466,6 +2813,16 @@ pub struct RunningDataflow {
     timers: BTreeMap<Duration, BTreeSet<InputId>>,
     open_inputs: BTreeMap<NodeId, BTreeSet<DataId>>,
     running_nodes: BTreeMap<NodeId, RunningNode>,
+    /// Definitions of all local nodes, used for restarting them.
+    nodes: BTreeMap<NodeId, ResolvedNode>,
+    /// Number of restarts per node.
+    node_restarts: BTreeMap<NodeId, u32>,
+    /// Nodes that exited and wait for their restart, together with their last exit result.
+    pending_restarts: BTreeMap<NodeId, Result<(), NodeError>>,
+    /// Nodes that were stopped through `dora restart` and are respawned when they exit.
+    restarting: BTreeSet<NodeId>,
+    /// Nodes that were removed through `dora apply` and are stopping.
+    removed_nodes: BTreeSet<NodeId>,
 
     /// List of all dynamic node IDs.
     ///
@@ -1474,6 +2831,32 @@ pub struct RunningDataflow {
     dynamic_nodes: BTreeSet<NodeId>,
 
     open_external_mappings: HashMap<OutputId, BTreeMap<String, BTreeSet<InputId>>>,
+    /// Machines of all nodes of the dataflow.
+    node_machines: BTreeMap<NodeId, String>,
+    /// Free queue slots of local inputs with `overflow: block`.
+    ///
+    /// If the input is mapped to an output of another machine, the slots are held by the
+    /// daemon of that machine. The semaphore then counts the slots that were freed but
+    /// not given back yet, see `credit_returns`.
+    input_credits: BTreeMap<InputId, Arc<Semaphore>>,
+    /// Tasks that give freed queue slots of local inputs back to the daemons of their
+    /// remote senders.
+    credit_returns: BTreeMap<InputId, futures::future::RemoteHandle<()>>,
+    /// Free queue slots of inputs with `overflow: block` on other machines that are mapped
+    /// to outputs of local nodes.
+    remote_input_credits: BTreeMap<InputId, Arc<Semaphore>>,
+    /// Changes of `dora apply` that were validated, but not applied yet.
+    prepared_changes: Option<DataflowChanges>,
+    /// Local inputs with a `deadline`, removed when the input is closed.
+    input_deadlines: BTreeMap<InputId, InputDeadline>,
+    /// Publish rates of the outputs of local nodes.
+    output_rates: HashMap<OutputId, RateMonitor>,
+    /// Message counters per output of local nodes.
+    output_stats: HashMap<OutputId, OutputCounters>,
+    /// Settings of the inputs on other machines, per output and machine.
+    remote_inputs: HashMap<(OutputId, String), RemoteInputs>,
+    /// Message statistics of local inputs, shared with the listeners of the nodes.
+    input_stats: BTreeMap<InputId, Arc<Mutex<InputStats>>>,
 
     pending_drop_tokens: HashMap<DropToken, DropTokenInformation>,
 
@@ -1494,18 +2877,35 @@ pub struct RunningDataflow {
 }
 
 impl RunningDataflow {
-    fn new(dataflow_id: Uuid, machine_id: String) -> RunningDataflow {
+    fn new(dataflow_id: Uuid, descriptor: Descriptor) -> RunningDataflow {
         Self {
             id: dataflow_id,
-            pending_nodes: PendingNodes::new(dataflow_id, machine_id),
+            descriptor,
+            pending_nodes: PendingNodes::new(dataflow_id),
+            started: false,
             subscribe_channels: HashMap::new(),
             drop_channels: HashMap::new(),
             mappings: HashMap::new(),
             timers: BTreeMap::new(),
             open_inputs: BTreeMap::new(),
             running_nodes: BTreeMap::new(),
+            nodes: BTreeMap::new(),
+            node_restarts: BTreeMap::new(),
+            pending_restarts: BTreeMap::new(),
+            restarting: BTreeSet::new(),
+            removed_nodes: BTreeSet::new(),
             dynamic_nodes: BTreeSet::new(),
             open_external_mappings: HashMap::new(),
+            node_machines: BTreeMap::new(),
+            input_credits: BTreeMap::new(),
+            credit_returns: BTreeMap::new(),
+            remote_input_credits: BTreeMap::new(),
+            prepared_changes: None,
+            input_deadlines: BTreeMap::new(),
+            output_rates: HashMap::new(),
+            output_stats: HashMap::new(),
+            remote_inputs: HashMap::new(),
+            input_stats: BTreeMap::new(),
             pending_drop_tokens: HashMap::new(),
             _timer_handles: Vec::new(),
             stop_sent: false,
@@ -1516,64 +2916,355 @@ impl RunningDataflow {
         }
     }
 
-    async fn start(
+    /// Registers the given input mapping.
+    ///
+    /// If the dataflow is started already, the timer and the deadline watchdog of
+    /// the input are started right away.
+    fn add_input(
         &mut self,
+        input: NodeInput,
+        machine_id: &str,
+        backpressure: &Backpressure,
         events_tx: &mpsc::Sender<Timestamped<Event>>,
         clock: &Arc<HLC>,
-    ) -> eyre::Result<()> {
-        for interval in self.timers.keys().copied() {
-            let events_tx = events_tx.clone();
-            let dataflow_id = self.id;
-            let clock = clock.clone();
-            let task = async move {
-                let mut interval_stream = tokio::time::interval(interval);
-                let hlc = HLC::default();
-                loop {
-                    interval_stream.tick().await;
-
-                    let span = tracing::span!(tracing::Level::TRACE, "tick");
-                    let _ = span.enter();
-
-                    let mut parameters = BTreeMap::new();
-                    parameters.insert(
-                        "open_telemetry_context".to_string(),
-                        #[cfg(feature = "telemetry")]
-                        Parameter::String(serialize_context(&span.context())),
-                        #[cfg(not(feature = "telemetry"))]
-                        Parameter::String("".into()),
-                    );
+    ) {
+        let NodeInput {
+            node_id,
+            machine,
+            input_id,
+            input,
+        } = input;
+        if machine != machine_id {
+            if let InputMapping::User(mapping) = input.mapping {
+                let output_id = OutputId(mapping.source, mapping.output);
+                if input.overflow == OverflowPolicy::Block
+                    && self.node_machines.get(&output_id.0).map(String::as_str) == Some(machine_id)
+                {
+                    // the receiving daemon gives the credits back once the input was delivered
+                    let credits = Arc::new(Semaphore::new(input.queue_size.unwrap_or(10)));
+                    backpressure.register(self.id, output_id.clone(), credits.clone());
+                    self.remote_input_credits
+                        .insert((node_id.clone(), input_id.clone()), credits);
+                }
+                let inputs = RemoteInputs {
+                    compression: input.compression,
+                    queue_size: input.queue_size.unwrap_or(10),
+                    overflow: input.overflow,
+                    transport: input.transport,
+                };
+                self.remote_inputs
+                    .entry((output_id.clone(), machine.clone()))
+                    .and_modify(|existing| existing.merge(inputs))
+                    .or_insert(inputs);
+                self.open_external_mappings
+                    .entry(output_id)
+                    .or_default()
+                    .entry(machine)
+                    .or_default()
+                    .insert((node_id, input_id));
+            }
+            return;
+        }
 
-                    let metadata = metadata::Metadata::from_parameters(
-                        hlc.new_timestamp(),
-                        ArrowTypeInfo::empty(),
-                        parameters,
-                    );
+        self.open_inputs
+            .entry(node_id.clone())
+            .or_default()
+            .insert(input_id.clone());
+        // keep the statistics of rewired inputs, the listener of the node holds a reference
+        self.input_stats
+            .entry((node_id.clone(), input_id.clone()))
+            .or_default();
+        if let Some(deadline) = input.deadline {
+            let mut deadline = InputDeadline::new(deadline, input.max_deadline_misses);
+            if self.started {
+                deadline.start_watchdog(
+                    self.id,
+                    node_id.clone(),
+                    input_id.clone(),
+                    events_tx.clone(),
+                    clock.clone(),
+                );
+            }
+            self.input_deadlines
+                .insert((node_id.clone(), input_id.clone()), deadline);
+        }
+        let credits = (input.overflow == OverflowPolicy::Block).then(|| {
+            let credits = Arc::new(Semaphore::new(input.queue_size.unwrap_or(10)));
+            self.input_credits
+                .insert((node_id.clone(), input_id.clone()), credits.clone());
+            credits
+        });
+        match input.mapping {
+            InputMapping::User(mapping) => {
+                let source_machine = self.node_machines.get(&mapping.source);
+                match (credits, source_machine) {
+                    (Some(credits), Some(source_machine)) if source_machine != machine_id => {
+                        // the sending daemon holds the queue slots
+                        credits.forget_permits(credits.available_permits());
+                        let task = return_credits(
+                            self.id,
+                            node_id.clone(),
+                            input_id.clone(),
+                            credits,
+                            events_tx.clone(),
+                            clock.clone(),
+                        );
+                        let (task, handle) = task.remote_handle();
+                        tokio::spawn(task);
+                        self.credit_returns
+                            .insert((node_id.clone(), input_id.clone()), handle);
+                    }
+                    (Some(credits), _) => backpressure.register(
+                        self.id,
+                        OutputId(mapping.source.clone(), mapping.output.clone()),
+                        credits,
+                    ),
+                    (None, _) => {}
+                }
+                self.mappings
+                    .entry(OutputId(mapping.source, mapping.output))
+                    .or_default()
+                    .insert((node_id, input_id));
+            }
+            InputMapping::Timer { interval } => {
+                if self.started && !self.timers.contains_key(&interval) {
+                    self.start_timer(interval, events_tx, clock);
+                }
+                self.timers
+                    .entry(interval)
+                    .or_default()
+                    .insert((node_id, input_id));
+            }
+        }
+    }
 
-                    let event = Timestamped {
-                        inner: DoraEvent::Timer {
-                            dataflow_id,
-                            interval,
-                            metadata,
+    /// Unregisters the given input mapping, without notifying the receiving node.
+    fn remove_input(&mut self, input: &NodeInput, machine_id: &str, backpressure: &Backpressure) {
+        let input_key = (input.node_id.clone(), input.input_id.clone());
+        if input.machine != machine_id {
+            if let InputMapping::User(mapping) = &input.input.mapping {
+                let output_id = OutputId(mapping.source.clone(), mapping.output.clone());
+                if let Some(credits) = self.remote_input_credits.remove(&input_key) {
+                    backpressure.unregister(self.id, output_id.clone(), &credits);
+                }
+                if let Some(machines) = self.open_external_mappings.get_mut(&output_id) {
+                    if let Some(inputs) = machines.get_mut(&input.machine) {
+                        inputs.remove(&input_key);
+                        if inputs.is_empty() {
+                            machines.remove(&input.machine);
                         }
-                        .into(),
-                        timestamp: clock.new_timestamp(),
-                    };
-                    if events_tx.send(event).await.is_err() {
-                        break;
                     }
                 }
-            };
-            let (task, handle) = task.remote_handle();
-            tokio::spawn(task);
-            self._timer_handles.push(handle);
+            }
+            return;
+        }
+
+        if let Some(open_inputs) = self.open_inputs.get_mut(&input.node_id) {
+            open_inputs.remove(&input.input_id);
         }
+        self.input_deadlines.remove(&input_key);
+        let credits = self.input_credits.remove(&input_key);
+        self.credit_returns.remove(&input_key);
+        match &input.input.mapping {
+            InputMapping::User(mapping) => {
+                let output_id = OutputId(mapping.source.clone(), mapping.output.clone());
+                if let Some(credits) = credits {
+                    backpressure.unregister(self.id, output_id.clone(), &credits);
+                }
+                if let Some(receivers) = self.mappings.get_mut(&output_id) {
+                    receivers.remove(&input_key);
+                }
+            }
+            InputMapping::Timer { interval } => {
+                // keep the (possibly empty) entry because the timer task keeps running
+                if let Some(receivers) = self.timers.get_mut(interval) {
+                    receivers.remove(&input_key);
+                }
+            }
+        }
+    }
+
+    /// Sends a `Stop` event to the given node and kills it if it doesn't exit
+    /// within the grace duration.
+    fn stop_node(&mut self, node_id: &NodeId, grace_duration: Option<Duration>, clock: &HLC) {
+        if let Some(channel) = self.subscribe_channels.remove(node_id) {
+            let _ = send_with_timestamp(&channel, NodeEvent::Stop, clock);
+        }
+        let Some(pid) = self.running_nodes.get(node_id).and_then(|n| n.pid) else {
+            return;
+        };
+        let node_id = node_id.clone();
+        tokio::spawn(async move {
+            let duration = grace_duration.unwrap_or(Duration::from_millis(15000));
+            tokio::time::sleep(duration).await;
+            let mut system = sysinfo::System::new();
+            system.refresh_process(Pid::from(pid as usize));
+            if let Some(process) = system.process(Pid::from(pid as usize)) {
+                process.kill();
+                warn!(
+                    "{node_id} was killed due to not stopping within the {:#?} grace period",
+                    duration
+                )
+            }
+        });
+    }
+
+    fn queue_config(&self, node: &ResolvedNode, backpressure: &Backpressure) -> QueueConfig {
+        let inputs = node_inputs(node)
+            .into_iter()
+            .map(|(input_id, input)| {
+                let queue = InputQueue {
+                    size: input.queue_size.unwrap_or(10),
+                    overflow: input.overflow,
+                    credits: self
+                        .input_credits
+                        .get(&(node.id.clone(), input_id.clone()))
+                        .cloned(),
+                    stats: self
+                        .input_stats
+                        .get(&(node.id.clone(), input_id.clone()))
+                        .cloned()
+                        .unwrap_or_default(),
+                    unconsumed: Default::default(),
+                };
+                (input_id, queue)
+            })
+            .collect();
+        QueueConfig {
+            inputs,
+            backpressure: backpressure.clone(),
+            deferred_credits: matches!(node.kind, CoreNodeKind::Runtime(_)),
+        }
+    }
+
+    fn output_rates(&self) -> Vec<OutputRate> {
+        self.output_rates
+            .iter()
+            .map(|(OutputId(node_id, output_id), monitor)| OutputRate {
+                node_id: node_id.clone(),
+                output_id: output_id.clone(),
+                expected: monitor.expected().map(|e| e.rate),
+                tolerance_percent: monitor.expected().map(|e| e.tolerance_percent),
+                measured: monitor.measured(),
+            })
+            .collect()
+    }
+
+    /// Spawns the task that sends the `Timer` events of the given interval.
+    fn start_timer(
+        &mut self,
+        interval: Duration,
+        events_tx: &mpsc::Sender<Timestamped<Event>>,
+        clock: &Arc<HLC>,
+    ) {
+        let events_tx = events_tx.clone();
+        let dataflow_id = self.id;
+        let clock = clock.clone();
+        let task = async move {
+            let mut interval_stream = tokio::time::interval(interval);
+            let hlc = HLC::default();
+            loop {
+                interval_stream.tick().await;
+
+                let span = tracing::span!(tracing::Level::TRACE, "tick");
+                let _ = span.enter();
+
+                let mut parameters = BTreeMap::new();
+                parameters.insert(
+                    "open_telemetry_context".to_string(),
+                    #[cfg(feature = "telemetry")]
+                    Parameter::String(serialize_context(&span.context())),
+                    #[cfg(not(feature = "telemetry"))]
+                    Parameter::String("".into()),
+                );
+
+                let metadata = metadata::Metadata::from_parameters(
+                    hlc.new_timestamp(),
+                    ArrowTypeInfo::empty(),
+                    parameters,
+                );
+
+                let event = Timestamped {
+                    inner: DoraEvent::Timer {
+                        dataflow_id,
+                        interval,
+                        metadata,
+                    }
+                    .into(),
+                    timestamp: clock.new_timestamp(),
+                };
+                if events_tx.send(event).await.is_err() {
+                    break;
+                }
+            }
+        };
+        let (task, handle) = task.remote_handle();
+        tokio::spawn(task);
+        self._timer_handles.push(handle);
+    }
+
+    fn stats(&self) -> DataflowStats {
+        DataflowStats {
+            outputs: self
+                .output_rates
+                .keys()
+                .map(|output_id| {
+                    let OutputId(node_id, id) = output_id;
+                    let counters = self.output_stats.get(output_id);
+                    OutputStats {
+                        node_id: node_id.clone(),
+                        output_id: id.clone(),
+                        sent: counters.map(|c| c.sent).unwrap_or(0),
+                        remote_bytes: counters.map(|c| c.remote_bytes).unwrap_or(0),
+                        remote_compressed_bytes: counters
+                            .map(|c| c.remote_compressed_bytes)
+                            .unwrap_or(0),
+                    }
+                })
+                .collect(),
+            inputs: self
+                .input_stats
+                .iter()
+                .map(|((node_id, input_id), stats)| NodeInputStats {
+                    node_id: node_id.clone(),
+                    input_id: input_id.clone(),
+                    stats: stats.lock().unwrap().clone(),
+                })
+                .collect(),
+            connections: Vec::new(),
+            best_effort: Vec::new(),
+        }
+    }
+
+    async fn start(
+        &mut self,
+        events_tx: &mpsc::Sender<Timestamped<Event>>,
+        clock: &Arc<HLC>,
+    ) -> eyre::Result<()> {
+        let intervals: Vec<_> = self.timers.keys().copied().collect();
+        for interval in intervals {
+            self.start_timer(interval, events_tx, clock);
+        }
+        for monitor in self.output_rates.values_mut() {
+            monitor.reset();
+        }
+        for ((node_id, input_id), deadline) in &mut self.input_deadlines {
+            deadline.start_watchdog(
+                self.id,
+                node_id.clone(),
+                input_id.clone(),
+                events_tx.clone(),
+                clock.clone(),
+            );
+        }
+        self.started = true;
 
         Ok(())
     }
 
     async fn stop_all(
         &mut self,
-        coordinator_connection: &mut Option<TcpStream>,
+        coordinator_connection: &mut Option<CoordinatorConnection>,
         clock: &HLC,
         grace_duration: Option<Duration>,
     ) -> eyre::Result<()> {
@@ -1619,6 +3310,35 @@ impl RunningDataflow {
         self.open_inputs.get(node_id).unwrap_or(&self.empty_set)
     }
 
+    /// Whether the given node could still be restarted when it exits.
+    fn may_restart(&self, node_id: &NodeId) -> bool {
+        let restarts = self.node_restarts.get(node_id).copied().unwrap_or(0);
+        !self.stop_sent
+            && (self.restarting.contains(node_id)
+                || self
+                    .nodes
+                    .get(node_id)
+                    .map(|n| n.restart.should_restart(false, restarts))
+                    .unwrap_or(false))
+    }
+
+    /// Applies the restart policy of the given node after it exited.
+    ///
+    /// Returns the number of the upcoming restart and the delay before it, or `None`
+    /// if the node should not be restarted.
+    fn next_restart(&mut self, node_id: &NodeId, success: bool) -> Option<(u32, Duration)> {
+        if self.stop_sent {
+            return None;
+        }
+        let policy = &self.nodes.get(node_id)?.restart;
+        let restarts = self.node_restarts.entry(node_id.clone()).or_default();
+        if !policy.should_restart(success, *restarts) {
+            return None;
+        }
+        *restarts += 1;
+        Some((*restarts, policy.backoff(*restarts)))
+    }
+
     async fn check_drop_token(&mut self, token: DropToken, clock: &HLC) -> eyre::Result<()> {
         match self.pending_drop_tokens.entry(token) {
             std::collections::hash_map::Entry::Occupied(entry) => {
@@ -1673,12 +3393,40 @@ pub enum Event {
     },
     Coordinator(CoordinatorEvent),
     Daemon(InterDaemonEvent),
+    /// Event for another machine that should be sent through the coordinator.
+    Relay(RelayMessage),
     Dora(DoraEvent),
     DynamicNode(DynamicNodeEventWrapper),
     HeartbeatInterval,
+    /// The event stream of the coordinator connection with the given generation ended.
+    CoordinatorDisconnected {
+        generation: u64,
+    },
+    ReconnectCoordinator,
+    /// Result of a reconnect attempt started by [`Event::ReconnectCoordinator`].
+    CoordinatorReconnected(eyre::Result<Registration>),
     CtrlC,
 }
 
+/// Maps the events of a coordinator connection and reports the end of the connection.
+fn coordinator_events(
+    events: impl Stream<Item = Timestamped<CoordinatorEvent>> + Send + 'static,
+    generation: u64,
+    clock: Arc<HLC>,
+) -> Pin<Box<dyn Stream<Item = Timestamped<Event>> + Send>> {
+    let events = events.map(|Timestamped { inner, timestamp }| Timestamped {
+        inner: Event::Coordinator(inner),
+        timestamp,
+    });
+    let disconnected = stream::once(async move {
+        Timestamped {
+            inner: Event::CoordinatorDisconnected { generation },
+            timestamp: clock.new_timestamp(),
+        }
+    });
+    Box::pin(events.chain(disconnected))
+}
+
 impl From<DoraEvent> for Event {
     fn from(event: DoraEvent) -> Self {
         Event::Dora(event)
@@ -1732,6 +3480,30 @@ pub enum DoraEvent {
         dataflow_id: DataflowId,
         node_id: NodeId,
         exit_status: NodeExitStatus,
+        /// The node was killed because it exceeded its memory limit.
+        out_of_memory: bool,
+    },
+    RestartNode {
+        dataflow_id: DataflowId,
+        node_id: NodeId,
+    },
+    InputTimeout {
+        dataflow_id: DataflowId,
+        node_id: NodeId,
+        input_id: DataId,
+    },
+    /// Queue slots of a local input that should be given back to the remote sender.
+    ReturnCredits {
+        dataflow_id: DataflowId,
+        node_id: NodeId,
+        input_id: DataId,
+        credits: usize,
+    },
+    /// Output line of a build command, see [`build::spawn_build`].
+    BuildOutput(LogMessage),
+    BuildFinished {
+        build_id: Uuid,
+        results: BTreeMap<NodeId, Result<(), String>>,
     },
 }
 
@@ -1,11 +1,11 @@
-use crate::{DaemonNodeEvent, Event};
+use crate::{backpressure::Backpressure, DaemonNodeEvent, Event, OutputId};
 use dora_core::{
-    config::{DataId, LocalCommunicationConfig, NodeId},
+    config::{DataId, LocalCommunicationConfig, NodeId, OverflowPolicy},
     topics::LOCALHOST,
     uhlc,
 };
 use dora_message::{
-    common::{DropToken, Timestamped},
+    common::{DropToken, InputStats, Timestamped},
     daemon_to_node::{DaemonCommunication, DaemonReply, NodeDropEvent, NodeEvent},
     node_to_daemon::DaemonRequest,
     DataflowId,
@@ -16,7 +16,10 @@ use shared_memory_server::{ShmemConf, ShmemServer};
 use std::{
     collections::{BTreeMap, VecDeque},
     mem,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
     task::Poll,
 };
 #[cfg(unix)]
@@ -25,7 +28,7 @@ use tokio::{
     net::TcpListener,
     sync::{
         mpsc::{self, UnboundedReceiver},
-        oneshot,
+        oneshot, Semaphore,
     },
 };
 
@@ -35,12 +38,38 @@ pub mod tcp;
 #[cfg(unix)]
 pub mod unix_domain;
 
+/// Queue configuration of a node, used by its listener.
+#[derive(Debug, Clone)]
+pub struct QueueConfig {
+    pub inputs: BTreeMap<DataId, InputQueue>,
+    /// Used to wait for free queue slots of receivers that use `overflow: block`
+    /// before forwarding outputs of the node.
+    pub backpressure: Backpressure,
+    /// Set for nodes that free the queue slots of their inputs with `overflow: block`
+    /// through [`DaemonRequest::InputsConsumed`] instead of on delivery. This is the case
+    /// for runtime nodes, which queue the inputs of their operators.
+    pub deferred_credits: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct InputQueue {
+    pub size: usize,
+    pub overflow: OverflowPolicy,
+    /// Free queue slots of the input, set for inputs with `overflow: block`.
+    pub credits: Option<Arc<Semaphore>>,
+    /// Number of delivered inputs whose queue slots were not freed yet, see
+    /// [`QueueConfig::deferred_credits`].
+    pub unconsumed: Arc<AtomicUsize>,
+    /// Message statistics of the input, shared with the daemon for `dora stats`.
+    pub stats: Arc<Mutex<InputStats>>,
+}
+
 pub async fn spawn_listener_loop(
     dataflow_id: &DataflowId,
     node_id: &NodeId,
     daemon_tx: &mpsc::Sender<Timestamped<Event>>,
     config: LocalCommunicationConfig,
-    queue_sizes: BTreeMap<DataId, usize>,
+    queues: QueueConfig,
     clock: Arc<uhlc::HLC>,
 ) -> eyre::Result<DaemonCommunication> {
     match config {
@@ -60,7 +89,7 @@ pub async fn spawn_listener_loop(
             let event_loop_node_id = format!("{dataflow_id}/{node_id}");
             let daemon_tx = daemon_tx.clone();
             tokio::spawn(async move {
-                tcp::listener_loop(socket, daemon_tx, queue_sizes, clock).await;
+                tcp::listener_loop(socket, daemon_tx, queues, clock).await;
                 tracing::debug!("event listener loop finished for `{event_loop_node_id}`");
             });
 
@@ -92,9 +121,9 @@ pub async fn spawn_listener_loop(
                 let server = unsafe { ShmemServer::new(daemon_control_region) }
                     .wrap_err("failed to create control server")?;
                 let daemon_tx = daemon_tx.clone();
-                let queue_sizes = queue_sizes.clone();
+                let queues = queues.clone();
                 let clock = clock.clone();
-                tokio::spawn(shmem::listener_loop(server, daemon_tx, queue_sizes, clock));
+                tokio::spawn(shmem::listener_loop(server, daemon_tx, queues, clock));
             }
 
             {
@@ -102,10 +131,10 @@ pub async fn spawn_listener_loop(
                     .wrap_err("failed to create events server")?;
                 let event_loop_node_id = format!("{dataflow_id}/{node_id}");
                 let daemon_tx = daemon_tx.clone();
-                let queue_sizes = queue_sizes.clone();
+                let queues = queues.clone();
                 let clock = clock.clone();
                 tokio::task::spawn(async move {
-                    shmem::listener_loop(server, daemon_tx, queue_sizes, clock).await;
+                    shmem::listener_loop(server, daemon_tx, queues, clock).await;
                     tracing::debug!("event listener loop finished for `{event_loop_node_id}`");
                 });
             }
@@ -115,10 +144,10 @@ pub async fn spawn_listener_loop(
                     .wrap_err("failed to create drop server")?;
                 let drop_loop_node_id = format!("{dataflow_id}/{node_id}");
                 let daemon_tx = daemon_tx.clone();
-                let queue_sizes = queue_sizes.clone();
+                let queues = queues.clone();
                 let clock = clock.clone();
                 tokio::task::spawn(async move {
-                    shmem::listener_loop(server, daemon_tx, queue_sizes, clock).await;
+                    shmem::listener_loop(server, daemon_tx, queues, clock).await;
                     tracing::debug!("drop listener loop finished for `{drop_loop_node_id}`");
                 });
             }
@@ -130,7 +159,7 @@ pub async fn spawn_listener_loop(
                 let daemon_tx = daemon_tx.clone();
                 let clock = clock.clone();
                 tokio::task::spawn(async move {
-                    shmem::listener_loop(server, daemon_tx, queue_sizes, clock).await;
+                    shmem::listener_loop(server, daemon_tx, queues, clock).await;
                     tracing::debug!(
                         "events close listener loop finished for `{drop_loop_node_id}`"
                     );
@@ -152,7 +181,11 @@ pub async fn spawn_listener_loop(
             if !tmpfile_dir.exists() {
                 std::fs::create_dir_all(&tmpfile_dir).context("could not create tmp dir")?;
             }
-            let socket_file = tmpfile_dir.join(format!("{}.sock", node_id));
+            let socket_file = tmpfile_dir.join(format!("{}.sock", node_id.file_name()));
+            if socket_file.exists() {
+                // left over from a previous run of the node (e.g. after a restart)
+                std::fs::remove_file(&socket_file).context("could not remove old socket file")?;
+            }
             let socket = match UnixListener::bind(&socket_file) {
                 Ok(socket) => socket,
                 Err(err) => {
@@ -164,7 +197,7 @@ pub async fn spawn_listener_loop(
             let event_loop_node_id = format!("{dataflow_id}/{node_id}");
             let daemon_tx = daemon_tx.clone();
             tokio::spawn(async move {
-                unix_domain::listener_loop(socket, daemon_tx, queue_sizes, clock).await;
+                unix_domain::listener_loop(socket, daemon_tx, queues, clock).await;
                 tracing::debug!("event listener loop finished for `{event_loop_node_id}`");
             });
 
@@ -184,7 +217,7 @@ struct Listener {
     subscribed_events: Option<UnboundedReceiver<Timestamped<NodeEvent>>>,
     subscribed_drop_events: Option<UnboundedReceiver<Timestamped<NodeDropEvent>>>,
     queue: VecDeque<Box<Option<Timestamped<NodeEvent>>>>,
-    queue_sizes: BTreeMap<DataId, usize>,
+    queues: QueueConfig,
     clock: Arc<uhlc::HLC>,
 }
 
@@ -192,7 +225,7 @@ impl Listener {
     pub(crate) async fn run<C: Connection>(
         mut connection: C,
         daemon_tx: mpsc::Sender<Timestamped<Event>>,
-        queue_sizes: BTreeMap<DataId, usize>,
+        queues: QueueConfig,
         hlc: Arc<uhlc::HLC>,
     ) {
         // receive the first message
@@ -233,7 +266,7 @@ impl Listener {
                             daemon_tx,
                             subscribed_events: None,
                             subscribed_drop_events: None,
-                            queue_sizes,
+                            queues,
                             queue: VecDeque::new(),
                             clock: hlc.clone(),
                         };
@@ -310,15 +343,26 @@ impl Listener {
                 self.queue.push_back(Box::new(Some(event)));
             }
 
-            // drop oldest input events to maintain max queue length queue
-            self.drop_oldest_inputs().await?;
+            // drop input events according to the overflow policy of their input
+            self.drop_overflowing_inputs().await?;
         }
         Ok(())
     }
 
     #[tracing::instrument(skip(self), fields(%self.node_id), level = "trace")]
-    async fn drop_oldest_inputs(&mut self) -> Result<(), eyre::ErrReport> {
-        let mut queue_size_remaining = self.queue_sizes.clone();
+    async fn drop_overflowing_inputs(&mut self) -> Result<(), eyre::ErrReport> {
+        let mut queued_inputs: BTreeMap<DataId, usize> = BTreeMap::new();
+        for event in &self.queue {
+            if let Some(Timestamped {
+                inner: NodeEvent::Input { id, .. },
+                ..
+            }) = event.as_ref()
+            {
+                *queued_inputs.entry(id.clone()).or_default() += 1;
+            }
+        }
+
+        let mut newer_inputs: BTreeMap<DataId, usize> = BTreeMap::new();
         let mut dropped = 0;
         let mut drop_tokens = Vec::new();
 
@@ -331,20 +375,28 @@ impl Listener {
             else {
                 continue;
             };
-            match queue_size_remaining.get_mut(id) {
-                Some(0) => {
-                    dropped += 1;
-                    if let Some(drop_token) = data.as_ref().and_then(|d| d.drop_token()) {
-                        drop_tokens.push(drop_token);
-                    }
-                    *event.as_mut() = None;
-                }
-                Some(size_remaining) => {
-                    *size_remaining = size_remaining.saturating_sub(1);
-                }
-                None => {
-                    tracing::warn!("no queue size known for received input `{id}`");
+            let Some(queue) = self.queues.inputs.get(id) else {
+                tracing::warn!("no queue size known for received input `{id}`");
+                continue;
+            };
+            let newer = newer_inputs.entry(id.clone()).or_default();
+            let older = queued_inputs[id] - *newer - 1;
+            *newer += 1;
+
+            let max_len = queue.overflow.queue_size(queue.size);
+            let drop = match queue.overflow {
+                OverflowPolicy::DropOldest | OverflowPolicy::KeepLatest => *newer > max_len,
+                OverflowPolicy::DropNewest => older >= max_len,
+                // the queue length is limited by the sender, which waits for free slots
+                OverflowPolicy::Block => false,
+            };
+            if drop {
+                dropped += 1;
+                queue.stats.lock().unwrap().dropped += 1;
+                if let Some(drop_token) = data.as_ref().and_then(|d| d.drop_token()) {
+                    drop_tokens.push(drop_token);
                 }
+                *event.as_mut() = None;
             }
         }
         self.report_drop_tokens(drop_tokens).await?;
@@ -358,6 +410,63 @@ impl Listener {
         Ok(())
     }
 
+    /// Records the delivery and the latency of the given input events.
+    fn record_delivered<'a>(&self, events: impl IntoIterator<Item = &'a Timestamped<NodeEvent>>) {
+        let now = self.clock.new_timestamp().get_time().to_duration();
+        for event in events {
+            if let NodeEvent::Input { id, metadata, .. } = &event.inner {
+                if let Some(queue) = self.queues.inputs.get(id) {
+                    let sent = metadata.timestamp().get_time().to_duration();
+                    let mut stats = queue.stats.lock().unwrap();
+                    stats.delivered += 1;
+                    stats.latency.record(now.saturating_sub(sent));
+                }
+            }
+        }
+    }
+
+    /// Records the given input events as dropped, e.g. because the node exited.
+    fn record_dropped<'a>(&self, events: impl IntoIterator<Item = &'a Timestamped<NodeEvent>>) {
+        for event in events {
+            if let NodeEvent::Input { id, .. } = &event.inner {
+                if let Some(queue) = self.queues.inputs.get(id) {
+                    queue.stats.lock().unwrap().dropped += 1;
+                }
+            }
+        }
+    }
+
+    /// Gives back the queue slots of dropped inputs with `overflow: block`.
+    fn release_credits<'a>(&self, events: impl IntoIterator<Item = &'a Timestamped<NodeEvent>>) {
+        for event in events {
+            if let NodeEvent::Input { id, .. } = &event.inner {
+                if let Some(credits) = self.queues.inputs.get(id).and_then(|q| q.credits.as_ref()) {
+                    credits.add_permits(1);
+                }
+            }
+        }
+    }
+
+    /// Gives back the queue slots of delivered inputs with `overflow: block`, unless the
+    /// node frees them itself.
+    fn release_delivered_credits<'a>(
+        &self,
+        events: impl IntoIterator<Item = &'a Timestamped<NodeEvent>>,
+    ) {
+        if !self.queues.deferred_credits {
+            return self.release_credits(events);
+        }
+        for event in events {
+            if let NodeEvent::Input { id, .. } = &event.inner {
+                if let Some(queue) = self.queues.inputs.get(id) {
+                    if queue.credits.is_some() {
+                        queue.unconsumed.fetch_add(1, Ordering::AcqRel);
+                    }
+                }
+            }
+        }
+    }
+
     #[tracing::instrument(skip(self, connection), fields(%self.dataflow_id, %self.node_id), level = "trace")]
     async fn handle_message<C: Connection>(
         &mut self,
@@ -402,17 +511,50 @@ impl Listener {
                 )
                 .await?
             }
+            DaemonRequest::InputsConsumed(inputs) => {
+                for input_id in inputs {
+                    let Some(queue) = self.queues.inputs.get(&input_id) else {
+                        continue;
+                    };
+                    let consumed = queue
+                        .unconsumed
+                        .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| n.checked_sub(1))
+                        .is_ok();
+                    if let (true, Some(credits)) = (consumed, &queue.credits) {
+                        credits.add_permits(1);
+                    }
+                }
+                self.send_reply(DaemonReply::Result(Ok(())), connection)
+                    .await
+                    .wrap_err("failed to send InputsConsumed reply")?;
+            }
             DaemonRequest::SendMessage {
                 output_id,
                 metadata,
                 data,
+                blocking,
             } => {
+                // wait until all receivers with `overflow: block` have room for the message
+                self.queues
+                    .backpressure
+                    .acquire(
+                        self.dataflow_id,
+                        OutputId(self.node_id.clone(), output_id.clone()),
+                    )
+                    .await;
                 let event = crate::DaemonNodeEvent::SendOut {
                     output_id,
                     metadata,
                     data,
                 };
-                self.process_daemon_event(event, None, connection).await?;
+                let reply = blocking.then(|| {
+                    // empty replies are not sent over TCP and Unix domain sockets, so
+                    // reply with a result to unblock the sender
+                    let (reply_sender, reply) = oneshot::channel();
+                    let _ = reply_sender.send(DaemonReply::Result(Ok(())));
+                    reply
+                });
+                self.process_daemon_event(event, reply, connection).await?;
             }
             DaemonRequest::Subscribe => {
                 let (tx, rx) = mpsc::unbounded_channel();
@@ -454,7 +596,11 @@ impl Listener {
                     match self.subscribed_events.as_mut() {
                         // wait for next event
                         Some(events) => match events.recv().await {
-                            Some(event) => DaemonReply::NextEvents(vec![event]),
+                            Some(event) => {
+                                self.release_delivered_credits([&event]);
+                                self.record_delivered([&event]);
+                                DaemonReply::NextEvents(vec![event])
+                            }
                             None => DaemonReply::NextEvents(vec![]),
                         },
                         None => {
@@ -464,6 +610,8 @@ impl Listener {
                         }
                     }
                 } else {
+                    self.release_delivered_credits(&queued_events);
+                    self.record_delivered(&queued_events);
                     DaemonReply::NextEvents(queued_events)
                 };
 
@@ -592,6 +740,32 @@ impl Listener {
     }
 }
 
+impl Drop for Listener {
+    fn drop(&mut self) {
+        // the queued events are never delivered -> free their queue slots
+        let queued_events: Vec<_> = mem::take(&mut self.queue)
+            .into_iter()
+            .filter_map(|e| *e)
+            .collect();
+        self.release_credits(&queued_events);
+        self.record_dropped(&queued_events);
+        if let Some(mut events) = self.subscribed_events.take() {
+            events.close();
+            while let Ok(event) = events.try_recv() {
+                self.release_credits([&event]);
+                self.record_dropped([&event]);
+            }
+        }
+        // the node exited, so the inputs that it did not consume yet never will be
+        for queue in self.queues.inputs.values() {
+            let unconsumed = queue.unconsumed.swap(0, Ordering::AcqRel);
+            if let Some(credits) = &queue.credits {
+                credits.add_permits(unconsumed);
+            }
+        }
+    }
+}
+
 #[async_trait::async_trait]
 trait Connection {
     async fn receive_message(&mut self) -> eyre::Result<Option<Timestamped<DaemonRequest>>>;
@@ -1,11 +1,11 @@
-use std::{collections::BTreeMap, io::ErrorKind, sync::Arc};
+use std::{io::ErrorKind, sync::Arc};
 
-use super::{Connection, Listener};
+use super::{Connection, Listener, QueueConfig};
 use crate::{
     socket_stream_utils::{socket_stream_receive, socket_stream_send},
     Event,
 };
-use dora_core::{config::DataId, uhlc::HLC};
+use dora_core::uhlc::HLC;
 use dora_message::{
     common::Timestamped, daemon_to_node::DaemonReply, node_to_daemon::DaemonRequest,
 };
@@ -19,7 +19,7 @@ use tokio::{
 pub async fn listener_loop(
     listener: TcpListener,
     daemon_tx: mpsc::Sender<Timestamped<Event>>,
-    queue_sizes: BTreeMap<DataId, usize>,
+    queues: QueueConfig,
     clock: Arc<HLC>,
 ) {
     loop {
@@ -35,7 +35,7 @@ pub async fn listener_loop(
                 tokio::spawn(handle_connection_loop(
                     connection,
                     daemon_tx.clone(),
-                    queue_sizes.clone(),
+                    queues.clone(),
                     clock.clone(),
                 ));
             }
@@ -47,14 +47,14 @@ pub async fn listener_loop(
 async fn handle_connection_loop(
     connection: TcpStream,
     daemon_tx: mpsc::Sender<Timestamped<Event>>,
-    queue_sizes: BTreeMap<DataId, usize>,
+    queues: QueueConfig,
     clock: Arc<HLC>,
 ) {
     if let Err(err) = connection.set_nodelay(true) {
         tracing::warn!("failed to set nodelay for connection: {err}");
     }
 
-    Listener::run(TcpConnection(connection), daemon_tx, queue_sizes, clock).await
+    Listener::run(TcpConnection(connection), daemon_tx, queues, clock).await
 }
 
 struct TcpConnection(TcpStream);
@@ -1,6 +1,6 @@
-use std::{collections::BTreeMap, io::ErrorKind, sync::Arc};
+use std::{io::ErrorKind, sync::Arc};
 
-use dora_core::{config::DataId, uhlc::HLC};
+use dora_core::uhlc::HLC;
 use dora_message::{
     common::Timestamped, daemon_to_node::DaemonReply, node_to_daemon::DaemonRequest,
 };
@@ -15,13 +15,13 @@ use crate::{
     Event,
 };
 
-use super::{Connection, Listener};
+use super::{Connection, Listener, QueueConfig};
 
 #[tracing::instrument(skip(listener, daemon_tx, clock), level = "trace")]
 pub async fn listener_loop(
     listener: UnixListener,
     daemon_tx: mpsc::Sender<Timestamped<Event>>,
-    queue_sizes: BTreeMap<DataId, usize>,
+    queues: QueueConfig,
     clock: Arc<HLC>,
 ) {
     loop {
@@ -37,7 +37,7 @@ pub async fn listener_loop(
                 tokio::spawn(handle_connection_loop(
                     connection,
                     daemon_tx.clone(),
-                    queue_sizes.clone(),
+                    queues.clone(),
                     clock.clone(),
                 ));
             }
@@ -49,10 +49,10 @@ pub async fn listener_loop(
 async fn handle_connection_loop(
     connection: UnixStream,
     daemon_tx: mpsc::Sender<Timestamped<Event>>,
-    queue_sizes: BTreeMap<DataId, usize>,
+    queues: QueueConfig,
     clock: Arc<HLC>,
 ) {
-    Listener::run(UnixConnection(connection), daemon_tx, queue_sizes, clock).await
+    Listener::run(UnixConnection(connection), daemon_tx, queues, clock).await
 }
 
 struct UnixConnection(UnixStream);
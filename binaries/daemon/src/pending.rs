@@ -5,18 +5,17 @@ use dora_core::{
     uhlc::{Timestamp, HLC},
 };
 use dora_message::{
-    daemon_to_coordinator::{CoordinatorRequest, DaemonEvent, LogLevel, LogMessage, Timestamped},
+    daemon_to_coordinator::{DaemonEvent, LogLevel, LogMessage},
     daemon_to_node::DaemonReply,
     DataflowId,
 };
-use eyre::{bail, Context};
-use tokio::{net::TcpStream, sync::oneshot};
+use eyre::bail;
+use tokio::sync::oneshot;
 
-use crate::{socket_stream_utils::socket_stream_send, CascadingErrorCauses};
+use crate::{coordinator::CoordinatorConnection, CascadingErrorCauses};
 
 pub struct PendingNodes {
     dataflow_id: DataflowId,
-    machine_id: String,
 
     /// The local nodes that are still waiting to start.
     local_nodes: HashSet<NodeId>,
@@ -38,10 +37,9 @@ pub struct PendingNodes {
 }
 
 impl PendingNodes {
-    pub fn new(dataflow_id: DataflowId, machine_id: String) -> Self {
+    pub fn new(dataflow_id: DataflowId) -> Self {
         Self {
             dataflow_id,
-            machine_id,
             local_nodes: HashSet::new(),
             external_nodes: false,
             waiting_subscribers: HashMap::new(),
@@ -62,7 +60,7 @@ impl PendingNodes {
         &mut self,
         node_id: NodeId,
         reply_sender: oneshot::Sender<DaemonReply>,
-        coordinator_connection: &mut Option<TcpStream>,
+        coordinator_connection: &mut Option<CoordinatorConnection>,
         clock: &HLC,
         cascading_errors: &mut CascadingErrorCauses,
     ) -> eyre::Result<DataflowStatus> {
@@ -77,7 +75,7 @@ impl PendingNodes {
     pub async fn handle_node_stop(
         &mut self,
         node_id: &NodeId,
-        coordinator_connection: &mut Option<TcpStream>,
+        coordinator_connection: &mut Option<CoordinatorConnection>,
         clock: &HLC,
         cascading_errors: &mut CascadingErrorCauses,
     ) -> eyre::Result<Vec<LogMessage>> {
@@ -102,7 +100,7 @@ impl PendingNodes {
 
     pub async fn handle_dataflow_stop(
         &mut self,
-        coordinator_connection: &mut Option<TcpStream>,
+        coordinator_connection: &mut Option<CoordinatorConnection>,
         clock: &HLC,
         cascading_errors: &mut CascadingErrorCauses,
         dynamic_nodes: &BTreeSet<NodeId>,
@@ -135,7 +133,7 @@ impl PendingNodes {
 
     async fn update_dataflow_status(
         &mut self,
-        coordinator_connection: &mut Option<TcpStream>,
+        coordinator_connection: &mut Option<CoordinatorConnection>,
         clock: &HLC,
         cascading_errors: &mut CascadingErrorCauses,
     ) -> eyre::Result<DataflowStatus> {
@@ -191,7 +189,7 @@ impl PendingNodes {
 
     async fn report_nodes_ready(
         &self,
-        coordinator_connection: &mut Option<TcpStream>,
+        coordinator_connection: &mut Option<CoordinatorConnection>,
         timestamp: Timestamp,
     ) -> eyre::Result<()> {
         let Some(connection) = coordinator_connection else {
@@ -203,19 +201,15 @@ impl PendingNodes {
             self.exited_before_subscribe
         );
 
-        let msg = serde_json::to_vec(&Timestamped {
-            inner: CoordinatorRequest::Event {
-                machine_id: self.machine_id.clone(),
-                event: DaemonEvent::AllNodesReady {
+        connection
+            .send_event(
+                DaemonEvent::AllNodesReady {
                     dataflow_id: self.dataflow_id,
                     exited_before_subscribe: self.exited_before_subscribe.clone(),
                 },
-            },
-            timestamp,
-        })?;
-        socket_stream_send(connection, &msg)
-            .await
-            .wrap_err("failed to send AllNodesReady message to dora-coordinator")?;
+                timestamp,
+            )
+            .await;
         Ok(())
     }
 }
@@ -0,0 +1,138 @@
+//! Measures the publish rate of node outputs.
+//!
+//! The rate is measured over windows of at least one second (or ten expected
+//! periods). When an output has an `expected_rate`, the measured rate of every
+//! window is compared to it and changes between deviating and matching are
+//! reported.
+
+use dora_core::config::{ExpectedRate, Frequency};
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+const MIN_WINDOW: Duration = Duration::from_secs(1);
+
+#[derive(Debug)]
+pub struct RateMonitor {
+    expected: Option<ExpectedRate>,
+    window_start: Instant,
+    count: u64,
+    /// Rate of the last completed window.
+    measured: Option<Frequency>,
+    deviating: bool,
+}
+
+impl RateMonitor {
+    pub fn new(expected: Option<ExpectedRate>) -> Self {
+        Self {
+            expected,
+            window_start: Instant::now(),
+            count: 0,
+            measured: None,
+            deviating: false,
+        }
+    }
+
+    pub fn expected(&self) -> Option<&ExpectedRate> {
+        self.expected.as_ref()
+    }
+
+    /// Starts measuring from scratch, e.g. after all nodes of the dataflow are ready.
+    pub fn reset(&mut self) {
+        self.window_start = Instant::now();
+        self.count = 0;
+        self.measured = None;
+        self.deviating = false;
+    }
+
+    fn window(&self) -> Duration {
+        match &self.expected {
+            Some(expected) => Duration::from_secs_f64(10.0 / expected.rate.as_hz()).max(MIN_WINDOW),
+            None => MIN_WINDOW,
+        }
+    }
+
+    /// Records a sent message.
+    ///
+    /// Returns a report if the measured rate started to deviate from the expected
+    /// rate, or if it is back within the tolerance.
+    pub fn record(&mut self) -> Option<RateDeviation> {
+        self.count += 1;
+        self.check()
+    }
+
+    /// Completes the current window if it is over, without recording a message.
+    ///
+    /// Called periodically, so that outputs that stopped publishing are reported too.
+    pub fn check(&mut self) -> Option<RateDeviation> {
+        let now = Instant::now();
+        let elapsed = now - self.window_start;
+        if elapsed < self.window() {
+            return None;
+        }
+        let measured = Frequency::from_hz(self.count as f64 / elapsed.as_secs_f64());
+        self.measured = Some(measured);
+        self.window_start = now;
+        self.count = 0;
+
+        let expected = self.expected?;
+        let deviating = !expected.matches(measured);
+        if deviating == self.deviating {
+            return None;
+        }
+        self.deviating = deviating;
+        Some(RateDeviation {
+            expected,
+            measured,
+            recovered: !deviating,
+        })
+    }
+
+    /// Returns the currently measured rate.
+    ///
+    /// Includes the running window if it is already overdue, so that outputs that
+    /// stopped publishing are reported with a decreasing rate.
+    pub fn measured(&self) -> Option<Frequency> {
+        let elapsed = self.window_start.elapsed();
+        if elapsed >= self.window() {
+            Some(Frequency::from_hz(
+                self.count as f64 / elapsed.as_secs_f64(),
+            ))
+        } else {
+            self.measured
+        }
+    }
+}
+
+/// The measured rate of an output changed between deviating and matching its
+/// `expected_rate`.
+pub struct RateDeviation {
+    pub expected: ExpectedRate,
+    pub measured: Frequency,
+    /// The rate is back within the tolerance.
+    pub recovered: bool,
+}
+
+impl fmt::Display for RateDeviation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self {
+            expected,
+            measured,
+            recovered,
+        } = self;
+        if *recovered {
+            write!(
+                f,
+                "publish rate is back at {measured} (expected {} ±{}%)",
+                expected.rate, expected.tolerance_percent
+            )
+        } else {
+            write!(
+                f,
+                "publish rate is {measured}, which deviates from the expected {} by more than {}%",
+                expected.rate, expected.tolerance_percent
+            )
+        }
+    }
+}
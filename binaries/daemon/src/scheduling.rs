@@ -0,0 +1,69 @@
+//! Applies the `scheduling` options of a node to its process.
+
+use dora_core::descriptor::Scheduling;
+
+/// Sets up the given command to apply the scheduling options in the spawned
+/// process, before it executes the node.
+#[cfg(target_os = "linux")]
+pub fn apply(command: &mut tokio::process::Command, scheduling: &Scheduling) -> eyre::Result<()> {
+    use dora_core::descriptor::{check_scheduling_privileges, SchedulingPolicy};
+    use std::{io, mem};
+
+    check_scheduling_privileges(scheduling)?;
+
+    let policy = scheduling.policy.map(|policy| match policy {
+        SchedulingPolicy::Other => libc::SCHED_OTHER,
+        SchedulingPolicy::Fifo => libc::SCHED_FIFO,
+        SchedulingPolicy::RoundRobin => libc::SCHED_RR,
+        SchedulingPolicy::Batch => libc::SCHED_BATCH,
+        SchedulingPolicy::Idle => libc::SCHED_IDLE,
+    });
+    let priority = scheduling.priority.map(i32::from).unwrap_or(0);
+    let nice = scheduling.nice.map(i32::from);
+    let cpus = match &scheduling.cpus {
+        Some(cpus) => {
+            // SAFETY: an all-zero `cpu_set_t` is a valid empty set
+            let mut set: libc::cpu_set_t = unsafe { mem::zeroed() };
+            for &cpu in &cpus.0 {
+                if cpu >= libc::CPU_SETSIZE as usize {
+                    eyre::bail!("invalid CPU index {cpu}");
+                }
+                // SAFETY: `cpu` is within the bounds of the set
+                unsafe { libc::CPU_SET(cpu, &mut set) };
+            }
+            Some(set)
+        }
+        None => None,
+    };
+
+    // SAFETY: the closure only issues syscalls, which are async-signal-safe
+    unsafe {
+        command.pre_exec(move || {
+            if let Some(cpus) = &cpus {
+                if libc::sched_setaffinity(0, mem::size_of::<libc::cpu_set_t>(), cpus) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            if let Some(nice) = nice {
+                if libc::setpriority(libc::PRIO_PROCESS, 0, nice) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            if let Some(policy) = policy {
+                let param = libc::sched_param {
+                    sched_priority: priority,
+                };
+                if libc::sched_setscheduler(0, policy, &param) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            Ok(())
+        });
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn apply(_command: &mut tokio::process::Command, _scheduling: &Scheduling) -> eyre::Result<()> {
+    eyre::bail!("scheduling options are only supported on Linux")
+}
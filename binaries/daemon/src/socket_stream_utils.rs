@@ -23,3 +23,25 @@ pub async fn socket_stream_receive(
     connection.read_exact(&mut reply).await?;
     Ok(reply)
 }
+
+/// Like [`socket_stream_receive`], but fails with [`std::io::ErrorKind::InvalidData`] instead of
+/// allocating a buffer if the announced message is larger than `max_len`.
+pub async fn socket_stream_receive_bounded(
+    connection: &mut (impl AsyncRead + Unpin),
+    max_len: usize,
+) -> std::io::Result<Vec<u8>> {
+    let len = {
+        let mut raw = [0; 8];
+        connection.read_exact(&mut raw).await?;
+        u64::from_le_bytes(raw)
+    };
+    if len > max_len as u64 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("message of {len} bytes exceeds the limit of {max_len} bytes"),
+        ));
+    }
+    let mut message = vec![0; len as usize];
+    connection.read_exact(&mut message).await?;
+    Ok(message)
+}
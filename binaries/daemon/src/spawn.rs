@@ -1,6 +1,8 @@
 use crate::{
-    log, node_communication::spawn_listener_loop, node_inputs, DoraEvent, Event, OutputId,
-    RunningNode,
+    cgroup::NodeCgroup,
+    log,
+    node_communication::{spawn_listener_loop, QueueConfig},
+    scheduling, DoraEvent, Event, OutputId, RunningNode,
 };
 use aligned_vec::{AVec, ConstAlign};
 use crossbeam::queue::ArrayQueue;
@@ -33,35 +35,33 @@ use std::{
     sync::Arc,
 };
 use tokio::{
-    fs::File,
+    fs::OpenOptions,
     io::{AsyncBufReadExt, AsyncWriteExt},
     sync::{mpsc, oneshot},
 };
 use tracing::error;
 
 /// clock is required for generating timestamps when dropping messages early because queue is full
+#[allow(clippy::too_many_arguments)]
 pub async fn spawn_node(
     dataflow_id: DataflowId,
     working_dir: &Path,
     node: ResolvedNode,
     daemon_tx: mpsc::Sender<Timestamped<Event>>,
     dataflow_descriptor: Descriptor,
+    queues: QueueConfig,
     clock: Arc<HLC>,
     node_stderr_most_recent: Arc<ArrayQueue<String>>,
 ) -> eyre::Result<RunningNode> {
     let node_id = node.id.clone();
     tracing::debug!("Spawning node `{dataflow_id}/{node_id}`");
 
-    let queue_sizes = node_inputs(&node)
-        .into_iter()
-        .map(|(k, v)| (k, v.queue_size.unwrap_or(10)))
-        .collect();
     let daemon_communication = spawn_listener_loop(
         &dataflow_id,
         &node_id,
         &daemon_tx,
         dataflow_descriptor.communication.local,
-        queue_sizes,
+        queues,
         clock.clone(),
     )
     .await?;
@@ -78,6 +78,14 @@ pub async fn spawn_node(
         dynamic: node.kind.dynamic(),
     };
 
+    let cgroup = if node.resources.is_empty() {
+        None
+    } else {
+        let cgroup = NodeCgroup::create(dataflow_id, &node_id, &node.resources)
+            .wrap_err("failed to apply resource limits")?;
+        Some(cgroup)
+    };
+
     let mut child = match node.kind {
         dora_core::descriptor::CoreNodeKind::Custom(n) => {
             let mut command = match n.source.as_str() {
@@ -157,6 +165,13 @@ pub async fn spawn_node(
                     command.env(key, value.to_string());
                 }
             }
+            if let Some(cgroup) = &cgroup {
+                join_cgroup(&mut command, cgroup)?;
+            }
+            if !node.scheduling.is_empty() {
+                scheduling::apply(&mut command, &node.scheduling)
+                    .wrap_err("failed to apply scheduling options")?;
+            }
             command
                 .stdin(Stdio::null())
                 .stdout(Stdio::piped())
@@ -254,6 +269,13 @@ pub async fn spawn_node(
                 }
             }
 
+            if let Some(cgroup) = &cgroup {
+                join_cgroup(&mut command, cgroup)?;
+            }
+            if !node.scheduling.is_empty() {
+                scheduling::apply(&mut command, &node.scheduling)
+                    .wrap_err("failed to apply scheduling options")?;
+            }
             command
                 .stdin(Stdio::null())
                 .stdout(Stdio::piped())
@@ -271,7 +293,11 @@ pub async fn spawn_node(
         std::fs::create_dir_all(&dataflow_dir).context("could not create dataflow_dir")?;
     }
     let (tx, mut rx) = mpsc::channel(10);
-    let mut file = File::create(log::log_path(working_dir, &dataflow_id, &node_id))
+    // append to existing log file to keep the output of previous runs when the node is restarted
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log::log_path(working_dir, &dataflow_id, &node_id))
         .await
         .expect("Failed to create log file");
     let mut child_stdout =
@@ -393,11 +419,20 @@ pub async fn spawn_node(
     let (log_finish_tx, log_finish_rx) = oneshot::channel();
     tokio::spawn(async move {
         let exit_status = NodeExitStatus::from(child.wait().await);
+        let out_of_memory = match cgroup {
+            Some(cgroup) => {
+                let out_of_memory = cgroup.out_of_memory();
+                cgroup.remove().await;
+                out_of_memory
+            }
+            None => false,
+        };
         let _ = log_finish_rx.await;
         let event = DoraEvent::SpawnedNodeResult {
             dataflow_id,
             node_id,
             exit_status,
+            out_of_memory,
         }
         .into();
         let event = Timestamped {
@@ -464,3 +499,21 @@ pub async fn spawn_node(
     });
     Ok(running_node)
 }
+
+/// Moves the spawned process into the given cgroup before it executes the node, so
+/// that the limits apply from the start.
+fn join_cgroup(command: &mut tokio::process::Command, cgroup: &NodeCgroup) -> eyre::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::io::Write;
+
+        let procs = cgroup.procs_file()?;
+        // SAFETY: the closure only issues a `write` syscall, which is async-signal-safe
+        unsafe {
+            command.pre_exec(move || (&procs).write_all(b"0"));
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = (command, cgroup);
+    Ok(())
+}
@@ -1,7 +1,7 @@
 #![warn(unsafe_op_in_unsafe_fn)]
 
 use dora_core::{
-    config::{DataId, OperatorId},
+    config::{DataId, OperatorId, OverflowPolicy},
     descriptor::OperatorConfig,
 };
 use dora_message::daemon_to_node::{NodeConfig, RuntimeConfig};
@@ -63,10 +63,17 @@ pub fn main() -> eyre::Result<()> {
         .wrap_err("Could not build a tokio runtime.")?;
 
     let mut operator_channels = HashMap::new();
-    let queue_sizes = queue_sizes(&operator_definition.config);
-    let (operator_channel, incoming_events) =
-        operator::channel::channel(tokio_runtime.handle(), queue_sizes);
+    let queues = input_queues(&operator_definition.config);
+    let (operator_channel, incoming_events, consumed_inputs) =
+        operator::channel::channel(tokio_runtime.handle(), queues);
     operator_channels.insert(operator_definition.id.clone(), operator_channel);
+    let consumed_operator_id = operator_definition.id.clone();
+    let consumed_inputs =
+        consumed_inputs
+            .into_stream()
+            .map(move |input_id| RuntimeEvent::InputConsumed {
+                id: operator_output_id(&consumed_operator_id, &input_id),
+            });
 
     tracing::info!("spawning main task");
     let operator_config = [(
@@ -80,7 +87,7 @@ pub fn main() -> eyre::Result<()> {
         tokio_runtime.block_on(run(
             operator_config,
             config,
-            operator_events,
+            (operator_events, consumed_inputs).merge(),
             operator_channels,
             init_done,
         ))
@@ -105,13 +112,13 @@ pub fn main() -> eyre::Result<()> {
     Ok(())
 }
 
-fn queue_sizes(config: &OperatorConfig) -> std::collections::BTreeMap<DataId, usize> {
-    let mut sizes = BTreeMap::new();
+fn input_queues(config: &OperatorConfig) -> BTreeMap<DataId, (usize, OverflowPolicy)> {
+    let mut queues = BTreeMap::new();
     for (input_id, input) in &config.inputs {
         let queue_size = input.queue_size.unwrap_or(10);
-        sizes.insert(input_id.clone(), queue_size);
+        queues.insert(input_id.clone(), (queue_size, input.overflow));
     }
-    sizes
+    queues
 }
 
 #[tracing::instrument(skip(operator_events, operator_channels), level = "trace")]
@@ -228,6 +235,16 @@ async fn run(
                     }
                 }
             }
+            RuntimeEvent::InputConsumed { id } => {
+                let result;
+                (node, result) = tokio::task::spawn_blocking(move || {
+                    let result = node.report_consumed_inputs(vec![id]);
+                    (node, result)
+                })
+                .await
+                .wrap_err("failed to wait for report_consumed_inputs task")?;
+                result.wrap_err("failed to report consumed operator input")?;
+            }
             RuntimeEvent::Event(Event::Stop) => {
                 // forward stop event to all operators and close the event channels
                 for (_, channel) in operator_channels.drain() {
@@ -310,6 +327,32 @@ async fn run(
                     }
                 }
             }
+            RuntimeEvent::Event(Event::InputTimeout { id }) => {
+                let Some((operator_id, input_id)) = id.as_str().split_once('/') else {
+                    tracing::warn!("received InputTimeout event for non-operator input {id}");
+                    continue;
+                };
+                let operator_id = OperatorId::from(operator_id.to_owned());
+                let input_id = DataId::from(input_id.to_owned());
+
+                let Some(operator_channel) = operator_channels.get(&operator_id) else {
+                    tracing::warn!("received input {id} for unknown operator");
+                    continue;
+                };
+                if let Err(err) = operator_channel
+                    .send_async(Event::InputTimeout {
+                        id: input_id.clone(),
+                    })
+                    .await
+                    .wrap_err_with(|| {
+                        format!(
+                            "failed to send InputTimeout({input_id}) to operator `{operator_id}`"
+                        )
+                    })
+                {
+                    tracing::warn!("{err}");
+                }
+            }
             RuntimeEvent::Event(Event::Error(err)) => eyre::bail!("received error event: {err}"),
             RuntimeEvent::Event(other) => {
                 tracing::warn!("received unknown event `{other:?}`");
@@ -332,5 +375,9 @@ enum RuntimeEvent {
         id: OperatorId,
         event: OperatorEvent,
     },
+    /// An input with `overflow: block` was passed to its operator.
+    InputConsumed {
+        id: DataId,
+    },
     Event(Event),
 }
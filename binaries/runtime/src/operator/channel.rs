@@ -1,4 +1,4 @@
-use dora_core::config::DataId;
+use dora_core::config::{DataId, OverflowPolicy};
 use dora_node_api::Event;
 use futures::{
     future::{self, FusedFuture},
@@ -6,31 +6,49 @@ use futures::{
 };
 use std::collections::{BTreeMap, VecDeque};
 
+/// Creates the input channel of an operator.
+///
+/// The returned `consumed` receiver yields the IDs of the inputs with `overflow: block`
+/// that were passed to the operator. Their queue slots need to be freed through
+/// [`DoraNode::report_consumed_inputs`](dora_node_api::DoraNode::report_consumed_inputs).
 pub fn channel(
     runtime: &tokio::runtime::Handle,
-    queue_sizes: BTreeMap<DataId, usize>,
-) -> (flume::Sender<Event>, flume::Receiver<Event>) {
+    queues: BTreeMap<DataId, (usize, OverflowPolicy)>,
+) -> (
+    flume::Sender<Event>,
+    flume::Receiver<Event>,
+    flume::Receiver<DataId>,
+) {
     let (incoming_tx, incoming_rx) = flume::bounded(10);
     let (outgoing_tx, outgoing_rx) = flume::bounded(0);
+    let (consumed_tx, consumed_rx) = flume::unbounded();
 
     runtime.spawn(async {
-        let mut buffer = InputBuffer::new(queue_sizes);
+        let mut buffer = InputBuffer::new(queues, consumed_tx);
         buffer.run(incoming_rx, outgoing_tx).await;
     });
 
-    (incoming_tx, outgoing_rx)
+    (incoming_tx, outgoing_rx, consumed_rx)
 }
 
 struct InputBuffer {
     queue: VecDeque<Option<Event>>,
-    queue_sizes: BTreeMap<DataId, usize>,
+    queues: BTreeMap<DataId, (usize, OverflowPolicy)>,
+    /// The blocking input that is currently passed to the operator.
+    sending: Option<DataId>,
+    consumed: flume::Sender<DataId>,
 }
 
 impl InputBuffer {
-    pub fn new(queue_sizes: BTreeMap<DataId, usize>) -> Self {
+    pub fn new(
+        queues: BTreeMap<DataId, (usize, OverflowPolicy)>,
+        consumed: flume::Sender<DataId>,
+    ) -> Self {
         Self {
             queue: VecDeque::new(),
-            queue_sizes,
+            queues,
+            sending: None,
+            consumed,
         }
     }
 
@@ -65,6 +83,9 @@ impl InputBuffer {
                 }
                 future::Either::Right((send_result, _)) => match send_result {
                     Ok(()) => {
+                        if let Some(input_id) = self.sending.take() {
+                            let _ = self.consumed.send(input_id);
+                        }
                         send_out_buf = self.send_next_queued(&outgoing);
                     }
                     Err(flume::SendError(_)) => break,
@@ -82,7 +103,17 @@ impl InputBuffer {
     ) -> future::Fuse<flume::r#async::SendFut<'a, Event>> {
         loop {
             match self.queue.pop_front() {
-                Some(Some(next)) => break outgoing.send_async(next).fuse(),
+                Some(Some(next)) => {
+                    self.sending = match &next {
+                        Event::Input { id, .. } => self
+                            .queues
+                            .get(id)
+                            .filter(|(_, overflow)| *overflow == OverflowPolicy::Block)
+                            .map(|_| id.clone()),
+                        _ => None,
+                    };
+                    break outgoing.send_async(next).fuse();
+                }
                 Some(None) => {
                     // dropped event, try again with next one
                 }
@@ -94,12 +125,19 @@ impl InputBuffer {
     fn add_event(&mut self, event: Event) {
         self.queue.push_back(Some(event));
 
-        // drop oldest input events to maintain max queue length queue
-        self.drop_oldest_inputs();
+        // drop input events according to the overflow policy of their input
+        self.drop_overflowing_inputs();
     }
 
-    fn drop_oldest_inputs(&mut self) {
-        let mut queue_size_remaining = self.queue_sizes.clone();
+    fn drop_overflowing_inputs(&mut self) {
+        let mut queued_inputs: BTreeMap<DataId, usize> = BTreeMap::new();
+        for event in self.queue.iter().flatten() {
+            if let Event::Input { id, .. } = event {
+                *queued_inputs.entry(id.clone()).or_default() += 1;
+            }
+        }
+
+        let mut newer_inputs: BTreeMap<DataId, usize> = BTreeMap::new();
         let mut dropped = 0;
 
         // iterate over queued events, newest first
@@ -107,17 +145,25 @@ impl InputBuffer {
             let Some(Event::Input { id: input_id, .. }) = event.as_mut() else {
                 continue;
             };
-            match queue_size_remaining.get_mut(input_id) {
-                Some(0) => {
-                    dropped += 1;
-                    *event = None;
-                }
-                Some(size_remaining) => {
-                    *size_remaining = size_remaining.saturating_sub(1);
-                }
-                None => {
-                    tracing::warn!("no queue size known for received operator input `{input_id}`");
-                }
+            let Some((queue_size, overflow)) = self.queues.get(input_id) else {
+                tracing::warn!("no queue size known for received operator input `{input_id}`");
+                continue;
+            };
+            let newer = newer_inputs.entry(input_id.clone()).or_default();
+            let older = queued_inputs[input_id] - *newer - 1;
+            *newer += 1;
+
+            let max_len = overflow.queue_size(*queue_size);
+            let drop = match overflow {
+                OverflowPolicy::DropOldest | OverflowPolicy::KeepLatest => *newer > max_len,
+                OverflowPolicy::DropNewest => older >= max_len,
+                // the queue length is limited by the daemon, which waits until the
+                // operator consumed the input before it frees the queue slot
+                OverflowPolicy::Block => false,
+            };
+            if drop {
+                dropped += 1;
+                *event = None;
             }
         }
 
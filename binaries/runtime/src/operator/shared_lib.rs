@@ -192,6 +192,7 @@ impl<'lib> SharedLibraryOperator<'lib> {
                     input_closed: None,
                     stop: true,
                     error: None,
+                    input_timeout: None,
                 },
                 Event::Input {
                     id: input_id,
@@ -213,6 +214,7 @@ impl<'lib> SharedLibraryOperator<'lib> {
                         input_closed: None,
                         stop: false,
                         error: None,
+                        input_timeout: None,
                     }
                 }
                 Event::InputClosed { id: input_id } => dora_operator_api_types::RawEvent {
@@ -220,6 +222,14 @@ impl<'lib> SharedLibraryOperator<'lib> {
                     input: None,
                     stop: false,
                     error: None,
+                    input_timeout: None,
+                },
+                Event::InputTimeout { id: input_id } => dora_operator_api_types::RawEvent {
+                    input_timeout: Some(input_id.to_string().into()),
+                    input_closed: None,
+                    input: None,
+                    stop: false,
+                    error: None,
                 },
                 Event::Reload { .. } => {
                     // Reloading shared lib operator is not supported. See: https://github.com/dora-rs/dora/pull/239#discussion_r1154313139
@@ -230,6 +240,7 @@ impl<'lib> SharedLibraryOperator<'lib> {
                     input_closed: None,
                     input: None,
                     stop: false,
+                    input_timeout: None,
                 },
                 other => {
                     tracing::warn!("unexpected event: {other:?}");
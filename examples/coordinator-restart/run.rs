@@ -0,0 +1,206 @@
+//! Kills and restarts the coordinator while a dataflow is running.
+//!
+//! The daemon should keep the dataflow running, reconnect to the new coordinator, and
+//! re-announce the dataflow so that it can still be stopped.
+
+use communication_layer_request_reply::{RequestReplyLayer, TcpLayer, TcpRequestReplyConnection};
+use dora_core::descriptor::Descriptor;
+use dora_message::{
+    cli_to_coordinator::ControlRequest,
+    coordinator_to_cli::{ControlRequestReply, DataflowList},
+};
+use dora_tracing::set_up_tracing;
+use eyre::{bail, Context};
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    path::Path,
+    time::Duration,
+};
+use tokio::process::Child;
+use uuid::Uuid;
+
+const COORDINATOR_PORT: u16 = 53390;
+const COORDINATOR_CONTROL_PORT: u16 = 6112;
+const DATAFLOW_NAME: &str = "coordinator-restart";
+
+#[tokio::main]
+async fn main() -> eyre::Result<()> {
+    set_up_tracing("coordinator-restart-runner").wrap_err("failed to set up tracing subscriber")?;
+
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    std::env::set_current_dir(root.join(file!()).parent().unwrap())
+        .wrap_err("failed to set working dir")?;
+
+    let dataflow = Path::new("dataflow.yml");
+    build_dataflow(dataflow).await?;
+
+    let state_dir = Path::new("out").join("coordinator-state");
+    if state_dir.exists() {
+        std::fs::remove_dir_all(&state_dir).wrap_err("failed to clean up state dir")?;
+    }
+
+    let mut coordinator = spawn_coordinator(&state_dir)?;
+    retry("connect to coordinator", || async { connect().map(|_| ()) }).await?;
+    let daemon = tokio::spawn(run_daemon());
+
+    let uuid = retry("start dataflow", || start_dataflow(dataflow)).await?;
+    tracing::info!("started dataflow under ID `{uuid}`");
+    tokio::time::sleep(Duration::from_secs(2)).await;
+
+    tracing::info!("killing coordinator");
+    coordinator.kill().await?;
+    tokio::time::sleep(Duration::from_secs(2)).await;
+
+    tracing::info!("restarting coordinator");
+    let mut coordinator = spawn_coordinator(&state_dir)?;
+    retry("find re-announced dataflow", || async {
+        let list = dataflow_list()?;
+        let Some(entry) = list.get_active().into_iter().find(|d| d.uuid == uuid) else {
+            bail!("dataflow `{uuid}` is not running");
+        };
+        if entry.name.as_deref() != Some(DATAFLOW_NAME) {
+            bail!("unexpected name of recovered dataflow: {:?}", entry.name);
+        }
+        Ok(())
+    })
+    .await?;
+    tracing::info!("dataflow `{uuid}` was recovered, stopping it");
+
+    match request(&ControlRequest::Stop {
+        dataflow_uuid: uuid,
+        grace_duration: None,
+    })? {
+        ControlRequestReply::DataflowStopped { result, .. } if result.is_ok() => {}
+        ControlRequestReply::DataflowStopped { result, .. } => {
+            bail!("dataflow failed: {:?}", result.node_results)
+        }
+        other => bail!("unexpected stop reply: {other:?}"),
+    }
+    let list = dataflow_list()?;
+    if !list.get_active().is_empty() {
+        bail!("dataflow still running after stop");
+    }
+
+    match request(&ControlRequest::Destroy)? {
+        ControlRequestReply::DestroyOk => {}
+        other => bail!("unexpected destroy reply: {other:?}"),
+    }
+    daemon.await??;
+    coordinator.wait().await?;
+
+    tracing::info!("done");
+    Ok(())
+}
+
+fn spawn_coordinator(state_dir: &Path) -> eyre::Result<Child> {
+    let cargo = std::env::var("CARGO").unwrap();
+    let mut cmd = tokio::process::Command::new(cargo);
+    cmd.arg("run");
+    cmd.arg("--package").arg("dora-cli");
+    cmd.arg("--")
+        .arg("coordinator")
+        .arg("--port")
+        .arg(COORDINATOR_PORT.to_string())
+        .arg("--control-port")
+        .arg(COORDINATOR_CONTROL_PORT.to_string())
+        .arg("--state-dir")
+        .arg(state_dir);
+    cmd.kill_on_drop(true);
+    cmd.spawn().wrap_err("failed to spawn coordinator")
+}
+
+async fn run_daemon() -> eyre::Result<()> {
+    let cargo = std::env::var("CARGO").unwrap();
+    let mut cmd = tokio::process::Command::new(&cargo);
+    cmd.arg("run");
+    cmd.arg("--package").arg("dora-cli");
+    cmd.arg("--")
+        .arg("daemon")
+        .arg("--coordinator-addr")
+        .arg(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), COORDINATOR_PORT).to_string());
+    if !cmd.status().await?.success() {
+        bail!("failed to run daemon");
+    };
+    Ok(())
+}
+
+async fn build_dataflow(dataflow: &Path) -> eyre::Result<()> {
+    let cargo = std::env::var("CARGO").unwrap();
+    let mut cmd = tokio::process::Command::new(&cargo);
+    cmd.arg("run");
+    cmd.arg("--package").arg("dora-cli");
+    cmd.arg("--").arg("build").arg(dataflow);
+    if !cmd.status().await?.success() {
+        bail!("failed to build dataflow");
+    };
+    Ok(())
+}
+
+async fn start_dataflow(dataflow: &Path) -> eyre::Result<Uuid> {
+    let working_dir = dataflow
+        .canonicalize()
+        .context("failed to canonicalize dataflow path")?
+        .parent()
+        .ok_or_else(|| eyre::eyre!("dataflow path has no parent dir"))?
+        .to_owned();
+    let dataflow = Descriptor::read(dataflow)
+        .await
+        .wrap_err("failed to read yaml dataflow")?;
+    match request(&ControlRequest::Start {
+        dataflow,
+        name: Some(DATAFLOW_NAME.to_owned()),
+        local_working_dir: working_dir,
+        artifacts: Default::default(),
+        artifact_data: Vec::new(),
+    })? {
+        ControlRequestReply::DataflowStarted { uuid } => Ok(uuid),
+        other => bail!("unexpected start dataflow reply: {other:?}"),
+    }
+}
+
+fn dataflow_list() -> eyre::Result<DataflowList> {
+    match request(&ControlRequest::List)? {
+        ControlRequestReply::DataflowList(list) => Ok(list),
+        other => bail!("unexpected list reply: {other:?}"),
+    }
+}
+
+fn request(request: &ControlRequest) -> eyre::Result<ControlRequestReply> {
+    let mut session = connect()?;
+    let reply_raw = session
+        .request(&serde_json::to_vec(request)?)
+        .wrap_err("failed to send request to coordinator")?;
+    match serde_json::from_slice(&reply_raw).wrap_err("failed to parse reply")? {
+        ControlRequestReply::Error(err) => bail!("{err}"),
+        other => Ok(other),
+    }
+}
+
+fn connect() -> eyre::Result<Box<TcpRequestReplyConnection>> {
+    TcpLayer::new()
+        .connect(SocketAddr::new(
+            Ipv4Addr::LOCALHOST.into(),
+            COORDINATOR_CONTROL_PORT,
+        ))
+        .wrap_err("failed to connect to coordinator")
+}
+
+/// Retries the given operation for up to 30 seconds, e.g. until the coordinator and
+/// the daemon are (re)connected.
+async fn retry<T, F>(operation: &str, mut f: impl FnMut() -> F) -> eyre::Result<T>
+where
+    F: std::future::Future<Output = eyre::Result<T>>,
+{
+    let mut retries = 0;
+    loop {
+        match f().await {
+            Ok(value) => break Ok(value),
+            Err(err) if retries < 60 => {
+                tracing::debug!("failed to {operation}, retrying: {err:?}");
+                tokio::time::sleep(Duration::from_millis(500)).await;
+                retries += 1;
+            }
+            Err(err) => break Err(err.wrap_err(format!("failed to {operation}"))),
+        }
+    }
+}
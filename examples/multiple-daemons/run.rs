@@ -1,5 +1,6 @@
 use dora_coordinator::{ControlEvent, Event};
 use dora_core::{
+    config::NodeId,
     descriptor::Descriptor,
     topics::{DORA_COORDINATOR_PORT_CONTROL_DEFAULT, DORA_COORDINATOR_PORT_DEFAULT},
 };
@@ -7,13 +8,14 @@ use dora_message::{
     cli_to_coordinator::ControlRequest,
     coordinator_to_cli::{ControlRequestReply, DataflowIdAndName},
 };
+use dora_security::{Security, SecurityConfig, TlsConfig};
 use dora_tracing::set_up_tracing;
 use eyre::{bail, Context};
 
 use std::{
-    collections::BTreeSet,
+    collections::{BTreeMap, BTreeSet},
     net::{IpAddr, Ipv4Addr, SocketAddr},
-    path::Path,
+    path::{Path, PathBuf},
     time::Duration,
 };
 use tokio::{
@@ -34,9 +36,32 @@ async fn main() -> eyre::Result<()> {
     std::env::set_current_dir(root.join(file!()).parent().unwrap())
         .wrap_err("failed to set working dir")?;
 
-    let dataflow = Path::new("dataflow.yml");
+    // with `--zenoh`, the daemons communicate through peer-to-peer zenoh sessions
+    let zenoh = std::env::args().any(|arg| arg == "--zenoh");
+    // with `--best-effort`, the outputs to the other machine are sent through UDP
+    let best_effort = std::env::args().any(|arg| arg == "--best-effort");
+    // with `--selector`, the coordinator places the nodes based on the machine labels
+    let selector = std::env::args().any(|arg| arg == "--selector");
+    let dataflow = if zenoh {
+        Path::new("dataflow_zenoh.yml")
+    } else if best_effort {
+        Path::new("dataflow_best_effort.yml")
+    } else if selector {
+        Path::new("dataflow_selector.yml")
+    } else {
+        Path::new("dataflow.yml")
+    };
     build_dataflow(dataflow).await?;
 
+    // with `--secure`, all connections use TLS and require a pre-shared token
+    let secure = std::env::args().any(|arg| arg == "--secure");
+    let (config_path, security) = if secure {
+        let (path, security) = create_secure_config().await?;
+        (Some(path), security)
+    } else {
+        (None, Security::default())
+    };
+
     let (coordinator_events_tx, coordinator_events_rx) = mpsc::channel(1);
     let coordinator_bind = SocketAddr::new(
         IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)),
@@ -49,12 +74,29 @@ async fn main() -> eyre::Result<()> {
     let (coordinator_port, coordinator) = dora_coordinator::start(
         coordinator_bind,
         coordinator_control_bind,
+        None,
+        security,
         ReceiverStream::new(coordinator_events_rx),
     )
     .await?;
     let coordinator_addr = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), coordinator_port);
-    let daemon_a = run_daemon(coordinator_addr.to_string(), "A", 9843); // Random port
-    let daemon_b = run_daemon(coordinator_addr.to_string(), "B", 9842);
+    let zenoh_config = |path| zenoh.then(|| Path::new(path));
+    let daemon_a = run_daemon(
+        coordinator_addr.to_string(),
+        "A",
+        9843, // Random port
+        "camera=true",
+        zenoh_config("zenoh-a.json5"),
+        config_path.clone(),
+    );
+    let daemon_b = run_daemon(
+        coordinator_addr.to_string(),
+        "B",
+        9842,
+        "gpu=true",
+        zenoh_config("zenoh-b.json5"),
+        config_path,
+    );
 
     tracing::info!("Spawning coordinator and daemons");
     let mut tasks = JoinSet::new();
@@ -84,6 +126,13 @@ async fn main() -> eyre::Result<()> {
     if !running.iter().map(|d| d.uuid).any(|id| id == uuid) {
         bail!("dataflow `{uuid}` is not running");
     }
+    if selector {
+        let placement = placement(uuid, &coordinator_events_tx).await?;
+        let machine = placement.get(&NodeId::from("rust-sink".to_owned()));
+        if machine.map(String::as_str) != Some("B") {
+            bail!("expected `rust-sink` to be placed on machine `B`, got {machine:?}");
+        }
+    }
 
     tracing::info!("waiting for dataflow `{uuid}` to finish");
     let mut retries = 0;
@@ -135,6 +184,8 @@ async fn start_dataflow(
                 dataflow: dataflow_descriptor,
                 local_working_dir: working_dir,
                 name: None,
+                artifacts: Default::default(),
+                artifact_data: Vec::new(),
             },
             reply_sender,
         }))
@@ -186,6 +237,30 @@ async fn running_dataflows(
     Ok(dataflows)
 }
 
+async fn placement(
+    uuid: Uuid,
+    coordinator_events_tx: &Sender<Event>,
+) -> eyre::Result<BTreeMap<NodeId, String>> {
+    let (reply_sender, reply) = oneshot::channel();
+    coordinator_events_tx
+        .send(Event::Control(ControlEvent::IncomingRequest {
+            request: ControlRequest::List,
+            reply_sender,
+        }))
+        .await?;
+    let result = reply.await??;
+    let list = match result {
+        ControlRequestReply::DataflowList(list) => list,
+        ControlRequestReply::Error(err) => bail!("{err}"),
+        other => bail!("unexpected list dataflow reply: {other:?}"),
+    };
+    list.0
+        .into_iter()
+        .find(|d| d.id.uuid == uuid)
+        .map(|d| d.placement)
+        .ok_or_else(|| eyre::eyre!("dataflow `{uuid}` is not listed"))
+}
+
 async fn destroy(coordinator_events_tx: &Sender<Event>) -> eyre::Result<()> {
     let (reply_sender, reply) = oneshot::channel();
     coordinator_events_tx
@@ -214,10 +289,50 @@ async fn build_dataflow(dataflow: &Path) -> eyre::Result<()> {
     Ok(())
 }
 
+/// Generates certificates through `dora certs` and writes a config file that enables
+/// TLS and token authentication.
+async fn create_secure_config() -> eyre::Result<(PathBuf, Security)> {
+    let out_dir = Path::new("out");
+    let certs_dir = out_dir.join("certs");
+    let cargo = std::env::var("CARGO").unwrap();
+    let mut cmd = tokio::process::Command::new(&cargo);
+    cmd.arg("run");
+    cmd.arg("--package").arg("dora-cli");
+    cmd.arg("--")
+        .arg("certs")
+        .arg("--out-dir")
+        .arg(&certs_dir)
+        .arg("--force");
+    if !cmd.status().await?.success() {
+        bail!("failed to generate certificates");
+    };
+
+    let certs_dir = certs_dir.canonicalize()?;
+    let config = SecurityConfig {
+        tls: Some(TlsConfig {
+            ca: certs_dir.join("ca.pem"),
+            cert: certs_dir.join("cert.pem"),
+            key: certs_dir.join("key.pem"),
+        }),
+        token: Some("multiple-daemons-example".to_owned()),
+    };
+    let mut file = serde_yaml::Mapping::new();
+    file.insert("security".into(), serde_yaml::to_value(&config)?);
+    let config_path = out_dir.join("dora-config.yml");
+    std::fs::write(&config_path, serde_yaml::to_string(&file)?)
+        .wrap_err("failed to write config file")?;
+
+    let security = Security::load(&config).wrap_err("failed to load security config")?;
+    Ok((config_path, security))
+}
+
 async fn run_daemon(
     coordinator: String,
     machine_id: &str,
     local_listen_port: u16,
+    label: &str,
+    zenoh_config: Option<&Path>,
+    config: Option<PathBuf>,
 ) -> eyre::Result<()> {
     let cargo = std::env::var("CARGO").unwrap();
     let mut cmd = tokio::process::Command::new(&cargo);
@@ -230,7 +345,15 @@ async fn run_daemon(
         .arg("--coordinator-addr")
         .arg(coordinator)
         .arg("--local-listen-port")
-        .arg(local_listen_port.to_string());
+        .arg(local_listen_port.to_string())
+        .arg("--label")
+        .arg(label);
+    if let Some(zenoh_config) = zenoh_config {
+        cmd.arg("--zenoh-config").arg(zenoh_config);
+    }
+    if let Some(config) = config {
+        cmd.arg("--config").arg(config);
+    }
     if !cmd.status().await?.success() {
         bail!("failed to run dataflow");
     };
@@ -4,10 +4,12 @@ use super::{CommunicationLayer, Publisher, Subscriber};
 use crate::{BoxError, ReceivedSample};
 use std::{borrow::Cow, sync::Arc, time::Duration};
 use zenoh::{
-    prelude::{sync::SyncResolve, Config, Priority, SessionDeclarations, SplitBuffer},
+    prelude::{sync::SyncResolve, Priority, SessionDeclarations, SplitBuffer},
     publication::CongestionControl,
 };
 
+pub use zenoh::config::Config;
+
 /// Allows communication over `zenoh`.
 pub struct ZenohCommunicationLayer {
     zenoh: Arc<zenoh::Session>,
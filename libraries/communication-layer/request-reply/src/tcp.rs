@@ -94,11 +94,16 @@ impl RequestReplyLayer for TcpLayer {
     }
 }
 
-pub struct TcpConnection {
-    pub stream: TcpStream,
+/// Length-prefixed messages over a TCP stream, or over a wrapper of it (e.g. a TLS
+/// session).
+pub struct TcpConnection<S = TcpStream> {
+    pub stream: S,
 }
 
-impl ListenConnection for TcpConnection {
+impl<S> ListenConnection for TcpConnection<S>
+where
+    S: Read + Write + Send + Sync,
+{
     type RequestData = Vec<u8>;
     type ReplyData = Vec<u8>;
     type Error = std::io::Error;
@@ -114,7 +119,10 @@ impl ListenConnection for TcpConnection {
     }
 }
 
-impl RequestReplyConnection for TcpConnection {
+impl<S> RequestReplyConnection for TcpConnection<S>
+where
+    S: Read + Write + Send + Sync,
+{
     type RequestData = Vec<u8>;
     type ReplyData = Vec<u8>;
     type Error = std::io::Error;
@@ -127,7 +135,7 @@ impl RequestReplyConnection for TcpConnection {
     }
 }
 
-impl TcpConnection {
+impl<S: Read + Write> TcpConnection<S> {
     pub fn send(&mut self, request: &[u8]) -> std::io::Result<()> {
         let len_raw = (request.len() as u64).to_le_bytes();
         self.stream.write_all(&len_raw)?;
@@ -1,3 +1,4 @@
+use crate::schema::DataSchema;
 use once_cell::sync::OnceCell;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
@@ -6,6 +7,7 @@ use std::{
     collections::{BTreeMap, BTreeSet},
     convert::Infallible,
     fmt,
+    ops::Deref,
     str::FromStr,
     time::Duration,
 };
@@ -29,6 +31,16 @@ impl From<String> for NodeId {
     }
 }
 
+impl NodeId {
+    /// The ID in a form that can be used as file name.
+    ///
+    /// The IDs of nodes in included subflows contain `/` separators, which are
+    /// replaced by `%2F`.
+    pub fn file_name(&self) -> String {
+        self.0.replace('/', "%2F")
+    }
+}
+
 impl std::fmt::Display for NodeId {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         std::fmt::Display::fmt(&self.0, f)
@@ -163,16 +175,47 @@ impl Serialize for InputMapping {
     where
         S: serde::Serializer,
     {
-        serializer.collect_str(self)
+        match self {
+            // the `<source>/<output>` form is ambiguous for the namespaced IDs of nodes
+            // in included subflows
+            InputMapping::User(mapping) if mapping.source.as_ref().contains('/') => {
+                NamespacedInputMapping {
+                    source: mapping.source.clone(),
+                    output: mapping.output.clone(),
+                }
+                .serialize(serializer)
+            }
+            _ => serializer.collect_str(self),
+        }
     }
 }
 
+/// Unambiguous form of input mappings whose source node ID contains a `/`.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NamespacedInputMapping {
+    source: NodeId,
+    output: DataId,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum InputMappingDef {
+    String(String),
+    Namespaced(NamespacedInputMapping),
+}
+
 impl<'de> Deserialize<'de> for InputMapping {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: serde::Deserializer<'de>,
     {
-        let string = String::deserialize(deserializer)?;
+        let string = match InputMappingDef::deserialize(deserializer)? {
+            InputMappingDef::String(string) => string,
+            InputMappingDef::Namespaced(NamespacedInputMapping { source, output }) => {
+                return Ok(Self::User(UserInputMapping { source, output }));
+            }
+        };
         let (source, output) = string
             .split_once('/')
             .ok_or_else(|| serde::de::Error::custom("input must start with `<source>/`"))?;
@@ -249,6 +292,150 @@ pub fn format_duration(interval: Duration) -> FormattedDuration {
     FormattedDuration(interval)
 }
 
+/// Formats a duration as a human-readable string such as `500ms` or `2s`.
+///
+/// The output can be parsed again through [`duration_str::parse`].
+pub struct HumanDuration(pub Duration);
+
+impl fmt::Display for HumanDuration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.subsec_nanos() == 0 {
+            write!(f, "{}s", self.0.as_secs())
+        } else if self.0.subsec_nanos() % 1_000_000 == 0 {
+            write!(f, "{}ms", self.0.as_millis())
+        } else {
+            write!(f, "{}ns", self.0.as_nanos())
+        }
+    }
+}
+
+/// (De)serializes a [`Duration`] as a human-readable string such as `500ms` or `2s`.
+pub mod human_duration {
+    use super::HumanDuration;
+    use serde::Deserialize;
+    use std::time::Duration;
+
+    pub fn serialize<S>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_str(&HumanDuration(*duration))
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let raw = String::deserialize(deserializer)?;
+        duration_str::parse(&raw)
+            .map_err(|err| serde::de::Error::custom(format!("invalid duration `{raw}`: {err}")))
+    }
+
+    /// Variant of [`human_duration`](self) for optional durations.
+    pub mod option {
+        use super::HumanDuration;
+        use serde::Deserialize;
+        use std::time::Duration;
+
+        pub fn serialize<S>(duration: &Option<Duration>, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: serde::Serializer,
+        {
+            match duration {
+                Some(duration) => serializer.collect_str(&HumanDuration(*duration)),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+        where
+            D: serde::Deserializer<'de>,
+        {
+            Option::<String>::deserialize(deserializer)?
+                .map(|raw| {
+                    duration_str::parse(&raw).map_err(|err| {
+                        serde::de::Error::custom(format!("invalid duration `{raw}`: {err}"))
+                    })
+                })
+                .transpose()
+        }
+    }
+}
+
+/// A frequency such as `30hz` or `1.5khz`.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, JsonSchema)]
+#[schemars(transparent)]
+pub struct Frequency(#[schemars(with = "String")] f64);
+
+// parsing rejects NaN, so the `PartialEq` impl is a full equivalence relation
+impl Eq for Frequency {}
+
+impl Frequency {
+    pub fn from_hz(hz: f64) -> Self {
+        Self(hz)
+    }
+
+    pub fn as_hz(&self) -> f64 {
+        self.0
+    }
+}
+
+impl fmt::Display for Frequency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hz = self.0;
+        if hz >= 1000.0 {
+            write!(f, "{}khz", hz / 1000.0)
+        } else if hz >= 10.0 || hz == hz.round() {
+            write!(f, "{}hz", (hz * 10.0).round() / 10.0)
+        } else {
+            write!(f, "{}hz", (hz * 1000.0).round() / 1000.0)
+        }
+    }
+}
+
+impl FromStr for Frequency {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.trim().to_ascii_lowercase();
+        let (number, factor) = if let Some(number) = lower.strip_suffix("khz") {
+            (number, 1000.0)
+        } else if let Some(number) = lower.strip_suffix("hz") {
+            (number, 1.0)
+        } else {
+            return Err(format!("missing unit (expected `hz` or `khz`) in `{s}`"));
+        };
+        let value: f64 = number
+            .trim()
+            .parse()
+            .map_err(|_| format!("invalid number `{}`", number.trim()))?;
+        if !value.is_finite() || value < 0.0 {
+            return Err(format!("frequency must not be negative, got `{s}`"));
+        }
+        Ok(Self(value * factor))
+    }
+}
+
+impl Serialize for Frequency {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Frequency {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let raw = String::deserialize(deserializer)?;
+        raw.parse()
+            .map_err(|err| serde::de::Error::custom(format!("invalid frequency `{raw}`: {err}")))
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 pub struct NodeRunConfig {
     /// Inputs for the nodes as a map from input ID to `node_id/output_id`.
@@ -261,7 +448,7 @@ pub struct NodeRunConfig {
     ///
     #[serde(default)]
     pub inputs: BTreeMap<DataId, Input>,
-    /// List of output IDs.
+    /// List of output IDs, optionally with their data type.
     ///
     /// e.g.
     ///
@@ -269,9 +456,195 @@ pub struct NodeRunConfig {
     ///
     ///  - output_1
     ///
-    ///  - output_2
+    ///  - id: output_2
+    ///    schema: struct<x: float32, y: float32>
+    ///    expected_rate: 30hz
     #[serde(default)]
-    pub outputs: BTreeSet<DataId>,
+    pub outputs: Outputs,
+}
+
+/// Set of output IDs, together with their declared data types and rates.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "Vec<OutputDef>", into = "Vec<OutputDef>")]
+pub struct Outputs {
+    ids: BTreeSet<DataId>,
+    schemas: BTreeMap<DataId, DataSchema>,
+    rates: BTreeMap<DataId, ExpectedRate>,
+}
+
+impl Outputs {
+    /// Adds the given output, replacing the declared data type of an existing output.
+    pub fn insert(&mut self, id: DataId, schema: Option<DataSchema>) {
+        match schema {
+            Some(schema) => self.schemas.insert(id.clone(), schema),
+            None => self.schemas.remove(&id),
+        };
+        self.ids.insert(id);
+    }
+
+    /// Removes the given output together with its data type and rate, returns whether
+    /// the output existed.
+    pub fn remove(&mut self, id: &DataId) -> bool {
+        self.schemas.remove(id);
+        self.rates.remove(id);
+        self.ids.remove(id)
+    }
+
+    /// Returns the declared data type of the given output, if any.
+    pub fn schema(&self, id: &DataId) -> Option<&DataSchema> {
+        self.schemas.get(id)
+    }
+
+    /// Sets the expected publish rate of the given output, adding the output if needed.
+    pub fn set_expected_rate(&mut self, id: DataId, rate: ExpectedRate) {
+        self.ids.insert(id.clone());
+        self.rates.insert(id, rate);
+    }
+
+    /// Returns the declared publish rate of the given output, if any.
+    pub fn expected_rate(&self, id: &DataId) -> Option<&ExpectedRate> {
+        self.rates.get(id)
+    }
+}
+
+/// Publish rate that an output is expected to have.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExpectedRate {
+    pub rate: Frequency,
+    /// Allowed deviation from `rate`, in percent.
+    pub tolerance_percent: u32,
+}
+
+impl ExpectedRate {
+    pub const DEFAULT_TOLERANCE_PERCENT: u32 = 20;
+
+    /// Checks whether the given measured rate is within the tolerance.
+    pub fn matches(&self, measured: Frequency) -> bool {
+        let expected = self.rate.as_hz();
+        let deviation = (measured.as_hz() - expected).abs() / expected;
+        deviation * 100.0 <= f64::from(self.tolerance_percent)
+    }
+}
+
+impl Deref for Outputs {
+    type Target = BTreeSet<DataId>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.ids
+    }
+}
+
+impl IntoIterator for Outputs {
+    type Item = DataId;
+    type IntoIter = std::collections::btree_set::IntoIter<DataId>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.ids.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Outputs {
+    type Item = &'a DataId;
+    type IntoIter = std::collections::btree_set::Iter<'a, DataId>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.ids.iter()
+    }
+}
+
+impl FromIterator<DataId> for Outputs {
+    fn from_iter<T: IntoIterator<Item = DataId>>(iter: T) -> Self {
+        Self {
+            ids: iter.into_iter().collect(),
+            schemas: BTreeMap::new(),
+            rates: BTreeMap::new(),
+        }
+    }
+}
+
+impl JsonSchema for Outputs {
+    fn schema_name() -> String {
+        "Outputs".into()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        <Vec<OutputDef>>::json_schema(gen)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum OutputDef {
+    IdOnly(DataId),
+    WithOptions {
+        id: DataId,
+        /// Arrow data type of the output, e.g. `float32` or `struct<x: float32, y: float32>`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        schema: Option<DataSchema>,
+        /// Expected publish rate of the output, e.g. `30hz`
+        ///
+        /// Deviations of the measured rate are logged by the daemon.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        expected_rate: Option<Frequency>,
+        /// Allowed deviation from `expected_rate` in percent (default: 20)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        rate_tolerance: Option<u32>,
+    },
+}
+
+impl From<Vec<OutputDef>> for Outputs {
+    fn from(defs: Vec<OutputDef>) -> Self {
+        let mut outputs = Self::default();
+        for def in defs {
+            match def {
+                OutputDef::IdOnly(id) => outputs.insert(id, None),
+                OutputDef::WithOptions {
+                    id,
+                    schema,
+                    expected_rate,
+                    rate_tolerance,
+                } => {
+                    if let Some(rate) = expected_rate {
+                        outputs.set_expected_rate(
+                            id.clone(),
+                            ExpectedRate {
+                                rate,
+                                tolerance_percent: rate_tolerance
+                                    .unwrap_or(ExpectedRate::DEFAULT_TOLERANCE_PERCENT),
+                            },
+                        );
+                    }
+                    outputs.insert(id, schema)
+                }
+            }
+        }
+        outputs
+    }
+}
+
+impl From<Outputs> for Vec<OutputDef> {
+    fn from(mut outputs: Outputs) -> Self {
+        outputs
+            .ids
+            .into_iter()
+            .map(|id| {
+                let schema = outputs.schemas.remove(&id);
+                let rate = outputs.rates.remove(&id);
+                if schema.is_none() && rate.is_none() {
+                    OutputDef::IdOnly(id)
+                } else {
+                    OutputDef::WithOptions {
+                        id,
+                        schema,
+                        expected_rate: rate.map(|r| r.rate),
+                        rate_tolerance: rate
+                            .map(|r| r.tolerance_percent)
+                            .filter(|&t| t != ExpectedRate::DEFAULT_TOLERANCE_PERCENT),
+                    }
+                }
+            })
+            .collect()
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
@@ -279,6 +652,48 @@ pub struct NodeRunConfig {
 pub struct Input {
     pub mapping: InputMapping,
     pub queue_size: Option<usize>,
+    /// What to do when the input queue is full.
+    pub overflow: OverflowPolicy,
+    /// Expected data type of the input, checked against the schema of the connected output.
+    pub schema: Option<DataSchema>,
+    /// Maximum time between two messages before an `InputTimeout` event is sent to the node.
+    #[schemars(with = "Option<String>")]
+    pub deadline: Option<Duration>,
+    /// Stop the dataflow after this many consecutive deadline misses.
+    pub max_deadline_misses: Option<u32>,
+    /// Compression of the messages that are sent to this input from another machine.
+    pub compression: Option<Compression>,
+    /// How messages are sent to this input from another machine.
+    pub transport: Transport,
+}
+
+/// Defines how an input queue behaves once it holds `queue_size` messages.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum OverflowPolicy {
+    /// Drop the oldest queued message to make room for the new one (default).
+    #[default]
+    DropOldest,
+    /// Drop the new message and keep the queued ones.
+    DropNewest,
+    /// Only keep the most recent message, regardless of the `queue_size`.
+    KeepLatest,
+    /// Block the sender until the receiver has room in its queue.
+    Block,
+}
+
+impl OverflowPolicy {
+    /// The effective maximum number of queued messages for the given `queue_size`.
+    pub fn queue_size(&self, queue_size: usize) -> usize {
+        match self {
+            OverflowPolicy::KeepLatest => 1,
+            _ => queue_size,
+        }
+    }
+
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -288,22 +703,97 @@ pub enum InputDef {
     WithOptions {
         source: InputMapping,
         queue_size: Option<usize>,
+        #[serde(default, skip_serializing_if = "OverflowPolicy::is_default")]
+        overflow: OverflowPolicy,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        schema: Option<DataSchema>,
+        /// Maximum time between two messages, e.g. `500ms`.
+        #[serde(
+            default,
+            with = "human_duration::option",
+            skip_serializing_if = "Option::is_none"
+        )]
+        deadline: Option<Duration>,
+        /// Stop the dataflow after this many consecutive deadline misses.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_deadline_misses: Option<u32>,
+        /// Compression of messages from other machines, either `lz4` or `zstd`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        compression: Option<Compression>,
+        /// Transport of messages from other machines, either `reliable` or `best-effort`.
+        #[serde(default, skip_serializing_if = "Transport::is_default")]
+        transport: Transport,
     },
 }
 
+/// Compression codec for messages that are sent between machines.
+///
+/// The codecs are ordered by their compression ratio. If several inputs on the same
+/// machine request a different compression for the same output, the strongest codec
+/// is used.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, JsonSchema,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Compression {
+    /// Fast compression with a moderate ratio.
+    Lz4,
+    /// Slower compression with a higher ratio.
+    Zstd,
+}
+
+/// Transport for messages that are sent to an input on another machine.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum Transport {
+    /// Send the messages through the TCP connection between the daemons (default).
+    #[default]
+    Reliable,
+    /// Send the messages as UDP datagrams, which avoids head-of-line blocking.
+    ///
+    /// Messages that are larger than a datagram are split into fragments. If a fragment
+    /// gets lost, the whole message is dropped. Only supported with
+    /// `_unstable_remote: tcp`.
+    BestEffort,
+}
+
+impl Transport {
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
 impl From<Input> for InputDef {
     fn from(input: Input) -> Self {
         match input {
             Input {
                 mapping,
                 queue_size: None,
+                overflow: OverflowPolicy::DropOldest,
+                schema: None,
+                deadline: None,
+                max_deadline_misses: None,
+                compression: None,
+                transport: Transport::Reliable,
             } => Self::MappingOnly(mapping),
             Input {
                 mapping,
                 queue_size,
+                overflow,
+                schema,
+                deadline,
+                max_deadline_misses,
+                compression,
+                transport,
             } => Self::WithOptions {
                 source: mapping,
                 queue_size,
+                overflow,
+                schema,
+                deadline,
+                max_deadline_misses,
+                compression,
+                transport,
             },
         }
     }
@@ -315,10 +805,31 @@ impl From<InputDef> for Input {
             InputDef::MappingOnly(mapping) => Self {
                 mapping,
                 queue_size: None,
+                overflow: OverflowPolicy::default(),
+                schema: None,
+                deadline: None,
+                max_deadline_misses: None,
+                compression: None,
+                transport: Transport::default(),
             },
-            InputDef::WithOptions { source, queue_size } => Self {
+            InputDef::WithOptions {
+                source,
+                queue_size,
+                overflow,
+                schema,
+                deadline,
+                max_deadline_misses,
+                compression,
+                transport,
+            } => Self {
                 mapping: source,
                 queue_size,
+                overflow,
+                schema,
+                deadline,
+                max_deadline_misses,
+                compression,
+                transport,
             },
         }
     }
@@ -361,11 +872,17 @@ impl Default for LocalCommunicationConfig {
 #[serde(deny_unknown_fields, rename_all = "lowercase")]
 pub enum RemoteCommunicationConfig {
     Tcp,
-    // TODO:a
-    // Zenoh {
-    //     config: Option<serde_yaml::Value>,
-    //     prefix: String,
-    // },
+    /// Sends the messages between daemons through a [zenoh](https://zenoh.io/) session.
+    ///
+    /// The zenoh session is configured per machine through the `--zenoh-config` argument
+    /// of `dora daemon`.
+    Zenoh,
+    /// Sends the messages between daemons through the coordinator.
+    ///
+    /// Useful if the daemons cannot reach each other, e.g. because they are behind NAT.
+    /// With `tcp`, messages are relayed automatically for machines that the daemon fails
+    /// to connect to.
+    Relay,
 }
 
 impl Default for RemoteCommunicationConfig {
@@ -373,3 +890,34 @@ impl Default for RemoteCommunicationConfig {
         Self::Tcp
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{DataId, Outputs};
+
+    #[test]
+    fn outputs_insert_remove() {
+        let mut outputs: Outputs =
+            serde_yaml::from_str("[a, {id: b, schema: float32, expected_rate: 10hz}]").unwrap();
+        let b = DataId::from("b".to_owned());
+        assert!(outputs.schema(&b).is_some());
+        assert!(outputs.expected_rate(&b).is_some());
+
+        assert!(outputs.remove(&b));
+        assert!(!outputs.remove(&b));
+        assert!(!outputs.contains(&b));
+        assert!(outputs.schema(&b).is_none());
+        assert!(outputs.expected_rate(&b).is_none());
+
+        // re-adding the output does not bring back the old data type and rate
+        outputs.insert(b.clone(), None);
+        assert!(outputs.contains(&b));
+        assert!(outputs.schema(&b).is_none());
+        assert!(outputs.expected_rate(&b).is_none());
+        assert_eq!(serde_yaml::to_string(&outputs).unwrap(), "- a\n- b\n");
+
+        outputs.insert(b.clone(), Some("uint8".parse().unwrap()));
+        outputs.insert(b.clone(), None);
+        assert!(outputs.schema(&b).is_none());
+    }
+}
@@ -1,5 +1,6 @@
 use crate::config::{
-    CommunicationConfig, DataId, Input, InputMapping, NodeId, NodeRunConfig, OperatorId,
+    human_duration, CommunicationConfig, DataId, Input, InputMapping, NodeId, NodeRunConfig,
+    OperatorId, Outputs, UserInputMapping,
 };
 use eyre::{bail, eyre, Context, OptionExt, Result};
 use schemars::JsonSchema;
@@ -10,13 +11,24 @@ use std::{
     env::consts::EXE_EXTENSION,
     fmt,
     path::{Path, PathBuf},
+    time::Duration,
 };
 use tracing::warn;
+pub use variables::Variables;
 pub use visualize::collect_dora_timers;
 mod validate;
+pub use validate::check_scheduling_privileges;
+mod variables;
 mod visualize;
 pub const SHELL_SOURCE: &str = "shell";
 pub const DYNAMIC_SOURCE: &str = "dynamic";
+/// Input source that refers to the input ports of the enclosing subflow.
+pub const SUBFLOW_PORTS_SOURCE: &str = "ports";
+/// Separates the namespace of an included subflow from the IDs of its nodes.
+///
+/// Input mappings of namespaced nodes are serialized as `{source, output}` maps, since
+/// `/` also separates the node and output IDs in the `<source>/<output>` form.
+pub const NAMESPACE_SEPARATOR: &str = "/";
 
 /// Dataflow description
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
@@ -27,19 +39,44 @@ pub struct Descriptor {
     #[serde(default)]
     pub communication: CommunicationConfig,
     #[schemars(skip)]
-    #[serde(default, rename = "_unstable_deploy")]
+    #[serde(default, rename = "_unstable_deploy", alias = "deploy")]
     pub deploy: Deploy,
+    /// Variables with their default values
+    ///
+    /// Variables can be used as `${name}` in any string value of the dataflow file. Their
+    /// values can be overridden when reading the dataflow, e.g. through
+    /// `dora start --var name=value`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    #[schemars(with = "BTreeMap<String, serde_json::Value>")]
+    pub variables: Variables,
+    /// Other dataflow files that should be included into this dataflow
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<Subflow>,
+    /// What happens to the dataflow when the connection to one of its machines is lost
+    #[serde(default)]
+    pub on_machine_lost: MachineLostPolicy,
+    /// Working directory of the dataflow per machine ID
+    ///
+    /// Relative node paths are resolved against the working directory of the machine
+    /// that runs the node. Relative entries are joined to the working directory that the
+    /// daemon advertises through `dora daemon --working-dir`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub working_dirs: BTreeMap<String, PathBuf>,
     pub nodes: Vec<Node>,
 }
 
 pub const SINGLE_OPERATOR_DEFAULT_ID: &str = "op";
 
+/// Maps `(subflow, port)` pairs to the node outputs that they expose.
+type ExposedPorts = BTreeMap<(NodeId, DataId), UserInputMapping>;
+
 impl Descriptor {
     pub fn resolve_aliases_and_set_defaults(&self) -> eyre::Result<Vec<ResolvedNode>> {
         let default_op_id = OperatorId::from(SINGLE_OPERATOR_DEFAULT_ID.to_string());
 
-        let single_operator_nodes: HashMap<_, _> = self
-            .nodes
+        let nodes = self.flatten_nodes()?;
+
+        let single_operator_nodes: HashMap<_, _> = nodes
             .iter()
             .filter_map(|n| {
                 n.operator
@@ -49,7 +86,7 @@ impl Descriptor {
             .collect();
 
         let mut resolved = vec![];
-        for mut node in self.nodes.clone() {
+        for mut node in nodes.clone() {
             // adjust input mappings
             let mut node_kind = node.kind_mut()?;
             let input_mappings: Vec<_> = match &mut node_kind {
@@ -103,6 +140,10 @@ impl Descriptor {
                 description: node.description,
                 env: node.env,
                 deploy: ResolvedDeploy::new(node.deploy, self),
+                restart: node.restart,
+                resources: node.resources,
+                scheduling: node.scheduling,
+                build_dir: node.build_dir,
                 kind,
             });
         }
@@ -110,44 +151,318 @@ impl Descriptor {
         Ok(resolved)
     }
 
+    /// Returns all nodes of the dataflow, including the nodes of included subflows.
+    ///
+    /// The IDs of included nodes are prefixed with the subflow namespace and all
+    /// references to subflow ports are replaced by the mapped node outputs.
+    pub fn flatten_nodes(&self) -> eyre::Result<Vec<Node>> {
+        let (nodes, _) = self.flatten()?;
+        Ok(nodes)
+    }
+
+    /// Flattens the subflows and returns the nodes together with the outputs that
+    /// the subflows of this dataflow expose.
+    fn flatten(&self) -> eyre::Result<(Vec<Node>, ExposedPorts)> {
+        let mut nodes = self.nodes.clone();
+        let mut exposed = BTreeMap::new();
+
+        for subflow in &self.include {
+            if self.nodes.iter().any(|n| n.id == subflow.id) {
+                bail!("subflow ID `{}` is already used by a node", subflow.id);
+            }
+            let dataflow = subflow.dataflow.as_ref().ok_or_else(|| {
+                eyre!(
+                    "subflow `{}` was not loaded (dataflow must be read from a file)",
+                    subflow.id
+                )
+            })?;
+            let (subflow_nodes, subflow_exposed) = dataflow
+                .flatten()
+                .wrap_err_with(|| format!("failed to resolve subflow `{}`", subflow.id))?;
+
+            let local_ids: BTreeSet<_> = subflow_nodes.iter().map(|n| n.id.clone()).collect();
+            for mut node in subflow_nodes {
+                node.id = namespaced(&subflow.id, &node.id);
+                let node_id = node.id.clone();
+                for input in node.inputs_mut()? {
+                    let InputMapping::User(mapping) = &mut input.mapping else {
+                        continue;
+                    };
+                    if mapping.source.as_ref() == SUBFLOW_PORTS_SOURCE {
+                        input.mapping =
+                            subflow
+                                .inputs
+                                .get(&mapping.output)
+                                .cloned()
+                                .ok_or_else(|| {
+                                    eyre!(
+                                    "node `{node_id}` uses unknown input port `{}` of subflow `{}`",
+                                    mapping.output,
+                                    subflow.id
+                                )
+                                })?;
+                    } else if local_ids.contains(&mapping.source) {
+                        mapping.source = namespaced(&subflow.id, &mapping.source);
+                    }
+                }
+                nodes.push(node);
+            }
+
+            for (port, mapping) in &subflow.outputs {
+                let InputMapping::User(mapping) = mapping else {
+                    bail!(
+                        "output port `{port}` of subflow `{}` must refer to a node output",
+                        subflow.id
+                    );
+                };
+                // the output might be exposed by a nested subflow
+                let mapping = subflow_exposed
+                    .get(&(mapping.source.clone(), mapping.output.clone()))
+                    .unwrap_or(mapping);
+                exposed.insert(
+                    (subflow.id.clone(), port.clone()),
+                    UserInputMapping {
+                        source: namespaced(&subflow.id, &mapping.source),
+                        output: mapping.output.clone(),
+                    },
+                );
+            }
+        }
+
+        // replace references to subflow ports by the exposed node outputs
+        for node in &mut nodes {
+            let node_id = node.id.clone();
+            for input in node.inputs_mut()? {
+                let InputMapping::User(mapping) = &mut input.mapping else {
+                    continue;
+                };
+                if let Some(resolved) =
+                    exposed.get(&(mapping.source.clone(), mapping.output.clone()))
+                {
+                    *mapping = resolved.clone();
+                } else if self.include.iter().any(|s| s.id == mapping.source) {
+                    bail!(
+                        "node `{node_id}` uses unknown output port `{}` of subflow `{}`",
+                        mapping.output,
+                        mapping.source
+                    );
+                }
+            }
+        }
+
+        Ok((nodes, exposed))
+    }
+
     pub fn visualize_as_mermaid(&self) -> eyre::Result<String> {
         let resolved = self.resolve_aliases_and_set_defaults()?;
-        let flowchart = visualize::visualize_nodes(&resolved);
+        let subflows = self.subflow_groups(None);
+        let flowchart = visualize::visualize_nodes(&resolved, &subflows);
 
         Ok(flowchart)
     }
 
+    fn subflow_groups(&self, namespace: Option<&NodeId>) -> Vec<visualize::SubflowGroup> {
+        self.include
+            .iter()
+            .filter_map(|subflow| {
+                let dataflow = subflow.dataflow.as_ref()?;
+                let id = match namespace {
+                    Some(namespace) => namespaced(namespace, &subflow.id),
+                    None => subflow.id.clone(),
+                };
+                Some(visualize::SubflowGroup {
+                    nodes: dataflow
+                        .nodes
+                        .iter()
+                        .map(|n| namespaced(&id, &n.id))
+                        .collect(),
+                    subflows: dataflow.subflow_groups(Some(&id)),
+                    name: subflow.id.clone(),
+                    id,
+                })
+            })
+            .collect()
+    }
+
     pub async fn read(path: &Path) -> eyre::Result<Descriptor> {
         let buf = tokio::fs::read(path)
             .await
             .context("failed to open given file")?;
-        Descriptor::parse(buf)
+        let mut descriptor = Descriptor::parse(buf)?;
+        descriptor.load_includes(path, &mut Vec::new(), &mut BTreeSet::new())?;
+        Ok(descriptor)
     }
 
     pub fn blocking_read(path: &Path) -> eyre::Result<Descriptor> {
+        Self::blocking_read_with_variables(path, &Variables::new())
+    }
+
+    /// Reads the given dataflow file, using the given values for its variables.
+    ///
+    /// The given values override the defaults of the `variables` section. Values for
+    /// variables that neither the dataflow nor its subflows use are rejected.
+    pub fn blocking_read_with_variables(
+        path: &Path,
+        variables: &Variables,
+    ) -> eyre::Result<Descriptor> {
         let buf = std::fs::read(path).context("failed to open given file")?;
-        Descriptor::parse(buf)
+        let mut known = BTreeSet::new();
+        let mut descriptor = Descriptor::parse_substituted(buf, variables, &mut known)?;
+        descriptor.load_includes(path, &mut Vec::new(), &mut known)?;
+        variables::check_overrides(variables, &known)?;
+        Ok(descriptor)
+    }
+
+    /// Reads the dataflow files of all `include` entries, recursively.
+    ///
+    /// The `path` is the path of the dataflow file that contains this descriptor. The
+    /// variables that the subflows declare or use are added to `known_variables`.
+    fn load_includes(
+        &mut self,
+        path: &Path,
+        parents: &mut Vec<PathBuf>,
+        known_variables: &mut BTreeSet<String>,
+    ) -> eyre::Result<()> {
+        if self.include.is_empty() {
+            return Ok(());
+        }
+        let canonicalized = path
+            .canonicalize()
+            .with_context(|| format!("failed to canonicalize `{}`", path.display()))?;
+        if parents.contains(&canonicalized) {
+            bail!("dataflow `{}` includes itself", path.display());
+        }
+        parents.push(canonicalized);
+
+        let base_dir = path.parent().unwrap_or(Path::new(""));
+        for subflow in &mut self.include {
+            let subflow_path = base_dir.join(&subflow.path);
+            let buf = std::fs::read(&subflow_path).with_context(|| {
+                format!(
+                    "failed to read subflow `{}` at `{}`",
+                    subflow.id,
+                    subflow_path.display()
+                )
+            })?;
+            // subflows inherit the variables of the including dataflow
+            let mut dataflow = Descriptor::parse_substituted(buf, &self.variables, known_variables)
+                .with_context(|| format!("failed to parse subflow `{}`", subflow.id))?;
+            dataflow.load_includes(&subflow_path, parents, known_variables)?;
+
+            // relative node paths of the subflow are relative to the subflow file
+            if let Some(subflow_dir) = subflow.path.parent() {
+                dataflow.rebase_sources(base_dir, subflow_dir);
+            }
+            subflow.dataflow = Some(Box::new(dataflow));
+        }
+
+        parents.pop();
+        Ok(())
+    }
+
+    /// Makes the relative node sources and build directories of a subflow in
+    /// `base_dir/relative_dir` relative to `base_dir`.
+    ///
+    /// Sources are rebased whether they exist or not, since they might be created by
+    /// a build command. Only bare program names without extension that don't exist in
+    /// the subflow directory are kept, because they refer to executables in `$PATH`.
+    fn rebase_sources(&mut self, base_dir: &Path, relative_dir: &Path) {
+        let rebase = |source: &mut String| {
+            if Path::new(source).is_relative() && !source_is_url(source) {
+                *source = relative_dir.join(&*source).to_string_lossy().into_owned();
+            }
+        };
+        let rebase_program = |source: &mut String| {
+            let path = Path::new(source);
+            let bare_program_name = path.components().count() == 1
+                && path.extension().is_none()
+                && !base_dir.join(relative_dir).join(path).exists();
+            if !bare_program_name && !matches!(source.as_str(), SHELL_SOURCE | DYNAMIC_SOURCE) {
+                rebase(source);
+            }
+        };
+        for node in &mut self.nodes {
+            node.build_dir = Some(relative_dir.join(node.build_dir.take().unwrap_or_default()));
+            if let Some(path) = &mut node.path {
+                rebase_program(path);
+            }
+            if let Some(custom) = &mut node.custom {
+                rebase_program(&mut custom.source);
+            }
+            let operators = node
+                .operators
+                .iter_mut()
+                .flat_map(|n| n.operators.iter_mut().map(|op| &mut op.config.source))
+                .chain(node.operator.iter_mut().map(|op| &mut op.config.source));
+            for source in operators {
+                match source {
+                    OperatorSource::SharedLibrary(source) | OperatorSource::Wasm(source) => {
+                        rebase(source)
+                    }
+                    OperatorSource::Python(python) => rebase(&mut python.source),
+                }
+            }
+        }
+        for subflow in &mut self.include {
+            if let Some(dataflow) = &mut subflow.dataflow {
+                dataflow.rebase_sources(base_dir, relative_dir);
+            }
+        }
     }
 
     pub fn parse(buf: Vec<u8>) -> eyre::Result<Descriptor> {
-        serde_yaml::from_slice(&buf).context("failed to parse given descriptor")
+        Self::parse_with_variables(buf, &Variables::new())
+    }
+
+    /// Parses the given descriptor and replaces all `${name}` placeholders of its variables.
+    ///
+    /// The given values override the defaults of the `variables` section. Values for
+    /// variables that the dataflow doesn't use are rejected.
+    pub fn parse_with_variables(buf: Vec<u8>, variables: &Variables) -> eyre::Result<Descriptor> {
+        let mut known = BTreeSet::new();
+        let descriptor = Self::parse_substituted(buf, variables, &mut known)?;
+        variables::check_overrides(variables, &known)?;
+        Ok(descriptor)
+    }
+
+    /// Parses the given descriptor and replaces the placeholders of its variables,
+    /// adding the names of the declared and used variables to `known_variables`.
+    fn parse_substituted(
+        buf: Vec<u8>,
+        variables: &Variables,
+        known_variables: &mut BTreeSet<String>,
+    ) -> eyre::Result<Descriptor> {
+        let mut value: serde_yaml::Value =
+            serde_yaml::from_slice(&buf).context("failed to parse given descriptor")?;
+        let variables = variables::collect(&mut value, variables, known_variables)?;
+        variables::substitute(&mut value, &variables, known_variables)?;
+        let mut descriptor: Descriptor =
+            serde_yaml::from_value(value).context("failed to parse given descriptor")?;
+        descriptor.variables = variables;
+        Ok(descriptor)
     }
 
     pub fn check(&self, working_dir: &Path) -> eyre::Result<()> {
-        validate::check_dataflow(self, working_dir, None, false)
+        validate::check_dataflow(self, working_dir, None, &[], false)
             .wrap_err("Dataflow could not be validated.")
     }
 
+    /// Validates the dataflow for the given set of machines.
+    ///
+    /// `working_dir_machines` are the machines that advertise their own working
+    /// directory, so that relative node paths are resolved on them.
     pub fn check_in_daemon(
         &self,
         working_dir: &Path,
         remote_machine_id: &[&str],
+        working_dir_machines: &[&str],
         coordinator_is_remote: bool,
     ) -> eyre::Result<()> {
         validate::check_dataflow(
             self,
             working_dir,
             Some(remote_machine_id),
+            working_dir_machines,
             coordinator_is_remote,
         )
         .wrap_err("Dataflow could not be validated.")
@@ -158,6 +473,428 @@ impl Descriptor {
 #[serde(deny_unknown_fields)]
 pub struct Deploy {
     pub machine: Option<String>,
+    /// Labels that the machine must have (e.g. `gpu: "true"`)
+    ///
+    /// The coordinator picks one of the matching machines when the dataflow is started.
+    /// Can't be combined with `machine`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub selector: BTreeMap<String, String>,
+}
+
+impl Deploy {
+    fn is_empty(&self) -> bool {
+        self.machine.is_none() && self.selector.is_empty()
+    }
+}
+
+/// Specifies how a dataflow reacts when one of its machines is lost.
+///
+/// The nodes of the lost machine are always marked as failed and the inputs that they
+/// feed are closed on the remaining machines.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum MachineLostPolicy {
+    /// Stop the nodes on the remaining machines (default).
+    #[default]
+    Stop,
+    /// Keep the remaining nodes running, so that the dataflow finishes once they exit.
+    Continue,
+}
+
+/// Specifies whether and how the daemon restarts a node after it exited.
+///
+/// Can be given either as a plain mode (`restart: on-failure`) or with options:
+///
+/// ```yaml
+/// restart:
+///   mode: on-failure
+///   max_retries: 5
+///   backoff: 500ms
+///   max_backoff: 30s
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "RestartPolicyDef", into = "RestartPolicyDef")]
+pub struct RestartPolicy {
+    pub mode: RestartMode,
+    /// Maximum number of restarts, unlimited if not set.
+    pub max_retries: Option<u32>,
+    /// Delay before the first restart. Doubled after each further restart.
+    pub backoff: Duration,
+    /// Upper limit for the restart delay.
+    pub max_backoff: Duration,
+}
+
+impl RestartPolicy {
+    pub const DEFAULT_BACKOFF: Duration = Duration::from_millis(500);
+    pub const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+    /// Whether a node that exited with the given status should be restarted
+    /// after `restarts` previous restarts.
+    pub fn should_restart(&self, success: bool, restarts: u32) -> bool {
+        let mode_matches = match self.mode {
+            RestartMode::Never => false,
+            RestartMode::OnFailure => !success,
+            RestartMode::Always => true,
+        };
+        mode_matches && self.max_retries.map(|max| restarts < max).unwrap_or(true)
+    }
+
+    /// The delay before the restart number `restart` (starting at 1).
+    pub fn backoff(&self, restart: u32) -> Duration {
+        let factor = 2u32.saturating_pow(restart.saturating_sub(1));
+        self.backoff
+            .checked_mul(factor)
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff)
+    }
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        RestartMode::Never.into()
+    }
+}
+
+impl From<RestartMode> for RestartPolicy {
+    fn from(mode: RestartMode) -> Self {
+        Self {
+            mode,
+            max_retries: None,
+            backoff: Self::DEFAULT_BACKOFF,
+            max_backoff: Self::DEFAULT_MAX_BACKOFF,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartMode {
+    /// Never restart the node (default).
+    #[default]
+    Never,
+    /// Restart the node if it exits with an error.
+    OnFailure,
+    /// Restart the node whenever it exits.
+    Always,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum RestartPolicyDef {
+    ModeOnly(RestartMode),
+    WithOptions {
+        mode: RestartMode,
+        /// Maximum number of restarts, unlimited if not set.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_retries: Option<u32>,
+        /// Delay before the first restart, e.g. `500ms`. Doubled after each further restart.
+        #[serde(default, with = "human_duration::option")]
+        #[schemars(with = "Option<String>")]
+        backoff: Option<Duration>,
+        /// Upper limit for the restart delay, e.g. `30s`.
+        #[serde(default, with = "human_duration::option")]
+        #[schemars(with = "Option<String>")]
+        max_backoff: Option<Duration>,
+    },
+}
+
+impl From<RestartPolicy> for RestartPolicyDef {
+    fn from(policy: RestartPolicy) -> Self {
+        if policy == RestartPolicy::from(policy.mode) {
+            Self::ModeOnly(policy.mode)
+        } else {
+            Self::WithOptions {
+                mode: policy.mode,
+                max_retries: policy.max_retries,
+                backoff: Some(policy.backoff),
+                max_backoff: Some(policy.max_backoff),
+            }
+        }
+    }
+}
+
+impl From<RestartPolicyDef> for RestartPolicy {
+    fn from(value: RestartPolicyDef) -> Self {
+        match value {
+            RestartPolicyDef::ModeOnly(mode) => mode.into(),
+            RestartPolicyDef::WithOptions {
+                mode,
+                max_retries,
+                backoff,
+                max_backoff,
+            } => Self {
+                mode,
+                max_retries,
+                backoff: backoff.unwrap_or(Self::DEFAULT_BACKOFF),
+                max_backoff: max_backoff.unwrap_or(Self::DEFAULT_MAX_BACKOFF),
+            },
+        }
+    }
+}
+
+/// Resource limits of a node process, enforced through cgroups v2 (Linux only).
+///
+/// ```yaml
+/// resources:
+///   memory_max: 512M
+///   cpu_quota: 1.5
+///   pids_max: 64
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Resources {
+    /// Maximum memory usage in bytes, e.g. `512M` or `2G` (units are powers of 1024)
+    ///
+    /// The node is killed by the kernel when it exceeds this limit.
+    #[serde(
+        default,
+        with = "memory_size::option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[schemars(with = "Option<String>")]
+    pub memory_max: Option<u64>,
+    /// Maximum CPU usage as a number of CPUs, e.g. `0.5` or `2`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu_quota: Option<f64>,
+    /// Maximum number of processes and threads
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pids_max: Option<u64>,
+}
+
+impl Resources {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+/// Scheduling options of a node process (Linux only).
+///
+/// ```yaml
+/// scheduling:
+///   policy: fifo
+///   priority: 80
+///   cpus: 2-3
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Scheduling {
+    /// Scheduling policy of the node process (default: `other`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy: Option<SchedulingPolicy>,
+    /// Real-time priority for the `fifo` and `rr` policies, from 1 (lowest) to 99
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<u8>,
+    /// Nice value, from -20 (highest priority) to 19 (lowest priority)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nice: Option<i8>,
+    /// CPUs that the node process may run on, e.g. `[2, 3]` or `2-3,6`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<CpuSetDef>")]
+    pub cpus: Option<CpuSet>,
+}
+
+impl Scheduling {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SchedulingPolicy {
+    /// The default time-sharing policy (`SCHED_OTHER`).
+    Other,
+    /// First-in first-out real-time policy (`SCHED_FIFO`).
+    Fifo,
+    /// Round-robin real-time policy (`SCHED_RR`).
+    #[serde(rename = "rr")]
+    RoundRobin,
+    /// Policy for CPU-intensive background work (`SCHED_BATCH`).
+    Batch,
+    /// Policy for very low priority background work (`SCHED_IDLE`).
+    Idle,
+}
+
+impl SchedulingPolicy {
+    pub fn is_realtime(&self) -> bool {
+        matches!(self, Self::Fifo | Self::RoundRobin)
+    }
+}
+
+/// A set of CPU indices.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "CpuSetDef", into = "CpuSetDef")]
+pub struct CpuSet(pub BTreeSet<usize>);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum CpuSetDef {
+    Single(usize),
+    List(BTreeSet<usize>),
+    /// Comma-separated CPU indices and ranges, e.g. `2-3,6`
+    Ranges(String),
+}
+
+impl From<CpuSet> for CpuSetDef {
+    fn from(cpus: CpuSet) -> Self {
+        Self::List(cpus.0)
+    }
+}
+
+impl CpuSet {
+    /// Maximum number of CPUs supported in a set (`CPU_SETSIZE` on Linux).
+    pub const MAX_CPUS: usize = 1024;
+}
+
+impl TryFrom<CpuSetDef> for CpuSet {
+    type Error = String;
+
+    fn try_from(def: CpuSetDef) -> Result<Self, Self::Error> {
+        let check = |cpu: usize| {
+            if cpu < CpuSet::MAX_CPUS {
+                Ok(cpu)
+            } else {
+                Err(format!(
+                    "CPU index {cpu} is out of range (must be below {})",
+                    CpuSet::MAX_CPUS
+                ))
+            }
+        };
+        let ranges = match def {
+            CpuSetDef::Single(cpu) => return Ok(Self([check(cpu)?].into())),
+            CpuSetDef::List(cpus) => {
+                return cpus
+                    .into_iter()
+                    .map(check)
+                    .collect::<Result<_, _>>()
+                    .map(Self)
+            }
+            CpuSetDef::Ranges(ranges) => ranges,
+        };
+        let parse = |cpu: &str| {
+            cpu.trim()
+                .parse::<usize>()
+                .map_err(|_| format!("invalid CPU index `{cpu}` in `{ranges}`"))
+                .and_then(check)
+        };
+        let mut cpus = BTreeSet::new();
+        for part in ranges.split(',') {
+            match part.split_once('-') {
+                Some((start, end)) => cpus.extend(parse(start)?..=parse(end)?),
+                None => {
+                    cpus.insert(parse(part)?);
+                }
+            }
+        }
+        Ok(Self(cpus))
+    }
+}
+
+/// (De)serializes a byte count as an integer or as a string such as `512M` or `2G`.
+mod memory_size {
+    const UNITS: [(&str, u64); 4] = [
+        ("T", 1 << 40),
+        ("G", 1 << 30),
+        ("M", 1 << 20),
+        ("K", 1 << 10),
+    ];
+
+    fn parse(raw: &str) -> Result<u64, String> {
+        let upper = raw.trim().to_ascii_uppercase();
+        let without_suffix = upper
+            .strip_suffix("IB")
+            .or_else(|| upper.strip_suffix('B'))
+            .unwrap_or(&upper);
+        let (number, factor) = UNITS
+            .iter()
+            .find_map(|(unit, factor)| {
+                without_suffix
+                    .strip_suffix(unit)
+                    .map(|number| (number, *factor))
+            })
+            .unwrap_or((without_suffix, 1));
+        let value: f64 = number
+            .trim()
+            .parse()
+            .map_err(|_| format!("invalid memory size `{raw}`"))?;
+        if !value.is_finite() || value < 0.0 {
+            return Err(format!("invalid memory size `{raw}`"));
+        }
+        Ok((value * factor as f64) as u64)
+    }
+
+    fn format(bytes: u64) -> String {
+        UNITS
+            .iter()
+            .find(|(_, factor)| bytes >= *factor && bytes % factor == 0)
+            .map(|(unit, factor)| format!("{}{unit}", bytes / factor))
+            .unwrap_or_else(|| bytes.to_string())
+    }
+
+    pub mod option {
+        use serde::Deserialize;
+
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Bytes(u64),
+            WithUnit(String),
+        }
+
+        pub fn serialize<S>(bytes: &Option<u64>, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: serde::Serializer,
+        {
+            match bytes {
+                Some(bytes) => serializer.serialize_str(&super::format(*bytes)),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+        where
+            D: serde::Deserializer<'de>,
+        {
+            Option::<Raw>::deserialize(deserializer)?
+                .map(|raw| match raw {
+                    Raw::Bytes(bytes) => Ok(bytes),
+                    Raw::WithUnit(raw) => super::parse(&raw).map_err(serde::de::Error::custom),
+                })
+                .transpose()
+        }
+    }
+}
+
+/// A dataflow file that is included into another dataflow
+///
+/// The nodes of the included dataflow are added to the including dataflow under the
+/// namespace `id`, e.g. a node `camera` of a subflow `perception` gets the ID
+/// `perception/camera`.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Subflow {
+    /// Namespace for the included nodes
+    pub id: NodeId,
+    /// Path to the included dataflow file, relative to the including file
+    pub path: PathBuf,
+    /// Input ports of the subflow, mapped to outputs of the including dataflow
+    ///
+    /// Nodes of the subflow receive these inputs through `ports/<port>` mappings.
+    #[serde(default)]
+    #[schemars(with = "BTreeMap<String, String>")]
+    pub inputs: BTreeMap<DataId, InputMapping>,
+    /// Outputs of subflow nodes that are exposed to the including dataflow as `<id>/<port>`
+    #[serde(default)]
+    #[schemars(with = "BTreeMap<String, String>")]
+    pub outputs: BTreeMap<DataId, InputMapping>,
+    /// Content of the included file, loaded when reading the including dataflow.
+    #[schemars(skip)]
+    #[serde(default, rename = "_loaded", skip_serializing_if = "Option::is_none")]
+    pub dataflow: Option<Box<Descriptor>>,
+}
+
+fn namespaced(namespace: &NodeId, id: &NodeId) -> NodeId {
+    NodeId::from(format!("{namespace}{NAMESPACE_SEPARATOR}{id}"))
 }
 
 /// Dora Node
@@ -175,9 +912,22 @@ pub struct Node {
 
     /// Unstable machine deployment configuration
     #[schemars(skip)]
-    #[serde(default, rename = "_unstable_deploy")]
+    #[serde(default, rename = "_unstable_deploy", alias = "deploy")]
     pub deploy: Deploy,
 
+    /// Restart policy for the node process
+    #[serde(default)]
+    #[schemars(with = "Option<RestartPolicyDef>")]
+    pub restart: RestartPolicy,
+
+    /// Resource limits for the node process (Linux only)
+    #[serde(default, skip_serializing_if = "Resources::is_empty")]
+    pub resources: Resources,
+
+    /// Scheduling policy, priority and CPU affinity of the node process (Linux only)
+    #[serde(default, skip_serializing_if = "Scheduling::is_empty")]
+    pub scheduling: Scheduling,
+
     #[serde(default, skip_serializing_if = "Option::is_none")]
     operators: Option<RuntimeNode>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
@@ -196,7 +946,17 @@ pub struct Node {
     #[serde(default)]
     pub inputs: BTreeMap<DataId, Input>,
     #[serde(default)]
-    pub outputs: BTreeSet<DataId>,
+    pub outputs: Outputs,
+
+    /// Directory of the subflow file that defines the node, relative to the including
+    /// dataflow. Build commands of the node run in this directory.
+    #[schemars(skip)]
+    #[serde(
+        default,
+        rename = "_build_dir",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub build_dir: Option<PathBuf>,
 }
 
 impl Node {
@@ -221,6 +981,20 @@ impl Node {
         }
     }
 
+    fn inputs_mut(&mut self) -> eyre::Result<Vec<&mut Input>> {
+        let inputs = match self.kind_mut()? {
+            NodeKindMut::Standard { path: _, inputs } => inputs.values_mut().collect(),
+            NodeKindMut::Runtime(node) => node
+                .operators
+                .iter_mut()
+                .flat_map(|op| op.config.inputs.values_mut())
+                .collect(),
+            NodeKindMut::Custom(node) => node.run_config.inputs.values_mut().collect(),
+            NodeKindMut::Operator(operator) => operator.config.inputs.values_mut().collect(),
+        };
+        Ok(inputs)
+    }
+
     fn kind_mut(&mut self) -> eyre::Result<NodeKindMut> {
         match self.kind()? {
             NodeKind::Standard(_) => self
@@ -281,6 +1055,20 @@ pub struct ResolvedNode {
     #[serde(default)]
     pub deploy: ResolvedDeploy,
 
+    #[serde(default)]
+    pub restart: RestartPolicy,
+
+    #[serde(default)]
+    pub resources: Resources,
+
+    #[serde(default)]
+    pub scheduling: Scheduling,
+
+    /// Directory in which the build commands of the node run, relative to the
+    /// dataflow directory. Set for nodes of included subflows.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build_dir: Option<PathBuf>,
+
     #[serde(flatten)]
     pub kind: CoreNodeKind,
 }
@@ -315,15 +1103,24 @@ impl ResolvedNode {
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ResolvedDeploy {
     pub machine: String,
+    /// Labels that the machine must have.
+    ///
+    /// The coordinator sets `machine` to the chosen machine before spawning the node.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub selector: BTreeMap<String, String>,
 }
 impl ResolvedDeploy {
     fn new(deploy: Deploy, descriptor: &Descriptor) -> Self {
-        let default_machine = descriptor.deploy.machine.as_deref().unwrap_or_default();
-        let machine = match deploy.machine {
-            Some(m) => m,
-            None => default_machine.to_owned(),
+        // node-level settings replace the dataflow-level ones
+        let deploy = if deploy.is_empty() {
+            descriptor.deploy.clone()
+        } else {
+            deploy
         };
-        Self { machine }
+        Self {
+            machine: deploy.machine.unwrap_or_default(),
+            selector: deploy.selector,
+        }
     }
 }
 
@@ -350,17 +1147,18 @@ pub fn runtime_node_inputs(n: &RuntimeNode) -> BTreeMap<DataId, Input> {
         .collect()
 }
 
-fn runtime_node_outputs(n: &RuntimeNode) -> BTreeSet<DataId> {
-    n.operators
-        .iter()
-        .flat_map(|operator| {
-            operator
-                .config
-                .outputs
-                .iter()
-                .map(|output_id| DataId::from(format!("{}/{output_id}", operator.id)))
-        })
-        .collect()
+fn runtime_node_outputs(n: &RuntimeNode) -> Outputs {
+    let mut outputs = Outputs::default();
+    for operator in &n.operators {
+        for output_id in operator.config.outputs.iter() {
+            let id = DataId::from(format!("{}/{output_id}", operator.id));
+            if let Some(rate) = operator.config.outputs.expected_rate(output_id) {
+                outputs.set_expected_rate(id.clone(), *rate);
+            }
+            outputs.insert(id, operator.config.outputs.schema(output_id).cloned());
+        }
+    }
+    outputs
 }
 
 impl CoreNodeKind {
@@ -411,7 +1209,7 @@ pub struct OperatorConfig {
     #[serde(default)]
     pub inputs: BTreeMap<DataId, Input>,
     #[serde(default)]
-    pub outputs: BTreeSet<DataId>,
+    pub outputs: Outputs,
 
     #[serde(flatten)]
     pub source: OperatorSource,
@@ -558,3 +1356,395 @@ impl fmt::Display for EnvValue {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{CpuSet, Descriptor, Node, RestartMode, RestartPolicy};
+    use crate::config::{InputMapping, UserInputMapping};
+    use std::{
+        path::{Path, PathBuf},
+        time::Duration,
+    };
+
+    fn policy(mode: RestartMode, max_retries: Option<u32>) -> RestartPolicy {
+        RestartPolicy {
+            mode,
+            max_retries,
+            backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(1),
+        }
+    }
+
+    #[test]
+    fn restart_modes() {
+        let never = policy(RestartMode::Never, None);
+        assert!(!never.should_restart(true, 0));
+        assert!(!never.should_restart(false, 0));
+
+        let on_failure = policy(RestartMode::OnFailure, None);
+        assert!(!on_failure.should_restart(true, 0));
+        assert!(on_failure.should_restart(false, 0));
+        assert!(on_failure.should_restart(false, 1000));
+
+        let always = policy(RestartMode::Always, None);
+        assert!(always.should_restart(true, 0));
+        assert!(always.should_restart(false, 0));
+    }
+
+    #[test]
+    fn restart_max_retries() {
+        let policy = policy(RestartMode::Always, Some(2));
+        assert!(policy.should_restart(false, 0));
+        assert!(policy.should_restart(false, 1));
+        assert!(!policy.should_restart(false, 2));
+        assert!(!policy.should_restart(true, 3));
+
+        let no_retries = RestartPolicy {
+            max_retries: Some(0),
+            ..policy
+        };
+        assert!(!no_retries.should_restart(false, 0));
+    }
+
+    #[test]
+    fn restart_backoff() {
+        let policy = policy(RestartMode::OnFailure, None);
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff(3), Duration::from_millis(400));
+        assert_eq!(policy.backoff(4), Duration::from_millis(800));
+        // capped at `max_backoff`
+        assert_eq!(policy.backoff(5), Duration::from_secs(1));
+        assert_eq!(policy.backoff(40), Duration::from_secs(1));
+        assert_eq!(policy.backoff(u32::MAX), Duration::from_secs(1));
+        // `0` is treated like the first restart
+        assert_eq!(policy.backoff(0), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn restart_policy_yaml() {
+        let policy: RestartPolicy = serde_yaml::from_str("on-failure").unwrap();
+        assert_eq!(policy, RestartMode::OnFailure.into());
+
+        let policy: RestartPolicy =
+            serde_yaml::from_str("{mode: always, max_retries: 3, backoff: 1s}").unwrap();
+        assert_eq!(policy.mode, RestartMode::Always);
+        assert_eq!(policy.max_retries, Some(3));
+        assert_eq!(policy.backoff, Duration::from_secs(1));
+        assert_eq!(policy.max_backoff, RestartPolicy::DEFAULT_MAX_BACKOFF);
+    }
+
+    #[test]
+    fn namespaced_input_mapping() {
+        let mapping = InputMapping::User(UserInputMapping {
+            source: "perception/camera".to_owned().into(),
+            output: "image".to_owned().into(),
+        });
+        let serialized = serde_json::to_string(&mapping).unwrap();
+        assert_eq!(
+            serialized,
+            r#"{"source":"perception/camera","output":"image"}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<InputMapping>(&serialized).unwrap(),
+            mapping
+        );
+
+        // operator outputs keep using the `<source>/<output>` form
+        let mapping: InputMapping = serde_yaml::from_str("runtime/op/out").unwrap();
+        let InputMapping::User(mapping) = &mapping else {
+            panic!("expected user mapping");
+        };
+        assert_eq!(mapping.source.as_ref(), "runtime");
+        assert_eq!(mapping.output.as_str(), "op/out");
+    }
+
+    #[test]
+    fn cpu_set_bounds() {
+        let cpus: CpuSet = serde_yaml::from_str("0-2,5").unwrap();
+        assert_eq!(cpus.0, [0, 1, 2, 5].into());
+        let cpus: CpuSet = serde_yaml::from_str("1023").unwrap();
+        assert_eq!(cpus.0, [1023].into());
+
+        assert!(serde_yaml::from_str::<CpuSet>("0-4294967295").is_err());
+        assert!(serde_yaml::from_str::<CpuSet>("1024").is_err());
+        assert!(serde_yaml::from_str::<CpuSet>("[0, 2048]").is_err());
+    }
+
+    #[test]
+    fn remote_working_dirs() {
+        let dataflow = |working_dirs: &str| -> Descriptor {
+            serde_yaml::from_str(&format!(
+                "{working_dirs}
+nodes:
+  - id: node
+    _unstable_deploy:
+      machine: remote
+    path: node"
+            ))
+            .unwrap()
+        };
+        let working_dir = std::env::temp_dir();
+
+        let err = dataflow("")
+            .check_in_daemon(&working_dir, &["remote"], &[], false)
+            .unwrap_err();
+        assert!(format!("{err:?}").contains("paths of remote nodes must be absolute"));
+
+        // the path is resolved on the remote machine
+        dataflow("working_dirs: {remote: robot}")
+            .check_in_daemon(&working_dir, &["remote"], &[], false)
+            .unwrap();
+        dataflow("")
+            .check_in_daemon(&working_dir, &["remote"], &["remote"], false)
+            .unwrap();
+    }
+
+    /// Writes the given dataflow files into a new temporary directory.
+    fn write_dataflows(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("dora-subflow-test-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        for (path, content) in files {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+        dir
+    }
+
+    fn node<'a>(nodes: &'a [Node], id: &str) -> &'a Node {
+        nodes
+            .iter()
+            .find(|n| n.id.as_ref() == id)
+            .unwrap_or_else(|| panic!("missing node `{id}`"))
+    }
+
+    fn input(node: &Node, input: &str) -> String {
+        node.inputs[input].mapping.to_string()
+    }
+
+    const NESTED_SUBFLOWS: &[(&str, &str)] = &[
+        (
+            "dataflow.yml",
+            "
+nodes:
+  - id: camera
+    path: camera
+    outputs: [frame]
+  - id: sink
+    path: /usr/bin/sink
+    inputs:
+      image: perception/image
+      filtered: perception/filtered
+include:
+  - id: perception
+    path: sub/perception.yml
+    inputs:
+      trigger: camera/frame
+    outputs:
+      image: detector/image
+      filtered: inner/out
+",
+        ),
+        (
+            "sub/perception.yml",
+            "
+nodes:
+  - id: detector
+    path: detector.py
+    build: pip install -e .
+    inputs:
+      tick: ports/trigger
+    outputs: [image]
+  - id: viewer
+    path: python
+    inputs:
+      image: detector/image
+include:
+  - id: inner
+    path: nested/inner.yml
+    inputs:
+      in: detector/image
+    outputs:
+      out: filter/out
+",
+        ),
+        (
+            "sub/nested/inner.yml",
+            "
+nodes:
+  - id: filter
+    path: bin/filter
+    inputs:
+      x: ports/in
+    outputs: [out]
+",
+        ),
+    ];
+
+    #[test]
+    fn subflow_port_mapping() {
+        let dir = write_dataflows("ports", NESTED_SUBFLOWS);
+        let dataflow = Descriptor::blocking_read(&dir.join("dataflow.yml")).unwrap();
+        let nodes = dataflow.flatten_nodes().unwrap();
+
+        let mut ids: Vec<_> = nodes.iter().map(|n| n.id.to_string()).collect();
+        ids.sort();
+        assert_eq!(
+            ids,
+            [
+                "camera",
+                "perception/detector",
+                "perception/inner/filter",
+                "perception/viewer",
+                "sink"
+            ]
+        );
+
+        // input ports are replaced by the mapped outputs of the including dataflow
+        let detector = node(&nodes, "perception/detector");
+        assert_eq!(input(detector, "tick"), "camera/frame");
+        // references between nodes of the same subflow are namespaced
+        let viewer = node(&nodes, "perception/viewer");
+        assert_eq!(input(viewer, "image"), "perception/detector/image");
+        let filter = node(&nodes, "perception/inner/filter");
+        assert_eq!(input(filter, "x"), "perception/detector/image");
+
+        // exposed outputs are resolved to the node outputs, also through nested subflows
+        let sink = node(&nodes, "sink");
+        assert_eq!(input(sink, "image"), "perception/detector/image");
+        assert_eq!(input(sink, "filtered"), "perception/inner/filter/out");
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn subflow_source_rebasing() {
+        let dir = write_dataflows("rebase", NESTED_SUBFLOWS);
+        let dataflow = Descriptor::blocking_read(&dir.join("dataflow.yml")).unwrap();
+        let nodes = dataflow.flatten_nodes().unwrap();
+
+        let path = |id: &str| PathBuf::from(node(&nodes, id).path.as_ref().unwrap());
+        let build_dir = |id: &str| node(&nodes, id).build_dir.clone();
+
+        // nodes of the including dataflow are unchanged
+        assert_eq!(path("camera"), Path::new("camera"));
+        assert_eq!(path("sink"), Path::new("/usr/bin/sink"));
+        assert_eq!(build_dir("camera"), None);
+
+        // relative sources are made relative to the including dataflow
+        assert_eq!(path("perception/detector"), Path::new("sub/detector.py"));
+        assert_eq!(build_dir("perception/detector"), Some("sub".into()));
+        assert_eq!(
+            path("perception/inner/filter"),
+            Path::new("sub/nested/bin/filter")
+        );
+        assert_eq!(
+            build_dir("perception/inner/filter"),
+            Some("sub/nested".into())
+        );
+        // bare program names refer to executables in `$PATH`
+        assert_eq!(path("perception/viewer"), Path::new("python"));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn subflow_include_cycle() {
+        let dir = write_dataflows(
+            "cycle",
+            &[
+                (
+                    "a.yml",
+                    "
+nodes: []
+include:
+  - id: b
+    path: sub/b.yml
+",
+                ),
+                (
+                    "sub/b.yml",
+                    "
+nodes: []
+include:
+  - id: a
+    path: ../a.yml
+",
+                ),
+            ],
+        );
+        let err = Descriptor::blocking_read(&dir.join("a.yml")).unwrap_err();
+        assert!(format!("{err:?}").contains("includes itself"), "{err:?}");
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn subflow_unknown_ports() {
+        let subflow = "
+nodes:
+  - id: detector
+    path: detector
+    inputs:
+      tick: ports/trigger
+    outputs: [image]
+";
+        let dataflow = |inputs: &str| {
+            format!(
+                "
+nodes:
+  - id: sink
+    path: sink
+    inputs:
+      {inputs}
+include:
+  - id: perception
+    path: perception.yml
+    inputs:
+      trigger: sink/out
+    outputs:
+      image: detector/image
+"
+            )
+        };
+
+        let dir = write_dataflows(
+            "unknown-output",
+            &[
+                ("dataflow.yml", &dataflow("image: perception/depth")),
+                ("perception.yml", subflow),
+            ],
+        );
+        let err = Descriptor::blocking_read(&dir.join("dataflow.yml"))
+            .unwrap()
+            .flatten_nodes()
+            .unwrap_err();
+        assert!(
+            format!("{err:?}").contains("unknown output port `depth` of subflow `perception`"),
+            "{err:?}"
+        );
+        let _ = std::fs::remove_dir_all(dir);
+
+        let dir = write_dataflows(
+            "unknown-input",
+            &[
+                ("dataflow.yml", &dataflow("image: perception/image")),
+                (
+                    "perception.yml",
+                    &subflow.replace("ports/trigger", "ports/other"),
+                ),
+            ],
+        );
+        let err = Descriptor::blocking_read(&dir.join("dataflow.yml"))
+            .unwrap()
+            .flatten_nodes()
+            .unwrap_err();
+        assert!(
+            format!("{err:?}").contains("unknown input port `other` of subflow `perception`"),
+            "{err:?}"
+        );
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
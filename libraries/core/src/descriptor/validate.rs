@@ -1,28 +1,59 @@
 use crate::{
     adjust_shared_library_path,
-    config::{DataId, Input, InputMapping, OperatorId, UserInputMapping},
-    descriptor::{self, source_is_url, CoreNodeKind, OperatorSource, EXE_EXTENSION},
+    config::{
+        DataId, Input, InputMapping, NodeId, OperatorId, OverflowPolicy, RemoteCommunicationConfig,
+        Transport, UserInputMapping,
+    },
+    descriptor::{
+        self, source_is_url, CoreNodeKind, OperatorSource, ResolvedNode, Scheduling, EXE_EXTENSION,
+    },
     get_python_path,
+    schema::SchemaMismatch,
 };
 
 use eyre::{bail, eyre, Context};
 use std::{path::Path, process::Command};
 use tracing::info;
 
-use super::{resolve_path, Descriptor, DYNAMIC_SOURCE, SHELL_SOURCE};
+use super::{resolve_path, variables, Descriptor, DYNAMIC_SOURCE, SHELL_SOURCE};
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
 pub fn check_dataflow(
     dataflow: &Descriptor,
     working_dir: &Path,
     remote_daemon_id: Option<&[&str]>,
+    working_dir_machines: &[&str],
     coordinator_is_remote: bool,
 ) -> eyre::Result<()> {
+    if let Some((location, name)) = variables::unresolved(dataflow)?.into_iter().next() {
+        bail!(
+            "unresolved variable `${{{name}}}` in `{location}` (set it through \
+            `--var {name}=<value>` or add a default value to the `variables` section)"
+        );
+    }
+
     let nodes = dataflow.resolve_aliases_and_set_defaults()?;
     let mut has_python_operator = false;
 
+    for node in &nodes {
+        if !node.deploy.machine.is_empty() && !node.deploy.selector.is_empty() {
+            bail!(
+                "node `{}` must not specify both a `machine` and a `selector`",
+                node.id
+            );
+        }
+    }
+
     // check that nodes and operators exist
+    //
+    // The paths of nodes on machines with their own working dir are resolved on that
+    // machine, so they can't be checked here. Other remote nodes need absolute paths.
     for node in &nodes {
+        let own_working_dir = dataflow.working_dirs.contains_key(&node.deploy.machine)
+            || working_dir_machines.contains(&node.deploy.machine.as_str());
+        let remote = remote_daemon_id.is_some_and(|remote_daemon_id| {
+            is_remote(node, remote_daemon_id, coordinator_is_remote)
+        });
         match &node.kind {
             descriptor::CoreNodeKind::Custom(custom) => match custom.source.as_str() {
                 SHELL_SOURCE => (),
@@ -30,10 +61,10 @@ pub fn check_dataflow(
                 source => {
                     if source_is_url(source) {
                         info!("{source} is a URL."); // TODO: Implement url check.
-                    } else if let Some(remote_daemon_id) = remote_daemon_id {
-                        if remote_daemon_id.contains(&node.deploy.machine.as_str())
-                            || coordinator_is_remote
-                        {
+                    } else if own_working_dir || remote {
+                        // the CLI doesn't know the working dirs that the daemons
+                        // advertise, so it leaves this check to the coordinator
+                        if remote && !own_working_dir && !coordinator_is_remote {
                             let path = Path::new(&source);
                             let path = if path.extension().is_none() {
                                 path.with_extension(EXE_EXTENSION)
@@ -41,25 +72,30 @@ pub fn check_dataflow(
                                 path.to_owned()
                             };
                             if path.is_relative() {
-                                eyre::bail!(
-                                    "paths of remote nodes must be absolute (node `{}`)",
+                                bail!(
+                                    "paths of remote nodes must be absolute (node `{}`), \
+                                    unless their machine has a working dir in \
+                                    `working_dirs` or through `dora daemon --working-dir`",
                                     node.id
                                 );
                             }
-                            info!("skipping path check for remote node `{}`", node.id);
-                        } else {
-                            resolve_path(source, working_dir).wrap_err_with(|| {
-                                format!("Could not find source path `{}`", source)
-                            })?;
                         }
+                        info!("skipping path check for remote node `{}`", node.id);
                     } else {
                         resolve_path(source, working_dir)
                             .wrap_err_with(|| format!("Could not find source path `{}`", source))?;
                     };
                 }
             },
-            descriptor::CoreNodeKind::Runtime(node) => {
-                for operator_definition in &node.operators {
+            descriptor::CoreNodeKind::Runtime(runtime_node) => {
+                for operator_definition in &runtime_node.operators {
+                    if let OperatorSource::Python(_) = &operator_definition.config.source {
+                        has_python_operator = true;
+                    }
+                    if own_working_dir || remote {
+                        info!("skipping operator path check for remote node `{}`", node.id);
+                        continue;
+                    }
                     match &operator_definition.config.source {
                         OperatorSource::SharedLibrary(path) => {
                             if source_is_url(path) {
@@ -72,7 +108,6 @@ pub fn check_dataflow(
                             }
                         }
                         OperatorSource::Python(python_source) => {
-                            has_python_operator = true;
                             let path = &python_source.source;
                             if source_is_url(path) {
                                 info!("{path} is a URL."); // TODO: Implement url check.
@@ -98,7 +133,13 @@ pub fn check_dataflow(
         match &node.kind {
             descriptor::CoreNodeKind::Custom(custom_node) => {
                 for (input_id, input) in &custom_node.run_config.inputs {
-                    check_input(input, &nodes, &format!("{}/{input_id}", node.id))?;
+                    check_input(
+                        input,
+                        &nodes,
+                        &format!("{}/{input_id}", node.id),
+                        (node.id.clone(), input_id.clone()),
+                        &dataflow.communication.remote,
+                    )?;
                 }
             }
             descriptor::CoreNodeKind::Runtime(runtime_node) => {
@@ -108,6 +149,11 @@ pub fn check_dataflow(
                             input,
                             &nodes,
                             &format!("{}/{}/{input_id}", operator_definition.id, node.id),
+                            (
+                                node.id.clone(),
+                                DataId::from(format!("{}/{input_id}", operator_definition.id)),
+                            ),
+                            &dataflow.communication.remote,
                         )?;
                     }
                 }
@@ -115,6 +161,105 @@ pub fn check_dataflow(
         };
     }
 
+    // check that resource limits are valid
+    for node in &nodes {
+        let resources = &node.resources;
+        if resources.is_empty() {
+            continue;
+        }
+        if node.kind.dynamic() {
+            bail!("dynamic node `{}` does not support `resources`", node.id);
+        }
+        if resources
+            .cpu_quota
+            .is_some_and(|quota| !quota.is_finite() || quota <= 0.0)
+        {
+            bail!("`cpu_quota` of node `{}` must be positive", node.id);
+        }
+        if resources.memory_max == Some(0) {
+            bail!("`memory_max` of node `{}` must be positive", node.id);
+        }
+        if resources.pids_max == Some(0) {
+            bail!("`pids_max` of node `{}` must be positive", node.id);
+        }
+    }
+
+    // check that scheduling options are valid and can be applied
+    for node in &nodes {
+        let scheduling = &node.scheduling;
+        if scheduling.is_empty() {
+            continue;
+        }
+        if node.kind.dynamic() {
+            bail!("dynamic node `{}` does not support `scheduling`", node.id);
+        }
+        let realtime = scheduling.policy.is_some_and(|p| p.is_realtime());
+        match scheduling.priority {
+            Some(_) if !realtime => bail!(
+                "`scheduling.priority` of node `{}` requires the `fifo` or `rr` policy",
+                node.id
+            ),
+            Some(priority) if !(1..=99).contains(&priority) => bail!(
+                "`scheduling.priority` of node `{}` must be between 1 and 99",
+                node.id
+            ),
+            None if realtime => bail!(
+                "real-time policy of node `{}` requires a `scheduling.priority`",
+                node.id
+            ),
+            _ => {}
+        }
+        if scheduling
+            .nice
+            .is_some_and(|nice| !(-20..=19).contains(&nice))
+        {
+            bail!(
+                "`scheduling.nice` of node `{}` must be between -20 and 19",
+                node.id
+            );
+        }
+        if scheduling
+            .cpus
+            .as_ref()
+            .is_some_and(|cpus| cpus.0.is_empty())
+        {
+            bail!("`scheduling.cpus` of node `{}` must not be empty", node.id);
+        }
+
+        // privileges can only be checked on the machine that runs the node
+        let remote = remote_daemon_id.is_some_and(|remote_daemon_id| {
+            is_remote(node, remote_daemon_id, coordinator_is_remote)
+        });
+        if remote {
+            info!(
+                "skipping scheduling privilege check for remote node `{}`",
+                node.id
+            );
+        } else {
+            check_scheduling_privileges(scheduling).wrap_err_with(|| {
+                format!(
+                    "cannot apply the `scheduling` options of node `{}`",
+                    node.id
+                )
+            })?;
+        }
+    }
+
+    // check that expected output rates are positive
+    for node in &nodes {
+        let outputs = node.kind.run_config().outputs;
+        for output in &outputs {
+            if let Some(expected) = outputs.expected_rate(output) {
+                if expected.rate.as_hz() <= 0.0 {
+                    bail!(
+                        "`expected_rate` of output `{}/{output}` must be positive",
+                        node.id
+                    );
+                }
+            }
+        }
+    }
+
     // Check that nodes can resolve `send_stdout_as`
     for node in &nodes {
         node.send_stdout_as()
@@ -128,18 +273,142 @@ pub fn check_dataflow(
     Ok(())
 }
 
+/// Whether the node runs on a remote machine, so that its paths can't be checked locally.
+///
+/// Nodes with a `selector` might be placed on any of the machines, so they are treated
+/// as remote as soon as a remote machine is connected.
+fn is_remote(node: &ResolvedNode, remote_daemon_id: &[&str], coordinator_is_remote: bool) -> bool {
+    coordinator_is_remote
+        || remote_daemon_id.contains(&node.deploy.machine.as_str())
+        || (!node.deploy.selector.is_empty() && !remote_daemon_id.is_empty())
+}
+
+/// Checks whether the current process is allowed to apply the given scheduling
+/// options to its child processes.
+#[cfg(target_os = "linux")]
+pub fn check_scheduling_privileges(scheduling: &Scheduling) -> eyre::Result<()> {
+    let rlimit = |resource| {
+        let mut limit = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        // SAFETY: `limit` is a valid, writable `rlimit` struct
+        if unsafe { libc::getrlimit(resource, &mut limit) } != 0 {
+            return Err(std::io::Error::last_os_error()).wrap_err("failed to get resource limit");
+        }
+        Ok(limit.rlim_cur)
+    };
+    let cap_sys_nice = has_cap_sys_nice();
+
+    if let Some(priority) = scheduling.priority {
+        let limit = rlimit(libc::RLIMIT_RTPRIO)?;
+        if !cap_sys_nice && limit < u64::from(priority) {
+            bail!(
+                "real-time priority {priority} requires the `CAP_SYS_NICE` capability \
+                or an `RLIMIT_RTPRIO` of at least {priority} (current: {limit})"
+            );
+        }
+    }
+    if let Some(nice) = scheduling.nice {
+        let nice = i32::from(nice);
+        // SAFETY: no preconditions
+        let current = unsafe { libc::getpriority(libc::PRIO_PROCESS, 0) };
+        // unprivileged processes can only lower the nice value down to `20 - RLIMIT_NICE`
+        let limit = rlimit(libc::RLIMIT_NICE)?.min(40) as i32;
+        if nice < current && !cap_sys_nice && nice < 20 - limit {
+            bail!(
+                "nice value {nice} requires the `CAP_SYS_NICE` capability \
+                or an `RLIMIT_NICE` of at least {} (current: {limit})",
+                20 - nice
+            );
+        }
+    }
+    if let Some(cpus) = &scheduling.cpus {
+        // SAFETY: an all-zero `cpu_set_t` is a valid empty set
+        let mut available: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+        // SAFETY: `available` is a valid, writable `cpu_set_t` of the given size
+        let result = unsafe {
+            libc::sched_getaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &mut available)
+        };
+        if result != 0 {
+            return Err(std::io::Error::last_os_error()).wrap_err("failed to get CPU affinity");
+        }
+        for &cpu in &cpus.0 {
+            // SAFETY: `cpu` is checked against the size of the set first
+            if cpu >= libc::CPU_SETSIZE as usize || !unsafe { libc::CPU_ISSET(cpu, &available) } {
+                bail!(
+                    "CPU {cpu} is not available (it does not exist, is offline, \
+                    or is excluded by the CPU affinity of the daemon)"
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn has_cap_sys_nice() -> bool {
+    const CAP_SYS_NICE: u32 = 23;
+    std::fs::read_to_string("/proc/self/status")
+        .ok()
+        .and_then(|status| {
+            status.lines().find_map(|line| {
+                let caps = line.strip_prefix("CapEff:")?;
+                u64::from_str_radix(caps.trim(), 16).ok()
+            })
+        })
+        .is_some_and(|caps| caps & (1 << CAP_SYS_NICE) != 0)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn check_scheduling_privileges(_scheduling: &Scheduling) -> eyre::Result<()> {
+    bail!("scheduling options are only supported on Linux")
+}
+
 fn check_input(
     input: &Input,
     nodes: &[super::ResolvedNode],
     input_id_str: &str,
+    receiver: (NodeId, DataId),
+    remote: &RemoteCommunicationConfig,
 ) -> Result<(), eyre::ErrReport> {
+    if input.max_deadline_misses.is_some() && input.deadline.is_none() {
+        bail!("input `{input_id_str}` sets `max_deadline_misses` without a `deadline`");
+    }
+    if input.transport == Transport::BestEffort {
+        if input.overflow == OverflowPolicy::Block {
+            bail!(
+                "input `{input_id_str}` with `transport: best-effort` does not \
+                support `overflow: block`"
+            );
+        }
+        if !matches!(remote, RemoteCommunicationConfig::Tcp) {
+            bail!(
+                "input `{input_id_str}` sets `transport: best-effort`, which \
+                requires `_unstable_remote: tcp`"
+            );
+        }
+    }
     match &input.mapping {
-        InputMapping::Timer { interval: _ } => {}
+        InputMapping::Timer { interval: _ } => {
+            if input.overflow == OverflowPolicy::Block {
+                bail!("timer input `{input_id_str}` does not support `overflow: block`");
+            }
+            if input.deadline.is_some() {
+                bail!("timer input `{input_id_str}` does not support `deadline`");
+            }
+            if input.compression.is_some() {
+                bail!("timer input `{input_id_str}` does not support `compression`");
+            }
+            if input.transport != Transport::Reliable {
+                bail!("timer input `{input_id_str}` does not support `transport`");
+            }
+        }
         InputMapping::User(UserInputMapping { source, output }) => {
             let source_node = nodes.iter().find(|n| &n.id == source).ok_or_else(|| {
                 eyre!("source node `{source}` mapped to input `{input_id_str}` does not exist",)
             })?;
-            match &source_node.kind {
+            let declared_schema = match &source_node.kind {
                 CoreNodeKind::Custom(custom_node) => {
                     if !custom_node.run_config.outputs.contains(output) {
                         bail!(
@@ -147,6 +416,7 @@ fn check_input(
                             input `{input_id_str}` does not exist",
                         );
                     }
+                    custom_node.run_config.outputs.schema(output)
                 }
                 CoreNodeKind::Runtime(runtime) => {
                     let (operator_id, output) = output.split_once('/').unwrap_or_default();
@@ -170,6 +440,20 @@ fn check_input(
                             input `{input_id_str}` does not exist",
                         );
                     }
+                    operator.config.outputs.schema(&output)
+                }
+            };
+
+            // check that the declared data types of both ends match
+            if let (Some(expected), Some(declared)) = (&input.schema, declared_schema) {
+                if !expected.matches(&declared.0) {
+                    return Err(SchemaMismatch {
+                        output: (source.clone(), output.clone()),
+                        inputs: vec![receiver],
+                        expected: expected.clone(),
+                        found: declared.clone(),
+                    }
+                    .into());
                 }
             }
         }
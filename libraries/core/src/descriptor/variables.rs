@@ -0,0 +1,282 @@
+use super::Descriptor;
+use eyre::{bail, Context};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Values for the `${name}` placeholders of a dataflow descriptor.
+pub type Variables = BTreeMap<String, serde_yaml::Value>;
+
+/// Removes the `variables` section from the given descriptor value and merges it with
+/// the given overrides.
+///
+/// The names of the variables of the section are added to `known`.
+pub fn collect(
+    descriptor: &mut serde_yaml::Value,
+    overrides: &Variables,
+    known: &mut BTreeSet<String>,
+) -> eyre::Result<Variables> {
+    let section = match descriptor.as_mapping_mut() {
+        Some(map) => map.remove("variables"),
+        None => None,
+    };
+    let mut variables: Variables = match section {
+        Some(section) => {
+            serde_yaml::from_value(section).context("failed to parse `variables` section")?
+        }
+        None => Variables::new(),
+    };
+    known.extend(variables.keys().cloned());
+    variables.extend(overrides.iter().map(|(k, v)| (k.clone(), v.clone())));
+    Ok(variables)
+}
+
+/// Replaces the `${name}` placeholders of known variables in all string scalars of the
+/// given value.
+///
+/// Substitution happens after parsing, so variable values can't change the structure
+/// of the dataflow. A scalar that consists of a single placeholder is replaced by the
+/// value of the variable, so that numbers and booleans keep their type. Placeholders of
+/// unknown variables are kept, so that they can be reported by [`unresolved`].
+///
+/// The names of the substituted variables are added to `known`.
+pub fn substitute(
+    value: &mut serde_yaml::Value,
+    variables: &Variables,
+    known: &mut BTreeSet<String>,
+) -> eyre::Result<()> {
+    match value {
+        serde_yaml::Value::String(text) => {
+            if let Some(name) = text
+                .strip_prefix("${")
+                .and_then(|rest| rest.strip_suffix('}'))
+            {
+                if let Some(variable) = variables.get(name) {
+                    format_value(name, variable)?;
+                    known.insert(name.to_owned());
+                    *value = variable.clone();
+                    return Ok(());
+                }
+            }
+            *text = substitute_str(text, variables, known)?;
+        }
+        serde_yaml::Value::Sequence(values) => {
+            for value in values {
+                substitute(value, variables, known)?;
+            }
+        }
+        serde_yaml::Value::Mapping(map) => {
+            for (_, value) in map.iter_mut() {
+                substitute(value, variables, known)?;
+            }
+        }
+        serde_yaml::Value::Tagged(tagged) => substitute(&mut tagged.value, variables, known)?,
+        serde_yaml::Value::Null | serde_yaml::Value::Bool(_) | serde_yaml::Value::Number(_) => {}
+    }
+    Ok(())
+}
+
+/// Checks that all given overrides refer to variables that the dataflow declares or uses.
+pub fn check_overrides(overrides: &Variables, known: &BTreeSet<String>) -> eyre::Result<()> {
+    let unknown: Vec<_> = overrides
+        .keys()
+        .filter(|name| !known.contains(*name))
+        .map(|name| format!("`{name}`"))
+        .collect();
+    if !unknown.is_empty() {
+        bail!(
+            "the dataflow does not use the variables {}",
+            unknown.join(", ")
+        );
+    }
+    Ok(())
+}
+
+fn substitute_str(
+    text: &str,
+    variables: &Variables,
+    known: &mut BTreeSet<String>,
+) -> eyre::Result<String> {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("${") {
+        result.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let variable = after
+            .find('}')
+            .and_then(|end| variables.get_key_value(&after[..end]).map(|v| (v, end)));
+        match variable {
+            Some(((name, value), end)) => {
+                result.push_str(&format_value(name, value)?);
+                known.insert(name.clone());
+                rest = &after[end + 1..];
+            }
+            None => {
+                result.push_str("${");
+                rest = after;
+            }
+        }
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+fn format_value(name: &str, value: &serde_yaml::Value) -> eyre::Result<String> {
+    let formatted = match value {
+        serde_yaml::Value::Null => String::new(),
+        serde_yaml::Value::Bool(value) => value.to_string(),
+        serde_yaml::Value::Number(value) => value.to_string(),
+        serde_yaml::Value::String(value) => value.clone(),
+        serde_yaml::Value::Sequence(_)
+        | serde_yaml::Value::Mapping(_)
+        | serde_yaml::Value::Tagged(_) => {
+            bail!("variable `{name}` must be a string, number, or boolean")
+        }
+    };
+    Ok(formatted)
+}
+
+/// Returns all `${name}` placeholders that are left in the given dataflow, together with
+/// their location.
+///
+/// Environment variables are expanded when the descriptor is parsed, so they are not
+/// reported here.
+pub fn unresolved(dataflow: &Descriptor) -> eyre::Result<Vec<(String, String)>> {
+    let mut value = serde_json::to_value(dataflow).context("failed to serialize dataflow")?;
+    if let Some(map) = value.as_object_mut() {
+        map.remove("variables");
+    }
+    let mut unresolved = Vec::new();
+    collect_unresolved(&value, String::new(), &mut unresolved);
+    Ok(unresolved)
+}
+
+fn collect_unresolved(
+    value: &serde_json::Value,
+    location: String,
+    unresolved: &mut Vec<(String, String)>,
+) {
+    match value {
+        serde_json::Value::String(s) => {
+            let mut rest = s.as_str();
+            while let Some(start) = rest.find("${") {
+                rest = &rest[start + 2..];
+                let Some(end) = rest.find('}') else { break };
+                let name = &rest[..end];
+                if !name.is_empty()
+                    && name
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+                {
+                    unresolved.push((location.clone(), name.to_owned()));
+                }
+            }
+        }
+        serde_json::Value::Array(values) => {
+            for (i, value) in values.iter().enumerate() {
+                collect_unresolved(value, format!("{location}[{i}]"), unresolved);
+            }
+        }
+        serde_json::Value::Object(map) => {
+            for (key, value) in map {
+                if key == "env" {
+                    continue;
+                }
+                let location = if location.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{location}.{key}")
+                };
+                collect_unresolved(value, location, unresolved);
+            }
+        }
+        serde_json::Value::Null | serde_json::Value::Bool(_) | serde_json::Value::Number(_) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{unresolved, Variables};
+    use crate::descriptor::{CoreNodeKind, Descriptor};
+
+    const DATAFLOW: &str = r#"
+variables:
+  camera: 0
+nodes:
+  - id: camera
+    path: build/camera-${camera}
+    args: --rate ${rate}
+    inputs:
+      tick:
+        source: dora/timer/millis/100
+        queue_size: ${queue}
+"#;
+
+    fn parse(variables: &[(&str, serde_yaml::Value)]) -> eyre::Result<Descriptor> {
+        let variables: Variables = variables
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.clone()))
+            .collect();
+        Descriptor::parse_with_variables(DATAFLOW.as_bytes().to_vec(), &variables)
+    }
+
+    #[test]
+    fn substitution() {
+        let dataflow = parse(&[
+            ("camera", 2.into()),
+            ("rate", "30".into()),
+            ("queue", 5.into()),
+        ])
+        .unwrap();
+        let node = &dataflow.nodes[0];
+        assert_eq!(node.path.as_deref(), Some("build/camera-2"));
+        assert_eq!(node.args.as_deref(), Some("--rate 30"));
+        // a scalar that only consists of a placeholder keeps the type of the value
+        assert_eq!(node.inputs["tick"].queue_size, Some(5));
+        assert!(unresolved(&dataflow).unwrap().is_empty());
+    }
+
+    #[test]
+    fn missing_variables() {
+        // `rate` is used as part of a string, so parsing succeeds
+        let dataflow = parse(&[("queue", 5.into())]).unwrap();
+        assert_eq!(dataflow.nodes[0].path.as_deref(), Some("build/camera-0"));
+        let unresolved = unresolved(&dataflow).unwrap();
+        assert_eq!(
+            unresolved,
+            [("nodes[0].args".to_owned(), "rate".to_owned())]
+        );
+
+        // `queue_size` must be a number
+        assert!(parse(&[("rate", 30.into())]).is_err());
+    }
+
+    #[test]
+    fn unknown_overrides() {
+        let err = parse(&[
+            ("rate", 30.into()),
+            ("queue", 5.into()),
+            ("camrea", 1.into()),
+        ])
+        .unwrap_err();
+        assert!(err.to_string().contains("`camrea`"), "{err}");
+    }
+
+    #[test]
+    fn no_injection() {
+        let injected = "x\n    build: rm -rf /\n    path: /bin/evil";
+        let dataflow = parse(&[
+            ("camera", injected.into()),
+            ("rate", "{ a: b }".into()),
+            ("queue", 5.into()),
+        ])
+        .unwrap();
+        let node = &dataflow.nodes[0];
+        assert_eq!(node.build, None);
+        assert_eq!(
+            node.path.as_deref(),
+            Some(format!("build/camera-{injected}").as_str())
+        );
+        assert_eq!(node.args.as_deref(), Some("--rate { a: b }"));
+        let resolved = dataflow.resolve_aliases_and_set_defaults().unwrap();
+        assert!(matches!(&resolved[0].kind, CoreNodeKind::Custom(n) if n.build.is_none()));
+    }
+}
@@ -6,14 +6,31 @@ use std::{
     time::Duration,
 };
 
-pub fn visualize_nodes(nodes: &[ResolvedNode]) -> String {
+/// The nodes of an included subflow, rendered as a subgraph.
+pub struct SubflowGroup {
+    /// Full namespace of the subflow, including the namespaces of parent subflows.
+    pub id: NodeId,
+    pub name: NodeId,
+    /// Nodes that are defined directly in the subflow.
+    pub nodes: Vec<NodeId>,
+    pub subflows: Vec<SubflowGroup>,
+}
+
+pub fn visualize_nodes(nodes: &[ResolvedNode], subflows: &[SubflowGroup]) -> String {
     let mut flowchart = "flowchart TB\n".to_owned();
     let mut all_nodes = HashMap::new();
 
+    let mut grouped = BTreeSet::new();
+    collect_grouped_nodes(subflows, &mut grouped);
     for node in nodes {
-        visualize_node(node, &mut flowchart);
+        if !grouped.contains(&node.id) {
+            visualize_node(node, &mut flowchart);
+        }
         all_nodes.insert(&node.id, node);
     }
+    for subflow in subflows {
+        visualize_subflow(subflow, &all_nodes, &mut flowchart);
+    }
 
     let dora_timers = collect_dora_timers(nodes);
     if !dora_timers.is_empty() {
@@ -34,6 +51,35 @@ pub fn visualize_nodes(nodes: &[ResolvedNode]) -> String {
     flowchart
 }
 
+fn collect_grouped_nodes<'a>(subflows: &'a [SubflowGroup], grouped: &mut BTreeSet<&'a NodeId>) {
+    for subflow in subflows {
+        grouped.extend(&subflow.nodes);
+        collect_grouped_nodes(&subflow.subflows, grouped);
+    }
+}
+
+fn visualize_subflow(
+    subflow: &SubflowGroup,
+    nodes: &HashMap<&NodeId, &ResolvedNode>,
+    flowchart: &mut String,
+) {
+    writeln!(
+        flowchart,
+        "subgraph ___subflow_{}___ [{}]",
+        subflow.id, subflow.name
+    )
+    .unwrap();
+    for node_id in &subflow.nodes {
+        if let Some(node) = nodes.get(node_id) {
+            visualize_node(node, flowchart);
+        }
+    }
+    for nested in &subflow.subflows {
+        visualize_subflow(nested, nodes, flowchart);
+    }
+    flowchart.push_str("end\n");
+}
+
 pub fn collect_dora_timers(nodes: &[ResolvedNode]) -> BTreeSet<Duration> {
     let mut dora_timers = BTreeSet::new();
     for node in nodes {
@@ -9,6 +9,7 @@ pub use uhlc;
 
 pub mod config;
 pub mod descriptor;
+pub mod schema;
 pub mod topics;
 
 pub fn adjust_shared_library_path(path: &Path) -> Result<std::path::PathBuf, eyre::ErrReport> {
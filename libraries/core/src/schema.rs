@@ -0,0 +1,336 @@
+//! Declared Arrow data types of node outputs and inputs.
+//!
+//! Data types are written in a compact syntax, for example `float32`, `utf8`,
+//! `list<uint8>`, `fixed_size_list<float64, 3>`, or `struct<x: float32, y: float32>`.
+
+use crate::config::{DataId, NodeId};
+use arrow_schema::{DataType, Field, Fields, TimeUnit};
+use eyre::{bail, eyre};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr, sync::Arc};
+
+/// An Arrow data type declared in the dataflow descriptor.
+#[derive(Debug, Clone, PartialEq, Eq, JsonSchema)]
+#[schemars(transparent)]
+pub struct DataSchema(#[schemars(with = "String")] pub DataType);
+
+impl DataSchema {
+    /// Checks whether data of the given type matches this schema.
+    ///
+    /// Nullability and the names of list item fields are ignored.
+    pub fn matches(&self, data_type: &DataType) -> bool {
+        types_match(&self.0, data_type)
+    }
+}
+
+fn types_match(expected: &DataType, actual: &DataType) -> bool {
+    match (expected, actual) {
+        (DataType::List(expected), DataType::List(actual))
+        | (DataType::LargeList(expected), DataType::LargeList(actual)) => {
+            types_match(expected.data_type(), actual.data_type())
+        }
+        (
+            DataType::FixedSizeList(expected, expected_len),
+            DataType::FixedSizeList(actual, actual_len),
+        ) => expected_len == actual_len && types_match(expected.data_type(), actual.data_type()),
+        (DataType::Struct(expected), DataType::Struct(actual)) => {
+            expected.len() == actual.len()
+                && expected
+                    .iter()
+                    .zip(actual.iter())
+                    .all(|(e, a)| e.name() == a.name() && types_match(e.data_type(), a.data_type()))
+        }
+        (expected, actual) => expected == actual,
+    }
+}
+
+impl fmt::Display for DataSchema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_data_type(&self.0, f)
+    }
+}
+
+fn write_data_type(data_type: &DataType, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let name = match data_type {
+        DataType::Null => "null",
+        DataType::Boolean => "bool",
+        DataType::Int8 => "int8",
+        DataType::Int16 => "int16",
+        DataType::Int32 => "int32",
+        DataType::Int64 => "int64",
+        DataType::UInt8 => "uint8",
+        DataType::UInt16 => "uint16",
+        DataType::UInt32 => "uint32",
+        DataType::UInt64 => "uint64",
+        DataType::Float16 => "float16",
+        DataType::Float32 => "float32",
+        DataType::Float64 => "float64",
+        DataType::Utf8 => "utf8",
+        DataType::LargeUtf8 => "large_utf8",
+        DataType::Binary => "binary",
+        DataType::LargeBinary => "large_binary",
+        DataType::Date32 => "date32",
+        DataType::Date64 => "date64",
+        DataType::Timestamp(unit, None) => {
+            return write!(f, "timestamp<{}>", time_unit_name(unit));
+        }
+        DataType::Duration(unit) => return write!(f, "duration<{}>", time_unit_name(unit)),
+        DataType::List(field) => {
+            f.write_str("list<")?;
+            write_data_type(field.data_type(), f)?;
+            return f.write_str(">");
+        }
+        DataType::LargeList(field) => {
+            f.write_str("large_list<")?;
+            write_data_type(field.data_type(), f)?;
+            return f.write_str(">");
+        }
+        DataType::FixedSizeList(field, len) => {
+            f.write_str("fixed_size_list<")?;
+            write_data_type(field.data_type(), f)?;
+            return write!(f, ", {len}>");
+        }
+        DataType::Struct(fields) => {
+            f.write_str("struct<")?;
+            for (i, field) in fields.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(", ")?;
+                }
+                write!(f, "{}: ", field.name())?;
+                write_data_type(field.data_type(), f)?;
+            }
+            return f.write_str(">");
+        }
+        other => return write!(f, "{other:?}"),
+    };
+    f.write_str(name)
+}
+
+fn time_unit_name(unit: &TimeUnit) -> &'static str {
+    match unit {
+        TimeUnit::Second => "s",
+        TimeUnit::Millisecond => "ms",
+        TimeUnit::Microsecond => "us",
+        TimeUnit::Nanosecond => "ns",
+    }
+}
+
+impl FromStr for DataSchema {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser { rest: s };
+        let data_type = parser.data_type()?;
+        parser.skip_whitespace();
+        if !parser.rest.is_empty() {
+            bail!("unexpected `{}` after data type", parser.rest);
+        }
+        Ok(Self(data_type))
+    }
+}
+
+struct Parser<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Parser<'a> {
+    fn skip_whitespace(&mut self) {
+        self.rest = self.rest.trim_start();
+    }
+
+    fn ident(&mut self) -> eyre::Result<&'a str> {
+        self.skip_whitespace();
+        let end = self
+            .rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(self.rest.len());
+        if end == 0 {
+            bail!("expected identifier, found `{}`", self.rest);
+        }
+        let (ident, rest) = self.rest.split_at(end);
+        self.rest = rest;
+        Ok(ident)
+    }
+
+    fn expect(&mut self, c: char) -> eyre::Result<()> {
+        self.skip_whitespace();
+        match self.rest.strip_prefix(c) {
+            Some(rest) => {
+                self.rest = rest;
+                Ok(())
+            }
+            None => bail!("expected `{c}`, found `{}`", self.rest),
+        }
+    }
+
+    fn try_consume(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        match self.rest.strip_prefix(c) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn data_type(&mut self) -> eyre::Result<DataType> {
+        let name = self.ident()?;
+        let data_type = match name.to_ascii_lowercase().as_str() {
+            "null" => DataType::Null,
+            "bool" | "boolean" => DataType::Boolean,
+            "int8" => DataType::Int8,
+            "int16" => DataType::Int16,
+            "int32" => DataType::Int32,
+            "int64" => DataType::Int64,
+            "uint8" => DataType::UInt8,
+            "uint16" => DataType::UInt16,
+            "uint32" => DataType::UInt32,
+            "uint64" => DataType::UInt64,
+            "float16" => DataType::Float16,
+            "float32" => DataType::Float32,
+            "float64" => DataType::Float64,
+            "utf8" | "string" => DataType::Utf8,
+            "large_utf8" | "large_string" => DataType::LargeUtf8,
+            "binary" => DataType::Binary,
+            "large_binary" => DataType::LargeBinary,
+            "date32" => DataType::Date32,
+            "date64" => DataType::Date64,
+            "timestamp" => DataType::Timestamp(self.time_unit()?, None),
+            "duration" => DataType::Duration(self.time_unit()?),
+            "list" => DataType::List(self.item_field()?),
+            "large_list" => DataType::LargeList(self.item_field()?),
+            "fixed_size_list" => {
+                self.expect('<')?;
+                let item = self.data_type()?;
+                self.expect(',')?;
+                let len = self.ident()?;
+                let len = len
+                    .parse()
+                    .map_err(|_| eyre!("invalid list length `{len}`"))?;
+                self.expect('>')?;
+                DataType::FixedSizeList(Arc::new(Field::new("item", item, true)), len)
+            }
+            "struct" => {
+                self.expect('<')?;
+                let mut fields = Vec::new();
+                if !self.try_consume('>') {
+                    loop {
+                        let name = self.ident()?;
+                        self.expect(':')?;
+                        let data_type = self.data_type()?;
+                        fields.push(Field::new(name, data_type, true));
+                        if self.try_consume('>') {
+                            break;
+                        }
+                        self.expect(',')?;
+                    }
+                }
+                DataType::Struct(Fields::from(fields))
+            }
+            other => bail!("unknown data type `{other}`"),
+        };
+        Ok(data_type)
+    }
+
+    fn item_field(&mut self) -> eyre::Result<Arc<Field>> {
+        self.expect('<')?;
+        let item = self.data_type()?;
+        self.expect('>')?;
+        Ok(Arc::new(Field::new("item", item, true)))
+    }
+
+    fn time_unit(&mut self) -> eyre::Result<TimeUnit> {
+        self.expect('<')?;
+        let unit = match self.ident()? {
+            "s" => TimeUnit::Second,
+            "ms" => TimeUnit::Millisecond,
+            "us" => TimeUnit::Microsecond,
+            "ns" => TimeUnit::Nanosecond,
+            other => bail!("unknown time unit `{other}` (expected `s`, `ms`, `us`, or `ns`)"),
+        };
+        self.expect('>')?;
+        Ok(unit)
+    }
+}
+
+impl Serialize for DataSchema {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for DataSchema {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let raw = String::deserialize(deserializer)?;
+        raw.parse()
+            .map_err(|err| serde::de::Error::custom(format!("invalid data type `{raw}`: {err}")))
+    }
+}
+
+/// The data type of an output does not match the declared expectation.
+///
+/// Names both ends of the affected connections.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaMismatch {
+    /// The sending end of the connection
+    pub output: (NodeId, DataId),
+    /// The receiving ends of the connection
+    pub inputs: Vec<(NodeId, DataId)>,
+    pub expected: DataSchema,
+    pub found: DataSchema,
+}
+
+impl fmt::Display for SchemaMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (node, output) = &self.output;
+        write!(
+            f,
+            "data type mismatch between output `{node}/{output}` and "
+        )?;
+        match self.inputs.as_slice() {
+            [] => write!(f, "its receivers")?,
+            [(node, input)] => write!(f, "input `{node}/{input}`")?,
+            inputs => {
+                f.write_str("inputs ")?;
+                for (i, (node, input)) in inputs.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "`{node}/{input}`")?;
+                }
+            }
+        }
+        write!(f, ": expected `{}`, found `{}`", self.expected, self.found)
+    }
+}
+
+impl std::error::Error for SchemaMismatch {}
+
+#[cfg(test)]
+mod tests {
+    use super::DataSchema;
+
+    #[test]
+    fn parse_and_format() {
+        for raw in [
+            "float32",
+            "list<uint8>",
+            "fixed_size_list<float64, 3>",
+            "struct<x: float32, y: list<int64>, label: utf8>",
+            "timestamp<ns>",
+        ] {
+            let schema: DataSchema = raw.parse().unwrap();
+            assert_eq!(schema.to_string(), raw);
+        }
+        assert!("struct<x float32>".parse::<DataSchema>().is_err());
+        assert!("list<float32".parse::<DataSchema>().is_err());
+    }
+}
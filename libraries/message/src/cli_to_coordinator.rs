@@ -1,4 +1,4 @@
-use std::{path::PathBuf, time::Duration};
+use std::{collections::BTreeMap, path::PathBuf, time::Duration};
 
 use dora_core::{
     config::{NodeId, OperatorId},
@@ -6,20 +6,72 @@ use dora_core::{
 };
 use uuid::Uuid;
 
+use crate::common::{ArtifactData, NodeArtifact};
+
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 pub enum ControlRequest {
+    /// Presents the pre-shared token of the coordinator.
+    ///
+    /// Must be the first request of a connection if the coordinator is configured with a
+    /// token.
+    Authenticate {
+        token: String,
+    },
     Start {
         dataflow: Descriptor,
         name: Option<String>,
-        // TODO: remove this once we figure out deploying of node/operator
-        // binaries from CLI to coordinator/daemon
+        /// Working directory of the CLI, used on the machines whose daemon doesn't
+        /// advertise its own working directory.
         local_working_dir: PathBuf,
+        /// Files uploaded for the nodes through `dora start --upload`.
+        #[serde(default)]
+        artifacts: BTreeMap<NodeId, Vec<NodeArtifact>>,
+        /// Content of the `artifacts` that the target daemons didn't cache yet, see
+        /// [`ControlRequest::MissingArtifacts`].
+        #[serde(default)]
+        artifact_data: Vec<ArtifactData>,
+    },
+    /// Asks which of the given artifacts are not cached yet by the daemons that might
+    /// run their nodes.
+    MissingArtifacts {
+        dataflow: Descriptor,
+        artifacts: BTreeMap<NodeId, Vec<NodeArtifact>>,
+    },
+    /// Runs the build commands of the dataflow on the daemons of the machines that run
+    /// the nodes.
+    ///
+    /// The output of the build commands is sent to the subscribers of
+    /// `BuildLogSubscribe`.
+    Build {
+        dataflow: Descriptor,
+        /// Working directory of the CLI, used on the local machines whose daemon doesn't
+        /// advertise its own working directory.
+        local_working_dir: PathBuf,
+    },
+    /// Waits until all build commands of the given build finished.
+    WaitForBuild {
+        build_id: Uuid,
+    },
+    /// Subscribes to the output of the build commands of the given build.
+    ///
+    /// The coordinator confirms the subscription with `BuildLogSubscribed`, followed by
+    /// the log messages of the build so far. The connection is closed when the build
+    /// is finished.
+    BuildLogSubscribe {
+        build_id: Uuid,
+        level: log::LevelFilter,
     },
     Reload {
         dataflow_id: Uuid,
         node_id: NodeId,
         operator_id: Option<OperatorId>,
     },
+    Apply {
+        dataflow_uuid: Uuid,
+        dataflow: Descriptor,
+        local_working_dir: PathBuf,
+        grace_duration: Option<Duration>,
+    },
     Check {
         dataflow_uuid: Uuid,
     },
@@ -31,11 +83,22 @@ pub enum ControlRequest {
         name: String,
         grace_duration: Option<Duration>,
     },
+    RestartNode {
+        dataflow: Uuid,
+        node_id: NodeId,
+        grace_duration: Option<Duration>,
+    },
     Logs {
         uuid: Option<Uuid>,
         name: Option<String>,
         node: String,
     },
+    OutputRates {
+        dataflow_uuid: Uuid,
+    },
+    Stats {
+        dataflow_uuid: Uuid,
+    },
     Destroy,
     List,
     DaemonConnected,
@@ -1,15 +1,19 @@
 use core::fmt;
-use std::borrow::Cow;
+use std::{borrow::Cow, time::Duration};
 
 use aligned_vec::{AVec, ConstAlign};
-use dora_core::{config::NodeId, uhlc};
+use dora_core::{
+    config::{DataId, Frequency, NodeId, OperatorId},
+    uhlc,
+};
+use sha2::{Digest, Sha256};
 use uuid::Uuid;
 
 use crate::DataflowId;
 
 pub use log::Level as LogLevel;
 
-#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 #[must_use]
 pub struct LogMessage {
     pub dataflow_id: DataflowId,
@@ -22,6 +26,172 @@ pub struct LogMessage {
     pub message: String,
 }
 
+/// Declared and measured publish rate of a node output.
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct OutputRate {
+    pub node_id: NodeId,
+    pub output_id: DataId,
+    /// The `expected_rate` of the output, if declared.
+    pub expected: Option<Frequency>,
+    /// Allowed deviation from `expected`, in percent.
+    pub tolerance_percent: Option<u32>,
+    /// Publish rate measured by the daemon, `None` if the output was not sent yet.
+    pub measured: Option<Frequency>,
+}
+
+/// Message statistics of a running dataflow.
+#[derive(Debug, Clone, Default, serde::Deserialize, serde::Serialize)]
+pub struct DataflowStats {
+    pub outputs: Vec<OutputStats>,
+    pub inputs: Vec<NodeInputStats>,
+    #[serde(default)]
+    pub connections: Vec<ConnectionStats>,
+    #[serde(default)]
+    pub best_effort: Vec<BestEffortStats>,
+}
+
+/// State of the connection from the daemon of one machine to the daemon of another.
+///
+/// The connections are shared by all dataflows, so the counters include the messages
+/// of other dataflows too.
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct ConnectionStats {
+    pub machine_id: String,
+    pub peer_machine_id: String,
+    /// Whether the last connection attempt or send succeeded.
+    pub connected: bool,
+    /// Whether the messages are relayed through the coordinator, either because of
+    /// `_unstable_remote: relay` or because no direct connection could be established.
+    #[serde(default)]
+    pub relayed: bool,
+    /// Number of times the connection was re-established after it was lost.
+    pub reconnects: u64,
+    /// Number of messages that are waiting to be sent.
+    pub queued: u64,
+    /// Number of messages that were dropped because the queue was full.
+    pub dropped: u64,
+}
+
+/// Messages that the daemon of one machine received from another machine through
+/// `transport: best-effort`.
+///
+/// Like [`ConnectionStats`], the counters include the messages of other dataflows.
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct BestEffortStats {
+    pub machine_id: String,
+    pub peer_machine_id: String,
+    /// Number of messages that were received completely.
+    pub received: u64,
+    /// Number of messages that were dropped because some of their fragments were lost.
+    pub incomplete: u64,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct OutputStats {
+    pub node_id: NodeId,
+    pub output_id: DataId,
+    /// Number of messages that the node sent on this output.
+    pub sent: u64,
+    /// Total size of the messages sent to other machines, before compression.
+    #[serde(default)]
+    pub remote_bytes: u64,
+    /// Total size of the messages sent to other machines, after compression.
+    #[serde(default)]
+    pub remote_compressed_bytes: u64,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct NodeInputStats {
+    pub node_id: NodeId,
+    pub input_id: DataId,
+    pub stats: InputStats,
+}
+
+#[derive(Debug, Clone, Default, serde::Deserialize, serde::Serialize)]
+pub struct InputStats {
+    /// Number of messages that were passed to the node.
+    pub delivered: u64,
+    /// Number of messages that were dropped, e.g. because the input queue was full.
+    pub dropped: u64,
+    /// Time between sending a message and passing it to the receiving node, based
+    /// on the HLC timestamps of the message and of the receiving daemon.
+    pub latency: LatencyHistogram,
+}
+
+/// Histogram with fixed buckets, see [`LatencyHistogram::BUCKETS`].
+#[derive(Debug, Clone, Default, serde::Deserialize, serde::Serialize)]
+pub struct LatencyHistogram {
+    /// Number of samples per bucket, with an additional bucket for samples that
+    /// exceed the largest bound.
+    counts: Vec<u64>,
+    sum: Duration,
+    max: Duration,
+}
+
+impl LatencyHistogram {
+    /// Upper bounds of the histogram buckets.
+    pub const BUCKETS: [Duration; 13] = [
+        Duration::from_micros(100),
+        Duration::from_micros(250),
+        Duration::from_micros(500),
+        Duration::from_millis(1),
+        Duration::from_micros(2500),
+        Duration::from_millis(5),
+        Duration::from_millis(10),
+        Duration::from_millis(25),
+        Duration::from_millis(50),
+        Duration::from_millis(100),
+        Duration::from_millis(250),
+        Duration::from_millis(500),
+        Duration::from_secs(1),
+    ];
+
+    pub fn record(&mut self, latency: Duration) {
+        if self.counts.is_empty() {
+            self.counts = vec![0; Self::BUCKETS.len() + 1];
+        }
+        let bucket = Self::BUCKETS
+            .iter()
+            .position(|bound| latency <= *bound)
+            .unwrap_or(Self::BUCKETS.len());
+        self.counts[bucket] += 1;
+        self.sum += latency;
+        self.max = self.max.max(latency);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    pub fn mean(&self) -> Option<Duration> {
+        let count = self.count();
+        (count > 0).then(|| Duration::from_secs_f64(self.sum.as_secs_f64() / count as f64))
+    }
+
+    pub fn max(&self) -> Option<Duration> {
+        (self.count() > 0).then_some(self.max)
+    }
+
+    /// Estimates the given quantile (between 0 and 1) as the upper bound of the
+    /// bucket that contains it.
+    pub fn quantile(&self, quantile: f64) -> Option<Duration> {
+        let count = self.count();
+        if count == 0 {
+            return None;
+        }
+        let rank = ((quantile * count as f64).ceil() as u64).clamp(1, count);
+        let mut seen = 0;
+        for (bucket, bucket_count) in self.counts.iter().enumerate() {
+            seen += bucket_count;
+            if seen >= rank {
+                let bound = Self::BUCKETS.get(bucket).copied().unwrap_or(self.max);
+                return Some(bound.min(self.max));
+            }
+        }
+        Some(self.max)
+    }
+}
+
 #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct NodeError {
     pub timestamp: uhlc::Timestamp,
@@ -31,6 +201,12 @@ pub struct NodeError {
 
 impl std::fmt::Display for NodeError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let NodeErrorCause::MachineLost { machine_id } = &self.cause {
+            return write!(
+                f,
+                "lost connection to machine `{machine_id}` running the node"
+            );
+        }
         match &self.exit_status {
             NodeExitStatus::Success => write!(f, "<success>"),
             NodeExitStatus::IoError(err) => write!(f, "I/O error while reading exit status: {err}"),
@@ -54,6 +230,11 @@ impl std::fmt::Display for NodeError {
                 };
                 if matches!(self.cause, NodeErrorCause::GraceDuration) {
                     write!(f, "node was killed by dora because it didn't react to a stop message in time ({signal_str})")
+                } else if matches!(self.cause, NodeErrorCause::OutOfMemory) {
+                    write!(
+                        f,
+                        "node was killed because it exceeded its memory limit ({signal_str})"
+                    )
                 } else {
                     write!(f, "exited because of signal {signal_str}")
                 }
@@ -63,6 +244,9 @@ impl std::fmt::Display for NodeError {
 
         match &self.cause {
             NodeErrorCause::GraceDuration => {}, // handled above
+            NodeErrorCause::OutOfMemory if matches!(self.exit_status, NodeExitStatus::Signal(_)) => {}, // handled above
+            NodeErrorCause::OutOfMemory => write!(f, ". The node exceeded its memory limit.")?,
+            NodeErrorCause::MachineLost { .. } => {}, // handled above
             NodeErrorCause::Cascading { caused_by_node } => write!(
                 f,
                 ". This error occurred because node `{caused_by_node}` exited before connecting to dora."
@@ -86,6 +270,12 @@ pub enum NodeErrorCause {
     Cascading {
         caused_by_node: NodeId,
     },
+    /// Node was killed by the kernel because it exceeded its `memory_max` limit.
+    OutOfMemory,
+    /// The machine that the node ran on was lost, so the node's exit status is unknown.
+    MachineLost {
+        machine_id: String,
+    },
     Other {
         stderr: String,
     },
@@ -130,6 +320,81 @@ pub struct Timestamped<T> {
     pub timestamp: uhlc::Timestamp,
 }
 
+/// Result of the build commands of a node on one machine.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct NodeBuildResult {
+    pub node_id: NodeId,
+    pub machine_id: String,
+    pub result: Result<(), String>,
+}
+
+/// Maximum total size of the artifacts that are uploaded with a single `dora start`.
+///
+/// The artifacts are sent base64-encoded in a single JSON message, which the coordinator
+/// holds in memory until the dataflow is spawned.
+pub const MAX_ARTIFACT_UPLOAD_SIZE: usize = 128 * 1024 * 1024;
+
+/// File that a node needs to run, uploaded by the CLI to the daemon that runs the node.
+///
+/// Examples are the executable of a custom node or the shared library of an operator.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct NodeArtifact {
+    /// Operator that the artifact belongs to, `None` for the `source` of a custom node.
+    pub operator: Option<OperatorId>,
+    /// File name of the artifact after resolving the path of the descriptor.
+    ///
+    /// For example, the shared library of operator `foo` is named `libfoo.so` on Linux.
+    pub file_name: String,
+    /// Content hash of the artifact, see [`ArtifactData::hash`].
+    pub hash: String,
+}
+
+/// Content of an uploaded artifact.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct ArtifactData {
+    pub hash: String,
+    #[serde(with = "base64_bytes")]
+    pub data: Vec<u8>,
+}
+
+impl ArtifactData {
+    pub fn new(data: Vec<u8>) -> Self {
+        Self {
+            hash: Self::hash(&data),
+            data,
+        }
+    }
+
+    /// Returns the hex-encoded SHA-256 hash of the given content.
+    pub fn hash(data: &[u8]) -> String {
+        format!("{:x}", Sha256::digest(data))
+    }
+}
+
+impl fmt::Debug for ArtifactData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ArtifactData")
+            .field("hash", &self.hash)
+            .field("len", &self.data.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Serializes bytes as base64 string, which is more compact than a JSON array.
+pub(crate) mod base64_bytes {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        STANDARD.decode(encoded).map_err(serde::de::Error::custom)
+    }
+}
+
 pub type SharedMemoryId = String;
 
 #[derive(serde::Serialize, serde::Deserialize, Clone)]
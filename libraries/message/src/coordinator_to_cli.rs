@@ -4,21 +4,68 @@ use dora_core::config::NodeId;
 use dora_core::uhlc;
 use uuid::Uuid;
 
-pub use crate::common::LogMessage;
+pub use crate::common::{DataflowStats, LogMessage, NodeBuildResult, OutputRate};
 pub use crate::common::{NodeError, NodeErrorCause, NodeExitStatus};
 
 #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub enum ControlRequestReply {
     Error(String),
+    Authenticated,
     CoordinatorStopped,
-    DataflowStarted { uuid: Uuid },
-    DataflowReloaded { uuid: Uuid },
-    DataflowStopped { uuid: Uuid, result: DataflowResult },
+    DataflowStarted {
+        uuid: Uuid,
+    },
+    DataflowReloaded {
+        uuid: Uuid,
+    },
+    NodeRestarted {
+        uuid: Uuid,
+        node_id: NodeId,
+    },
+    DataflowChangesApplied {
+        uuid: Uuid,
+        changes: NodeChanges,
+    },
+    DataflowStopped {
+        uuid: Uuid,
+        result: DataflowResult,
+    },
     DataflowList(DataflowList),
     DestroyOk,
     DaemonConnected(bool),
     ConnectedMachines(BTreeSet<String>),
     Logs(Vec<u8>),
+    OutputRates(Vec<OutputRate>),
+    Stats(DataflowStats),
+    MissingArtifacts(BTreeSet<String>),
+    BuildStarted {
+        build_id: Uuid,
+    },
+    BuildFinished {
+        build_id: Uuid,
+        results: Vec<NodeBuildResult>,
+    },
+    BuildLogSubscribed,
+}
+
+/// Summary of the node changes applied to a running dataflow.
+#[derive(Debug, Clone, Default, serde::Deserialize, serde::Serialize)]
+pub struct NodeChanges {
+    pub added: BTreeSet<NodeId>,
+    pub removed: BTreeSet<NodeId>,
+    /// Nodes that were restarted because their definition changed.
+    pub restarted: BTreeSet<NodeId>,
+    /// Nodes whose input mappings were changed without restarting them.
+    pub rewired: BTreeSet<NodeId>,
+}
+
+impl NodeChanges {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.restarted.is_empty()
+            && self.rewired.is_empty()
+    }
 }
 
 #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
@@ -26,6 +73,9 @@ pub struct DataflowResult {
     pub uuid: Uuid,
     pub timestamp: uhlc::Timestamp,
     pub node_results: BTreeMap<NodeId, Result<(), NodeError>>,
+    /// Number of times that nodes were restarted because of their restart policy.
+    #[serde(default)]
+    pub node_restarts: BTreeMap<NodeId, u32>,
 }
 
 impl DataflowResult {
@@ -34,6 +84,7 @@ impl DataflowResult {
             uuid,
             timestamp,
             node_results: Default::default(),
+            node_restarts: Default::default(),
         }
     }
 
@@ -59,6 +110,9 @@ impl DataflowList {
 pub struct DataflowListEntry {
     pub id: DataflowIdAndName,
     pub status: DataflowStatus,
+    /// Machines that the coordinator chose for the nodes with a deploy `selector`.
+    #[serde(default)]
+    pub placement: BTreeMap<NodeId, String>,
 }
 
 #[derive(Debug, Clone, Copy, serde::Deserialize, serde::Serialize, PartialEq, Eq)]
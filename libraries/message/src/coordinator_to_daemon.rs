@@ -1,12 +1,23 @@
-use std::{collections::BTreeMap, net::SocketAddr, path::PathBuf, time::Duration};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    net::SocketAddr,
+    path::PathBuf,
+    time::Duration,
+};
 
 use dora_core::{
-    config::{NodeId, OperatorId},
+    config::{DataId, Input, NodeId, OperatorId},
     // TODO: how should we version these?
     descriptor::{Descriptor, ResolvedNode},
 };
 
-use crate::DataflowId;
+use uuid::Uuid;
+
+use crate::{
+    common::{ArtifactData, NodeArtifact},
+    daemon_to_daemon::RelayedEvent,
+    DataflowId,
+};
 
 pub use crate::common::Timestamped;
 
@@ -28,6 +39,16 @@ impl RegisterResult {
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 pub enum DaemonCoordinatorEvent {
     Spawn(SpawnDataflowNodes),
+    /// Validates the given changes and keeps them until they are applied or discarded.
+    PrepareChanges(DataflowChanges),
+    /// Applies the changes that were prepared before.
+    ApplyChanges {
+        dataflow_id: DataflowId,
+    },
+    /// Drops the prepared changes, e.g. because they failed on another machine.
+    DiscardChanges {
+        dataflow_id: DataflowId,
+    },
     AllNodesReady {
         dataflow_id: DataflowId,
         exited_before_subscribe: Vec<NodeId>,
@@ -41,12 +62,49 @@ pub enum DaemonCoordinatorEvent {
         node_id: NodeId,
         operator_id: Option<OperatorId>,
     },
+    RestartNode {
+        dataflow_id: DataflowId,
+        node_id: NodeId,
+        grace_duration: Option<Duration>,
+    },
     Logs {
         dataflow_id: DataflowId,
         node_id: NodeId,
     },
+    OutputRates {
+        dataflow_id: DataflowId,
+    },
+    Stats {
+        dataflow_id: DataflowId,
+    },
+    /// The connection to the given machine was lost.
+    ///
+    /// The given inputs were fed by nodes of the lost machine and should be closed.
+    MachineLost {
+        dataflow_id: DataflowId,
+        machine_id: String,
+        closed_inputs: BTreeSet<(NodeId, DataId)>,
+    },
+    /// Runs the build commands of the given nodes in parallel.
+    ///
+    /// The daemon reports the output as log messages of the build ID and sends a
+    /// `BuildFinished` event when all commands finished.
+    Build {
+        build_id: Uuid,
+        nodes: BTreeMap<NodeId, NodeBuild>,
+    },
+    /// Asks which of the given artifact hashes are not in the artifact cache of the daemon.
+    MissingArtifacts {
+        hashes: BTreeSet<String>,
+    },
     Destroy,
     Heartbeat,
+    /// Event that another daemon sent through the coordinator, see
+    /// [`RelayedEvent`].
+    Relay {
+        source_machine: String,
+        event: RelayedEvent,
+    },
 }
 
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
@@ -56,4 +114,47 @@ pub struct SpawnDataflowNodes {
     pub nodes: Vec<ResolvedNode>,
     pub machine_listen_ports: BTreeMap<String, SocketAddr>,
     pub dataflow_descriptor: Descriptor,
+    /// Files uploaded for the nodes, which replace the paths given in the descriptor.
+    #[serde(default)]
+    pub artifacts: BTreeMap<NodeId, Vec<NodeArtifact>>,
+    /// Content of the artifacts of the local nodes that the daemon didn't cache yet.
+    #[serde(default)]
+    pub artifact_data: Vec<ArtifactData>,
+}
+
+/// Changes to the nodes of a running dataflow, applied through `dora apply`.
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct DataflowChanges {
+    pub dataflow_id: DataflowId,
+    pub working_dir: PathBuf,
+    /// All nodes of the updated dataflow.
+    pub nodes: Vec<ResolvedNode>,
+    pub dataflow_descriptor: Descriptor,
+    /// Nodes that should be spawned.
+    pub added: BTreeSet<NodeId>,
+    /// Nodes that should be stopped.
+    pub removed: BTreeSet<NodeId>,
+    /// Nodes that should be restarted because their definition changed.
+    pub restarted: BTreeSet<NodeId>,
+    /// Input mappings that no longer exist, including the inputs of removed nodes.
+    pub removed_inputs: Vec<NodeInput>,
+    /// New input mappings, including the inputs of added nodes.
+    pub added_inputs: Vec<NodeInput>,
+    pub grace_duration: Option<Duration>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct NodeInput {
+    pub node_id: NodeId,
+    pub machine: String,
+    pub input_id: DataId,
+    pub input: Input,
+}
+
+/// Build commands of a node, see [`DaemonCoordinatorEvent::Build`].
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct NodeBuild {
+    pub working_dir: PathBuf,
+    /// Commands that are run one after another.
+    pub commands: Vec<String>,
 }
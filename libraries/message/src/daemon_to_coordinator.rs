@@ -1,15 +1,26 @@
-use std::collections::BTreeMap;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::PathBuf,
+};
 
 use dora_core::{config::NodeId, uhlc};
 
 pub use crate::common::{
-    DataMessage, LogLevel, LogMessage, NodeError, NodeErrorCause, NodeExitStatus, Timestamped,
+    DataMessage, DataflowStats, LogLevel, LogMessage, NodeError, NodeErrorCause, NodeExitStatus,
+    OutputRate, Timestamped,
 };
-use crate::DataflowId;
+use uuid::Uuid;
+
+use crate::{daemon_to_daemon::RelayedEvent, DataflowId};
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub enum CoordinatorRequest {
     Register(DaemonRegisterRequest),
+    /// Presents the pre-shared token on a connection that is only used for sending
+    /// events.
+    Authenticate {
+        token: Option<String>,
+    },
     Event {
         machine_id: String,
         event: DaemonEvent,
@@ -21,14 +32,57 @@ pub struct DaemonRegisterRequest {
     dora_version: String,
     pub machine_id: String,
     pub listen_port: u16,
+    /// Dataflows that are still running on the daemon.
+    ///
+    /// Allows a restarted coordinator to pick up the dataflows that it started before.
+    #[serde(default)]
+    pub running_dataflows: Vec<DataflowId>,
+    /// Pre-shared token, required if the coordinator is configured with a token.
+    #[serde(default)]
+    pub token: Option<String>,
+    #[serde(default)]
+    pub properties: MachineProperties,
+}
+
+/// Describes a machine to the coordinator.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct MachineProperties {
+    /// Labels that are matched against the `selector` of nodes (e.g. `gpu=true`).
+    pub labels: BTreeMap<String, String>,
+    /// Maximum number of nodes that run on the machine at the same time.
+    ///
+    /// Machines that reached their capacity are skipped when placing nodes.
+    pub capacity: Option<usize>,
+    /// Root directory for the dataflows that run on the machine.
+    ///
+    /// If not set, the working directory of the CLI that started the dataflow is used.
+    pub working_dir: Option<PathBuf>,
+}
+
+impl MachineProperties {
+    /// Whether the machine has all labels of the given selector.
+    pub fn matches(&self, selector: &BTreeMap<String, String>) -> bool {
+        selector
+            .iter()
+            .all(|(key, value)| self.labels.get(key) == Some(value))
+    }
 }
 
 impl DaemonRegisterRequest {
-    pub fn new(machine_id: String, listen_port: u16) -> Self {
+    pub fn new(
+        machine_id: String,
+        listen_port: u16,
+        running_dataflows: Vec<DataflowId>,
+        token: Option<String>,
+        properties: MachineProperties,
+    ) -> Self {
         Self {
             dora_version: env!("CARGO_PKG_VERSION").to_owned(),
             machine_id,
             listen_port,
+            running_dataflows,
+            token,
+            properties,
         }
     }
 
@@ -46,7 +100,7 @@ impl DaemonRegisterRequest {
     }
 }
 
-#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub enum DaemonEvent {
     AllNodesReady {
         dataflow_id: DataflowId,
@@ -58,12 +112,32 @@ pub enum DaemonEvent {
     },
     Heartbeat,
     Log(LogMessage),
+    /// Requests to stop the dataflow on all machines, e.g. because an input exceeded
+    /// its `max_deadline_misses`.
+    StopDataflow {
+        dataflow_id: DataflowId,
+    },
+    /// All build commands of a `DaemonCoordinatorEvent::Build` finished.
+    BuildFinished {
+        build_id: Uuid,
+        results: BTreeMap<NodeId, Result<(), String>>,
+    },
+    /// Event for the daemon of another machine that cannot be reached directly.
+    ///
+    /// The coordinator forwards it to the target daemon.
+    Relay {
+        target_machine: String,
+        event: RelayedEvent,
+    },
 }
 
 #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct DataflowDaemonResult {
     pub timestamp: uhlc::Timestamp,
     pub node_results: BTreeMap<NodeId, Result<(), NodeError>>,
+    /// Number of times that nodes were restarted because of their restart policy.
+    #[serde(default)]
+    pub node_restarts: BTreeMap<NodeId, u32>,
 }
 
 impl DataflowDaemonResult {
@@ -77,10 +151,16 @@ pub enum DaemonCoordinatorReply {
     SpawnResult(Result<(), String>),
     ReloadResult(Result<(), String>),
     StopResult(Result<(), String>),
+    RestartResult(Result<(), String>),
+    PrepareResult(Result<(), String>),
+    ApplyResult(Result<(), String>),
     DestroyResult {
         result: Result<(), String>,
         #[serde(skip)]
         notify: Option<tokio::sync::oneshot::Sender<()>>,
     },
     Logs(Result<Vec<u8>, String>),
+    OutputRates(Result<Vec<OutputRate>, String>),
+    Stats(Result<DataflowStats, String>),
+    MissingArtifacts(BTreeSet<String>),
 }
@@ -1,9 +1,9 @@
 use std::collections::BTreeSet;
 
 use aligned_vec::{AVec, ConstAlign};
-use dora_core::config::{DataId, NodeId};
+use dora_core::config::{Compression, DataId, NodeId};
 
-use crate::{metadata::Metadata, DataflowId};
+use crate::{common::base64_bytes, metadata::Metadata, DataflowId};
 
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 pub enum InterDaemonEvent {
@@ -13,9 +13,26 @@ pub enum InterDaemonEvent {
         output_id: DataId,
         metadata: Metadata,
         data: Option<AVec<u8, ConstAlign<128>>>,
+        /// Codec that was used to compress the `data`.
+        compression: Option<Compression>,
     },
     InputsClosed {
         dataflow_id: DataflowId,
         inputs: BTreeSet<(NodeId, DataId)>,
     },
+    /// Gives back queue slots of an input with `overflow: block` to the daemon of the
+    /// sending node, after the input events were delivered to the receiving node.
+    InputCredits {
+        dataflow_id: DataflowId,
+        node_id: NodeId,
+        input_id: DataId,
+        credits: usize,
+    },
 }
+
+/// A serialized [`InterDaemonEvent`] that the coordinator relays between two daemons
+/// that cannot reach each other directly.
+///
+/// The coordinator connections use JSON, so the event is transmitted as base64 string.
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct RelayedEvent(#[serde(with = "base64_bytes")] pub Vec<u8>);
@@ -68,6 +68,10 @@ pub enum NodeEvent {
     InputClosed {
         id: DataId,
     },
+    /// No message was received on the input within its `deadline`.
+    InputTimeout {
+        id: DataId,
+    },
     AllInputsClosed,
 }
 
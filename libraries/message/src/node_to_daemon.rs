@@ -13,8 +13,16 @@ pub enum DaemonRequest {
         output_id: DataId,
         metadata: Metadata,
         data: Option<DataMessage>,
+        /// Wait for a reply, which is sent once all receivers with `overflow: block`
+        /// have room for the message.
+        blocking: bool,
     },
     CloseOutputs(Vec<DataId>),
+    /// Frees the queue slots of the given delivered inputs with `overflow: block`.
+    ///
+    /// Only used by nodes that queue their inputs themselves, i.e. the runtime, whose
+    /// queue slots are not freed on delivery.
+    InputsConsumed(Vec<DataId>),
     /// Signals that the node is finished sending outputs and that it received all
     /// required drop tokens.
     OutputsDone,
@@ -36,12 +44,12 @@ impl DaemonRequest {
     pub fn expects_tcp_bincode_reply(&self) -> bool {
         #[allow(clippy::match_like_matches_macro)]
         match self {
-            DaemonRequest::SendMessage { .. }
-            | DaemonRequest::NodeConfig { .. }
-            | DaemonRequest::ReportDropTokens { .. } => false,
+            DaemonRequest::SendMessage { blocking, .. } => *blocking,
+            DaemonRequest::NodeConfig { .. } | DaemonRequest::ReportDropTokens { .. } => false,
             DaemonRequest::Register(NodeRegisterRequest { .. })
             | DaemonRequest::Subscribe
             | DaemonRequest::CloseOutputs(_)
+            | DaemonRequest::InputsConsumed(_)
             | DaemonRequest::OutputsDone
             | DaemonRequest::NextEvent { .. }
             | DaemonRequest::SubscribeDrop
@@ -57,6 +65,7 @@ impl DaemonRequest {
             DaemonRequest::Register(NodeRegisterRequest { .. })
             | DaemonRequest::Subscribe
             | DaemonRequest::CloseOutputs(_)
+            | DaemonRequest::InputsConsumed(_)
             | DaemonRequest::OutputsDone
             | DaemonRequest::NextEvent { .. }
             | DaemonRequest::SubscribeDrop
@@ -0,0 +1,64 @@
+use crate::TLS_SERVER_NAME;
+use eyre::Context;
+use rcgen::{
+    BasicConstraints, CertificateParams, DistinguishedName, DnType, ExtendedKeyUsagePurpose, IsCa,
+    KeyPair, KeyUsagePurpose,
+};
+
+/// PEM encoded certificates created by [`generate_certificates`].
+pub struct GeneratedCertificates {
+    /// Self-signed certificate of the certificate authority.
+    pub ca_cert: String,
+    /// Certificate shared by all dora components, signed by the CA.
+    pub cert: String,
+    /// Private key of `cert`.
+    pub key: String,
+}
+
+/// Creates a new certificate authority and uses it to sign a certificate for the dora
+/// components.
+///
+/// The certificate is valid for [`TLS_SERVER_NAME`] and the given additional host names
+/// or IP addresses. It can be used both as server and as client certificate. The key of
+/// the CA is not kept, so new certificates require a new CA.
+pub fn generate_certificates(hostnames: &[String]) -> eyre::Result<GeneratedCertificates> {
+    let ca_key = KeyPair::generate().wrap_err("failed to generate CA key")?;
+    let mut ca_params = CertificateParams::default();
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    ca_params.distinguished_name = distinguished_name("dora CA");
+    ca_params.key_usages = vec![KeyUsagePurpose::KeyCertSign, KeyUsagePurpose::CrlSign];
+    let ca_cert = ca_params
+        .self_signed(&ca_key)
+        .wrap_err("failed to create CA certificate")?;
+
+    let key = KeyPair::generate().wrap_err("failed to generate key")?;
+    let subject_alt_names: Vec<_> = std::iter::once(TLS_SERVER_NAME.to_owned())
+        .chain(hostnames.iter().cloned())
+        .collect();
+    let mut params =
+        CertificateParams::new(subject_alt_names).wrap_err("invalid host name for certificate")?;
+    params.distinguished_name = distinguished_name(TLS_SERVER_NAME);
+    params.key_usages = vec![
+        KeyUsagePurpose::DigitalSignature,
+        KeyUsagePurpose::KeyEncipherment,
+    ];
+    params.extended_key_usages = vec![
+        ExtendedKeyUsagePurpose::ServerAuth,
+        ExtendedKeyUsagePurpose::ClientAuth,
+    ];
+    let cert = params
+        .signed_by(&key, &ca_cert, &ca_key)
+        .wrap_err("failed to sign certificate")?;
+
+    Ok(GeneratedCertificates {
+        ca_cert: ca_cert.pem(),
+        cert: cert.pem(),
+        key: key.serialize_pem(),
+    })
+}
+
+fn distinguished_name(common_name: &str) -> DistinguishedName {
+    let mut name = DistinguishedName::new();
+    name.push(DnType::CommonName, common_name);
+    name
+}
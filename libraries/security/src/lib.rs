@@ -0,0 +1,254 @@
+//! TLS encryption and shared-secret authentication for the connections between the
+//! dora CLI, the coordinator, and the daemons.
+//!
+//! All components use a certificate that is signed by a common certificate authority,
+//! which can be created through [`generate_certificates`]. Connections are mutually
+//! authenticated: the accepting side requires a client certificate and the connecting
+//! side verifies the server certificate against the configured CA.
+
+use eyre::{eyre, Context};
+use rustls::{
+    pki_types::{CertificateDer, PrivateKeyDer, ServerName},
+    server::WebPkiClientVerifier,
+    ClientConfig, ClientConnection, RootCertStore, ServerConfig,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio::net::TcpStream;
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+pub use certs::{generate_certificates, GeneratedCertificates};
+pub use stream::{BlockingStream, MaybeTlsStream};
+
+mod certs;
+mod stream;
+
+/// Server name that all dora certificates are issued for.
+///
+/// Clients verify server certificates against this name instead of the host name, so
+/// that the certificates stay valid when the address of a machine changes.
+pub const TLS_SERVER_NAME: &str = "dora";
+
+/// Maximum size of a message that is accepted before the peer is authenticated.
+///
+/// Connections that announce a larger message before presenting a valid token are
+/// closed, so that unauthenticated peers can't make the receiver allocate large buffers.
+pub const MAX_PRE_AUTH_MESSAGE_SIZE: usize = 16 * 1024;
+
+/// Security settings, as specified in the `security` section of the dora config file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SecurityConfig {
+    /// Encrypts all coordinator, daemon, and CLI connections using TLS.
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+    /// Pre-shared token that the CLI and the daemons need to present to the
+    /// coordinator and to other daemons.
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    /// PEM file with the certificate of the CA that signed all dora certificates.
+    pub ca: PathBuf,
+    /// PEM file with the certificate of this machine.
+    pub cert: PathBuf,
+    /// PEM file with the private key of the machine certificate.
+    pub key: PathBuf,
+}
+
+impl SecurityConfig {
+    /// Resolves relative certificate paths against the given directory, typically the
+    /// directory of the config file.
+    pub fn resolve_paths(&mut self, base_dir: &Path) {
+        if let Some(tls) = &mut self.tls {
+            for path in [&mut tls.ca, &mut tls.cert, &mut tls.key] {
+                if path.is_relative() {
+                    *path = base_dir.join(&*path);
+                }
+            }
+        }
+    }
+}
+
+/// Loaded security settings, used to secure incoming and outgoing connections.
+///
+/// The default value neither encrypts connections nor requires a token.
+#[derive(Clone, Default)]
+pub struct Security {
+    tls: Option<Tls>,
+    token: Option<Arc<str>>,
+}
+
+#[derive(Clone)]
+struct Tls {
+    acceptor: TlsAcceptor,
+    connector: TlsConnector,
+    client_config: Arc<ClientConfig>,
+}
+
+impl Security {
+    /// Reads the certificates referenced by the given config.
+    pub fn load(config: &SecurityConfig) -> eyre::Result<Self> {
+        let tls = config.tls.as_ref().map(Tls::load).transpose()?;
+        Ok(Self {
+            tls,
+            token: config.token.as_deref().map(Into::into),
+        })
+    }
+
+    pub fn tls_enabled(&self) -> bool {
+        self.tls.is_some()
+    }
+
+    /// The token that this component presents to others.
+    pub fn token(&self) -> Option<&str> {
+        self.token.as_deref()
+    }
+
+    /// Checks the token presented by a peer.
+    ///
+    /// Always succeeds if no token is configured.
+    pub fn verify_token(&self, token: Option<&str>) -> bool {
+        match &self.token {
+            None => true,
+            Some(expected) => token.is_some_and(|t| constant_time_eq(expected, t)),
+        }
+    }
+
+    /// Performs the TLS handshake for an incoming connection, if TLS is enabled.
+    pub async fn accept(&self, stream: TcpStream) -> std::io::Result<MaybeTlsStream> {
+        match &self.tls {
+            Some(tls) => {
+                let stream = tls.acceptor.accept(stream).await?;
+                Ok(MaybeTlsStream::Tls(Box::new(stream.into())))
+            }
+            None => Ok(MaybeTlsStream::Plain(stream)),
+        }
+    }
+
+    /// Performs the TLS handshake for an outgoing connection, if TLS is enabled.
+    pub async fn connect(&self, stream: TcpStream) -> std::io::Result<MaybeTlsStream> {
+        match &self.tls {
+            Some(tls) => {
+                let stream = tls.connector.connect(server_name(), stream).await?;
+                Ok(MaybeTlsStream::Tls(Box::new(stream.into())))
+            }
+            None => Ok(MaybeTlsStream::Plain(stream)),
+        }
+    }
+
+    /// Blocking version of [`Self::connect`].
+    pub fn connect_blocking(
+        &self,
+        mut stream: std::net::TcpStream,
+    ) -> std::io::Result<BlockingStream> {
+        match &self.tls {
+            Some(tls) => {
+                let mut connection =
+                    ClientConnection::new(tls.client_config.clone(), server_name())
+                        .map_err(std::io::Error::other)?;
+                while connection.is_handshaking() {
+                    connection.complete_io(&mut stream)?;
+                }
+                Ok(BlockingStream::Tls(Box::new(rustls::StreamOwned::new(
+                    connection, stream,
+                ))))
+            }
+            None => Ok(BlockingStream::Plain(stream)),
+        }
+    }
+}
+
+impl std::fmt::Debug for Security {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Security")
+            .field("tls", &self.tls.is_some())
+            .field("token", &self.token.as_ref().map(|_| "<redacted>"))
+            .finish()
+    }
+}
+
+impl Tls {
+    fn load(config: &TlsConfig) -> eyre::Result<Self> {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+
+        let mut roots = RootCertStore::empty();
+        for cert in load_certs(&config.ca)? {
+            roots
+                .add(cert)
+                .wrap_err_with(|| format!("invalid CA certificate `{}`", config.ca.display()))?;
+        }
+        let roots = Arc::new(roots);
+        let certs = load_certs(&config.cert)?;
+        let key = load_key(&config.key)?;
+
+        let client_verifier =
+            WebPkiClientVerifier::builder_with_provider(roots.clone(), provider.clone())
+                .build()
+                .wrap_err("failed to create client certificate verifier")?;
+        let server_config = ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .wrap_err("failed to set up TLS protocol versions")?
+            .with_client_cert_verifier(client_verifier)
+            .with_single_cert(certs.clone(), key.clone_key())
+            .wrap_err("invalid TLS certificate or key")?;
+        let client_config = ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .wrap_err("failed to set up TLS protocol versions")?
+            .with_root_certificates(roots)
+            .with_client_auth_cert(certs, key)
+            .wrap_err("invalid TLS certificate or key")?;
+        let client_config = Arc::new(client_config);
+
+        Ok(Self {
+            acceptor: TlsAcceptor::from(Arc::new(server_config)),
+            connector: TlsConnector::from(client_config.clone()),
+            client_config,
+        })
+    }
+}
+
+fn server_name() -> ServerName<'static> {
+    ServerName::try_from(TLS_SERVER_NAME).expect("TLS_SERVER_NAME is a valid DNS name")
+}
+
+fn load_certs(path: &Path) -> eyre::Result<Vec<CertificateDer<'static>>> {
+    let file = std::fs::read(path)
+        .wrap_err_with(|| format!("failed to read certificate `{}`", path.display()))?;
+    let certs = rustls_pemfile::certs(&mut file.as_slice())
+        .collect::<Result<Vec<_>, _>>()
+        .wrap_err_with(|| format!("failed to parse certificate `{}`", path.display()))?;
+    if certs.is_empty() {
+        eyre::bail!("no certificate found in `{}`", path.display());
+    }
+    Ok(certs)
+}
+
+fn load_key(path: &Path) -> eyre::Result<PrivateKeyDer<'static>> {
+    let file = std::fs::read(path)
+        .wrap_err_with(|| format!("failed to read private key `{}`", path.display()))?;
+    rustls_pemfile::private_key(&mut file.as_slice())
+        .wrap_err_with(|| format!("failed to parse private key `{}`", path.display()))?
+        .ok_or_else(|| eyre!("no private key found in `{}`", path.display()))
+}
+
+/// Compares the given strings in constant time to avoid leaking the token through
+/// timing differences.
+///
+/// The SHA-256 digests of the strings are compared, so that the comparison doesn't
+/// depend on the length of the strings either.
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    let digest = |s: &str| ring::digest::digest(&ring::digest::SHA256, s.as_bytes());
+    let (a, b) = (digest(a), digest(b));
+    a.as_ref()
+        .iter()
+        .zip(b.as_ref())
+        .fold(0, |acc, (a, b)| acc | (a ^ b))
+        == 0
+}